@@ -0,0 +1,165 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use scylla_orm::ColumnsMap;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::Arc,
+    time::Duration,
+};
+use structured_logger::{async_json::new_writer, Builder};
+use tokio::{io, task::JoinHandle};
+use writing::{conf, db};
+
+const USAGE: &str = "usage:\nSCYLLA_NODES=127.0.0.1:9042 ./audit-content [--concurrency 8] [--rate 100] [--output audit-content.ndjson] [--dry-run]";
+
+struct Args {
+    concurrency: usize,
+    rate: u32, // max verified rows per second, 0 means unlimited
+    output: String,
+    dry_run: bool, // report the mismatches without flagging them
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut args = Args {
+        concurrency: 8,
+        rate: 100,
+        output: "audit-content.ndjson".to_string(),
+        dry_run: false,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--concurrency" => {
+                args.concurrency = iter.next().unwrap_or_default().parse()?;
+            }
+            "--rate" => {
+                args.rate = iter.next().unwrap_or_default().parse()?;
+            }
+            "--output" => {
+                args.output = iter.next().unwrap_or_default();
+            }
+            "--dry-run" => args.dry_run = true,
+            _ => anyhow::bail!("unknown argument: {}\n{}", arg, USAGE),
+        }
+    }
+
+    if args.concurrency < 1 {
+        anyhow::bail!("--concurrency should be greater than 0\n{}", USAGE);
+    }
+    if args.output.is_empty() {
+        anyhow::bail!("--output should not be empty\n{}", USAGE);
+    }
+    Ok(args)
+}
+
+// verifies the content row, returns the report line of a mismatch.
+async fn audit(
+    sess: Arc<db::scylladb::ScyllaDB>,
+    mut doc: db::Content,
+    dry_run: bool,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let ok = doc.hash_matches();
+    let mut flagged = doc.corrupt;
+    if !ok && !doc.corrupt && !dry_run {
+        doc.set_corrupt(&sess, true).await?;
+        flagged = true;
+    }
+    if ok {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::json!({
+        "id": doc.id.to_string(),
+        "gid": doc.gid.to_string(),
+        "cid": doc.cid.to_string(),
+        "status": doc.status,
+        "version": doc.version,
+        "length": doc.length,
+        "size": doc.content.len(),
+        "flagged": flagged,
+    })))
+}
+
+fn report(
+    out: &mut BufWriter<File>,
+    res: Result<anyhow::Result<Option<serde_json::Value>>, tokio::task::JoinError>,
+    mismatched: &mut usize,
+) -> anyhow::Result<()> {
+    if let Some(line) = res?? {
+        *mismatched += 1;
+        serde_json::to_writer(&mut *out, &line)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
+async fn main() -> anyhow::Result<()> {
+    Builder::with_level("info")
+        .with_target_writer("*", new_writer(io::stdout()))
+        .init();
+
+    let args = parse_args()?;
+    let nodes = std::env::var("SCYLLA_NODES")
+        .unwrap_or_else(|_| panic!("env SCYLLA_NODES required:\n{}", USAGE));
+
+    let cfg = conf::ScyllaDB {
+        nodes: nodes.split(',').map(|s| s.to_string()).collect(),
+        username: "".to_string(),
+        password: "".to_string(),
+        ..Default::default()
+    };
+
+    let sess = Arc::new(db::scylladb::ScyllaDB::new(cfg, "writing").await?);
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    println!(
+        "audit contents, concurrency: {}, rate: {}, output: {}, dry_run: {}",
+        args.concurrency, args.rate, args.output, args.dry_run
+    );
+
+    let fields = vec![
+        "id".to_string(),
+        "gid".to_string(),
+        "cid".to_string(),
+        "status".to_string(),
+        "version".to_string(),
+        "length".to_string(),
+        "hash".to_string(),
+        "content".to_string(),
+        "corrupt".to_string(),
+    ];
+    let query = format!("SELECT {} FROM content", fields.join(","));
+    let mut stream = sess.stream(query, ()).await?;
+    let mut tasks: FuturesUnordered<JoinHandle<anyhow::Result<Option<serde_json::Value>>>> =
+        FuturesUnordered::new();
+    let mut total: usize = 0;
+    let mut mismatched: usize = 0;
+
+    while let Some(row) = stream.next().await {
+        let mut cols = ColumnsMap::with_capacity(fields.len());
+        cols.fill(row?, &fields)?;
+        let mut doc = db::Content::default();
+        doc.fill(&cols);
+        total += 1;
+
+        if tasks.len() >= args.concurrency {
+            if let Some(res) = tasks.next().await {
+                report(&mut out, res, &mut mismatched)?;
+            }
+        }
+        tasks.push(tokio::spawn(audit(sess.clone(), doc, args.dry_run)));
+
+        if args.rate > 0 {
+            tokio::time::sleep(Duration::from_millis(1000 / args.rate as u64)).await;
+        }
+    }
+
+    while let Some(res) = tasks.next().await {
+        report(&mut out, res, &mut mismatched)?;
+    }
+    out.flush()?;
+
+    println!("total: {}, mismatched: {}", total, mismatched);
+    Ok(())
+}
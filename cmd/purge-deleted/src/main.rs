@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use structured_logger::{async_json::new_writer, Builder};
+use tokio::io;
+use writing::{conf, db};
+
+const USAGE: &str = "usage:\nSCYLLA_NODES=127.0.0.1:9042 ./purge-deleted [--retention-days 180] [--rate 100] [--dry-run]";
+
+struct Args {
+    retention_days: i32,
+    rate: u32, // max purged rows per second, 0 means unlimited
+    dry_run: bool,
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut args = Args {
+        retention_days: 180,
+        rate: 100,
+        dry_run: false,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--retention-days" => {
+                args.retention_days = iter.next().unwrap_or_default().parse()?;
+            }
+            "--rate" => {
+                args.rate = iter.next().unwrap_or_default().parse()?;
+            }
+            "--dry-run" => args.dry_run = true,
+            _ => anyhow::bail!("unknown argument: {}\n{}", arg, USAGE),
+        }
+    }
+
+    if args.retention_days < 1 {
+        anyhow::bail!("--retention-days should be greater than 0\n{}", USAGE);
+    }
+    Ok(args)
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 2)]
+async fn main() -> anyhow::Result<()> {
+    Builder::with_level("info")
+        .with_target_writer("*", new_writer(io::stdout()))
+        .init();
+
+    let args = parse_args()?;
+    let nodes = std::env::var("SCYLLA_NODES")
+        .unwrap_or_else(|_| panic!("env SCYLLA_NODES required:\n{}", USAGE));
+
+    let cfg = conf::ScyllaDB {
+        nodes: nodes.split(',').map(|s| s.to_string()).collect(),
+        username: "".to_string(),
+        password: "".to_string(),
+        ..Default::default()
+    };
+
+    let sess = db::scylladb::ScyllaDB::new(cfg, "writing").await?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let day = db::unix_ms_day(now) - args.retention_days;
+    println!(
+        "purge rows deleted before day {}, retention_days: {}, rate: {}, dry_run: {}",
+        day, args.retention_days, args.rate, args.dry_run
+    );
+
+    let stats = db::Creation::purge_deleted_before(&sess, day, args.dry_run, args.rate).await?;
+    println!(
+        "deleted_creation scanned: {}, purged: {}, purged_contents: {}, kept_contents: {}",
+        stats.scanned, stats.purged, stats.purged_contents, stats.kept_contents
+    );
+
+    let stats = db::Publication::purge_deleted_before(&sess, day, args.dry_run, args.rate).await?;
+    println!(
+        "deleted_publication scanned: {}, purged: {}, purged_contents: {}, kept_contents: {}",
+        stats.scanned, stats.purged, stats.purged_contents, stats.kept_contents
+    );
+
+    Ok(())
+}
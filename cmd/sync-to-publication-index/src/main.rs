@@ -1,8 +1,10 @@
 use futures::stream::StreamExt;
-use scylla_orm::{ColumnsMap, ToCqlVal};
+use scylla_orm::ToCqlVal;
 use structured_logger::{async_json::new_writer, Builder};
 use tokio::io;
-use writing::{conf, db};
+use writing::{api::model::validate_model, conf, db};
+
+const REINDEX_BATCH: usize = 100;
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 2)]
 async fn main() -> anyhow::Result<()> {
@@ -11,42 +13,53 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let nodes = std::env::var("SCYLLA_NODES").expect(
-        "env SCYLLA_NODES required:\nSCYLLA_NODES=127.0.0.1:9042 ./sync-to-publication-index",
+        "env SCYLLA_NODES required:\nSCYLLA_NODES=127.0.0.1:9042 [CONFIG_FILE_PATH=./config/default.toml] [MEILI_URL=http://127.0.0.1:7700 MEILI_API_KEY=...] ./sync-to-publication-index",
     );
 
     let cfg = conf::ScyllaDB {
         nodes: nodes.split(',').map(|s| s.to_string()).collect(),
         username: "".to_string(),
         password: "".to_string(),
+        ..Default::default()
+    };
+
+    // the models are read from the config file if CONFIG_FILE_PATH is set, the ones not
+    // allowed are fixed to the default model, the aliases to their canonical names.
+    let models = match std::env::var("CONFIG_FILE_PATH") {
+        Ok(file) => conf::Conf::from(&file)?.models,
+        Err(_) => conf::Models::default(),
     };
 
     let sess = db::scylladb::ScyllaDB::new(cfg, "writing").await?;
-    let publication_fields = vec![
-        "gid".to_string(),
-        "cid".to_string(),
-        "language".to_string(),
-        "version".to_string(),
-        "status".to_string(),
-        "model".to_string(),
-        "from_language".to_string(),
-    ];
-    let query = format!("SELECT {} FROM publication", publication_fields.join(","));
-    let mut stream = sess.stream(query, ()).await?;
+
+    // the published documents are reindexed to backfill the `cid` of the documents indexed
+    // before it was added, the content statistics are left out and kept as indexed.
+    let meili = match std::env::var("MEILI_URL") {
+        Ok(url) => Some(
+            db::meili::MeiliSearch::new(conf::Meili {
+                url,
+                api_key: std::env::var("MEILI_API_KEY").unwrap_or_default(),
+            })
+            .await?,
+        ),
+        Err(_) => None,
+    };
+    let mut docs: Vec<db::meili::Document> = Vec::with_capacity(REINDEX_BATCH);
+    let mut reindexed: usize = 0;
+    let mut stream = sess.stream_model::<db::Publication>("", ()).await?;
     let mut total: usize = 0;
     let mut fixed: usize = 0;
     let mut synced: usize = 0;
 
     let update_mode_query =
         "UPDATE publication SET model=? WHERE gid=? AND cid=? AND language=? AND version=?";
-    while let Some(row) = stream.next().await {
-        let mut cols = ColumnsMap::with_capacity(publication_fields.len());
-        cols.fill(row?, &publication_fields)?;
-        let mut doc = db::Publication::default();
-        doc.fill(&cols);
+    while let Some(doc) = stream.next().await {
+        let doc = doc?;
         total += 1;
-        if doc.model != "gpt-3.5" && doc.model != "gpt-4" {
+        let model = validate_model(&models, &doc.model).unwrap_or_else(|_| models.default.clone());
+        if doc.model != model {
             let params = (
-                "gpt-3.5",
+                model,
                 doc.gid.to_cql(),
                 doc.cid.to_cql(),
                 doc.language.to_cql(),
@@ -70,10 +83,32 @@ async fn main() -> anyhow::Result<()> {
                 synced += 1;
                 println!("doc: {} {} {}", idoc.cid, idoc.language, idoc.version);
             }
+
+            if let Some(meili) = &meili {
+                docs.push(doc.to_meili());
+                if docs.len() >= REINDEX_BATCH {
+                    reindexed += docs.len();
+                    meili
+                        .add_or_update(db::meili::Space::Pub(None), std::mem::take(&mut docs))
+                        .await?;
+                }
+            }
         }
     }
 
-    println!("total: {}, fixed: {}, synced: {}", total, fixed, synced);
+    if let Some(meili) = &meili {
+        if !docs.is_empty() {
+            reindexed += docs.len();
+            meili
+                .add_or_update(db::meili::Space::Pub(None), docs)
+                .await?;
+        }
+    }
+
+    println!(
+        "total: {}, fixed: {}, synced: {}, reindexed: {}",
+        total, fixed, synced, reindexed
+    );
 
     Ok(())
 }
@@ -3,6 +3,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use schemars::JsonSchema;
 use scylla::transport::query_result::SingleRowError;
 use serde::{Deserialize, Serialize};
 use std::{convert::From, error::Error, fmt, fmt::Debug};
@@ -11,13 +12,13 @@ use validator::{ValidationError, ValidationErrors};
 use crate::object::PackObject;
 
 /// ErrorResponse is the response body for error.
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct ErrorResponse {
     pub error: HTTPError,
 }
 
 /// SuccessResponse is the response body for success.
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct SuccessResponse<T> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_size: Option<u64>,
@@ -36,7 +37,7 @@ impl<T> SuccessResponse<T> {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct HTTPError {
     pub code: u16,
     pub message: String,
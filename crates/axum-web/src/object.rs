@@ -12,6 +12,11 @@ use axum::{
 };
 use base64::{engine::general_purpose, Engine as _};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
 use serde::{
     de::{self, DeserializeOwned},
     ser::Serializer,
@@ -187,6 +192,89 @@ impl Serialize for PackObject<uuid::Uuid> {
     }
 }
 
+// the JSON schema of the packed value, it is always a string in JSON,
+// and the description documents its CBOR representation.
+fn pack_schema(format: &str, description: &str) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        format: Some(format.to_string()),
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.to_string()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+impl JsonSchema for PackObject<Vec<u8>> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "PackObject_bytes".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        pack_schema(
+            "base64url",
+            "Base64 URL-safe string without padding in JSON, byte string in CBOR.",
+        )
+    }
+}
+
+impl JsonSchema for PackObject<xid::Id> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "PackObject_xid".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        pack_schema(
+            "xid",
+            "20 chars xid string in JSON, 12 bytes byte string in CBOR.",
+        )
+    }
+}
+
+impl JsonSchema for PackObject<isolang::Language> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "PackObject_language".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        pack_schema(
+            "language",
+            "Language autonym or English name in JSON, ISO 639-3 code in CBOR. ISO 639-1 and ISO 639-3 codes are accepted in both.",
+        )
+    }
+}
+
+impl JsonSchema for PackObject<uuid::Uuid> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "PackObject_uuid".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        pack_schema(
+            "uuid",
+            "Hyphenated UUID string in JSON, 16 bytes byte string in CBOR.",
+        )
+    }
+}
+
 struct PackObjectBytesVisitor;
 
 impl<'de> de::Visitor<'de> for PackObjectBytesVisitor {
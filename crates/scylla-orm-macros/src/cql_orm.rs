@@ -58,6 +58,20 @@ pub fn cql_orm(input: TokenStream) -> TokenStream {
                 cols
             }
         }
+
+        impl scylla_orm::CqlModel for #struct_name {
+            fn fields() -> Vec<String> {
+                #struct_name::fields()
+            }
+
+            fn fill(&mut self, cols: &scylla_orm::ColumnsMap) {
+                #struct_name::fill(self, cols)
+            }
+
+            fn to(&self) -> scylla_orm::ColumnsMap {
+                #struct_name::to(self)
+            }
+        }
     };
 
     TokenStream::from(expanded)
@@ -1,4 +1,5 @@
 use isolang::Language;
+use scylla_orm::CqlModel;
 use scylla_orm_macros::CqlOrm;
 
 #[derive(Debug, Default, Clone, CqlOrm, PartialEq, Eq)]
@@ -39,3 +40,28 @@ fn derive_cql_orm_works() {
     doc2._fields = doc._fields.clone();
     assert_eq!(doc2, doc);
 }
+
+fn to_and_fill<T: CqlModel + Default>(doc: &T) -> T {
+    assert_eq!(doc.to().len(), T::fields().len());
+    let mut res = T::default();
+    res.fill(&doc.to());
+    res
+}
+
+#[test]
+fn derive_cql_model_works() {
+    assert_eq!(
+        <Document as CqlModel>::fields(),
+        vec!["id", "status", "language", "authors", "content"]
+    );
+
+    let doc = Document {
+        id: xid::new(),
+        status: 1,
+        language: Language::Eng,
+        authors: vec!["John".to_string()],
+        content: vec![1, 2, 3],
+        _fields: vec![],
+    };
+    assert_eq!(to_and_fill(&doc), doc);
+}
@@ -1,18 +1,21 @@
+use indexmap::{map::Iter, IndexMap};
 use scylla::frame::response::result::Row;
-use std::collections::{hash_map::Iter, HashMap};
+use std::collections::HashMap;
 
 use crate::{CqlValue, FromCqlVal, ToCqlVal};
 
+// ColumnsMap keeps the columns in insertion order, so `keys`, `iter` and `ordered_pairs`
+// always yield the same order for the same map.
 #[derive(Debug, Default, PartialEq)]
-pub struct ColumnsMap(HashMap<String, CqlValue>);
+pub struct ColumnsMap(IndexMap<String, CqlValue>);
 
 impl ColumnsMap {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(IndexMap::new())
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
-        Self(HashMap::with_capacity(capacity))
+        Self(IndexMap::with_capacity(capacity))
     }
 
     pub fn len(&self) -> usize {
@@ -42,6 +45,12 @@ impl ColumnsMap {
         self.0.iter()
     }
 
+    // ordered_pairs yields (name, value) in insertion order, build both the query
+    // and its params from it.
+    pub fn ordered_pairs(&self) -> impl Iterator<Item = (&str, &CqlValue)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
     pub fn get_as<T: FromCqlVal>(&self, key: &str) -> anyhow::Result<T> {
         match self.0.get(key) {
             Some(v) => T::from_cql(v).map_err(anyhow::Error::new),
@@ -56,6 +65,17 @@ impl ColumnsMap {
         self.0.insert(key.to_string(), val.to_cql());
     }
 
+    // set_if_some sets the value if it is Some, returns whether it was set.
+    pub fn set_if_some<T: ToCqlVal>(&mut self, key: &str, val: Option<&T>) -> bool {
+        match val {
+            Some(v) => {
+                self.set_as(key, v);
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn append_map<T: ToCqlVal>(&mut self, map_name: &str, key: &str, val: T) {
         let mut map: HashMap<String, CqlValue> = self.get_as(map_name).unwrap_or_default();
 
@@ -199,4 +219,69 @@ mod tests {
         assert!(map2.fill(row, &fields).is_ok());
         assert_eq!(map2, map);
     }
+
+    #[test]
+    fn set_if_some_works() {
+        let mut map = ColumnsMap::new();
+
+        assert!(!map.set_if_some::<String>("title", None));
+        assert!(!map.has("title"));
+        assert!(map.is_empty());
+
+        assert!(map.set_if_some("title", Some(&"Hello".to_string())));
+        assert_eq!(map.get_as::<String>("title").unwrap(), "Hello".to_string());
+
+        // None does not clear the value set before
+        assert!(!map.set_if_some::<String>("title", None));
+        assert_eq!(map.get_as::<String>("title").unwrap(), "Hello".to_string());
+
+        assert!(map.set_if_some("price", Some(&-1i64)));
+        assert!(map.set_if_some("title", Some(&"World".to_string())));
+        assert_eq!(map.keys(), vec!["title".to_string(), "price".to_string()]);
+        assert_eq!(map.get_as::<String>("title").unwrap(), "World".to_string());
+        assert_eq!(map.get_as::<i64>("price").unwrap(), -1);
+    }
+
+    #[test]
+    fn ordered_pairs_works() {
+        let names = [
+            "title", "cover", "keywords", "labels", "authors", "summary", "license",
+        ];
+        let mut map = ColumnsMap::new();
+        for (i, name) in names.iter().enumerate() {
+            map.set_as(name, &(i as i32));
+        }
+
+        let keys = map.keys();
+        assert_eq!(keys, names);
+        for _ in 0..10 {
+            assert_eq!(map.keys(), keys);
+            let pairs: Vec<(&str, &CqlValue)> = map.ordered_pairs().collect();
+            assert_eq!(pairs.len(), names.len());
+            for (i, (k, v)) in pairs.into_iter().enumerate() {
+                assert_eq!(k, names[i]);
+                assert_eq!(v, &CqlValue::Int(i as i32));
+            }
+        }
+
+        // updating a value keeps its position
+        map.set_as("cover", &100i32);
+        assert_eq!(map.keys(), keys);
+        assert_eq!(
+            map.ordered_pairs().nth(1),
+            Some(("cover", &CqlValue::Int(100)))
+        );
+
+        // fill follows the fields order
+        let fields: Vec<String> = names.iter().rev().map(|v| v.to_string()).collect();
+        let row = Row {
+            columns: fields
+                .iter()
+                .map(|v| Some(CqlValue::Text(v.to_owned())))
+                .collect(),
+        };
+        let mut map2 = ColumnsMap::with_capacity(fields.len());
+        assert!(map2.fill(row, &fields).is_ok());
+        assert_eq!(map2.keys(), fields);
+    }
 }
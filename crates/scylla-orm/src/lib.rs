@@ -3,3 +3,11 @@ mod cql_value;
 
 pub use columns::*;
 pub use cql_value::*;
+
+// CqlModel is implemented by `#[derive(CqlOrm)]`, it exposes the derived methods to generic
+// code, e.g. streaming the rows of a table as typed models.
+pub trait CqlModel {
+    fn fields() -> Vec<String>;
+    fn fill(&mut self, cols: &ColumnsMap);
+    fn to(&self) -> ColumnsMap;
+}
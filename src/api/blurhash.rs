@@ -0,0 +1,255 @@
+use axum::{extract::State, Extension};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use validator::{Validate, ValidationError};
+
+use axum_web::context::ReqContext;
+use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
+use axum_web::object::PackObject;
+use scylla_orm::ColumnsMap;
+
+use super::{valid_cover_host, AppState};
+
+const BASE83_CHARS: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+// the max bytes of a cover image to fetch.
+#[cfg(feature = "blurhash")]
+const MAX_COVER_IMAGE_LEN: u64 = 5 * 1024 * 1024;
+#[cfg(feature = "blurhash")]
+const COVER_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+// the image is downscaled before encoding, the placeholder is blurry anyway.
+#[cfg(feature = "blurhash")]
+const BLURHASH_SAMPLE_SIZE: u32 = 64;
+
+// validate_blurhash checks the length and the base83 alphabet, and that the length matches
+// the number of components encoded in the first char.
+pub fn validate_blurhash(hash: &str) -> Result<(), ValidationError> {
+    if hash.len() < 6 || hash.len() > 100 {
+        return Err(ValidationError::new("blurhash length should be 6 to 100"));
+    }
+
+    if !hash.bytes().all(|c| BASE83_CHARS.contains(&c)) {
+        return Err(ValidationError::new(
+            "blurhash is not a valid base83 string",
+        ));
+    }
+
+    let size = BASE83_CHARS
+        .iter()
+        .position(|v| *v == hash.as_bytes()[0])
+        .unwrap_or_default();
+    let (x, y) = (size % 9 + 1, size / 9 + 1);
+    if y > 9 || hash.len() != 4 + 2 * x * y {
+        return Err(ValidationError::new("blurhash components not match"));
+    }
+    Ok(())
+}
+
+// sets the cover columns to update, a new cover without a blurhash clears the previous one.
+pub fn set_cover_columns(
+    cols: &mut ColumnsMap,
+    cover: Option<&String>,
+    cover_blurhash: Option<&String>,
+) {
+    cols.set_if_some("cover", cover);
+    match cover_blurhash {
+        Some(v) => cols.set_as("cover_blurhash", v),
+        None if cover.is_some() => cols.set_as("cover_blurhash", &String::new()),
+        None => {}
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct BlurhashInput {
+    #[validate(url)]
+    pub url: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct BlurhashOutput {
+    pub url: String,
+    pub blurhash: String,
+}
+
+// computes the blurhash of a cover image for the client to submit with `cover_blurhash`,
+// the create and update handlers never fetch images.
+pub async fn create(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<BlurhashInput>,
+) -> Result<PackObject<SuccessResponse<BlurhashOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    ctx.set_kvs(vec![
+        ("action", "create_blurhash".into()),
+        ("url", input.url.clone().into()),
+    ])
+    .await;
+
+    // never fetches from arbitrary hosts.
+    if app.cover.allowed_hosts.is_empty() {
+        return Err(HTTPError::new(
+            400,
+            "Cover allowed_hosts is not configured".to_string(),
+        ));
+    }
+    valid_cover_host(&app.cover.allowed_hosts, &input.url)?;
+
+    let blurhash = cover_blurhash(&input.url).await?;
+    Ok(to.with(SuccessResponse::new(BlurhashOutput {
+        url: input.url,
+        blurhash,
+    })))
+}
+
+#[cfg(feature = "blurhash")]
+async fn cover_blurhash(url: &str) -> Result<String, HTTPError> {
+    let data = fetch_image(url).await?;
+    tokio::task::spawn_blocking(move || encode_blurhash(&data))
+        .await
+        .map_err(|err| HTTPError::new(500, err.to_string()))?
+}
+
+#[cfg(not(feature = "blurhash"))]
+async fn cover_blurhash(_url: &str) -> Result<String, HTTPError> {
+    Err(HTTPError::new(
+        501,
+        "Blurhash is not enabled in this build".to_string(),
+    ))
+}
+
+// fetches the image without following redirects, they may lead to a host not allowed.
+#[cfg(feature = "blurhash")]
+async fn fetch_image(url: &str) -> Result<Vec<u8>, HTTPError> {
+    let fetch_error =
+        |err: reqwest::Error| HTTPError::new(400, format!("Fetch cover failed: {}", err));
+    let client = reqwest::Client::builder()
+        .timeout(COVER_FETCH_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|err| HTTPError::new(500, err.to_string()))?;
+
+    let mut res = client.get(url).send().await.map_err(fetch_error)?;
+    if !res.status().is_success() {
+        return Err(HTTPError::new(
+            400,
+            format!("Fetch cover failed, status: {}", res.status()),
+        ));
+    }
+
+    let too_large = || {
+        HTTPError::new(
+            400,
+            format!(
+                "Cover image is too large, expected <= {} bytes",
+                MAX_COVER_IMAGE_LEN
+            ),
+        )
+    };
+    if res.content_length().unwrap_or_default() > MAX_COVER_IMAGE_LEN {
+        return Err(too_large());
+    }
+    let mut data: Vec<u8> = Vec::new();
+    while let Some(chunk) = res.chunk().await.map_err(fetch_error)? {
+        if (data.len() + chunk.len()) as u64 > MAX_COVER_IMAGE_LEN {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "blurhash")]
+pub fn encode_blurhash(data: &[u8]) -> Result<String, HTTPError> {
+    let invalid =
+        |err: image::ImageError| HTTPError::new(400, format!("Invalid cover image: {}", err));
+    let mut limits = image::io::Limits::default();
+    limits.max_image_width = Some(10000);
+    limits.max_image_height = Some(10000);
+    let mut reader = image::io::Reader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|err| HTTPError::new(400, format!("Invalid cover image: {}", err)))?;
+    reader.limits(limits);
+
+    let img = reader
+        .decode()
+        .map_err(invalid)?
+        .thumbnail(BLURHASH_SAMPLE_SIZE, BLURHASH_SAMPLE_SIZE)
+        .to_rgba8();
+    let (width, height) = img.dimensions();
+    blurhash::encode(4, 3, width, height, img.as_raw())
+        .map_err(|err| HTTPError::new(500, format!("Encode blurhash failed: {:?}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_blurhash_works() {
+        // 4x3 components
+        assert!(validate_blurhash("LEHV6nWB2yk8pyo0adR*.7kCMdnj").is_ok());
+        // 1x1 component
+        assert!(validate_blurhash("00TI:j").is_ok());
+
+        assert!(validate_blurhash("").is_err());
+        assert!(validate_blurhash("00TI:").is_err()); // too short
+        assert!(validate_blurhash(&"0".repeat(101)).is_err()); // too long
+        assert!(validate_blurhash("LEHV6nWB2yk8pyo0adR*.7kCMdn\"").is_err()); // not base83
+        assert!(validate_blurhash("LEHV6nWB2yk8pyo0adR*.7kCMdnjX").is_err()); // length not match
+        assert!(validate_blurhash("~0TI:j").is_err()); // invalid size flag
+    }
+
+    #[test]
+    fn set_cover_columns_works() {
+        let cover = "https://cdn.yiwen.pub/1.png".to_string();
+        let hash = "00TI:j".to_string();
+
+        let mut cols = ColumnsMap::new();
+        set_cover_columns(&mut cols, Some(&cover), Some(&hash));
+        assert_eq!(cols.get_as::<String>("cover").unwrap(), cover);
+        assert_eq!(cols.get_as::<String>("cover_blurhash").unwrap(), hash);
+
+        // a new cover clears the stale blurhash
+        let mut cols = ColumnsMap::new();
+        set_cover_columns(&mut cols, Some(&cover), None);
+        assert_eq!(cols.get_as::<String>("cover_blurhash").unwrap(), "");
+
+        // the blurhash can be updated alone
+        let mut cols = ColumnsMap::new();
+        set_cover_columns(&mut cols, None, Some(&hash));
+        assert!(!cols.has("cover"));
+        assert_eq!(cols.get_as::<String>("cover_blurhash").unwrap(), hash);
+
+        let mut cols = ColumnsMap::new();
+        set_cover_columns(&mut cols, None, None);
+        assert!(cols.is_empty());
+    }
+
+    #[cfg(feature = "blurhash")]
+    #[test]
+    fn encode_blurhash_works() {
+        let mut img = image::RgbImage::new(32, 24);
+        for (x, _, pixel) in img.enumerate_pixels_mut() {
+            *pixel = image::Rgb([(x * 8) as u8, 128, 255 - (x * 8) as u8]);
+        }
+        let mut data: Vec<u8> = Vec::new();
+        image::DynamicImage::ImageRgb8(img)
+            .write_to(
+                &mut std::io::Cursor::new(&mut data),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+
+        let hash = encode_blurhash(&data).unwrap();
+        assert!(validate_blurhash(&hash).is_ok());
+        assert_eq!(hash.len(), 4 + 2 * 4 * 3);
+
+        let err = encode_blurhash(b"not an image").unwrap_err();
+        assert_eq!(err.code, 400);
+    }
+}
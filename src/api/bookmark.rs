@@ -3,6 +3,7 @@ use axum::{
     Extension,
 };
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{convert::From, sync::Arc};
 use validator::Validate;
@@ -11,12 +12,12 @@ use crate::db;
 
 use axum_web::context::ReqContext;
 use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
-use axum_web::object::PackObject;
+use axum_web::object::{cbor_from_slice, cbor_to_vec, PackObject};
 use scylla_orm::ColumnsMap;
 
-use super::{get_fields, token_from_xid, token_to_xid, AppState, Pagination, QueryCid, QueryId};
+use super::{get_fields, token_from_xid, token_to_xid, AppState, QueryCid, QueryId};
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct CreateBookmarkInput {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -33,7 +34,7 @@ pub struct CreateBookmarkInput {
     pub payload: Option<PackObject<Vec<u8>>>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct BookmarkOutput {
     pub uid: PackObject<xid::Id>,
     pub id: PackObject<xid::Id>,
@@ -184,39 +185,92 @@ pub async fn get(
     Ok(to.with(SuccessResponse::new(BookmarkOutput::from(doc, &to))))
 }
 
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct ListBookmarkInput {
+    pub page_token: Option<PackObject<Vec<u8>>>,
+    #[validate(range(min = 2, max = 1000))]
+    pub page_size: Option<u16>,
+    pub fields: Option<Vec<String>>,
+    pub sort: Option<String>, // "created" (default) or "recent"
+}
+
+// the page token of "recent" sort is cbor encoded (updated_at, id) of the last bookmark.
+fn recent_token_from(doc: Option<&db::Bookmark>) -> Option<Vec<u8>> {
+    match doc {
+        Some(doc) => cbor_to_vec(&(doc.updated_at, PackObject::Cbor(doc.id))).ok(),
+        _ => None,
+    }
+}
+
+fn recent_token_to(page_token: &Option<PackObject<Vec<u8>>>) -> Option<(i64, xid::Id)> {
+    match page_token.as_ref().map(|v| v.unwrap_ref()) {
+        Some(v) => cbor_from_slice::<(i64, PackObject<xid::Id>)>(v)
+            .ok()
+            .map(|(updated_at, id)| (updated_at, id.unwrap())),
+        _ => None,
+    }
+}
+
 pub async fn list(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
-    to: PackObject<Pagination>,
+    to: PackObject<ListBookmarkInput>,
 ) -> Result<PackObject<SuccessResponse<Vec<BookmarkOutput>>>, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
     valid_user(ctx.user)?;
 
     let page_size = input.page_size.unwrap_or(10);
+    let sort = input.sort.unwrap_or_else(|| "created".to_string());
     ctx.set_kvs(vec![
         ("action", "list_bookmark".into()),
         ("page_size", page_size.into()),
+        ("sort", sort.clone().into()),
     ])
     .await;
 
     let fields = input.fields.unwrap_or_default();
-    let res = db::Bookmark::list(
-        &app.scylla,
-        ctx.user,
-        fields,
-        page_size,
-        token_to_xid(&input.page_token),
-    )
-    .await?;
+    let (res, next_page_token) = match sort.as_str() {
+        "created" => {
+            let res = db::Bookmark::list(
+                &app.scylla,
+                ctx.user,
+                fields,
+                page_size,
+                token_to_xid(&input.page_token),
+            )
+            .await?;
+            let next = token_from_xid(if res.len() >= page_size as usize {
+                Some(res.last().unwrap().id)
+            } else {
+                None
+            });
+            (res, next)
+        }
+        "recent" => {
+            let res = db::Bookmark::list_recent(
+                &app.scylla,
+                ctx.user,
+                fields,
+                page_size,
+                recent_token_to(&input.page_token),
+            )
+            .await?;
+            let next = recent_token_from(if res.len() >= page_size as usize {
+                res.last()
+            } else {
+                None
+            });
+            (res, next)
+        }
+        _ => {
+            return Err(HTTPError::new(400, format!("Invalid sort: {}", sort)));
+        }
+    };
 
     Ok(to.with(SuccessResponse {
         total_size: None,
-        next_page_token: to.with_option(token_from_xid(if res.len() >= page_size as usize {
-            Some(res.last().unwrap().id)
-        } else {
-            None
-        })),
+        next_page_token: to.with_option(next_page_token),
         result: res
             .iter()
             .map(|r| BookmarkOutput::from(r.to_owned(), &to))
@@ -255,7 +309,7 @@ pub async fn get_by_cid(
     }))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdateBookmarkInput {
     pub id: PackObject<xid::Id>,
     pub updated_at: i64,
@@ -338,3 +392,47 @@ pub async fn delete(
     let res = doc.delete(&app.scylla).await?;
     Ok(to.with(SuccessResponse::new(res)))
 }
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct BatchDeleteBookmarkInput {
+    #[validate(length(min = 1, max = 100))]
+    pub ids: Vec<PackObject<xid::Id>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct BatchDeleteBookmarkOutput {
+    pub id: PackObject<xid::Id>,
+    pub deleted: bool,
+}
+
+pub async fn batch_delete(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<BatchDeleteBookmarkInput>,
+) -> Result<PackObject<SuccessResponse<Vec<BatchDeleteBookmarkOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let ids: Vec<xid::Id> = input.ids.into_iter().map(|id| id.unwrap()).collect();
+    ctx.set_kvs(vec![
+        ("action", "batch_delete_bookmark".into()),
+        ("ids", ids.len().into()),
+    ])
+    .await;
+
+    let res = db::Bookmark::batch_delete(&app.scylla, ctx.user, ids).await?;
+    ctx.set(
+        "deleted",
+        res.iter().filter(|(_, deleted)| *deleted).count().into(),
+    )
+    .await;
+    Ok(to.with(SuccessResponse::new(
+        res.into_iter()
+            .map(|(id, deleted)| BatchDeleteBookmarkOutput {
+                id: to.with(id),
+                deleted,
+            })
+            .collect(),
+    )))
+}
@@ -1,8 +1,10 @@
 use axum::{
     extract::{Query, State},
+    response::{IntoResponse, Response},
     Extension,
 };
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, convert::From, sync::Arc};
 
@@ -16,12 +18,16 @@ use axum_web::object::PackObject;
 use scylla_orm::ColumnsMap;
 
 use super::{
-    get_fields, message, token_from_xid, token_to_xid, AppState, GIDPagination, IDGIDPagination,
-    Pagination, QueryGidCid, QueryGidId, QueryGidIdCid, QueryId, RFPInfo, SubscriptionInput,
-    SubscriptionOutput, UpdateStatusInput, RFP,
+    blurhash::{set_cover_columns, validate_blurhash},
+    check_grant_actor, desc_only, get_fields, grant_changelog, idempotency_lookup,
+    idempotency_save, meili_write, message, record_rfp_impression,
+    search::sanitize_query,
+    token_from_xid, token_to_xid, valid_cover_host, valid_grant_expire_at, AppState, GIDPagination,
+    GrantSubscriptionInput, IDGIDPagination, MeiliWrite, Pagination, QueryGidCid, QueryGidId,
+    QueryGidIdCid, QueryId, RFPInfo, SubscriptionInput, SubscriptionOutput, UpdateStatusInput, RFP,
 };
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct CreateCollectionInput {
     pub gid: PackObject<xid::Id>,
     pub language: PackObject<Language>,
@@ -30,14 +36,18 @@ pub struct CreateCollectionInput {
     pub info: CollectionInfoInput,
     #[validate(url)]
     pub cover: Option<String>,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
     #[validate(range(min = -1, max = 1000000))]
     pub price: Option<i64>,
     #[validate(range(min = -1, max = 100000))]
     pub creation_price: Option<i64>,
     pub parent: Option<PackObject<xid::Id>>,
+    #[validate(length(min = 8, max = 128))]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct CollectionInfoInput {
     #[validate(length(min = 1, max = 256))]
     pub title: Option<String>,
@@ -49,7 +59,7 @@ pub struct CollectionInfoInput {
     pub authors: Option<Vec<String>>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct CollectionOutput {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -60,6 +70,8 @@ pub struct CollectionOutput {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cover: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_blurhash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub price: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub creation_price: Option<i64>,
@@ -74,6 +86,13 @@ pub struct CollectionOutput {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub i18n_info: Option<HashMap<String, db::CollectionInfo>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub info_language: Option<PackObject<Language>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info_is_fallback: Option<bool>,
+    // with the "counts" field, may be slightly stale under concurrent edits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub subscription: Option<SubscriptionOutput>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rfp: Option<RFP>,
@@ -93,12 +112,19 @@ impl CollectionOutput {
             match v.as_str() {
                 "updated_at" => rt.updated_at = Some(val.updated_at),
                 "cover" => rt.cover = Some(val.cover.to_owned()),
+                "cover_blurhash" => rt.cover_blurhash = Some(val.cover_blurhash.to_owned()),
                 "price" => rt.price = Some(val.price),
                 "creation_price" => rt.creation_price = Some(val.creation_price),
                 _ => {}
             }
         }
 
+        let language = val._language.unwrap_or_default();
+        if let Some((lang, _, is_i18n)) = val.to_info(language) {
+            rt.info_language = Some(to.with(lang));
+            rt.info_is_fallback = Some(db::Collection::info_is_fallback(language, lang, is_i18n));
+        }
+
         if let Some(msg) = val._info {
             let msg = message::MessageOutput::from(msg, to);
             rt.language = msg.language;
@@ -119,13 +145,42 @@ impl CollectionOutput {
 
         rt
     }
+
+    // set_counts sets the outputs of the "counts" field: the children count, and the languages
+    // of the info including its primary language.
+    fn set_counts<T>(
+        &mut self,
+        children_count: usize,
+        languages: Option<Vec<Language>>,
+        to: &PackObject<T>,
+    ) {
+        self.children_count = Some(children_count as u32);
+        if let Some(languages) = languages {
+            self.languages = Some(languages.into_iter().map(|v| to.with(v)).collect());
+        }
+    }
+}
+
+// take_counts removes the "counts" pseudo field, the info is selected along with it since the
+// languages are derived from the info message.
+fn take_counts(fields: &mut Vec<String>) -> bool {
+    match fields.iter().position(|v| v == "counts") {
+        None => false,
+        Some(i) => {
+            fields.remove(i);
+            if !fields.is_empty() && !fields.iter().any(|v| v == "info") {
+                fields.push("info".to_string());
+            }
+            true
+        }
+    }
 }
 
 pub async fn create(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
     to: PackObject<CreateCollectionInput>,
-) -> Result<PackObject<SuccessResponse<CollectionOutput>>, HTTPError> {
+) -> Result<Response, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
     valid_user(ctx.user)?;
@@ -143,20 +198,32 @@ pub async fn create(
     ])
     .await;
 
-    let price = input.price.unwrap_or(0);
-    let creation_price = input.creation_price.unwrap_or(0);
-    if creation_price > price {
-        return Err(HTTPError::new(
-            400,
-            "Creation price cannot be greater than price".to_string(),
-        ));
+    let (idempotency, replayed) = idempotency_lookup(
+        &app,
+        ctx.user,
+        &input.idempotency_key,
+        "create_collection",
+        &to.with(&input),
+    )
+    .await?;
+    if let Some(res) = replayed {
+        ctx.set("idempotent_replayed", true.into()).await;
+        return Ok(res);
+    }
+
+    if let Some(cover) = &input.cover {
+        valid_cover_host(&app.cover.allowed_hosts, cover)?;
     }
 
+    let price = input.price.unwrap_or(0);
+    let creation_price = default_creation_price(Some(price), input.creation_price).unwrap_or(0);
+    db::Collection::valid_price(price, creation_price)?;
+
     let info = db::CollectionInfo {
         title: input.info.title.unwrap_or_default(),
         summary: input.info.summary.unwrap_or_default(),
-        keywords: input.info.keywords,
-        authors: input.info.authors,
+        keywords: input.info.keywords.map(db::normalize_tags),
+        authors: input.info.authors.map(db::normalize_names),
     };
     let info_msg: Vec<u8> = info.to_message()?;
 
@@ -199,7 +266,9 @@ pub async fn create(
     let mut doc = db::Collection {
         id: xid::new(),
         gid,
+        cover_derived: input.cover.is_none(),
         cover: input.cover.unwrap_or_default(),
+        cover_blurhash: input.cover_blurhash.unwrap_or_default(),
         price,
         creation_price,
         ..Default::default()
@@ -223,10 +292,12 @@ pub async fn create(
 
     let meili_start = ctx.start.elapsed().as_millis() as u64;
     let meili_doc = doc.to_meili(msg.language, &msg.message, msg.version, msg.updated_at)?;
-    if let Err(err) = app
-        .meili
-        .add_or_update(meili::Space::Group(doc.gid), vec![meili_doc])
-        .await
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![meili_doc]),
+    )
+    .await
     {
         log::error!(target: "meilisearch",
             action = "add_or_update",
@@ -252,7 +323,158 @@ pub async fn create(
     }
 
     doc._info = Some(msg);
-    Ok(to.with(SuccessResponse::new(CollectionOutput::from(doc, &to))))
+    let resource = doc.id.to_string();
+    let res = to.with(SuccessResponse::new(CollectionOutput::from(doc, &to)));
+    idempotency_save(idempotency, resource, &res).await;
+    Ok(res.into_response())
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CloneCollectionInput {
+    pub id: PackObject<xid::Id>,
+    pub gid: PackObject<xid::Id>,
+    #[serde(default)]
+    pub include_children: bool,
+    #[validate(length(min = 1, max = 256))]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CloneCollectionOutput {
+    pub collection: CollectionOutput,
+    pub children: usize,
+}
+
+pub async fn clone(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<CloneCollectionInput>,
+) -> Result<PackObject<SuccessResponse<CloneCollectionOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let id = *input.id;
+    let gid = *input.gid;
+    ctx.set_kvs(vec![
+        ("action", "clone_collection".into()),
+        ("id", id.to_string().into()),
+        ("gid", gid.to_string().into()),
+        ("include_children", input.include_children.into()),
+    ])
+    .await;
+
+    let mut src = db::Collection::with_pk(id);
+    src.get_one(
+        &app.scylla,
+        vec![
+            "mid".to_string(),
+            "cover".to_string(),
+            "cover_derived".to_string(),
+        ],
+        None,
+    )
+    .await?;
+    // a collection of another group can be cloned only when it is published
+    if src.gid != gid {
+        if src.status != 2 {
+            return Err(HTTPError::new(403, "Collection gid not match".to_string()));
+        }
+        if src.rating > ctx.rating {
+            return Err(HTTPError::new(451, "Collection unavailable".to_string()));
+        }
+    }
+
+    let mut src_msg = db::Message::with_pk(src.mid);
+    src_msg
+        .get_one(&app.scylla, vec!["i18n".to_string(), "context".to_string()])
+        .await?;
+
+    let mut doc = db::Collection {
+        id: xid::new(),
+        gid,
+        rating: src.rating,
+        cover_derived: src.cover_derived,
+        cover: src.cover,
+        cover_blurhash: src.cover_blurhash,
+        price: src.price,
+        creation_price: src.creation_price,
+        ..Default::default()
+    };
+    doc.save(&app.scylla).await?;
+
+    // the translations no longer match an overridden title, so they are not copied then.
+    let (message, i18n_messages) = match input.title {
+        Some(title) => {
+            let mut info = db::CollectionInfo::from_message(&src_msg.message)?;
+            info.title = title;
+            (info.to_message()?, HashMap::new())
+        }
+        None => (src_msg.message, src_msg._i18n_messages),
+    };
+    let mut msg = db::Message {
+        id: xid::new(),
+        attach_to: doc.id,
+        kind: "collection.info".to_string(),
+        language: src_msg.language,
+        context: src_msg.context,
+        message,
+        ..Default::default()
+    };
+    msg.save(&app.scylla).await?;
+    for lang in src_msg.languages {
+        if let Some(v) = i18n_messages.get(db::resolve_language(lang)) {
+            msg.update_message(&app.scylla, lang, v, msg.version)
+                .await?;
+        }
+    }
+    doc.mid = msg.id;
+    doc.update_field(&app.scylla, "mid").await?;
+
+    let children = if input.include_children {
+        db::CollectionChildren::copy_children(
+            &app.scylla,
+            src.id,
+            doc.id,
+            db::MAX_COLLECTION_CHILDREN,
+        )
+        .await?
+    } else {
+        0
+    };
+
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    let meili_doc = doc.to_meili(msg.language, &msg.message, msg.version, msg.updated_at)?;
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![meili_doc]),
+    )
+    .await
+    {
+        log::error!(target: "meilisearch",
+            action = "add_or_update",
+            space = "group",
+            rid = ctx.rid,
+            gid = doc.gid.to_string(),
+            id = doc.id.to_string(),
+            kind = 2i8,
+            elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+            "{}", err.to_string(),
+        );
+    }
+
+    ctx.set_kvs(vec![
+        ("new_id", doc.id.to_string().into()),
+        ("children", children.into()),
+    ])
+    .await;
+    msg.get_one(&app.scylla, vec!["i18n".to_string()]).await?;
+    doc._info = Some(msg);
+    Ok(to.with(SuccessResponse::new(CloneCollectionOutput {
+        collection: CollectionOutput::from(doc, &to),
+        children,
+    })))
 }
 
 pub async fn get(
@@ -275,7 +497,8 @@ pub async fn get(
     .await;
 
     let mut doc = db::Collection::with_pk(id);
-    let fields = get_fields(input.fields.clone());
+    let mut fields = get_fields(input.fields.clone());
+    let counts = take_counts(&mut fields);
     doc.get_one(&app.scylla, fields, ctx.language).await?;
     if doc.status == 2 && doc.rating > ctx.rating {
         return Err(HTTPError::new(451, "Collection unavailable".to_string()));
@@ -284,7 +507,12 @@ pub async fn get(
         return Err(HTTPError::new(403, "Collection gid not match".to_string()));
     }
     let price = doc.price;
+    let languages = doc._info.as_ref().map(|msg| msg.all_languages());
     let mut output = CollectionOutput::from(doc, &to);
+    if counts {
+        let count = db::CollectionChildren::count_children(&app.scylla, id).await?;
+        output.set_counts(count, languages, &to);
+    }
     if price > 0 {
         if ctx.user > db::MIN_ID && *output.gid != user_gid {
             let mut subscription = db::CollectionSubscription::with_pk(ctx.user, id);
@@ -325,6 +553,9 @@ pub async fn get(
         }
     }
 
+    if output.rfp.is_some() {
+        record_rfp_impression(&app, id, ctx.unix_ms);
+    }
     ctx.set_kvs(vec![
         ("rfp", output.rfp.is_some().into()),
         ("subscription", output.subscription.is_some().into()),
@@ -342,6 +573,7 @@ pub async fn list(
     input.validate()?;
     valid_user(ctx.user)?;
 
+    desc_only(input.order)?;
     let gid = *input.gid.to_owned();
     let page_size = input.page_size.unwrap_or(10);
     ctx.set_kvs(vec![
@@ -351,8 +583,9 @@ pub async fn list(
     ])
     .await;
 
-    let fields = input.fields.unwrap_or_default();
-    let res = db::Collection::list_by_gid(
+    let mut fields = input.fields.unwrap_or_default();
+    let counts = take_counts(&mut fields);
+    let (res, next_page_token, scan) = db::Collection::list_by_gid(
         &app.scylla,
         gid,
         fields,
@@ -362,15 +595,33 @@ pub async fn list(
         ctx.language,
     )
     .await?;
+    ctx.set(
+        "scan_stats",
+        serde_json::to_value(&scan).unwrap_or_default(),
+    )
+    .await;
+
+    let children_counts = if counts {
+        let ids: Vec<xid::Id> = res.iter().map(|r| r.id).collect();
+        db::CollectionChildren::count_children_many(&app.scylla, &ids).await?
+    } else {
+        vec![]
+    };
+
+    let mut result: Vec<CollectionOutput> = Vec::with_capacity(res.len());
+    for (i, r) in res.into_iter().enumerate() {
+        let languages = r._info.as_ref().map(|msg| msg.all_languages());
+        let mut output = CollectionOutput::from(r, &to);
+        if let Some(count) = children_counts.get(i) {
+            output.set_counts(*count, languages, &to);
+        }
+        result.push(output);
+    }
 
     Ok(to.with(SuccessResponse {
         total_size: None,
-        next_page_token: to.with_option(token_from_xid(res.1)),
-        result: res
-            .0
-            .iter()
-            .map(|r| CollectionOutput::from(r.to_owned(), &to))
-            .collect(),
+        next_page_token: to.with_option(token_from_xid(next_page_token)),
+        result,
     }))
 }
 
@@ -383,17 +634,24 @@ pub async fn list_latest(
     input.validate()?;
     valid_user(ctx.user)?;
 
+    desc_only(input.order)?;
     ctx.set_kvs(vec![("action", "list_latest_collections".into())])
         .await;
 
     let fields = input.fields.unwrap_or_default();
-    let (res, next_page_token) = db::Collection::list_latest(
+    let (res, next_page_token, scan) = db::Collection::list_latest(
         &app.scylla,
         fields,
         token_to_xid(&input.page_token),
         ctx.language,
+        ctx.rating,
     )
     .await?;
+    ctx.set(
+        "scan_stats",
+        serde_json::to_value(&scan).unwrap_or_default(),
+    )
+    .await;
 
     Ok(to.with(SuccessResponse {
         total_size: None,
@@ -405,13 +663,24 @@ pub async fn list_latest(
     }))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+// a collection that is not for sale defaults its creations to not for sale too, an explicit
+// creation_price is kept as is and checked by Collection::valid_price on create and update.
+fn default_creation_price(price: Option<i64>, creation_price: Option<i64>) -> Option<i64> {
+    match (price, creation_price) {
+        (Some(-1), None) => Some(-1),
+        (_, creation_price) => creation_price,
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdateCollectionInput {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
     pub updated_at: i64,
     #[validate(url)]
     pub cover: Option<String>,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
     #[validate(range(min = -1, max = 1000000))]
     pub price: Option<i64>,
     #[validate(range(min = -1, max = 100000))]
@@ -421,18 +690,12 @@ pub struct UpdateCollectionInput {
 impl UpdateCollectionInput {
     fn into(self) -> anyhow::Result<ColumnsMap, HTTPError> {
         let mut cols = ColumnsMap::new();
-        if let Some(cover) = self.cover {
-            cols.set_as("cover", &cover);
-        }
-        if let Some(creation_price) = self.creation_price {
-            cols.set_as("creation_price", &creation_price);
-        }
-        if let Some(price) = self.price {
-            cols.set_as("price", &price);
-            if price == -1 {
-                cols.set_as("creation_price", &price);
-            }
-        }
+        set_cover_columns(&mut cols, self.cover.as_ref(), self.cover_blurhash.as_ref());
+        cols.set_if_some(
+            "creation_price",
+            default_creation_price(self.price, self.creation_price).as_ref(),
+        );
+        cols.set_if_some("price", self.price.as_ref());
 
         if cols.is_empty() {
             return Err(HTTPError::new(400, "No fields to update".to_string()));
@@ -451,6 +714,10 @@ pub async fn update(
     input.validate()?;
     valid_user(ctx.user)?;
 
+    if let Some(cover) = &input.cover {
+        valid_cover_host(&app.cover.allowed_hosts, cover)?;
+    }
+
     let id = *input.id.to_owned();
     let gid = *input.gid.to_owned();
     let mut doc = db::Collection::with_pk(id);
@@ -462,7 +729,9 @@ pub async fn update(
     ])
     .await;
 
-    let ok = doc.update(&app.scylla, gid, cols, updated_at).await?;
+    let ok = doc
+        .update(&app.scylla, gid, cols, updated_at, ctx.user)
+        .await?;
     ctx.set("updated", ok.into()).await;
     doc._fields = vec!["updated_at".to_string()]; // only return `updated_at` field.
     Ok(to.with(SuccessResponse::new(CollectionOutput::from(doc, &to))))
@@ -562,36 +831,37 @@ pub async fn update_info(
         ok = info
             .update_message(&app.scylla, language, &message, version)
             .await?;
+        sync_info_to_meili(&app, &ctx, &doc, language, &message, &info).await?;
+    }
 
-        let meili_start = ctx.start.elapsed().as_millis() as u64;
-        let meili_doc = doc.to_meili(language, &message, info.version, info.updated_at)?;
-        if doc.status == 2 {
-            if let Err(err) = app
-                .meili
-                .add_or_update(meili::Space::Pub(None), vec![meili_doc.clone()])
-                .await
-            {
-                log::error!(target: "meilisearch",
-                    action = "add_or_update",
-                    space = "pub",
-                    rid = ctx.rid,
-                    gid = doc.gid.to_string(),
-                    id = doc.id.to_string(),
-                    kind = 2i8,
-                    elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
-                    "{}", err.to_string(),
-                );
-            }
-        }
+    ctx.set("updated", ok.into()).await;
+    Ok(to.with(SuccessResponse::new(message::MessageOutput::from(
+        info, &to,
+    ))))
+}
 
-        if let Err(err) = app
-            .meili
-            .add_or_update(meili::Space::Group(doc.gid), vec![meili_doc])
-            .await
+// syncs the updated collection info to the group space, and to the pub space if published.
+async fn sync_info_to_meili(
+    app: &AppState,
+    ctx: &ReqContext,
+    doc: &db::Collection,
+    language: Language,
+    message: &[u8],
+    info: &db::Message,
+) -> Result<(), HTTPError> {
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    let meili_doc = doc.to_meili(language, message, info.version, info.updated_at)?;
+    if doc.status == 2 {
+        if let Err(err) = meili_write(
+            app,
+            meili::Space::Pub(None),
+            MeiliWrite::AddOrUpdate(vec![meili_doc.clone()]),
+        )
+        .await
         {
             log::error!(target: "meilisearch",
                 action = "add_or_update",
-                space = "group",
+                space = "pub",
                 rid = ctx.rid,
                 gid = doc.gid.to_string(),
                 id = doc.id.to_string(),
@@ -602,72 +872,73 @@ pub async fn update_info(
         }
     }
 
-    ctx.set("updated", ok.into()).await;
-    Ok(to.with(SuccessResponse::new(message::MessageOutput::from(
-        info, &to,
-    ))))
+    if let Err(err) = meili_write(
+        app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![meili_doc]),
+    )
+    .await
+    {
+        log::error!(target: "meilisearch",
+            action = "add_or_update",
+            space = "group",
+            rid = ctx.rid,
+            gid = doc.gid.to_string(),
+            id = doc.id.to_string(),
+            kind = 2i8,
+            elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+            "{}", err.to_string(),
+        );
+    }
+    Ok(())
 }
 
-pub async fn update_status(
+pub async fn list_info_history(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
-    to: PackObject<UpdateStatusInput>,
-) -> Result<PackObject<SuccessResponse<CollectionOutput>>, HTTPError> {
-    let (to, input) = to.unpack();
+    to: PackObject<()>,
+    input: Query<QueryGidId>,
+) -> Result<PackObject<SuccessResponse<Vec<message::MessageHistoryOutput>>>, HTTPError> {
     input.validate()?;
     valid_user(ctx.user)?;
 
     let id = *input.id.to_owned();
-    let gid = *input.gid.unwrap_or_default();
+    let gid = *input.gid.to_owned();
 
-    let mut doc = db::Collection::with_pk(id);
     ctx.set_kvs(vec![
-        ("action", "update_collection_status".into()),
-        ("id", doc.id.to_string().into()),
+        ("action", "list_collection_info_history".into()),
+        ("id", id.to_string().into()),
         ("gid", gid.to_string().into()),
     ])
     .await;
 
-    let ok = doc
-        .update_status(&app.scylla, gid, input.status, input.updated_at)
-        .await?;
-
-    ctx.set("updated", ok.into()).await;
-    if ok && doc.status == 2 {
-        // get full doc for meili
-        let meili_start = ctx.start.elapsed().as_millis() as u64;
-        let mut info = db::Message::with_pk(doc.mid);
-        info.get_one(&app.scylla, vec!["i18n".to_string()]).await?;
-        for (language, message) in info.to_language_message() {
-            let meili_doc = doc.to_meili(language, &message, info.version, info.updated_at)?;
-            if let Err(err) = app
-                .meili
-                .add_or_update(meili::Space::Pub(None), vec![meili_doc])
-                .await
-            {
-                log::error!(target: "meilisearch",
-                    action = "add_or_update",
-                    space = "pub",
-                    rid = ctx.rid,
-                    gid = doc.gid.to_string(),
-                    id = doc.id.to_string(),
-                    kind = 2i8,
-                    elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
-                    "{}", err.to_string(),
-                );
-            }
-        }
+    let mut doc = db::Collection::with_pk(id);
+    doc.get_one(
+        &app.scylla,
+        vec!["mid".to_string(), "gid".to_string()],
+        None,
+    )
+    .await?;
+    if doc.gid != gid {
+        return Err(HTTPError::new(403, "Collection gid not match".to_string()));
     }
-    doc._fields = vec!["updated_at".to_string(), "status".to_string()];
-    Ok(to.with(SuccessResponse::new(CollectionOutput::from(doc, &to))))
+
+    let res = db::MessageHistory::list(&app.scylla, doc.mid).await?;
+    Ok(to.with(SuccessResponse::new(
+        res.into_iter()
+            .map(|v| message::MessageHistoryOutput::from(v, &to))
+            .collect(),
+    )))
 }
 
-pub async fn delete(
+// translation_status reports which languages of the collection info are missing or
+// translated from an older version of the primary message.
+pub async fn translation_status(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
     to: PackObject<()>,
     input: Query<QueryGidId>,
-) -> Result<PackObject<SuccessResponse<bool>>, HTTPError> {
+) -> Result<PackObject<SuccessResponse<message::TranslationStatusOutput>>, HTTPError> {
     input.validate()?;
     valid_user(ctx.user)?;
 
@@ -675,57 +946,312 @@ pub async fn delete(
     let gid = *input.gid.to_owned();
 
     ctx.set_kvs(vec![
-        ("action", "delete_collection".into()),
+        ("action", "get_collection_translation_status".into()),
         ("id", id.to_string().into()),
         ("gid", gid.to_string().into()),
     ])
     .await;
 
     let mut doc = db::Collection::with_pk(id);
-    let res = doc.delete(&app.scylla, gid).await?;
-    Ok(to.with(SuccessResponse::new(res)))
+    doc.get_one(
+        &app.scylla,
+        vec!["mid".to_string(), "gid".to_string()],
+        None,
+    )
+    .await?;
+    if doc.gid != gid {
+        return Err(HTTPError::new(403, "Collection gid not match".to_string()));
+    }
+
+    let mut info = db::Message::with_pk(doc.mid);
+    info.get_one(
+        &app.scylla,
+        vec!["languages".to_string(), "i18n_versions".to_string()],
+    )
+    .await?;
+    Ok(to.with(SuccessResponse::new(
+        message::TranslationStatusOutput::from(info, &to),
+    )))
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
-pub struct AddChildrenInput {
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct RestoreInfoInput {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
-    #[validate(length(min = 1, max = 1000))]
-    pub cids: Vec<PackObject<xid::Id>>,
-    #[validate(range(min = 0, max = 1))]
-    pub kind: i8,
+    #[validate(range(min = 1, max = 32767))]
+    pub version: i16, // the current version of the collection info
+    #[validate(range(min = 1, max = 32767))]
+    pub from_version: i16, // the version to restore from
+    pub language: PackObject<Language>,
 }
 
-pub async fn add_children(
+// restores a previous value of the collection info, as a new update.
+pub async fn restore_info(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
-    to: PackObject<AddChildrenInput>,
-) -> Result<PackObject<SuccessResponse<Vec<PackObject<xid::Id>>>>, HTTPError> {
+    to: PackObject<RestoreInfoInput>,
+) -> Result<PackObject<SuccessResponse<message::MessageOutput>>, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
     valid_user(ctx.user)?;
 
     let id = *input.id.to_owned();
     let gid = *input.gid.to_owned();
-    let cids: Vec<xid::Id> = input.cids.iter().map(|id| *id.to_owned()).collect();
+    let language = *input.language.to_owned();
+
     ctx.set_kvs(vec![
-        ("action", "add_collection_children".into()),
+        ("action", "restore_collection_info".into()),
         ("id", id.to_string().into()),
-        (
-            "cids",
-            cids.iter()
-                .map(|id| id.to_string())
-                .collect::<Vec<String>>()
-                .into(),
-        ),
-        ("kind", input.kind.into()),
+        ("version", input.version.into()),
+        ("from_version", input.from_version.into()),
+        ("language", language.to_639_3().into()),
     ])
     .await;
 
-    let mut parent = db::Collection::with_pk(id);
-    parent
-        .get_one(
-            &app.scylla,
+    let mut doc = db::Collection::with_pk(id);
+    doc.get_one(
+        &app.scylla,
+        vec!["gid".to_string(), "mid".to_string(), "status".to_string()],
+        None,
+    )
+    .await?;
+    if doc.gid != gid {
+        return Err(HTTPError::new(403, "Collection gid not match".to_string()));
+    }
+
+    let mut history = db::MessageHistory::with_pk(doc.mid, input.from_version, language);
+    history.get_one(&app.scylla).await?;
+
+    let mut info = db::Message::with_pk(doc.mid);
+    let ok = info
+        .update_message(&app.scylla, language, &history.message, input.version)
+        .await?;
+    sync_info_to_meili(&app, &ctx, &doc, language, &history.message, &info).await?;
+
+    ctx.set("updated", ok.into()).await;
+    Ok(to.with(SuccessResponse::new(message::MessageOutput::from(
+        info, &to,
+    ))))
+}
+
+pub async fn update_status(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<UpdateStatusInput>,
+) -> Result<PackObject<SuccessResponse<CollectionOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let id = *input.id.to_owned();
+    let gid = *input.gid.unwrap_or_default();
+
+    let mut doc = db::Collection::with_pk(id);
+    ctx.set_kvs(vec![
+        ("action", "update_collection_status".into()),
+        ("id", doc.id.to_string().into()),
+        ("gid", gid.to_string().into()),
+    ])
+    .await;
+
+    let mut prev = db::Collection::with_pk(id);
+    prev.get_one(&app.scylla, vec!["status".to_string()], None)
+        .await?;
+    if input.status == 2 && prev.status != 2 {
+        ensure_publishable(&app, id).await?;
+    }
+
+    let ok = doc
+        .update_status(&app.scylla, gid, input.status, input.updated_at)
+        .await?;
+
+    ctx.set("updated", ok.into()).await;
+    if ok && (doc.status == 2 || prev.status == 2) {
+        // get full doc for meili
+        let meili_start = ctx.start.elapsed().as_millis() as u64;
+        let mut info = db::Message::with_pk(doc.mid);
+        info.get_one(&app.scylla, vec!["i18n".to_string()]).await?;
+        let mut meili_docs: Vec<meili::Document> = Vec::new();
+        for (language, message) in info.to_language_message() {
+            meili_docs.push(doc.to_meili(language, &message, info.version, info.updated_at)?);
+        }
+
+        let space = meili::Space::Pub(Some(doc.gid));
+        let (action, res) = if doc.status == 2 {
+            // skips the documents that were indexed with the same content
+            let res = meili_write(&app, space, MeiliWrite::Upsert(meili_docs)).await;
+            if let Ok(n) = &res {
+                ctx.set("meili_indexed", (*n).into()).await;
+            }
+            ("upsert", res.map(|_| ()))
+        } else {
+            // no longer published, remove it from the public search space.
+            let ids = meili_docs.into_iter().map(|v| v.id).collect();
+            let res = meili_write(&app, space, MeiliWrite::Delete(ids)).await;
+            ("delete", res.map(|_| ()))
+        };
+        if let Err(err) = res {
+            log::error!(target: "meilisearch",
+                action = action,
+                space = "pub",
+                rid = ctx.rid,
+                gid = doc.gid.to_string(),
+                id = doc.id.to_string(),
+                kind = 2i8,
+                elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+                "{}", err.to_string(),
+            );
+        }
+    }
+    doc._fields = vec!["updated_at".to_string(), "status".to_string()];
+    Ok(to.with(SuccessResponse::new(CollectionOutput::from(doc, &to))))
+}
+
+// a collection can be published only when it has children
+// and at least one of the creation children is published.
+async fn ensure_publishable(app: &AppState, id: xid::Id) -> Result<(), HTTPError> {
+    let count = db::CollectionChildren::count_children(&app.scylla, id).await?;
+    if count == 0 {
+        return Err(HTTPError::new(
+            400,
+            "Collection has no children, can not be published".to_string(),
+        ));
+    }
+
+    let children = db::CollectionChildren::list_children(&app.scylla, id).await?;
+    for child in children {
+        if child.kind == 2 {
+            continue;
+        }
+        if db::PublicationIndex::get_implicit_published(
+            &app.scylla,
+            child.cid,
+            db::ZERO_ID,
+            Language::Und,
+        )
+        .await
+        .is_ok()
+        {
+            return Ok(());
+        }
+    }
+
+    Err(HTTPError::new(
+        400,
+        "Collection has no published children, can not be published".to_string(),
+    ))
+}
+
+pub async fn delete(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGidId>,
+) -> Result<PackObject<SuccessResponse<bool>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let id = *input.id.to_owned();
+    let gid = *input.gid.to_owned();
+
+    ctx.set_kvs(vec![
+        ("action", "delete_collection".into()),
+        ("id", id.to_string().into()),
+        ("gid", gid.to_string().into()),
+    ])
+    .await;
+
+    let mut doc = db::Collection::with_pk(id);
+    let res = doc.delete(&app.scylla, gid).await?;
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
+pub struct AddChildrenInput {
+    pub id: PackObject<xid::Id>,
+    pub gid: PackObject<xid::Id>,
+    #[validate(length(min = 1, max = 1000))]
+    pub cids: Vec<PackObject<xid::Id>>,
+    #[validate(range(min = 0, max = 2))]
+    pub kind: i8,
+}
+
+// the reason codes in AddChildOutput.
+pub const ADD_CHILD_OK: &str = "ok";
+pub const ADD_CHILD_SELF_REFERENCE: &str = "self_reference";
+pub const ADD_CHILD_RATING_EXCEEDS_PARENT: &str = "rating_exceeds_parent";
+pub const ADD_CHILD_NOT_PUBLISHED: &str = "not_published";
+pub const ADD_CHILD_DUPLICATE: &str = "duplicate";
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct AddChildOutput {
+    pub cid: PackObject<xid::Id>,
+    pub added: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl AddChildOutput {
+    fn new<T>(cid: xid::Id, reason: &str, to: &PackObject<T>) -> Self {
+        Self {
+            cid: to.with(cid),
+            added: reason == ADD_CHILD_OK,
+            reason: Some(reason.to_string()),
+        }
+    }
+}
+
+// returns the reason why the child can not be added to the parent collection, None if it can.
+// `published` is only checked for a child from another group.
+fn add_child_skip_reason(
+    parent: &db::Collection,
+    cid: xid::Id,
+    gid: xid::Id,
+    rating: i8,
+    published: bool,
+) -> Option<&'static str> {
+    if cid == parent.id {
+        return Some(ADD_CHILD_SELF_REFERENCE);
+    }
+    if rating > parent.rating {
+        return Some(ADD_CHILD_RATING_EXCEEDS_PARENT);
+    }
+    if gid != parent.gid && !published {
+        return Some(ADD_CHILD_NOT_PUBLISHED);
+    }
+    None
+}
+
+pub async fn add_children(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<AddChildrenInput>,
+) -> Result<PackObject<SuccessResponse<Vec<AddChildOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let id = *input.id.to_owned();
+    let gid = *input.gid.to_owned();
+    let cids: Vec<xid::Id> = input.cids.iter().map(|id| *id.to_owned()).collect();
+    ctx.set_kvs(vec![
+        ("action", "add_collection_children".into()),
+        ("id", id.to_string().into()),
+        (
+            "cids",
+            cids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .into(),
+        ),
+        ("kind", input.kind.into()),
+    ])
+    .await;
+
+    let mut parent = db::Collection::with_pk(id);
+    parent
+        .get_one(
+            &app.scylla,
             vec![
                 "gid".to_string(),
                 "rating".to_string(),
@@ -758,54 +1284,88 @@ pub async fn add_children(
 
     let ord = ctx.unix_ms as f64;
     let total = cids.len() as f64;
-    let mut processed: Vec<xid::Id> = Vec::with_capacity(cids.len());
+    let mut added: usize = 0;
+    let mut res: Vec<AddChildOutput> = Vec::with_capacity(cids.len());
 
     match input.kind {
         0 | 1 => {
+            // the group's rating policy rejects the whole request before any child is added
+            let setting = db::GroupSetting::load(&app.scylla, gid).await?;
+            let mut children: Vec<db::CreationIndex> = Vec::with_capacity(cids.len());
             for cid in cids {
                 let mut child = db::CreationIndex::with_pk(cid);
                 child.get_one(&app.scylla).await?;
-                if child.rating > parent.rating {
-                    continue;
-                }
+                setting.check_rating(child.rating)?;
+                children.push(child);
+            }
 
-                if parent.gid == child.gid {
-                    // update creation price
-                    if parent.creation_price > 0 && child.price == 0 {
-                        child.price = parent.creation_price;
-                        child.update_field(&app.scylla, "price").await?;
-                    }
+            for mut child in children {
+                let cid = child.id;
+
+                // ensure the creation from another group is published
+                let published = if parent.gid == child.gid || child.rating > parent.rating {
+                    true
                 } else {
-                    // ensure the creation is published
-                    let _ = db::PublicationIndex::get_implicit_published(
+                    match db::PublicationIndex::get_implicit_published(
                         &app.scylla,
                         cid,
                         db::ZERO_ID,
                         Language::Und,
                     )
-                    .await?;
+                    .await
+                    {
+                        Ok(_) => true,
+                        Err(err) if err.code == 404 => false,
+                        Err(err) => return Err(err),
+                    }
+                };
+                if let Some(reason) =
+                    add_child_skip_reason(&parent, cid, child.gid, child.rating, published)
+                {
+                    res.push(AddChildOutput::new(cid, reason, &to));
+                    continue;
+                }
+
+                // update creation price
+                if parent.gid == child.gid && parent.creation_price > 0 && child.price == 0 {
+                    child.price = parent.creation_price;
+                    child.update_field(&app.scylla, "price").await?;
                 }
 
                 let mut doc = db::CollectionChildren {
                     id,
                     cid,
                     kind: input.kind,
-                    ord: ord + processed.len() as f64 / total,
+                    ord: ord + added as f64 / total,
                     ..Default::default()
                 };
-                let ok = doc.save(&app.scylla).await?;
-                if ok {
-                    processed.push(cid);
-                }
+                let reason = if doc.save(&app.scylla).await? {
+                    added += 1;
+                    ADD_CHILD_OK
+                } else {
+                    ADD_CHILD_DUPLICATE
+                };
+                res.push(AddChildOutput::new(cid, reason, &to));
             }
         }
         2 => {
+            let ancestors =
+                db::CollectionChildren::list_ancestors(&app.scylla, id, db::MAX_COLLECTION_DEPTH)
+                    .await?;
+            if let Some(cid) = cids.iter().find(|cid| ancestors.contains(cid)) {
+                return Err(HTTPError::new(
+                    400,
+                    format!(
+                        "Collection {} is an ancestor of {}, it can not be a child",
+                        cid, id
+                    ),
+                ));
+            }
+
             for cid in cids {
                 if cid == id {
-                    return Err(HTTPError::new(
-                        400,
-                        "Parent collection can not be its own child".to_string(),
-                    ));
+                    res.push(AddChildOutput::new(cid, ADD_CHILD_SELF_REFERENCE, &to));
+                    continue;
                 }
 
                 let mut child = db::Collection::with_pk(cid);
@@ -820,39 +1380,41 @@ pub async fn add_children(
                         None,
                     )
                     .await?;
-                if child.rating > parent.rating {
+                if let Some(reason) =
+                    add_child_skip_reason(&parent, cid, child.gid, child.rating, child.status >= 2)
+                {
+                    res.push(AddChildOutput::new(cid, reason, &to));
                     continue;
                 }
-                if child.gid != parent.gid && child.status < 2 {
-                    return Err(HTTPError::new(
-                        400,
-                        "Child collection is not published".to_string(),
-                    ));
-                }
 
                 let mut doc = db::CollectionChildren {
                     id,
                     cid,
                     kind: input.kind,
-                    ord: ord + processed.len() as f64 / total,
+                    ord: ord + added as f64 / total,
                     ..Default::default()
                 };
-                let ok = doc.save(&app.scylla).await?;
-                if ok {
-                    processed.push(cid);
-                }
+                let reason = if doc.save(&app.scylla).await? {
+                    added += 1;
+                    ADD_CHILD_OK
+                } else {
+                    ADD_CHILD_DUPLICATE
+                };
+                res.push(AddChildOutput::new(cid, reason, &to));
             }
         }
         _ => return Err(HTTPError::new(400, "Invalid collection kind".to_string())),
     }
 
-    ctx.set("added", processed.len().into()).await;
-    Ok(to.with(SuccessResponse::new(
-        processed.into_iter().map(|id| to.with(id)).collect(),
-    )))
+    if added > 0 {
+        let _ = db::CollectionChildren::rebalance(&app.scylla, id).await;
+        let _ = parent.refresh_cover(&app.scylla).await;
+    }
+    ctx.set("added", added.into()).await;
+    Ok(to.with(SuccessResponse::new(res)))
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct UpdateChildrenInput {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -901,6 +1463,10 @@ pub async fn update_child(
 
     let mut doc = db::CollectionChildren::with_pk(id, cid);
     let ok = doc.update_ord(&app.scylla, input.ord).await?;
+    if ok {
+        let _ = db::CollectionChildren::rebalance(&app.scylla, id).await;
+        let _ = parent.refresh_cover(&app.scylla).await;
+    }
     Ok(to.with(SuccessResponse::new(ok)))
 }
 
@@ -945,10 +1511,242 @@ pub async fn remove_child(
 
     let mut doc = db::CollectionChildren::with_pk(id, cid);
     let ok = doc.delete(&app.scylla).await?;
+    if ok {
+        let _ = parent.refresh_cover(&app.scylla).await;
+    }
     Ok(to.with(SuccessResponse::new(ok)))
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
+pub struct MoveChildrenInput {
+    pub from_id: PackObject<xid::Id>,
+    pub to_id: PackObject<xid::Id>,
+    pub gid: PackObject<xid::Id>,
+    #[validate(length(min = 1, max = 1000))]
+    pub cids: Vec<PackObject<xid::Id>>,
+}
+
+// the reason codes in MoveChildOutput besides the ADD_CHILD_* ones.
+pub const MOVE_CHILD_NOT_FOUND: &str = "not_found";
+pub const MOVE_CHILD_FAILED: &str = "failed";
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct MoveChildOutput {
+    pub cid: PackObject<xid::Id>,
+    pub moved: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl MoveChildOutput {
+    fn new<T>(cid: xid::Id, reason: &str, to: &PackObject<T>) -> Self {
+        Self {
+            cid: to.with(cid),
+            moved: reason == ADD_CHILD_OK,
+            reason: Some(reason.to_string()),
+        }
+    }
+}
+
+// gets the collection with the fields to move children from or to, it should be active and
+// owned by the group.
+async fn get_owned_collection(
+    app: &AppState,
+    id: xid::Id,
+    gid: xid::Id,
+) -> Result<db::Collection, HTTPError> {
+    let mut doc = db::Collection::with_pk(id);
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "gid".to_string(),
+            "rating".to_string(),
+            "status".to_string(),
+        ],
+        None,
+    )
+    .await?;
+    if doc.status < 0 {
+        return Err(HTTPError::new(
+            400,
+            format!("Collection {} is archived", id),
+        ));
+    }
+    if doc.gid != gid {
+        return Err(HTTPError::new(403, "Collection gid not match".to_string()));
+    }
+    Ok(doc)
+}
+
+// moves the children from one collection to another of the group, the moved children keep
+// their kind and relative order and are appended to the destination. The children are checked
+// against the destination like add_children.
+// child_found returns false if the child's row is missing, then the child is skipped and
+// reported instead of aborting the whole move, the other errors are returned.
+fn child_found(res: anyhow::Result<()>) -> Result<bool, HTTPError> {
+    match res {
+        Ok(_) => Ok(true),
+        Err(err) => {
+            let err: HTTPError = err.into();
+            if err.code == 404 {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+    }
+}
+
+pub async fn move_children(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<MoveChildrenInput>,
+) -> Result<PackObject<SuccessResponse<Vec<MoveChildOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let from_id = *input.from_id.to_owned();
+    let to_id = *input.to_id.to_owned();
+    let gid = *input.gid.to_owned();
+    let cids: Vec<xid::Id> = input.cids.iter().map(|id| *id.to_owned()).collect();
+    ctx.set_kvs(vec![
+        ("action", "move_collection_children".into()),
+        ("from_id", from_id.to_string().into()),
+        ("to_id", to_id.to_string().into()),
+        ("gid", gid.to_string().into()),
+        ("cids", cids.len().into()),
+    ])
+    .await;
+
+    if from_id == to_id {
+        return Err(HTTPError::new(
+            400,
+            "Can not move children to the same collection".to_string(),
+        ));
+    }
+    let mut source = get_owned_collection(&app, from_id, gid).await?;
+    let mut dest = get_owned_collection(&app, to_id, gid).await?;
+
+    let dest_children = db::CollectionChildren::list_children(&app.scylla, to_id).await?;
+    let mut source_children = db::CollectionChildren::list_children(&app.scylla, from_id).await?;
+    source_children.retain(|v| cids.contains(&v.cid));
+    if dest_children.len() + source_children.len() > db::MAX_COLLECTION_CHILDREN {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "Parent collection can only have {} children",
+                db::MAX_COLLECTION_CHILDREN
+            ),
+        ));
+    }
+
+    if source_children.iter().any(|v| v.kind == 2) {
+        let ancestors =
+            db::CollectionChildren::list_ancestors(&app.scylla, to_id, db::MAX_COLLECTION_DEPTH)
+                .await?;
+        if let Some(v) = source_children
+            .iter()
+            .find(|v| v.kind == 2 && ancestors.contains(&v.cid))
+        {
+            return Err(HTTPError::new(
+                400,
+                format!(
+                    "Collection {} is an ancestor of {}, it can not be a child",
+                    v.cid, to_id
+                ),
+            ));
+        }
+    }
+
+    // the group's rating policy rejects the whole request before any child is moved, the same
+    // as add_children.
+    let setting = db::GroupSetting::load(&app.scylla, gid).await?;
+    let mut reasons: HashMap<xid::Id, &str> = HashMap::with_capacity(cids.len());
+    let mut moving: Vec<db::CollectionChildren> = Vec::with_capacity(source_children.len());
+    for child in source_children {
+        let reason = if dest_children.iter().any(|v| v.cid == child.cid) {
+            Some(ADD_CHILD_DUPLICATE)
+        } else if child.kind == 2 {
+            let mut doc = db::Collection::with_pk(child.cid);
+            let res = doc
+                .get_one(
+                    &app.scylla,
+                    vec![
+                        "gid".to_string(),
+                        "rating".to_string(),
+                        "status".to_string(),
+                    ],
+                    None,
+                )
+                .await;
+            if !child_found(res)? {
+                reasons.insert(child.cid, MOVE_CHILD_NOT_FOUND);
+                continue;
+            }
+            add_child_skip_reason(&dest, child.cid, doc.gid, doc.rating, doc.status >= 2)
+        } else {
+            let mut doc = db::CreationIndex::with_pk(child.cid);
+            if !child_found(doc.get_one(&app.scylla).await)? {
+                reasons.insert(child.cid, MOVE_CHILD_NOT_FOUND);
+                continue;
+            }
+            setting.check_rating(doc.rating)?;
+            let published = if dest.gid == doc.gid || doc.rating > dest.rating {
+                true
+            } else {
+                match db::PublicationIndex::get_implicit_published(
+                    &app.scylla,
+                    child.cid,
+                    db::ZERO_ID,
+                    Language::Und,
+                )
+                .await
+                {
+                    Ok(_) => true,
+                    Err(err) if err.code == 404 => false,
+                    Err(err) => return Err(err),
+                }
+            };
+            add_child_skip_reason(&dest, child.cid, doc.gid, doc.rating, published)
+        };
+
+        match reason {
+            Some(reason) => {
+                reasons.insert(child.cid, reason);
+            }
+            None => moving.push(child),
+        }
+    }
+
+    let after_ord = dest_children.last().map_or(0.0, |v| v.ord);
+    let res = db::CollectionChildren::move_children(&app.scylla, &moving, to_id, after_ord).await?;
+    let mut moved: usize = 0;
+    for (cid, ok) in res {
+        if ok {
+            moved += 1;
+            reasons.insert(cid, ADD_CHILD_OK);
+        } else {
+            reasons.insert(cid, MOVE_CHILD_FAILED);
+        }
+    }
+
+    if moved > 0 {
+        let _ = source.refresh_cover(&app.scylla).await;
+        let _ = dest.refresh_cover(&app.scylla).await;
+    }
+    ctx.set("moved", moved.into()).await;
+    Ok(to.with(SuccessResponse::new(
+        cids.into_iter()
+            .map(|cid| {
+                let reason = reasons.get(&cid).copied().unwrap_or(MOVE_CHILD_NOT_FOUND);
+                MoveChildOutput::new(cid, reason, &to)
+            })
+            .collect(),
+    )))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct CollectionChildrenOutput {
     pub parent: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -959,6 +1757,7 @@ pub struct CollectionChildrenOutput {
     pub rating: i8,
     pub updated_at: i64,
     pub cover: String,
+    pub cover_blurhash: String,
     pub price: i64,
     pub language: PackObject<Language>,
     pub version: i16,
@@ -966,6 +1765,77 @@ pub struct CollectionChildrenOutput {
     pub summary: String,
     pub keywords: Vec<String>,
     pub authors: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info_language: Option<PackObject<Language>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info_is_fallback: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rfp: Option<RFP>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscribed: Option<bool>,
+}
+
+// the caller's subscriptions for a page of children, loaded once per request.
+#[derive(Debug, Default)]
+struct ChildrenAccess {
+    anonymous: bool,
+    user_gid: xid::Id,
+    now_ms: i64,
+    parent_id: xid::Id,
+    parent_price: i64,
+    parent_expire_at: i64,
+    creations_expire_at: HashMap<xid::Id, i64>,
+    collections_expire_at: HashMap<xid::Id, i64>,
+}
+
+impl ChildrenAccess {
+    fn active(&self, expire_at: i64) -> bool {
+        expire_at > 0 && expire_at * 1000 >= self.now_ms
+    }
+
+    fn fill<T>(&self, output: &mut CollectionChildrenOutput, to: &PackObject<T>) {
+        let cid = *output.cid;
+        let expire_at = if output.kind == 2 {
+            self.collections_expire_at.get(&cid)
+        } else {
+            self.creations_expire_at.get(&cid)
+        };
+
+        let subscribed = !self.anonymous
+            && (self.active(self.parent_expire_at) || self.active(*expire_at.unwrap_or(&0)));
+        if !self.anonymous {
+            output.subscribed = Some(subscribed);
+        }
+
+        if output.price <= 0 || subscribed || *output.gid == self.user_gid {
+            return;
+        }
+
+        output.rfp = Some(if output.kind == 2 {
+            RFP {
+                creation: None,
+                collection: Some(RFPInfo {
+                    id: to.with(cid),
+                    price: output.price,
+                }),
+            }
+        } else {
+            RFP {
+                creation: Some(RFPInfo {
+                    id: to.with(cid),
+                    price: output.price,
+                }),
+                collection: if self.parent_price > 0 {
+                    Some(RFPInfo {
+                        id: to.with(self.parent_id),
+                        price: self.parent_price,
+                    })
+                } else {
+                    None
+                },
+            }
+        });
+    }
 }
 
 pub async fn list_children(
@@ -1009,6 +1879,43 @@ pub async fn list_children(
         None
     });
 
+    let mut access = ChildrenAccess {
+        anonymous: ctx.user <= db::MIN_ID,
+        user_gid,
+        now_ms: ctx.unix_ms as i64,
+        parent_id: id,
+        ..Default::default()
+    };
+    let mut parent = db::Collection::with_pk(id);
+    if parent
+        .get_one(&app.scylla, vec!["price".to_string()], None)
+        .await
+        .is_ok()
+    {
+        access.parent_price = parent.price;
+    }
+    if !access.anonymous {
+        let mut subscription = db::CollectionSubscription::with_pk(ctx.user, id);
+        if subscription
+            .get_one(&app.scylla, vec!["expire_at".to_string()])
+            .await
+            .is_ok()
+        {
+            access.parent_expire_at = subscription.expire_at;
+        }
+
+        let (collections, creations): (Vec<_>, Vec<_>) =
+            children.iter().partition(|child| child.kind == 2);
+        let cids: Vec<xid::Id> = creations.iter().map(|child| child.cid).collect();
+        for sub in db::CreationSubscription::batch_get(&app.scylla, ctx.user, &cids).await? {
+            access.creations_expire_at.insert(sub.cid, sub.expire_at);
+        }
+        let cids: Vec<xid::Id> = collections.iter().map(|child| child.cid).collect();
+        for sub in db::CollectionSubscription::batch_get(&app.scylla, ctx.user, &cids).await? {
+            access.collections_expire_at.insert(sub.cid, sub.expire_at);
+        }
+    }
+
     let mut res: Vec<CollectionChildrenOutput> = Vec::with_capacity(children.len());
     let collection_fields = vec![
         "gid".to_string(),
@@ -1049,10 +1956,13 @@ pub async fn list_children(
                     .is_ok()
                     && doc.status >= status
                 {
-                    if let Some((lang, info)) = doc.to_info(language) {
+                    if let Some((lang, info, is_i18n)) = doc.to_info(language) {
                         output.status = doc.status;
                         output.updated_at = doc._info.unwrap().updated_at;
                         output.language = to.with(lang);
+                        output.info_language = Some(to.with(lang));
+                        output.info_is_fallback =
+                            Some(db::Collection::info_is_fallback(language, lang, is_i18n));
                         output.title = info.title;
                         output.summary = info.summary;
                         output.keywords = info.keywords.unwrap_or_default();
@@ -1061,6 +1971,7 @@ pub async fn list_children(
                     output.gid = to.with(doc.gid);
                     output.rating = doc.rating;
                     output.cover = doc.cover;
+                    output.cover_blurhash = doc.cover_blurhash;
                     output.price = doc.price;
                 };
             }
@@ -1092,6 +2003,7 @@ pub async fn list_children(
                                 output.title = doc.title;
                                 output.summary = doc.summary;
                                 output.cover = doc.cover;
+                                output.cover_blurhash = doc.cover_blurhash;
                                 output.kind = 1;
                                 output.keywords = doc.keywords;
                                 output.authors = doc.authors;
@@ -1112,6 +2024,7 @@ pub async fn list_children(
                                 output.title = doc.title;
                                 output.summary = doc.summary;
                                 output.cover = doc.cover;
+                                output.cover_blurhash = doc.cover_blurhash;
                                 output.kind = 0;
                                 output.keywords = doc.keywords;
                                 output.authors = doc.authors;
@@ -1140,6 +2053,7 @@ pub async fn list_children(
                         output.title = doc.title;
                         output.summary = doc.summary;
                         output.cover = doc.cover;
+                        output.cover_blurhash = doc.cover_blurhash;
                         output.kind = 1;
                         output.keywords = doc.keywords;
                         output.authors = doc.authors;
@@ -1152,6 +2066,7 @@ pub async fn list_children(
         if (output.status == 2 && output.rating <= ctx.rating)
             || (output.status >= status && *output.gid == user_gid)
         {
+            access.fill(&mut output, &to);
             res.push(output)
         }
     }
@@ -1163,6 +2078,83 @@ pub async fn list_children(
     }))
 }
 
+// the max children to search in, the children after it by order are not searched.
+// Meilisearch evaluates the cids as one filter expression, a longer one gets slow.
+pub const MAX_SEARCH_CHILDREN: usize = 1000;
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct SearchChildrenInput {
+    pub id: PackObject<xid::Id>,
+    pub gid: PackObject<xid::Id>,
+    pub language: Option<PackObject<Language>>,
+    #[validate(length(min = 0, max = 1024))]
+    pub q: String,
+}
+
+// searches the published documents of the collection's children, only the first
+// MAX_SEARCH_CHILDREN children are searched.
+pub async fn search_children(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<SearchChildrenInput>,
+) -> Result<PackObject<SuccessResponse<meili::SearchOutput>>, HTTPError> {
+    input.validate()?;
+
+    let id = *input.id.to_owned();
+    let user_gid = *input.gid.to_owned();
+    let lang = input.language.to_owned().map(|v| v.unwrap());
+    let q = sanitize_query(&input.q, app.search.max_query_len)?;
+
+    ctx.set_kvs(vec![
+        ("action", "search_collection_child".into()),
+        ("id", id.to_string().into()),
+        ("user_gid", user_gid.to_string().into()),
+        ("q", q.as_str().into()),
+    ])
+    .await;
+
+    let mut doc = db::Collection::with_pk(id);
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "gid".to_string(),
+            "status".to_string(),
+            "rating".to_string(),
+        ],
+        None,
+    )
+    .await?;
+    if doc.status == 2 && doc.rating > ctx.rating {
+        return Err(HTTPError::new(451, "Collection unavailable".to_string()));
+    }
+    if doc.status < 2 && doc.gid != user_gid {
+        return Err(HTTPError::new(403, "Collection gid not match".to_string()));
+    }
+
+    let mut children = db::CollectionChildren::list_children(&app.scylla, id).await?;
+    ctx.set("children", children.len().into()).await;
+    children.truncate(MAX_SEARCH_CHILDREN);
+    let cids: Vec<xid::Id> = children.iter().map(|v| v.cid).collect();
+    let filter = match meili::cids_filter(&cids) {
+        Some(filter) => filter,
+        None => {
+            return Ok(to.with(SuccessResponse::new(meili::SearchOutput {
+                hits: vec![],
+                languages: HashMap::new(),
+                filtered: 0,
+            })))
+        }
+    };
+
+    let res = app
+        .meili
+        .search(meili::Space::Pub(None), lang, Some(filter), &q, &to)
+        .await?;
+    ctx.set("result", res.hits.len().into()).await;
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
 pub async fn list_by_child(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
@@ -1362,3 +2354,286 @@ pub async fn update_subscription(
         expire_at: doc.expire_at,
     })))
 }
+
+// grant_subscription creates or extends a user's subscription to a published collection
+// without a payment, the txn of a grant is ZERO_ID. It is recorded in the changelog with
+// the note.
+pub async fn grant_subscription(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<GrantSubscriptionInput>,
+) -> Result<PackObject<SuccessResponse<SubscriptionOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+    valid_grant_expire_at(input.expire_at, ctx.unix_ms as i64)?;
+
+    let uid = *input.uid.to_owned();
+    let cid = *input.cid.to_owned();
+
+    ctx.set_kvs(vec![
+        ("action", "grant_collection_subscription".into()),
+        ("uid", uid.to_string().into()),
+        ("cid", cid.to_string().into()),
+        ("expire_at", input.expire_at.into()),
+    ])
+    .await;
+
+    let mut collection = db::Collection::with_pk(cid);
+    collection
+        .get_one(&app.scylla, vec!["status".to_string()], None)
+        .await?;
+    check_grant_actor(
+        ctx.user,
+        input.gid.as_ref().map(|v| *v.to_owned()),
+        collection.gid,
+    )?;
+    if collection.status != 2 {
+        return Err(HTTPError::new(
+            400,
+            "Collection is not published".to_string(),
+        ));
+    }
+
+    let mut doc = db::CollectionSubscription::with_pk(uid, cid);
+    let old = match doc.get_one(&app.scylla, vec![]).await {
+        Ok(_) => {
+            if doc.expire_at >= input.expire_at {
+                return Err(HTTPError::new(
+                    400,
+                    "Subscription expire_at can only be extended".to_string(),
+                ));
+            }
+            let old = (doc.txn, doc.expire_at);
+            doc.update(&app.scylla, db::ZERO_ID, input.expire_at, doc.updated_at)
+                .await?;
+            ctx.set("updated", true.into()).await;
+            Some(old)
+        }
+        Err(_) => {
+            doc.txn = db::ZERO_ID;
+            doc.expire_at = input.expire_at;
+            doc.save(&app.scylla).await?;
+            ctx.set("created", true.into()).await;
+            None
+        }
+    };
+
+    let log = grant_changelog(&ctx, collection.gid, 2, &input, old);
+    let _ = db::Changelog::record(&app.scylla, vec![log]).await;
+
+    Ok(to.with(SuccessResponse::new(SubscriptionOutput {
+        uid: to.with(doc.uid),
+        cid: to.with(doc.cid),
+        gid: to.with(collection.gid),
+        txn: to.with(doc.txn),
+        updated_at: doc.updated_at,
+        expire_at: doc.expire_at,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn child(kind: i8, price: i64, gid: xid::Id) -> CollectionChildrenOutput {
+        CollectionChildrenOutput {
+            cid: PackObject::Cbor(xid::new()),
+            gid: PackObject::Cbor(gid),
+            kind,
+            price,
+            status: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn children_access_works() {
+        let to: PackObject<()> = PackObject::Cbor(());
+        let now_ms: i64 = 1_700_000_000_000;
+        let owner = xid::new();
+        let mut access = ChildrenAccess {
+            user_gid: xid::new(),
+            now_ms,
+            parent_id: xid::new(),
+            parent_price: 100,
+            ..Default::default()
+        };
+
+        // not subscribed
+        let mut output = child(0, 10, owner);
+        access.fill(&mut output, &to);
+        assert_eq!(output.subscribed, Some(false));
+        let rfp = output.rfp.unwrap();
+        assert_eq!(rfp.creation.unwrap().price, 10);
+        let collection = rfp.collection.unwrap();
+        assert_eq!(*collection.id, access.parent_id);
+        assert_eq!(collection.price, 100);
+
+        // free child
+        let mut output = child(0, 0, owner);
+        access.fill(&mut output, &to);
+        assert_eq!(output.subscribed, Some(false));
+        assert!(output.rfp.is_none());
+
+        // owned child
+        let mut output = child(0, 10, access.user_gid);
+        access.fill(&mut output, &to);
+        assert!(output.rfp.is_none());
+
+        // individually subscribed creation
+        let mut output = child(1, 10, owner);
+        access
+            .creations_expire_at
+            .insert(*output.cid, now_ms / 1000 + 3600);
+        access.fill(&mut output, &to);
+        assert_eq!(output.subscribed, Some(true));
+        assert!(output.rfp.is_none());
+
+        // expired creation subscription
+        let mut output = child(1, 10, owner);
+        access
+            .creations_expire_at
+            .insert(*output.cid, now_ms / 1000 - 1);
+        access.fill(&mut output, &to);
+        assert_eq!(output.subscribed, Some(false));
+        assert!(output.rfp.is_some());
+
+        // a sub collection
+        let mut output = child(2, 50, owner);
+        access.fill(&mut output, &to);
+        assert_eq!(output.subscribed, Some(false));
+        let rfp = output.rfp.unwrap();
+        assert!(rfp.creation.is_none());
+        assert_eq!(rfp.collection.unwrap().price, 50);
+
+        // subscribed parent covers all children
+        access.parent_expire_at = now_ms / 1000 + 3600;
+        for kind in [0, 1, 2] {
+            let mut output = child(kind, 10, owner);
+            access.fill(&mut output, &to);
+            assert_eq!(output.subscribed, Some(true));
+            assert!(output.rfp.is_none());
+        }
+
+        // expired parent subscription
+        access.parent_expire_at = now_ms / 1000 - 1;
+        let mut output = child(0, 10, owner);
+        access.fill(&mut output, &to);
+        assert_eq!(output.subscribed, Some(false));
+        assert!(output.rfp.is_some());
+
+        // anonymous
+        access.anonymous = true;
+        access.parent_expire_at = now_ms / 1000 + 3600;
+        let mut output = child(0, 10, owner);
+        access.fill(&mut output, &to);
+        assert!(output.subscribed.is_none());
+        assert!(output.rfp.is_some());
+    }
+
+    #[test]
+    fn take_counts_works() {
+        let mut fields: Vec<String> = vec![];
+        assert!(!take_counts(&mut fields));
+        assert!(fields.is_empty());
+
+        let mut fields = vec!["counts".to_string()];
+        assert!(take_counts(&mut fields));
+        assert!(fields.is_empty());
+
+        let mut fields = vec!["cover".to_string(), "counts".to_string()];
+        assert!(take_counts(&mut fields));
+        assert_eq!(fields, vec!["cover".to_string(), "info".to_string()]);
+
+        let mut fields = vec!["info".to_string(), "counts".to_string()];
+        assert!(take_counts(&mut fields));
+        assert_eq!(fields, vec!["info".to_string()]);
+    }
+
+    #[test]
+    fn set_counts_works() {
+        let to = PackObject::Json(());
+        let mut output = CollectionOutput::default();
+        output.set_counts(12, None, &to);
+        assert_eq!(output.children_count, Some(12));
+        assert!(output.languages.is_none());
+
+        output.set_counts(3, Some(vec![Language::Eng, Language::Zho]), &to);
+        assert_eq!(output.children_count, Some(3));
+        let languages: Vec<Language> = output.languages.unwrap().iter().map(|v| **v).collect();
+        assert_eq!(languages, vec![Language::Eng, Language::Zho]);
+    }
+
+    #[test]
+    fn default_creation_price_works() {
+        assert_eq!(default_creation_price(None, None), None);
+        assert_eq!(default_creation_price(Some(100), None), None);
+        assert_eq!(default_creation_price(Some(-1), None), Some(-1));
+        assert_eq!(default_creation_price(None, Some(10)), Some(10));
+        assert_eq!(default_creation_price(Some(100), Some(10)), Some(10));
+        // kept, so it is rejected by valid_price instead of forced to -1
+        assert_eq!(default_creation_price(Some(-1), Some(10)), Some(10));
+        assert!(db::Collection::valid_price(-1, 10).is_err());
+    }
+
+    #[test]
+    fn child_found_works() {
+        assert!(child_found(Ok(())).unwrap());
+        let err = HTTPError::new(404, "not found".to_string());
+        assert!(!child_found(Err(err.into())).unwrap());
+        let err = HTTPError::new(500, "timeout".to_string());
+        assert_eq!(child_found(Err(err.into())).unwrap_err().code, 500);
+    }
+
+    #[test]
+    fn add_child_skip_reason_works() {
+        let to: PackObject<()> = PackObject::Cbor(());
+        let mut parent = db::Collection::with_pk(xid::new());
+        parent.gid = xid::new();
+        parent.rating = 1;
+        let other_gid = xid::new();
+
+        let cid = xid::new();
+        assert_eq!(
+            add_child_skip_reason(&parent, cid, parent.gid, 1, false),
+            None
+        );
+        assert_eq!(
+            add_child_skip_reason(&parent, cid, other_gid, 0, true),
+            None
+        );
+        assert_eq!(
+            add_child_skip_reason(&parent, parent.id, parent.gid, 0, true),
+            Some(ADD_CHILD_SELF_REFERENCE)
+        );
+        assert_eq!(
+            add_child_skip_reason(&parent, cid, parent.gid, 2, true),
+            Some(ADD_CHILD_RATING_EXCEEDS_PARENT)
+        );
+        // rating is checked before the publication
+        assert_eq!(
+            add_child_skip_reason(&parent, cid, other_gid, 2, false),
+            Some(ADD_CHILD_RATING_EXCEEDS_PARENT)
+        );
+        assert_eq!(
+            add_child_skip_reason(&parent, cid, other_gid, 0, false),
+            Some(ADD_CHILD_NOT_PUBLISHED)
+        );
+
+        let output = AddChildOutput::new(cid, ADD_CHILD_OK, &to);
+        assert!(output.added);
+        assert_eq!(*output.cid, cid);
+        assert_eq!(output.reason.as_deref(), Some(ADD_CHILD_OK));
+        for reason in [
+            ADD_CHILD_SELF_REFERENCE,
+            ADD_CHILD_RATING_EXCEEDS_PARENT,
+            ADD_CHILD_NOT_PUBLISHED,
+            ADD_CHILD_DUPLICATE,
+        ] {
+            let output = AddChildOutput::new(cid, reason, &to);
+            assert!(!output.added);
+            assert_eq!(output.reason.as_deref(), Some(reason));
+        }
+    }
+}
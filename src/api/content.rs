@@ -1,16 +1,26 @@
+use schemars::JsonSchema;
 use serde::{de, Deserialize, Serialize};
 use std::{collections::BTreeMap, fmt};
 use validator::ValidationError;
 
+use axum_web::erring::HTTPError;
 use axum_web::object::{cbor_from_slice, cbor_to_vec, PackObject};
 
-use crate::db::MAX_CONTENT_LEN;
+use super::util::xml_escape;
+use crate::conf;
+use crate::db::{DomainError, MAX_CONTENT_LEN};
 pub const MAX_CREATION_CONTENT_LEN: usize = 512 * 1024;
 
+// the document schema version of the current editor, assumed when the document has no `version`.
+pub const CONTENT_VERSION: u16 = 1;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct DocumentNode {
     #[serde(rename = "type")]
     pub itype: String,
+    // the schema version, only on the top-level node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attrs: Option<BTreeMap<String, AttrValue>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -40,14 +50,35 @@ pub enum AttrValue {
     Null,
 }
 
+// reads the top-level `version` only, the nodes are validated by the version's schema.
+#[derive(Deserialize)]
+struct DocumentVersion {
+    version: Option<u16>,
+}
+
 pub fn validate_cbor_content(content: &PackObject<Vec<u8>>) -> Result<(), ValidationError> {
     if content.len() > MAX_CONTENT_LEN {
         return Err(ValidationError::new("content length is too long"));
     }
 
-    let _: DocumentNode = cbor_from_slice(content.unwrap_ref())
+    let doc: DocumentVersion = cbor_from_slice(content.unwrap_ref())
         .map_err(|_| ValidationError::new("content is not a valid cbor"))?;
-    Ok(())
+    validate_cbor_content_v(content, doc.version.unwrap_or(CONTENT_VERSION))
+}
+
+// validate_cbor_content_v validates the content with the document schema of the version.
+pub fn validate_cbor_content_v(
+    content: &PackObject<Vec<u8>>,
+    version: u16,
+) -> Result<(), ValidationError> {
+    match version {
+        1 => {
+            let _: DocumentNode = cbor_from_slice(content.unwrap_ref())
+                .map_err(|_| ValidationError::new("content is not a valid cbor"))?;
+            Ok(())
+        }
+        _ => Err(ValidationError::new("unsupported content version")),
+    }
 }
 
 pub fn segment_content(
@@ -69,6 +100,314 @@ pub fn segment_content(
     content
 }
 
+// slice_content keeps the top-level nodes in [offset, offset + limit) of the document, the
+// root node and its attrs are kept as is. It returns the sliced content and the total number
+// of top-level nodes, an offset out of range gives an empty document.
+pub fn slice_content(
+    content: &[u8],
+    offset: usize,
+    limit: usize,
+) -> Result<(Vec<u8>, usize), HTTPError> {
+    let not_sliceable = || {
+        HTTPError::new(
+            400,
+            "Content is not a document with top-level nodes".to_string(),
+        )
+    };
+    let mut doc: DocumentNode = cbor_from_slice(content).map_err(|_| not_sliceable())?;
+    if doc.itype != "doc" {
+        return Err(not_sliceable());
+    }
+    let nodes = doc.content.as_mut().ok_or_else(not_sliceable)?;
+
+    let total = nodes.len();
+    let start = offset.min(total);
+    let end = offset.saturating_add(limit).min(total);
+    nodes.truncate(end);
+    nodes.drain(..start);
+
+    let data = cbor_to_vec(&doc).map_err(|err| HTTPError::new(500, err.to_string()))?;
+    Ok((data, total))
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ContentStats {
+    pub nodes: usize, // top-level nodes
+    pub chars: usize, // non-whitespace characters of the text nodes
+}
+
+pub fn content_stats(content: &[u8]) -> Result<ContentStats, HTTPError> {
+    fn walk(node: &DocumentNode, chars: &mut usize) {
+        if let Some(ref text) = node.text {
+            *chars += text.chars().filter(|c| !c.is_whitespace()).count();
+        }
+        if let Some(ref content) = node.content {
+            for child in content {
+                walk(child, chars);
+            }
+        }
+    }
+
+    let doc: DocumentNode = cbor_from_slice(content)
+        .map_err(|_| HTTPError::new(400, "Content is not a valid document".to_string()))?;
+    let mut stats = ContentStats {
+        nodes: doc.content.as_ref().map_or(0, |v| v.len()),
+        chars: 0,
+    };
+    walk(&doc, &mut stats.chars);
+    Ok(stats)
+}
+
+// check_publishable rejects the document without top-level nodes or with less text than
+// `publish.min_chars`, e.g. an empty, whitespace-only or image-only document.
+pub fn check_publishable(cfg: &conf::Publish, content: &[u8]) -> Result<(), HTTPError> {
+    let stats = content_stats(content)?;
+    if stats.nodes > 0 && stats.chars >= cfg.min_chars {
+        return Ok(());
+    }
+
+    Err(DomainError::EmptyContent.with_data(
+        format!(
+            "Content is too short to publish, {} nodes and {} characters, expected >= {} characters",
+            stats.nodes, stats.chars, cfg.min_chars
+        ),
+        serde_json::json!({
+            "nodes": stats.nodes,
+            "chars": stats.chars,
+            "min_chars": cfg.min_chars,
+        }),
+    ))
+}
+
+// MediaRef is an external URL referenced by the document, with the ids of the blocks that
+// reference it in document order.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+pub struct MediaRef {
+    pub url: String,
+    pub kind: String, // the node or mark type of the first reference, e.g. "image", "link"
+    pub block_ids: Vec<String>,
+}
+
+// extract_media_refs collects the http(s) URLs in the `src` and `href` attrs of the nodes and
+// their marks, the URLs are deduped in the order of their first reference. A node without
+// an `id` attr belongs to the block of its nearest ancestor with one.
+pub fn extract_media_refs(content: &[u8]) -> Vec<MediaRef> {
+    fn url_of(attrs: &Option<BTreeMap<String, AttrValue>>) -> Option<&str> {
+        let attrs = attrs.as_ref()?;
+        for key in ["src", "href"] {
+            if let Some(AttrValue::Text(v)) = attrs.get(key) {
+                let v = v.trim();
+                if v.starts_with("https://") || v.starts_with("http://") {
+                    return Some(v);
+                }
+            }
+        }
+        None
+    }
+
+    fn add(refs: &mut Vec<MediaRef>, url: &str, kind: &str, block_id: Option<&str>) {
+        let i = match refs.iter().position(|r| r.url == url) {
+            Some(i) => i,
+            None => {
+                refs.push(MediaRef {
+                    url: url.to_string(),
+                    kind: kind.to_string(),
+                    block_ids: Vec::new(),
+                });
+                refs.len() - 1
+            }
+        };
+        if let Some(id) = block_id {
+            if !refs[i].block_ids.iter().any(|v| v == id) {
+                refs[i].block_ids.push(id.to_string());
+            }
+        }
+    }
+
+    fn walk<'a>(node: &'a DocumentNode, block_id: Option<&'a str>, refs: &mut Vec<MediaRef>) {
+        let block_id = match node.attrs.as_ref().and_then(|v| v.get("id")) {
+            Some(AttrValue::Text(id)) if !id.is_empty() => Some(id.as_str()),
+            _ => block_id,
+        };
+        if let Some(url) = url_of(&node.attrs) {
+            add(refs, url, &node.itype, block_id);
+        }
+        if let Some(ref marks) = node.marks {
+            for mark in marks {
+                if let Some(url) = url_of(&mark.attrs) {
+                    add(refs, url, &mark.itype, block_id);
+                }
+            }
+        }
+        if let Some(ref content) = node.content {
+            for child in content {
+                walk(child, block_id, refs);
+            }
+        }
+    }
+
+    let mut refs: Vec<MediaRef> = Vec::new();
+    if let Ok(doc) = cbor_from_slice::<DocumentNode>(content) {
+        walk(&doc, None, &mut refs);
+    }
+    refs
+}
+
+// content_text extracts the plain text of the document, text blocks are separated by newlines.
+pub fn content_text(doc: &DocumentNode, max_len: usize) -> String {
+    fn walk(node: &DocumentNode, out: &mut String, max_len: usize) {
+        if out.len() >= max_len {
+            return;
+        }
+        if let Some(ref text) = node.text {
+            out.push_str(text);
+        }
+        if let Some(ref content) = node.content {
+            for child in content {
+                walk(child, out, max_len);
+            }
+            if node.text.is_none() && !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    }
+
+    let mut out = String::new();
+    walk(doc, &mut out, max_len);
+    if out.len() > max_len {
+        let mut end = max_len;
+        while !out.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.truncate(end);
+    }
+    out.trim().to_string()
+}
+
+// to_html renders the document as an HTML fragment. Unknown nodes render their children only,
+// unknown marks are dropped, and URLs other than http(s) are not rendered.
+pub fn to_html(doc: &DocumentNode) -> String {
+    fn attr<'a>(
+        attrs: &'a Option<BTreeMap<String, AttrValue>>,
+        key: &str,
+    ) -> Option<&'a AttrValue> {
+        attrs.as_ref().and_then(|v| v.get(key))
+    }
+
+    fn url<'a>(attrs: &'a Option<BTreeMap<String, AttrValue>>, key: &str) -> Option<&'a str> {
+        match attr(attrs, key) {
+            Some(AttrValue::Text(v)) => {
+                let v = v.trim();
+                if v.starts_with("https://") || v.starts_with("http://") {
+                    Some(v)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn mark_tag(mark: &PartialNode) -> Option<(&'static str, Option<&str>)> {
+        match mark.itype.as_str() {
+            "bold" => Some(("strong", None)),
+            "italic" => Some(("em", None)),
+            "underline" => Some(("u", None)),
+            "strike" => Some(("s", None)),
+            "code" => Some(("code", None)),
+            "subscript" => Some(("sub", None)),
+            "superscript" => Some(("sup", None)),
+            "link" => url(&mark.attrs, "href").map(|href| ("a", Some(href))),
+            _ => None,
+        }
+    }
+
+    fn walk(node: &DocumentNode, out: &mut String) {
+        if let Some(ref text) = node.text {
+            let marks: Vec<(&str, Option<&str>)> = node
+                .marks
+                .as_ref()
+                .map(|v| v.iter().filter_map(mark_tag).collect())
+                .unwrap_or_default();
+            for (tag, href) in marks.iter() {
+                out.push('<');
+                out.push_str(tag);
+                if let Some(href) = href {
+                    out.push_str(" href=\"");
+                    out.push_str(&xml_escape(href));
+                    out.push('"');
+                }
+                out.push('>');
+            }
+            out.push_str(&xml_escape(text));
+            for (tag, _) in marks.iter().rev() {
+                out.push_str("</");
+                out.push_str(tag);
+                out.push('>');
+            }
+            return;
+        }
+
+        let tag = match node.itype.as_str() {
+            "paragraph" => "p",
+            "heading" => match attr(&node.attrs, "level") {
+                Some(AttrValue::Integer(2)) => "h2",
+                Some(AttrValue::Integer(3)) => "h3",
+                Some(AttrValue::Integer(4)) => "h4",
+                Some(AttrValue::Integer(5)) => "h5",
+                Some(AttrValue::Integer(6)) => "h6",
+                _ => "h1",
+            },
+            "blockquote" => "blockquote",
+            "bulletList" => "ul",
+            "orderedList" => "ol",
+            "listItem" => "li",
+            "codeBlock" => "pre",
+            "hardBreak" => {
+                out.push_str("<br/>");
+                return;
+            }
+            "horizontalRule" => {
+                out.push_str("<hr/>");
+                return;
+            }
+            "image" => {
+                if let Some(src) = url(&node.attrs, "src") {
+                    out.push_str("<img src=\"");
+                    out.push_str(&xml_escape(src));
+                    out.push_str("\" alt=\"");
+                    if let Some(AttrValue::Text(alt)) = attr(&node.attrs, "alt") {
+                        out.push_str(&xml_escape(alt));
+                    }
+                    out.push_str("\"/>");
+                }
+                return;
+            }
+            _ => "",
+        };
+
+        if !tag.is_empty() {
+            out.push('<');
+            out.push_str(tag);
+            out.push('>');
+        }
+        if let Some(ref content) = node.content {
+            for child in content {
+                walk(child, out);
+            }
+        }
+        if !tag.is_empty() {
+            out.push_str("</");
+            out.push_str(tag);
+            out.push('>');
+        }
+    }
+
+    let mut out = String::new();
+    walk(doc, &mut out);
+    out
+}
+
 impl Serialize for AttrValue {
     #[inline]
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -195,4 +534,333 @@ mod tests {
 
         validate_cbor_content(&PackObject::Cbor(cbor_data)).unwrap();
     }
+
+    #[test]
+    fn validate_cbor_content_version_works() {
+        let doc = |version: serde_json::Value| -> PackObject<Vec<u8>> {
+            let mut node = serde_json::json!({
+                "type": "doc",
+                "content": [{
+                    "type": "paragraph",
+                    "content": [{"type": "text", "text": "Hello"}],
+                }],
+            });
+            if !version.is_null() {
+                node["version"] = version;
+            }
+            let doc: DocumentNode = serde_json::from_value(node).unwrap();
+            PackObject::Cbor(cbor_to_vec(&doc).unwrap())
+        };
+
+        // defaults to the current version
+        let data = doc(serde_json::Value::Null);
+        validate_cbor_content(&data).unwrap();
+        validate_cbor_content_v(&data, CONTENT_VERSION).unwrap();
+
+        // explicit current version
+        let data = doc(serde_json::json!(CONTENT_VERSION));
+        let node: DocumentNode = cbor_from_slice(data.unwrap_ref()).unwrap();
+        assert_eq!(node.version, Some(CONTENT_VERSION));
+        validate_cbor_content(&data).unwrap();
+
+        // unsupported future version
+        let data = doc(serde_json::json!(CONTENT_VERSION + 1));
+        let err = validate_cbor_content(&data).unwrap_err();
+        assert_eq!(err.code, "unsupported content version");
+        assert!(validate_cbor_content_v(&data, 0).is_err());
+
+        // version must be an integer
+        let data = PackObject::Cbor(
+            cbor_to_vec(&serde_json::json!({"type": "doc", "version": "1"})).unwrap(),
+        );
+        assert!(validate_cbor_content(&data).is_err());
+    }
+
+    #[test]
+    fn check_publishable_works() {
+        let data = |value: serde_json::Value| {
+            let doc: DocumentNode = serde_json::from_value(value).unwrap();
+            cbor_to_vec(&doc).unwrap()
+        };
+        let cfg = conf::Publish::default();
+        assert_eq!(cfg.min_chars, 1);
+
+        // empty
+        let empty = data(serde_json::json!({"type": "doc", "content": []}));
+        assert_eq!(
+            content_stats(&empty).unwrap(),
+            ContentStats { nodes: 0, chars: 0 }
+        );
+        let err = check_publishable(&cfg, &empty).unwrap_err();
+        assert_eq!(err.code, 400);
+        assert_eq!(err.data.unwrap()["error"], DomainError::EmptyContent.code());
+        let err = check_publishable(&conf::Publish { min_chars: 0 }, &empty).unwrap_err();
+        assert_eq!(err.code, 400);
+
+        // whitespace-only
+        let blank = data(serde_json::json!({"type": "doc", "content": [
+            {"type": "paragraph", "content": [{"type": "text", "text": " \n\t\u{3000}"}]},
+            {"type": "paragraph"},
+        ]}));
+        assert_eq!(
+            content_stats(&blank).unwrap(),
+            ContentStats { nodes: 2, chars: 0 }
+        );
+        let err = check_publishable(&cfg, &blank).unwrap_err();
+        assert_eq!(err.data.unwrap()["error"], DomainError::EmptyContent.code());
+
+        // image-only
+        let image = data(serde_json::json!({"type": "doc", "content": [
+            {"type": "image", "attrs": {"src": "https://cdn.yiwen.pub/1.png", "alt": null}},
+        ]}));
+        assert_eq!(
+            content_stats(&image).unwrap(),
+            ContentStats { nodes: 1, chars: 0 }
+        );
+        assert!(check_publishable(&cfg, &image).is_err());
+        assert!(check_publishable(&conf::Publish { min_chars: 0 }, &image).is_ok());
+
+        let text = data(serde_json::json!({"type": "doc", "content": [
+            {"type": "paragraph", "content": [
+                {"type": "text", "text": "Hello "},
+                {"type": "text", "marks": [{"type": "bold"}], "text": "世界"},
+            ]},
+        ]}));
+        assert_eq!(
+            content_stats(&text).unwrap(),
+            ContentStats { nodes: 1, chars: 7 }
+        );
+        assert!(check_publishable(&cfg, &text).is_ok());
+        let err = check_publishable(&conf::Publish { min_chars: 8 }, &text).unwrap_err();
+        assert_eq!(err.code, 400);
+
+        assert_eq!(check_publishable(&cfg, b"not cbor").unwrap_err().code, 400);
+    }
+
+    #[test]
+    fn slice_content_works() {
+        let doc: DocumentNode = serde_json::from_value(serde_json::json!({
+            "type": "doc",
+            "attrs": {"id": "doc1"},
+            "content": [
+                {"type": "heading", "attrs": {"level": 1}, "content": [{"type": "text", "text": "0"}]},
+                {"type": "paragraph", "content": [{"type": "text", "text": "1"}]},
+                {"type": "paragraph", "content": [{"type": "text", "text": "2"}]},
+                {"type": "paragraph", "content": [{"type": "text", "text": "3"}]},
+            ],
+        }))
+        .unwrap();
+        let data = cbor_to_vec(&doc).unwrap();
+        let texts = |data: &[u8]| -> String {
+            let doc: DocumentNode = cbor_from_slice(data).unwrap();
+            assert_eq!(doc.itype, "doc");
+            assert_eq!(
+                doc.attrs.unwrap().get("id"),
+                Some(&AttrValue::Text("doc1".to_string()))
+            );
+            content_text(&doc, 1024).replace('\n', "")
+        };
+
+        let (res, total) = slice_content(&data, 0, 2).unwrap();
+        assert_eq!(total, 4);
+        assert_eq!(texts(&res), "01");
+
+        let (res, total) = slice_content(&data, 1, 2).unwrap();
+        assert_eq!(total, 4);
+        assert_eq!(texts(&res), "12");
+
+        // the limit exceeds the end
+        let (res, _) = slice_content(&data, 3, 100).unwrap();
+        assert_eq!(texts(&res), "3");
+        let (res, _) = slice_content(&data, 0, usize::MAX).unwrap();
+        assert_eq!(texts(&res), "0123");
+
+        // out of range offsets give an empty document
+        let (res, total) = slice_content(&data, 4, 2).unwrap();
+        assert_eq!(total, 4);
+        let doc: DocumentNode = cbor_from_slice(&res).unwrap();
+        assert_eq!(doc.content, Some(vec![]));
+        let (res, _) = slice_content(&data, usize::MAX, usize::MAX).unwrap();
+        assert_eq!(texts(&res), "");
+
+        // not a document with top-level nodes
+        let text = cbor_to_vec(&serde_json::json!({"type": "text", "text": "Hello"})).unwrap();
+        assert_eq!(slice_content(&text, 0, 1).unwrap_err().code, 400);
+        let empty = cbor_to_vec(&serde_json::json!({"type": "doc"})).unwrap();
+        assert_eq!(slice_content(&empty, 0, 1).unwrap_err().code, 400);
+        let list = cbor_to_vec(&serde_json::json!([{"type": "paragraph"}])).unwrap();
+        assert_eq!(slice_content(&list, 0, 1).unwrap_err().code, 400);
+        assert_eq!(slice_content(b"not cbor", 0, 1).unwrap_err().code, 400);
+    }
+
+    #[test]
+    fn content_text_works() {
+        let doc: DocumentNode = serde_json::from_value(serde_json::json!({
+            "type": "doc",
+            "content": [{
+                "type": "heading",
+                "attrs": {"id": "Y3T1Ik", "level": 1},
+                "content": [{"type": "text", "text": "Hello"}],
+            }, {
+                "type": "paragraph",
+                "content": [
+                    {"type": "text", "text": "World, "},
+                    {"type": "text", "text": "你好", "marks": [{"type": "bold"}]},
+                ],
+            }, {
+                "type": "paragraph",
+            }],
+        }))
+        .unwrap();
+
+        assert_eq!(content_text(&doc, 1024), "Hello\nWorld, 你好");
+        assert_eq!(content_text(&doc, 5), "Hello");
+        // truncated at a char boundary
+        assert_eq!(content_text(&doc, 15), "Hello\nWorld,");
+        assert_eq!(content_text(&doc, 17), "Hello\nWorld, 你");
+
+        let json_data = std::fs::read("sample/content.json").unwrap();
+        let doc: DocumentNode = serde_json::from_slice(&json_data).unwrap();
+        assert!(!content_text(&doc, 1024).is_empty());
+    }
+
+    #[test]
+    fn extract_media_refs_works() {
+        let data = cbor_to_vec(&serde_json::json!({
+            "type": "doc",
+            "content": [{
+                "type": "paragraph",
+                "attrs": {"id": "p1"},
+                "content": [
+                    {"type": "text", "text": "see "},
+                    {"type": "text", "text": "RFC", "marks": [
+                        {"type": "bold"},
+                        {"type": "link", "attrs": {"href": "https://www.rfc-editor.org/rfc/rfc8949", "target": "_blank"}},
+                    ]},
+                    {"type": "text", "text": "top", "marks": [
+                        {"type": "link", "attrs": {"href": "#p2"}},
+                    ]},
+                ],
+            }, {
+                "type": "image",
+                "attrs": {"id": "i1", "src": " https://cdn.yiwen.pub/a.png ", "alt": "a"},
+            }, {
+                "type": "blockquote",
+                "attrs": {"id": "q1"},
+                "content": [{
+                    "type": "paragraph",
+                    "content": [{"type": "text", "text": "again", "marks": [
+                        {"type": "link", "attrs": {"href": "https://www.rfc-editor.org/rfc/rfc8949"}},
+                    ]}],
+                }, {
+                    "type": "image",
+                    "attrs": {"src": "https://cdn.yiwen.pub/b.png"},
+                }, {
+                    "type": "image",
+                    "attrs": {"src": "data:image/png;base64,AAAA"},
+                }],
+            }, {
+                "type": "paragraph",
+                "attrs": {"id": "p1"},
+                "content": [{"type": "text", "text": "dup", "marks": [
+                    {"type": "link", "attrs": {"href": "https://www.rfc-editor.org/rfc/rfc8949"}},
+                ]}],
+            }],
+        }))
+        .unwrap();
+
+        let refs = extract_media_refs(&data);
+        assert_eq!(
+            refs,
+            vec![
+                MediaRef {
+                    url: "https://www.rfc-editor.org/rfc/rfc8949".to_string(),
+                    kind: "link".to_string(),
+                    block_ids: vec!["p1".to_string(), "q1".to_string()],
+                },
+                MediaRef {
+                    url: "https://cdn.yiwen.pub/a.png".to_string(),
+                    kind: "image".to_string(),
+                    block_ids: vec!["i1".to_string()],
+                },
+                MediaRef {
+                    url: "https://cdn.yiwen.pub/b.png".to_string(),
+                    kind: "image".to_string(),
+                    block_ids: vec!["q1".to_string()],
+                },
+            ]
+        );
+
+        // a node outside of any block has no block id
+        let data = cbor_to_vec(&serde_json::json!({
+            "type": "image",
+            "attrs": {"src": "https://cdn.yiwen.pub/c.png"},
+        }))
+        .unwrap();
+        let refs = extract_media_refs(&data);
+        assert_eq!(refs.len(), 1);
+        assert!(refs[0].block_ids.is_empty());
+
+        assert!(extract_media_refs(b"not cbor").is_empty());
+        let json_data = std::fs::read("sample/content.json").unwrap();
+        let doc: DocumentNode = serde_json::from_slice(&json_data).unwrap();
+        let refs = extract_media_refs(&cbor_to_vec(&doc).unwrap());
+        assert!(refs
+            .iter()
+            .any(|r| r.url == "https://www.rfc-editor.org/rfc/rfc7049" && r.kind == "link"));
+    }
+
+    #[test]
+    fn to_html_works() {
+        let doc: DocumentNode = serde_json::from_value(serde_json::json!({
+            "type": "doc",
+            "content": [{
+                "type": "heading",
+                "attrs": {"id": "h1", "level": 2},
+                "content": [{"type": "text", "text": "A <title>"}],
+            }, {
+                "type": "paragraph",
+                "content": [
+                    {"type": "text", "text": "see "},
+                    {"type": "text", "text": "RFC", "marks": [
+                        {"type": "bold"},
+                        {"type": "link", "attrs": {"href": "https://www.rfc-editor.org/rfc/rfc8949"}},
+                    ]},
+                    {"type": "hardBreak"},
+                    {"type": "text", "text": "x", "marks": [
+                        {"type": "link", "attrs": {"href": "javascript:alert(1)"}},
+                        {"type": "unknown"},
+                    ]},
+                ],
+            }, {
+                "type": "bulletList",
+                "content": [{
+                    "type": "listItem",
+                    "content": [{"type": "paragraph", "content": [{"type": "text", "text": "a & b"}]}],
+                }],
+            }, {
+                "type": "image",
+                "attrs": {"src": "https://cdn.yiwen.pub/a.png", "alt": "\"a\""},
+            }, {
+                "type": "image",
+                "attrs": {"src": "data:image/png;base64,AAAA"},
+            }],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            to_html(&doc),
+            concat!(
+                "<h2>A &lt;title&gt;</h2>",
+                "<p>see <strong><a href=\"https://www.rfc-editor.org/rfc/rfc8949\">RFC</a></strong><br/>x</p>",
+                "<ul><li><p>a &amp; b</p></li></ul>",
+                "<img src=\"https://cdn.yiwen.pub/a.png\" alt=\"&quot;a&quot;\"/>",
+            )
+        );
+
+        let json_data = std::fs::read("sample/content.json").unwrap();
+        let doc: DocumentNode = serde_json::from_slice(&json_data).unwrap();
+        let html = to_html(&doc);
+        assert!(html.contains("<a href=\"https://www.rfc-editor.org/rfc/rfc7049\">"));
+    }
 }
@@ -1,13 +1,23 @@
 use axum::{
     extract::{Query, State},
+    response::{IntoResponse, Response},
     Extension,
 };
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{convert::From, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::From,
+    str::FromStr,
+    sync::Arc,
+};
 use validator::Validate;
 
-use crate::db::{self, meili};
+use crate::{
+    conf,
+    db::{self, meili},
+};
 
 use axum_web::context::ReqContext;
 use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
@@ -15,12 +25,20 @@ use axum_web::object::PackObject;
 use scylla_orm::ColumnsMap;
 
 use super::{
-    get_fields, token_from_xid, token_to_xid, validate_cbor_content, AppState, GIDPagination,
-    QueryGidId, QueryId, SubscriptionInput, SubscriptionOutput, UpdateStatusInput,
-    MAX_CREATION_CONTENT_LEN,
+    blurhash::{set_cover_columns, validate_blurhash},
+    check_grant_actor, desc_only,
+    detect::detect_und_language,
+    extract_media_refs, get_fields, grant_changelog,
+    group::{check_quota, check_quota_update},
+    idempotency_lookup, idempotency_save,
+    license::normalize_license,
+    meili_write, normalize_genre, normalize_keywords, normalize_labels, token_from_updated,
+    token_from_xid, token_to_updated, token_to_xid, valid_grant_expire_at, validate_cbor_content,
+    AppState, GrantSubscriptionInput, MediaRef, MeiliWrite, Pagination, QueryGidId, QueryId,
+    SubscriptionInput, SubscriptionOutput, UpdateStatusInput, MAX_CREATION_CONTENT_LEN,
 };
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct CreateCreationInput {
     pub gid: PackObject<xid::Id>,
     pub language: PackObject<Language>,
@@ -35,6 +53,8 @@ pub struct CreateCreationInput {
     pub summary: Option<String>,
     #[validate(url)]
     pub cover: Option<String>,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
     #[validate(length(min = 0, max = 5))]
     pub keywords: Option<Vec<String>>,
     #[validate(length(min = 0, max = 5))]
@@ -43,12 +63,14 @@ pub struct CreateCreationInput {
     pub authors: Option<Vec<String>>,
     #[validate(custom = "validate_cbor_content")]
     pub content: PackObject<Vec<u8>>,
-    #[validate(url)]
+    #[validate(length(min = 1, max = 256))]
     pub license: Option<String>,
     pub parent: Option<PackObject<xid::Id>>,
+    #[validate(length(min = 8, max = 128))]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct CreationOutput {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -77,6 +99,8 @@ pub struct CreationOutput {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cover: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_blurhash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub keywords: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub labels: Option<Vec<String>>,
@@ -88,8 +112,13 @@ pub struct CreationOutput {
     pub summary: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<PackObject<Vec<u8>>>,
+    // the content failed the integrity verification, it may be damaged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_corrupt: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<bool>,
 }
 
 impl CreationOutput {
@@ -99,6 +128,8 @@ impl CreationOutput {
             id: to.with(val.id),
             rating: val._rating,
             price: val._price,
+            content_corrupt: if val._corrupt { Some(true) } else { None },
+            deleted: if val._deleted { Some(true) } else { None },
             ..Default::default()
         };
 
@@ -114,6 +145,7 @@ impl CreationOutput {
                 "genre" => rt.genre = Some(val.genre.to_owned()),
                 "title" => rt.title = Some(val.title.to_owned()),
                 "cover" => rt.cover = Some(val.cover.to_owned()),
+                "cover_blurhash" => rt.cover_blurhash = Some(val.cover_blurhash.to_owned()),
                 "keywords" => rt.keywords = Some(val.keywords.to_owned()),
                 "labels" => rt.labels = Some(val.labels.to_owned()),
                 "authors" => rt.authors = Some(val.authors.to_owned()),
@@ -133,7 +165,7 @@ pub async fn create(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
     to: PackObject<CreateCreationInput>,
-) -> Result<PackObject<SuccessResponse<CreationOutput>>, HTTPError> {
+) -> Result<Response, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
     if input.content.len() > MAX_CREATION_CONTENT_LEN {
@@ -150,14 +182,41 @@ pub async fn create(
     valid_user(ctx.user)?;
 
     let gid = *input.gid.to_owned();
-    let language = input.language.unwrap();
+    let mut language = input.language.unwrap();
+    let detected = detect_und_language(&app.content, language, input.content.unwrap_ref());
+    if let Some(lang) = detected {
+        language = lang;
+    }
     ctx.set_kvs(vec![
         ("action", "create_creation".into()),
         ("gid", gid.to_string().into()),
         ("language", language.to_639_3().into()),
+        ("language_detected", detected.is_some().into()),
     ])
     .await;
 
+    let (idempotency, replayed) = idempotency_lookup(
+        &app,
+        ctx.user,
+        &input.idempotency_key,
+        "create_creation",
+        &to.with(&input),
+    )
+    .await?;
+    if let Some(res) = replayed {
+        ctx.set("idempotent_replayed", true.into()).await;
+        return Ok(res);
+    }
+
+    check_quota(&app, gid, input.content.len()).await?;
+    let quota_day = db::unix_ms_day(ctx.unix_ms as i64);
+    let max_creations = creation_quota_max(&app.quota, ctx.user, Some(gid));
+    if max_creations > 0 {
+        db::UserCreationQuota::load(&app.scylla, ctx.user, quota_day)
+            .await?
+            .check(max_creations, ctx.unix_ms as i64)?;
+    }
+
     let mut price = input.price.unwrap_or(0);
     let parent = if let Some(parent) = input.parent {
         let mut doc = db::Collection::with_pk(*parent);
@@ -208,14 +267,19 @@ pub async fn create(
         language,
         creator: ctx.user,
         original_url: input.original_url.unwrap_or_default(),
-        genre: input.genre.unwrap_or_default(),
+        genre: normalize_genre(input.genre.unwrap_or_default())?,
         title: input.title,
         cover: input.cover.unwrap_or_default(),
-        keywords: input.keywords.unwrap_or_default(),
-        labels: input.labels.unwrap_or_default(),
-        authors: input.authors.unwrap_or_default(),
+        cover_blurhash: input.cover_blurhash.unwrap_or_default(),
+        keywords: normalize_keywords(input.keywords.unwrap_or_default())?,
+        labels: normalize_labels(input.labels.unwrap_or_default())?,
+        authors: db::normalize_names(input.authors.unwrap_or_default()),
         summary: input.summary.unwrap_or_default(),
-        license: input.license.unwrap_or_default(),
+        license: input
+            .license
+            .map(|v| normalize_license(&app.content, v))
+            .transpose()?
+            .unwrap_or_default(),
         ..Default::default()
     };
 
@@ -223,12 +287,25 @@ pub async fn create(
         .save_with(&app.scylla, price, input.content.unwrap())
         .await?;
     ctx.set("created", ok.into()).await;
+    if ok {
+        if let Err(err) = db::UserCreationQuota::incr(&app.scylla, ctx.user, quota_day, 1).await {
+            log::warn!(target: "quota",
+                action = "incr_creation_quota",
+                rid = ctx.rid,
+                uid = ctx.user.to_string(),
+                day = quota_day;
+                "{}", err.to_string(),
+            );
+        }
+    }
 
     let meili_start = ctx.start.elapsed().as_millis() as u64;
-    if let Err(err) = app
-        .meili
-        .add_or_update(meili::Space::Group(doc.gid), vec![doc.to_meili()])
-        .await
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+    )
+    .await
     {
         log::error!(target: "meilisearch",
             action = "add_or_update",
@@ -242,7 +319,7 @@ pub async fn create(
         );
     }
 
-    if let Some(parent) = parent {
+    if let Some(mut parent) = parent {
         let mut child = db::CollectionChildren {
             id: parent.id,
             cid: doc.id,
@@ -250,10 +327,15 @@ pub async fn create(
             ord: ctx.unix_ms as f64,
             ..Default::default()
         };
-        let _ = child.save(&app.scylla).await;
+        if let Ok(true) = child.save(&app.scylla).await {
+            let _ = parent.refresh_cover(&app.scylla).await;
+        }
     }
 
-    Ok(to.with(SuccessResponse::new(CreationOutput::from(doc, &to))))
+    let resource = format!("{}:{}", doc.gid, doc.id);
+    let res = to.with(SuccessResponse::new(CreationOutput::from(doc, &to)));
+    idempotency_save(idempotency, resource, &res).await;
+    Ok(res.into_response())
 }
 
 pub async fn get(
@@ -277,7 +359,8 @@ pub async fn get(
 
     let mut idoc = db::CreationIndex::with_pk(id);
     idoc.get_one(&app.scylla).await?;
-    let mut doc = db::Creation::with_pk(gid, id);
+    check_creation_access(&app, &ctx, &idoc, gid, db::COLLABORATOR_READER).await?;
+    let mut doc = db::Creation::with_pk(idoc.gid, id);
     doc.get_one(&app.scylla, get_fields(input.fields.clone()))
         .await?;
     doc._rating = Some(idoc.rating);
@@ -285,10 +368,94 @@ pub async fn get(
     Ok(to.with(SuccessResponse::new(CreationOutput::from(doc, &to))))
 }
 
+// media_refs lists the external media and links referenced by the creation's content,
+// for link-rot checks.
+pub async fn media_refs(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGidId>,
+) -> Result<PackObject<SuccessResponse<Vec<MediaRef>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let id = *input.id.to_owned();
+
+    ctx.set_kvs(vec![
+        ("action", "get_creation_media_refs".into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
+    ])
+    .await;
+
+    let mut idoc = db::CreationIndex::with_pk(id);
+    idoc.get_one(&app.scylla).await?;
+    check_creation_access(&app, &ctx, &idoc, gid, db::COLLABORATOR_READER).await?;
+    let mut doc = db::Creation::with_pk(idoc.gid, id);
+    doc.get_one(&app.scylla, vec!["content".to_string()])
+        .await?;
+
+    let refs = extract_media_refs(&doc._content);
+    ctx.set("refs", refs.len().into()).await;
+    Ok(to.with(SuccessResponse::new(refs)))
+}
+
+// check_creation_access passes the owner group of the creation, and the collaborators with
+// a role >= min_role whatever gid they request with. It returns true for the owner group.
+async fn check_creation_access(
+    app: &AppState,
+    ctx: &ReqContext,
+    idoc: &db::CreationIndex,
+    gid: xid::Id,
+    min_role: i8,
+) -> Result<bool, HTTPError> {
+    if idoc.gid == gid {
+        return Ok(true);
+    }
+
+    // only a missing collaborator is denied, the other errors are returned as they are.
+    let mut collaborator = db::CreationCollaborator::with_pk(idoc.id, ctx.user);
+    match collaborator.get_one(&app.scylla).await {
+        Ok(_) if collaborator.role >= min_role => {
+            ctx.set("collaborator_role", collaborator.role.into()).await;
+            return Ok(false);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            let err: HTTPError = err.into();
+            if err.code != 404 {
+                return Err(err);
+            }
+        }
+    }
+
+    Err(HTTPError::new(
+        403,
+        format!("Creation {} is not belong to group {}", idoc.id, gid),
+    ))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct ListCreationInput {
+    pub gid: PackObject<xid::Id>,
+    pub page_token: Option<PackObject<Vec<u8>>>,
+    #[validate(range(min = 2, max = 1000))]
+    pub page_size: Option<u16>,
+    #[validate(range(min = -1, max = 2))]
+    pub status: Option<i8>,
+    pub fields: Option<Vec<String>>,
+    pub include_deleted: Option<bool>, // also list creations from deleted_creation
+    #[validate(length(min = 1, max = 64))]
+    pub label: Option<String>,
+    pub sort: Option<String>,     // "created" (default) or "updated"
+    pub order: Option<db::Order>, // by id, default desc, only for sort by created
+}
+
 pub async fn list(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
-    to: PackObject<GIDPagination>,
+    to: PackObject<ListCreationInput>,
 ) -> Result<PackObject<SuccessResponse<Vec<CreationOutput>>>, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
@@ -296,13 +463,90 @@ pub async fn list(
 
     let gid = input.gid.unwrap();
     let page_size = input.page_size.unwrap_or(10);
+    let include_deleted = input.include_deleted.unwrap_or(false);
+    let order = input.order.unwrap_or_default();
     ctx.set_kvs(vec![
         ("action", "list_creation".into()),
         ("gid", gid.to_string().into()),
+        ("include_deleted", include_deleted.into()),
+        ("order", serde_json::to_value(order).unwrap_or_default()),
     ])
     .await;
 
     let fields = input.fields.unwrap_or_default();
+    let sort = input.sort.unwrap_or_else(|| "created".to_string());
+    match sort.as_str() {
+        "created" => {}
+        "updated" => {
+            if include_deleted || input.label.is_some() {
+                return Err(HTTPError::new(
+                    400,
+                    "sort by updated does not support include_deleted or label".to_string(),
+                ));
+            }
+            desc_only(input.order)?;
+
+            ctx.set("sort", "updated".into()).await;
+            let (res, next_page_token) = db::Creation::list_by_gid_updated(
+                &app.scylla,
+                gid,
+                fields,
+                page_size,
+                token_to_updated(&input.page_token),
+                input.status,
+            )
+            .await?;
+
+            return Ok(to.with(SuccessResponse {
+                total_size: None,
+                next_page_token: to.with_option(token_from_updated(next_page_token)),
+                result: res
+                    .iter()
+                    .map(|r| CreationOutput::from(r.to_owned(), &to))
+                    .collect(),
+            }));
+        }
+        _ => {
+            return Err(HTTPError::new(
+                400,
+                format!("Invalid sort: {}, expected created or updated", sort),
+            ));
+        }
+    }
+
+    if let Some(label) = input.label {
+        if include_deleted {
+            return Err(HTTPError::new(
+                400,
+                "label filter does not support include_deleted".to_string(),
+            ));
+        }
+        desc_only(input.order)?;
+
+        // labels are saved normalized, see normalize_labels
+        let label = db::normalize_tags(vec![label]).pop().unwrap_or_default();
+        ctx.set("label", label.clone().into()).await;
+        let (res, next_page_token) = db::Creation::list_by_gid_label(
+            &app.scylla,
+            gid,
+            &label,
+            fields,
+            page_size,
+            token_to_xid(&input.page_token),
+            input.status,
+        )
+        .await?;
+
+        return Ok(to.with(SuccessResponse {
+            total_size: None,
+            next_page_token: to.with_option(token_from_xid(next_page_token)),
+            result: res
+                .iter()
+                .map(|r| CreationOutput::from(r.to_owned(), &to))
+                .collect(),
+        }));
+    }
+
     let res = db::Creation::list_by_gid(
         &app.scylla,
         gid,
@@ -310,6 +554,8 @@ pub async fn list(
         page_size,
         token_to_xid(&input.page_token),
         input.status,
+        include_deleted,
+        order,
     )
     .await?;
 
@@ -327,7 +573,7 @@ pub async fn list(
     }))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdateCreationInput {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -338,6 +584,8 @@ pub struct UpdateCreationInput {
     pub title: Option<String>,
     #[validate(url)]
     pub cover: Option<String>,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
     #[validate(length(min = 0, max = 5))]
     pub keywords: Option<Vec<String>>,
     #[validate(length(min = 0, max = 5))]
@@ -346,40 +594,36 @@ pub struct UpdateCreationInput {
     pub authors: Option<Vec<String>>,
     #[validate(length(min = 0, max = 2048))]
     pub summary: Option<String>,
-    #[validate(url)]
+    #[validate(length(min = 1, max = 256))]
     pub license: Option<String>,
+    // updated along with the fields above under the same updated_at
+    #[validate(custom = "validate_cbor_content")]
+    pub content: Option<PackObject<Vec<u8>>>,
 }
 
 impl UpdateCreationInput {
-    fn into(self) -> anyhow::Result<ColumnsMap> {
+    // returns the columns to update and the new content if any.
+    fn into(self, content: &conf::Content) -> anyhow::Result<(ColumnsMap, Option<Vec<u8>>)> {
         let mut cols = ColumnsMap::new();
-        if let Some(title) = self.title {
-            cols.set_as("title", &title);
-        }
-        if let Some(cover) = self.cover {
-            cols.set_as("cover", &cover);
-        }
-        if let Some(keywords) = self.keywords {
-            cols.set_as("keywords", &keywords);
-        }
-        if let Some(labels) = self.labels {
-            cols.set_as("labels", &labels);
-        }
-        if let Some(authors) = self.authors {
-            cols.set_as("authors", &authors);
-        }
-        if let Some(summary) = self.summary {
-            cols.set_as("summary", &summary);
-        }
-        if let Some(license) = self.license {
-            cols.set_as("license", &license);
-        }
-
-        if cols.is_empty() && self.price.is_none() {
+        cols.set_if_some("title", self.title.as_ref());
+        set_cover_columns(&mut cols, self.cover.as_ref(), self.cover_blurhash.as_ref());
+        let keywords = self.keywords.map(normalize_keywords).transpose()?;
+        let labels = self.labels.map(normalize_labels).transpose()?;
+        cols.set_if_some("keywords", keywords.as_ref());
+        cols.set_if_some("labels", labels.as_ref());
+        cols.set_if_some("authors", self.authors.map(db::normalize_names).as_ref());
+        cols.set_if_some("summary", self.summary.as_ref());
+        let license = self
+            .license
+            .map(|v| normalize_license(content, v))
+            .transpose()?;
+        cols.set_if_some("license", license.as_ref());
+
+        if cols.is_empty() && self.price.is_none() && self.content.is_none() {
             return Err(HTTPError::new(400, "No fields to update".to_string()).into());
         }
 
-        Ok(cols)
+        Ok((cols, self.content.map(|v| v.unwrap())))
     }
 }
 
@@ -404,14 +648,19 @@ pub async fn update(
 
     let mut idoc = db::CreationIndex::with_pk(id);
     idoc.get_one(&app.scylla).await?;
-    if idoc.gid != gid {
-        return Err(HTTPError::new(
-            403,
-            format!("Creation {} is not belong to group {}", id, gid),
-        ));
-    }
+    let is_owner = check_creation_access(&app, &ctx, &idoc, gid, db::COLLABORATOR_EDITOR).await?;
+    let gid = idoc.gid;
 
     if let Some(price) = input.price {
+        if !is_owner {
+            return Err(HTTPError::new(
+                403,
+                format!(
+                    "Creation {} price can only be updated by the owner group",
+                    id
+                ),
+            ));
+        }
         if idoc.price < 0 {
             return Err(HTTPError::new(
                 400,
@@ -424,20 +673,34 @@ pub async fn update(
     }
 
     let updated_at = input.updated_at;
-    let cols = input.into()?;
+    let (cols, content) = input.into(&app.content)?;
     let mut doc = db::Creation::with_pk(gid, id);
-    if !cols.is_empty() {
-        let update_meili = cols.has("title") || cols.has("summary") || cols.has("keywords");
-        let ok = doc.update(&app.scylla, cols, updated_at).await?;
+    if let Some(content) = &content {
+        doc.get_one(&app.scylla, vec!["content".to_string()])
+            .await?;
+        check_quota_update(&app, gid, doc.content, content.len(), true).await?;
+    }
+    if !cols.is_empty() || content.is_some() {
+        let update_meili =
+            content.is_some() || cols.has("title") || cols.has("summary") || cols.has("keywords");
+        let ok = match content {
+            Some(content) => {
+                doc.update_full(&app.scylla, cols, content, updated_at, ctx.user)
+                    .await?
+            }
+            None => doc.update(&app.scylla, cols, updated_at, ctx.user).await?,
+        };
         doc._fields = vec!["updated_at".to_string()]; // only return `updated_at` field.
         ctx.set("updated", ok.into()).await;
 
         if update_meili {
             let meili_start = ctx.start.elapsed().as_millis() as u64;
-            if let Err(err) = app
-                .meili
-                .add_or_update(meili::Space::Group(doc.gid), vec![doc.to_meili()])
-                .await
+            if let Err(err) = meili_write(
+                &app,
+                meili::Space::Group(doc.gid),
+                MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+            )
+            .await
             {
                 log::error!(target: "meilisearch",
                     action = "add_or_update",
@@ -456,7 +719,114 @@ pub async fn update(
     Ok(to.with(SuccessResponse::new(CreationOutput::from(doc, &to))))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct UpdateCreationPriceInput {
+    pub id: PackObject<xid::Id>,
+    pub gid: PackObject<xid::Id>,
+    pub updated_at: i64,
+    #[validate(range(min = -1, max = 100000))]
+    pub price: i64,
+    pub propagate: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CreationPriceOutput {
+    pub id: PackObject<xid::Id>,
+    pub gid: PackObject<xid::Id>,
+    pub price: i64,
+    // parent collections whose creation_price differs from the new price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collections: Option<Vec<PackObject<xid::Id>>>,
+}
+
+pub async fn update_price(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<UpdateCreationPriceInput>,
+) -> Result<PackObject<SuccessResponse<CreationPriceOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let id = *input.id.to_owned();
+    let gid = *input.gid.to_owned();
+
+    ctx.set_kvs(vec![
+        ("action", "update_creation_price".into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
+        ("price", input.price.into()),
+    ])
+    .await;
+
+    let mut idoc = db::CreationIndex::with_pk(id);
+    idoc.get_one(&app.scylla).await?;
+    if idoc.gid != gid {
+        return Err(HTTPError::new(
+            403,
+            format!("Creation {} is not belong to group {}", id, gid),
+        ));
+    }
+    if idoc.price < 0 {
+        return Err(HTTPError::new(
+            400,
+            format!("Creation {} is free forever", id),
+        ));
+    }
+
+    let mut doc = db::Creation::with_pk(gid, id);
+    doc.get_one(&app.scylla, vec!["updated_at".to_string()])
+        .await?;
+    if doc.updated_at != input.updated_at {
+        return Err(HTTPError::new(
+            409,
+            format!(
+                "Creation updated_at conflict, expected {}, got {}",
+                doc.updated_at, input.updated_at
+            ),
+        ));
+    }
+
+    let mut ok = false;
+    if idoc.price != input.price {
+        idoc.price = input.price;
+        ok = idoc.update_field(&app.scylla, "price").await?;
+    }
+    ctx.set("updated", ok.into()).await;
+
+    let mut output = CreationPriceOutput {
+        id: to.with(id),
+        gid: to.with(gid),
+        price: idoc.price,
+        collections: None,
+    };
+
+    if input.propagate.unwrap_or(false) {
+        let mut collections: Vec<PackObject<xid::Id>> = Vec::new();
+        let parents = db::CollectionChildren::list_by_child(&app.scylla, id).await?;
+        for parent in parents {
+            let mut collection = db::Collection::with_pk(parent.id);
+            if collection
+                .get_one(
+                    &app.scylla,
+                    vec!["gid".to_string(), "creation_price".to_string()],
+                    None,
+                )
+                .await
+                .is_ok()
+                && collection.gid == gid
+                && collection.creation_price != idoc.price
+            {
+                collections.push(to.with(collection.id));
+            }
+        }
+        output.collections = Some(collections);
+    }
+
+    Ok(to.with(SuccessResponse::new(output)))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdateCreationContentInput {
     pub gid: PackObject<xid::Id>,
     pub id: PackObject<xid::Id>,
@@ -477,22 +847,75 @@ pub async fn update_content(
 
     let id = input.id.unwrap();
     let gid = input.gid.unwrap();
-    let language = input.language.unwrap();
+    let mut language = input.language.unwrap();
     let content = input.content.unwrap();
 
-    let mut doc = db::Creation::with_pk(gid, id);
     ctx.set_kvs(vec![
         ("action", "update_content".into()),
-        ("gid", doc.gid.to_string().into()),
-        ("id", doc.id.to_string().into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
     ])
     .await;
 
+    let mut idoc = db::CreationIndex::with_pk(id);
+    idoc.get_one(&app.scylla).await?;
+    check_creation_access(&app, &ctx, &idoc, gid, db::COLLABORATOR_EDITOR).await?;
+    let gid = idoc.gid;
+    let mut doc = db::Creation::with_pk(gid, id);
+    doc.get_one(&app.scylla, vec!["content".to_string()])
+        .await?;
+    check_quota_update(&app, gid, doc.content, content.len(), true).await?;
+
+    // Und keeps the current language, so only detect it if the creation has none.
+    if language == Language::Und && app.content.detect_language {
+        doc.get_one(&app.scylla, vec!["language".to_string()])
+            .await?;
+        let detected = detect_und_language(&app.content, doc.language, &content);
+        if let Some(lang) = detected {
+            language = lang;
+        }
+        ctx.set("language_detected", detected.is_some().into())
+            .await;
+    }
+
     let ok = doc
         .update_content(&app.scylla, language, content, input.updated_at)
         .await?;
 
     ctx.set("updated", ok.into()).await;
+
+    // the language may be changed, so indexes the whole document with the new word count.
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "genre".to_string(),
+            "title".to_string(),
+            "keywords".to_string(),
+            "authors".to_string(),
+            "summary".to_string(),
+        ],
+    )
+    .await?;
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+    )
+    .await
+    {
+        log::error!(target: "meilisearch",
+            action = "add_or_update",
+            space = "group",
+            rid = ctx.rid,
+            gid = doc.gid.to_string(),
+            cid = doc.id.to_string(),
+            kind = 0i8,
+            elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+            "{}", err.to_string(),
+        );
+    }
+
     doc._fields = vec![
         "updated_at".to_string(),
         "language".to_string(),
@@ -501,50 +924,138 @@ pub async fn update_content(
     Ok(to.with(SuccessResponse::new(CreationOutput::from(doc, &to))))
 }
 
-pub async fn update_status(
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ContentHistoryOutput {
+    pub id: PackObject<xid::Id>,
+    pub status: i8,
+    pub version: i16,
+    pub language: PackObject<Language>,
+    pub updated_at: i64,
+    pub length: i32,
+}
+
+pub async fn list_content_history(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
-    to: PackObject<UpdateStatusInput>,
+    to: PackObject<()>,
+    input: Query<QueryGidId>,
+) -> Result<PackObject<SuccessResponse<Vec<ContentHistoryOutput>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let id = *input.id.to_owned();
+
+    ctx.set_kvs(vec![
+        ("action", "list_creation_content_history".into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
+    ])
+    .await;
+
+    let mut doc = db::Creation::with_pk(gid, id);
+    let res = doc.list_content_history(&app.scylla).await?;
+    Ok(to.with(SuccessResponse::new(
+        res.into_iter()
+            .map(|v| ContentHistoryOutput {
+                id: to.with(v.id),
+                status: v.status,
+                version: v.version,
+                language: to.with(v.language),
+                updated_at: v.updated_at,
+                length: v.length,
+            })
+            .collect(),
+    )))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct RollbackCreationContentInput {
+    pub gid: PackObject<xid::Id>,
+    pub id: PackObject<xid::Id>,
+    pub content: PackObject<xid::Id>,
+    pub updated_at: i64,
+}
+
+pub async fn rollback_content(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<RollbackCreationContentInput>,
 ) -> Result<PackObject<SuccessResponse<CreationOutput>>, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
     valid_user(ctx.user)?;
 
-    let gid = input
-        .gid
-        .ok_or_else(|| HTTPError::new(400, "Missing required field `gid`".to_string()))?;
-    let gid = *gid.to_owned();
-    let id = *input.id.to_owned();
+    let gid = input.gid.unwrap();
+    let id = input.id.unwrap();
+    let content = input.content.unwrap();
 
     let mut doc = db::Creation::with_pk(gid, id);
     ctx.set_kvs(vec![
-        ("action", "update_status".into()),
+        ("action", "rollback_content".into()),
         ("gid", doc.gid.to_string().into()),
         ("id", doc.id.to_string().into()),
+        ("content", content.to_string().into()),
     ])
     .await;
 
     let ok = doc
-        .update_status(&app.scylla, input.status, input.updated_at)
+        .rollback_content(&app.scylla, content, input.updated_at)
         .await?;
 
     ctx.set("updated", ok.into()).await;
-    doc._fields = vec!["updated_at".to_string(), "status".to_string()];
+    doc._fields = vec![
+        "updated_at".to_string(),
+        "language".to_string(),
+        "content".to_string(),
+    ];
     Ok(to.with(SuccessResponse::new(CreationOutput::from(doc, &to))))
 }
 
-pub async fn delete(
+pub async fn update_status(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
-    to: PackObject<()>,
-    input: Query<QueryGidId>,
-) -> Result<PackObject<SuccessResponse<bool>>, HTTPError> {
+    to: PackObject<UpdateStatusInput>,
+) -> Result<PackObject<SuccessResponse<CreationOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
     input.validate()?;
     valid_user(ctx.user)?;
 
-    let gid = *input.gid.to_owned();
-    let id = *input.id.to_owned();
-
+    let gid = input
+        .gid
+        .ok_or_else(|| HTTPError::new(400, "Missing required field `gid`".to_string()))?;
+    let gid = *gid.to_owned();
+    let id = *input.id.to_owned();
+
+    let mut doc = db::Creation::with_pk(gid, id);
+    ctx.set_kvs(vec![
+        ("action", "update_status".into()),
+        ("gid", doc.gid.to_string().into()),
+        ("id", doc.id.to_string().into()),
+    ])
+    .await;
+
+    let ok = doc
+        .update_status(&app.scylla, input.status, input.updated_at)
+        .await?;
+
+    ctx.set("updated", ok.into()).await;
+    doc._fields = vec!["updated_at".to_string(), "status".to_string()];
+    Ok(to.with(SuccessResponse::new(CreationOutput::from(doc, &to))))
+}
+
+pub async fn delete(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGidId>,
+) -> Result<PackObject<SuccessResponse<bool>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let id = *input.id.to_owned();
+
     ctx.set_kvs(vec![
         ("action", "delete_creation".into()),
         ("gid", gid.to_string().into()),
@@ -553,13 +1064,15 @@ pub async fn delete(
     .await;
 
     let mut doc = db::Creation::with_pk(gid, id);
-    let res = doc.delete(&app.scylla).await?;
+    let res = doc.delete(&app.scylla, false).await?.would_delete;
 
     let meili_start = ctx.start.elapsed().as_millis() as u64;
-    if let Err(err) = app
-        .meili
-        .delete(meili::Space::Group(doc.gid), vec![doc.to_meili().id])
-        .await
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::Delete(vec![doc.to_meili().id]),
+    )
+    .await
     {
         log::error!(target: "meilisearch",
             action = "delete",
@@ -680,6 +1193,461 @@ pub async fn update_subscription(
     })))
 }
 
+// grant_subscription creates or extends a user's subscription to a published creation
+// without a payment, see collection::grant_subscription.
+pub async fn grant_subscription(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<GrantSubscriptionInput>,
+) -> Result<PackObject<SuccessResponse<SubscriptionOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+    valid_grant_expire_at(input.expire_at, ctx.unix_ms as i64)?;
+
+    let uid = *input.uid.to_owned();
+    let cid = *input.cid.to_owned();
+
+    ctx.set_kvs(vec![
+        ("action", "grant_creation_subscription".into()),
+        ("uid", uid.to_string().into()),
+        ("cid", cid.to_string().into()),
+        ("expire_at", input.expire_at.into()),
+    ])
+    .await;
+
+    let mut icreation = db::CreationIndex::with_pk(cid);
+    icreation.get_one(&app.scylla).await?;
+    check_grant_actor(
+        ctx.user,
+        input.gid.as_ref().map(|v| *v.to_owned()),
+        icreation.gid,
+    )?;
+    // ensure published
+    if db::PublicationIndex::get_implicit_published(&app.scylla, cid, icreation.gid, Language::Und)
+        .await
+        .is_err()
+    {
+        return Err(HTTPError::new(400, "Creation is not published".to_string()));
+    }
+
+    let mut doc = db::CreationSubscription::with_pk(uid, cid);
+    let old = match doc.get_one(&app.scylla, vec![]).await {
+        Ok(_) => {
+            if doc.expire_at >= input.expire_at {
+                return Err(HTTPError::new(
+                    400,
+                    "Subscription expire_at can only be extended".to_string(),
+                ));
+            }
+            let old = (doc.txn, doc.expire_at);
+            doc.update(&app.scylla, db::ZERO_ID, input.expire_at, doc.updated_at)
+                .await?;
+            ctx.set("updated", true.into()).await;
+            Some(old)
+        }
+        Err(_) => {
+            doc.txn = db::ZERO_ID;
+            doc.expire_at = input.expire_at;
+            doc.save(&app.scylla).await?;
+            ctx.set("created", true.into()).await;
+            None
+        }
+    };
+
+    let log = grant_changelog(&ctx, icreation.gid, 0, &input, old);
+    let _ = db::Changelog::record(&app.scylla, vec![log]).await;
+
+    Ok(to.with(SuccessResponse::new(SubscriptionOutput {
+        uid: to.with(doc.uid),
+        cid: to.with(doc.cid),
+        gid: to.with(icreation.gid),
+        txn: to.with(doc.txn),
+        updated_at: doc.updated_at,
+        expire_at: doc.expire_at,
+    })))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CollaboratorInput {
+    pub gid: PackObject<xid::Id>,
+    pub id: PackObject<xid::Id>,
+    pub uid: PackObject<xid::Id>,
+    #[validate(range(min = 0, max = 2))]
+    pub role: i8, // 0: reader, 1: reviewer, 2: editor
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryCollaborator {
+    pub gid: PackObject<xid::Id>,
+    pub id: PackObject<xid::Id>,
+    pub uid: PackObject<xid::Id>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CollaboratorOutput {
+    pub cid: PackObject<xid::Id>,
+    pub uid: PackObject<xid::Id>,
+    pub role: i8,
+    pub added_at: i64,
+}
+
+impl CollaboratorOutput {
+    fn from<T>(val: db::CreationCollaborator, to: &PackObject<T>) -> Self {
+        Self {
+            cid: to.with(val.cid),
+            uid: to.with(val.uid),
+            role: val.role,
+            added_at: val.added_at,
+        }
+    }
+}
+
+async fn owner_creation_index(
+    app: &AppState,
+    gid: xid::Id,
+    id: xid::Id,
+) -> Result<db::CreationIndex, HTTPError> {
+    let mut idoc = db::CreationIndex::with_pk(id);
+    idoc.get_one(&app.scylla).await?;
+    if idoc.gid != gid {
+        return Err(HTTPError::new(
+            403,
+            format!("Creation {} is not belong to group {}", id, gid),
+        ));
+    }
+    Ok(idoc)
+}
+
+pub async fn add_collaborator(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<CollaboratorInput>,
+) -> Result<PackObject<SuccessResponse<CollaboratorOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let id = *input.id.to_owned();
+    let uid = *input.uid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "add_creation_collaborator".into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
+        ("uid", uid.to_string().into()),
+        ("role", input.role.into()),
+    ])
+    .await;
+
+    owner_creation_index(&app, gid, id).await?;
+    let mut doc = db::CreationCollaborator::with_pk(id, uid);
+    doc.role = input.role;
+    let ok = doc.save(&app.scylla, gid).await?;
+    ctx.set("updated", ok.into()).await;
+    Ok(to.with(SuccessResponse::new(CollaboratorOutput::from(doc, &to))))
+}
+
+pub async fn remove_collaborator(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryCollaborator>,
+) -> Result<PackObject<SuccessResponse<bool>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let id = *input.id.to_owned();
+    let uid = *input.uid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "remove_creation_collaborator".into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
+        ("uid", uid.to_string().into()),
+    ])
+    .await;
+
+    owner_creation_index(&app, gid, id).await?;
+    let mut doc = db::CreationCollaborator::with_pk(id, uid);
+    let res = doc.delete(&app.scylla).await?;
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
+pub async fn list_collaborators(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGidId>,
+) -> Result<PackObject<SuccessResponse<Vec<CollaboratorOutput>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let id = *input.id.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "list_creation_collaborators".into()),
+        ("gid", gid.to_string().into()),
+        ("id", id.to_string().into()),
+    ])
+    .await;
+
+    let mut idoc = db::CreationIndex::with_pk(id);
+    idoc.get_one(&app.scylla).await?;
+    check_creation_access(&app, &ctx, &idoc, gid, db::COLLABORATOR_READER).await?;
+    let res = db::CreationCollaborator::list_by_cid(&app.scylla, id).await?;
+    Ok(to.with(SuccessResponse::new(
+        res.into_iter()
+            .map(|v| CollaboratorOutput::from(v, &to))
+            .collect(),
+    )))
+}
+
+// lists the creations of other groups that the user collaborates on.
+pub async fn list_by_collaborator(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<Pagination>,
+) -> Result<PackObject<SuccessResponse<Vec<CreationOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    desc_only(input.order)?;
+    let page_size = input.page_size.unwrap_or(10);
+    ctx.set_kvs(vec![("action", "list_creation_by_collaborator".into())])
+        .await;
+
+    let (res, next_page_token) = db::Creation::list_by_collaborator(
+        &app.scylla,
+        ctx.user,
+        input.fields.unwrap_or_default(),
+        page_size,
+        token_to_xid(&input.page_token),
+    )
+    .await?;
+
+    Ok(to.with(SuccessResponse {
+        total_size: None,
+        next_page_token: to.with_option(token_from_xid(next_page_token)),
+        result: res
+            .iter()
+            .map(|r| CreationOutput::from(r.to_owned(), &to))
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryRfpStats {
+    pub gid: PackObject<xid::Id>,
+    pub cid: PackObject<xid::Id>, // creation or collection id
+    #[validate(range(min = 1, max = 90))] // db::MAX_RFP_STATS_DAYS
+    pub days: Option<i32>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct RfpStatsOutput {
+    pub day: i32, // days since unix epoch
+    pub impressions: i64,
+}
+
+// fills the days without impression with 0, ordered by day asc and ends with `today`.
+fn rfp_series(rows: Vec<db::RfpImpression>, today: i32, days: i32) -> Vec<RfpStatsOutput> {
+    let mut res: Vec<RfpStatsOutput> = (today - days + 1..=today)
+        .map(|day| RfpStatsOutput {
+            day,
+            impressions: 0,
+        })
+        .collect();
+    for row in rows {
+        let i = row.day - (today - days + 1);
+        if i >= 0 && i < days {
+            res[i as usize].impressions += row.impressions;
+        }
+    }
+    res
+}
+
+// the daily paywall impressions of a creation or collection, for its owner group only.
+pub async fn rfp_stats(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryRfpStats>,
+) -> Result<PackObject<SuccessResponse<Vec<RfpStatsOutput>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let cid = *input.cid.to_owned();
+    let days = input.days.unwrap_or(30);
+    ctx.set_kvs(vec![
+        ("action", "get_rfp_stats".into()),
+        ("gid", gid.to_string().into()),
+        ("cid", cid.to_string().into()),
+        ("days", days.into()),
+    ])
+    .await;
+
+    let mut index = db::CreationIndex::with_pk(cid);
+    let owner = match index.get_one(&app.scylla).await {
+        Ok(_) => index.gid,
+        Err(_) => {
+            let mut doc = db::Collection::with_pk(cid);
+            doc.get_one(&app.scylla, vec!["gid".to_string()], None)
+                .await?;
+            doc.gid
+        }
+    };
+    if owner != gid {
+        return Err(HTTPError::new(403, "Creation gid not match".to_string()));
+    }
+
+    let today = db::unix_ms_day(ctx.unix_ms as i64);
+    let rows = db::RfpImpression::list(&app.scylla, cid, today - days + 1).await?;
+    Ok(to.with(SuccessResponse::new(rfp_series(rows, today, days))))
+}
+
+// the daily creation quota of the user in the group, <= 0 means unlimited. The system user
+// and the trusted groups bypass it.
+fn creation_quota_max(cfg: &conf::Quota, user: xid::Id, gid: Option<xid::Id>) -> i64 {
+    if user == xid::Id::from_str(db::USER_JARVIS).unwrap()
+        || gid.map_or(false, |gid| cfg.trusted_gids.contains(&gid.to_string()))
+    {
+        return 0;
+    }
+    cfg.max_creations_per_day
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryCreationQuota {
+    pub gid: Option<PackObject<xid::Id>>, // the group to create in, it may be trusted
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct CreationQuotaOutput {
+    pub day: i32, // days since unix epoch, in UTC
+    pub creations: i64,
+    pub max_creations: i64, // <= 0: unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<i64>, // absent if unlimited
+    pub reset_after_ms: i64,
+}
+
+// the user's creations today and the remaining allowance of the daily creation quota.
+pub async fn get_quota(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryCreationQuota>,
+) -> Result<PackObject<SuccessResponse<CreationQuotaOutput>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = input.gid.as_ref().map(|v| *v.to_owned());
+    ctx.set_kvs(vec![("action", "get_creation_quota".into())])
+        .await;
+
+    let day = db::unix_ms_day(ctx.unix_ms as i64);
+    let max_creations = creation_quota_max(&app.quota, ctx.user, gid);
+    let doc = db::UserCreationQuota::load(&app.scylla, ctx.user, day).await?;
+    Ok(to.with(SuccessResponse::new(CreationQuotaOutput {
+        day,
+        creations: doc.creations,
+        max_creations: max_creations.max(0),
+        remaining: doc.remaining(max_creations),
+        reset_after_ms: doc.retry_after_ms(ctx.unix_ms as i64),
+    })))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct BatchCheckInput {
+    #[validate(length(min = 1, max = 500))]
+    pub cids: Vec<PackObject<xid::Id>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct BatchCheckOutput {
+    pub cid: PackObject<xid::Id>,
+    pub exists: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<PackObject<xid::Id>>,
+    pub rating: i8,
+    pub price: i64,
+    pub published: bool,
+}
+
+// builds the check results in the input order, duplicated cids are returned once.
+fn batch_check_output<T>(
+    cids: &[xid::Id],
+    indexes: &HashMap<xid::Id, db::CreationIndex>,
+    published: &HashSet<xid::Id>,
+    to: &PackObject<T>,
+) -> Vec<BatchCheckOutput> {
+    let mut seen: HashSet<xid::Id> = HashSet::with_capacity(cids.len());
+    cids.iter()
+        .filter(|cid| seen.insert(**cid))
+        .map(|cid| match indexes.get(cid) {
+            Some(index) => BatchCheckOutput {
+                cid: to.with(*cid),
+                exists: true,
+                gid: Some(to.with(index.gid)),
+                rating: index.rating,
+                price: index.price,
+                published: published.contains(cid),
+            },
+            None => BatchCheckOutput {
+                cid: to.with(*cid),
+                ..Default::default()
+            },
+        })
+        .collect()
+}
+
+// checks the existence, owner, rating, price and publication state of creations
+// without loading them, for the gateway and other services.
+pub async fn batch_check(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<BatchCheckInput>,
+) -> Result<PackObject<SuccessResponse<Vec<BatchCheckOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let cids: Vec<xid::Id> = input.cids.iter().map(|v| *v.to_owned()).collect();
+    let mut ids = cids.clone();
+    ids.sort_by(|a, b| a.0.cmp(&b.0));
+    ids.dedup();
+    ctx.set_kvs(vec![
+        ("action", "batch_check_creations".into()),
+        ("cids", ids.len().into()),
+    ])
+    .await;
+
+    let indexes: HashMap<xid::Id, db::CreationIndex> =
+        db::CreationIndex::batch_get(&app.scylla, ids, i8::MAX)
+            .await?
+            .into_iter()
+            .map(|v| (v.id, v))
+            .collect();
+    let existing: Vec<xid::Id> = indexes.keys().cloned().collect();
+    let published = if existing.is_empty() {
+        HashSet::new()
+    } else {
+        db::PublicationIndex::published_cids(&app.scylla, &existing).await?
+    };
+    ctx.set_kvs(vec![
+        ("exists", indexes.len().into()),
+        ("published", published.len().into()),
+    ])
+    .await;
+
+    Ok(to.with(SuccessResponse::new(batch_check_output(
+        &cids, &indexes, &published, &to,
+    ))))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -688,6 +1656,25 @@ mod tests {
     use hex_literal::hex;
     use std::str::FromStr;
 
+    #[test]
+    fn creation_quota_max_works() {
+        let gid = xid::new();
+        let mut cfg = conf::Quota::default();
+        assert_eq!(cfg.max_creations_per_day, 1000);
+        assert_eq!(creation_quota_max(&cfg, xid::new(), Some(gid)), 1000);
+        assert_eq!(creation_quota_max(&cfg, xid::new(), None), 1000);
+        let jarvis = xid::Id::from_str(db::USER_JARVIS).unwrap();
+        assert_eq!(creation_quota_max(&cfg, jarvis, Some(gid)), 0);
+
+        cfg.trusted_gids = vec![gid.to_string()];
+        assert_eq!(creation_quota_max(&cfg, xid::new(), Some(gid)), 0);
+        assert_eq!(creation_quota_max(&cfg, xid::new(), Some(xid::new())), 1000);
+        assert_eq!(creation_quota_max(&cfg, xid::new(), None), 1000);
+
+        cfg.max_creations_per_day = 0;
+        assert_eq!(creation_quota_max(&cfg, xid::new(), None), 0);
+    }
+
     #[test]
     fn create_creation_input_works() {
         let data = hex!("a4636769644c00000000000000004d5bfcb8657469746c656e6669727374206372656174696f6e67636f6e74656e745859a2647479706563646f6367636f6e74656e7481a3647479706569706172616772617068656174747273a16269646631323334353667636f6e74656e7481a264746578746b48656c6c6f20776f726c6464747970656474657874686c616e677561676563656e67");
@@ -700,4 +1687,88 @@ mod tests {
         );
         assert_eq!(obj.language.unwrap(), Language::Eng);
     }
+
+    #[test]
+    fn rfp_series_works() {
+        assert!(rfp_series(vec![], 100, 0).is_empty());
+
+        let res = rfp_series(
+            vec![
+                db::RfpImpression {
+                    day: 101,
+                    impressions: 7,
+                },
+                db::RfpImpression {
+                    day: 100,
+                    impressions: 3,
+                },
+                db::RfpImpression {
+                    day: 98,
+                    impressions: 1,
+                },
+                db::RfpImpression {
+                    day: 97,
+                    impressions: 9,
+                },
+            ],
+            100,
+            3,
+        );
+        assert_eq!(
+            res,
+            vec![
+                RfpStatsOutput {
+                    day: 98,
+                    impressions: 1
+                },
+                RfpStatsOutput {
+                    day: 99,
+                    impressions: 0
+                },
+                RfpStatsOutput {
+                    day: 100,
+                    impressions: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_check_output_works() {
+        let to = PackObject::Json(());
+        let gid = xid::new();
+        let (c1, c2, c3) = (xid::new(), xid::new(), xid::new());
+        let mut indexes: HashMap<xid::Id, db::CreationIndex> = HashMap::new();
+        for (cid, rating) in [(c1, 1i8), (c2, 3i8)] {
+            indexes.insert(
+                cid,
+                db::CreationIndex {
+                    id: cid,
+                    gid,
+                    rating,
+                    price: 10,
+                    ..Default::default()
+                },
+            );
+        }
+        let published: HashSet<xid::Id> = [c2].into_iter().collect();
+
+        let res = batch_check_output(&[c3, c1, c2, c1], &indexes, &published, &to);
+        assert_eq!(res.len(), 3);
+        assert_eq!(
+            res[0],
+            BatchCheckOutput {
+                cid: to.with(c3),
+                ..Default::default()
+            }
+        );
+        assert_eq!(*res[1].cid, c1);
+        assert!(res[1].exists);
+        assert_eq!(res[1].gid, Some(to.with(gid)));
+        assert_eq!((res[1].rating, res[1].price), (1, 10));
+        assert!(!res[1].published);
+        assert_eq!(*res[2].cid, c2);
+        assert_eq!(res[2].rating, 3);
+        assert!(res[2].published);
+    }
 }
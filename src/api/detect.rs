@@ -0,0 +1,150 @@
+use axum::{extract::State, Extension};
+use isolang::Language;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use validator::Validate;
+
+use axum_web::context::ReqContext;
+use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
+use axum_web::object::{cbor_from_slice, PackObject};
+
+use super::{content_text, validate_cbor_content, AppState, DocumentNode};
+use crate::{conf, db};
+
+// the max bytes of plain text used for detection, it is enough for a reliable result.
+const MAX_DETECT_TEXT_LEN: usize = 4096;
+
+// detects the language of the CBOR content, returns the language and the confidence.
+pub fn detect_content_language(content: &[u8]) -> Option<(Language, f64)> {
+    let doc: DocumentNode = cbor_from_slice(content).ok()?;
+    let text = content_text(&doc, MAX_DETECT_TEXT_LEN);
+    detect_text_language(&text)
+}
+
+#[cfg(feature = "lang-detect")]
+pub fn detect_text_language(text: &str) -> Option<(Language, f64)> {
+    let info = whatlang::detect(text)?;
+    let lang = Language::from_639_3(info.lang().code())?;
+    // individual languages are stored as their macrolanguage, e.g. `cmn` as `zho`
+    let lang = Language::from_639_3(db::resolve_language(lang)).unwrap_or(lang);
+    Some((lang, info.confidence()))
+}
+
+#[cfg(not(feature = "lang-detect"))]
+pub fn detect_text_language(_text: &str) -> Option<(Language, f64)> {
+    None
+}
+
+// returns the detected language if `language` is Und and the detection is enabled,
+// a result below the configured confidence is ignored.
+pub fn detect_und_language(
+    cfg: &conf::Content,
+    language: Language,
+    content: &[u8],
+) -> Option<Language> {
+    if language != Language::Und || !cfg.detect_language {
+        return None;
+    }
+
+    match detect_content_language(content) {
+        Some((lang, confidence)) if confidence >= cfg.detect_confidence => Some(lang),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct DetectLanguageInput {
+    #[validate(custom = "validate_cbor_content")]
+    pub content: PackObject<Vec<u8>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct DetectLanguageOutput {
+    pub language: PackObject<Language>, // `und` if nothing detected
+    pub confidence: f64,
+    pub reliable: bool, // whether the confidence reaches the configured threshold
+}
+
+// suggests the language of the content, it does not change any creation.
+pub async fn detect_language(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<DetectLanguageInput>,
+) -> Result<PackObject<SuccessResponse<DetectLanguageOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let (language, confidence) =
+        detect_content_language(input.content.unwrap_ref()).unwrap_or((Language::Und, 0.0));
+    ctx.set_kvs(vec![
+        ("action", "detect_language".into()),
+        ("language", language.to_639_3().into()),
+        ("confidence", confidence.into()),
+    ])
+    .await;
+
+    Ok(to.with(SuccessResponse::new(DetectLanguageOutput {
+        language: to.with(language),
+        confidence,
+        reliable: language != Language::Und && confidence >= app.content.detect_confidence,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum_web::object::cbor_to_vec;
+
+    fn doc(text: &str) -> Vec<u8> {
+        let doc: DocumentNode = serde_json::from_value(serde_json::json!({
+            "type": "doc",
+            "content": [{
+                "type": "paragraph",
+                "content": [{"type": "text", "text": text}],
+            }],
+        }))
+        .unwrap();
+        cbor_to_vec(&doc).unwrap()
+    }
+
+    #[test]
+    fn detect_und_language_works() {
+        let en = doc("The quick brown fox jumps over the lazy dog, and then it runs back into the forest to find its family.");
+        let cfg = conf::Content::default();
+
+        // only for Und
+        assert_eq!(detect_und_language(&cfg, Language::Zho, &en), None);
+        // skippable
+        let disabled = conf::Content {
+            detect_language: false,
+            ..Default::default()
+        };
+        assert_eq!(detect_und_language(&disabled, Language::Und, &en), None);
+        // empty content
+        assert_eq!(detect_und_language(&cfg, Language::Und, &doc("")), None);
+        assert_eq!(detect_content_language(b"not cbor"), None);
+
+        if cfg!(feature = "lang-detect") {
+            assert_eq!(
+                detect_und_language(&cfg, Language::Und, &en),
+                Some(Language::Eng)
+            );
+            let zh = doc("敏捷的棕色狐狸跳过了那只懒狗，然后它跑回森林里去寻找它的家人。");
+            assert_eq!(
+                detect_und_language(&cfg, Language::Und, &zh),
+                Some(Language::Zho)
+            );
+
+            let strict = conf::Content {
+                detect_confidence: 1.1,
+                ..Default::default()
+            };
+            assert_eq!(detect_und_language(&strict, Language::Und, &en), None);
+        } else {
+            assert_eq!(detect_und_language(&cfg, Language::Und, &en), None);
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use axum::{
+    extract::{Query, State},
+    Extension,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc};
+use validator::Validate;
+
+use crate::db;
+
+use axum_web::context::ReqContext;
+use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
+use axum_web::object::PackObject;
+
+use super::{job::JobOutput, token_from_xid, token_to_xid, AppState};
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryGid {
+    pub gid: PackObject<xid::Id>,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct UpdateQuotaInput {
+    pub gid: PackObject<xid::Id>,
+    #[validate(range(min = 0))]
+    pub max_bytes: i64, // 0: reset to the default quota
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct QuotaOutput {
+    pub gid: PackObject<xid::Id>,
+    pub used_bytes: i64,
+    pub max_bytes: i64,
+    pub updated_at: i64,
+}
+
+impl QuotaOutput {
+    fn from<T>(val: db::GroupQuota, default_max_bytes: i64, to: &PackObject<T>) -> Self {
+        Self {
+            gid: to.with(val.gid),
+            used_bytes: val.used_bytes,
+            max_bytes: val.limit(default_max_bytes),
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+pub async fn get_quota(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGid>,
+) -> Result<PackObject<SuccessResponse<QuotaOutput>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "get_group_quota".into()),
+        ("gid", gid.to_string().into()),
+    ])
+    .await;
+
+    let doc = db::GroupQuota::load(&app.scylla, gid).await?;
+    Ok(to.with(SuccessResponse::new(QuotaOutput::from(
+        doc,
+        app.quota.max_bytes,
+        &to,
+    ))))
+}
+
+pub async fn update_quota(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<UpdateQuotaInput>,
+) -> Result<PackObject<SuccessResponse<QuotaOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can update group quota".to_string(),
+        ));
+    }
+
+    let gid = *input.gid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "update_group_quota".into()),
+        ("gid", gid.to_string().into()),
+        ("max_bytes", input.max_bytes.into()),
+    ])
+    .await;
+
+    let mut doc = db::GroupQuota::with_pk(gid);
+    let ok = doc.set_max_bytes(&app.scylla, input.max_bytes).await?;
+    ctx.set("updated", ok.into()).await;
+    Ok(to.with(SuccessResponse::new(QuotaOutput::from(
+        doc,
+        app.quota.max_bytes,
+        &to,
+    ))))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct UpdateSettingInput {
+    pub gid: PackObject<xid::Id>,
+    #[validate(range(min = 0))]
+    pub max_rating: i8, // 127: no restriction
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct SettingOutput {
+    pub gid: PackObject<xid::Id>,
+    pub max_rating: i8,
+    pub updated_at: i64,
+}
+
+impl SettingOutput {
+    fn from<T>(val: db::GroupSetting, to: &PackObject<T>) -> Self {
+        Self {
+            gid: to.with(val.gid),
+            max_rating: val.max_rating,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+pub async fn get_setting(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGid>,
+) -> Result<PackObject<SuccessResponse<SettingOutput>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "get_group_setting".into()),
+        ("gid", gid.to_string().into()),
+    ])
+    .await;
+
+    let doc = db::GroupSetting::load(&app.scylla, gid).await?;
+    Ok(to.with(SuccessResponse::new(SettingOutput::from(doc, &to))))
+}
+
+pub async fn update_setting(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<UpdateSettingInput>,
+) -> Result<PackObject<SuccessResponse<SettingOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can update group setting".to_string(),
+        ));
+    }
+
+    let gid = *input.gid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "update_group_setting".into()),
+        ("gid", gid.to_string().into()),
+        ("max_rating", input.max_rating.into()),
+    ])
+    .await;
+
+    let mut doc = db::GroupSetting::with_pk(gid);
+    let ok = doc.set_max_rating(&app.scylla, input.max_rating).await?;
+    ctx.set("updated", ok.into()).await;
+    Ok(to.with(SuccessResponse::new(SettingOutput::from(doc, &to))))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryChangelog {
+    pub gid: PackObject<xid::Id>,
+    pub target_id: Option<PackObject<xid::Id>>,
+    pub page_token: Option<PackObject<Vec<u8>>>,
+    #[validate(range(min = 2, max = 1000))]
+    pub page_size: Option<u16>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ChangelogOutput {
+    pub gid: PackObject<xid::Id>,
+    pub id: PackObject<xid::Id>,
+    pub actor: PackObject<xid::Id>,
+    pub target_kind: i8,
+    pub target_id: PackObject<xid::Id>,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub created_at: i64,
+}
+
+impl ChangelogOutput {
+    fn from<T>(val: db::Changelog, to: &PackObject<T>) -> Self {
+        Self {
+            gid: to.with(val.gid),
+            id: to.with(val.id),
+            actor: to.with(val.actor),
+            target_kind: val.target_kind,
+            target_id: to.with(val.target_id),
+            field: val.field,
+            old_value: val.old_value,
+            new_value: val.new_value,
+            created_at: val.created_at,
+        }
+    }
+}
+
+pub async fn list_changelog(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryChangelog>,
+) -> Result<PackObject<SuccessResponse<Vec<ChangelogOutput>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let target_id = input.target_id.to_owned().map(|v| v.unwrap());
+    let page_size = input.page_size.unwrap_or(10);
+    ctx.set_kvs(vec![
+        ("action", "list_group_changelog".into()),
+        ("gid", gid.to_string().into()),
+        ("page_size", page_size.into()),
+    ])
+    .await;
+
+    let res = db::Changelog::list(
+        &app.scylla,
+        gid,
+        target_id,
+        page_size,
+        token_to_xid(&input.page_token),
+    )
+    .await?;
+
+    Ok(to.with(SuccessResponse {
+        total_size: None,
+        next_page_token: to.with_option(token_from_xid(if res.len() >= page_size as usize {
+            Some(res.last().unwrap().id)
+        } else {
+            None
+        })),
+        result: res
+            .into_iter()
+            .map(|r| ChangelogOutput::from(r, &to))
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryJobId {
+    pub id: PackObject<xid::Id>,
+}
+
+// removes the group's documents from its group search space and the public search space,
+// it runs as a background job, the progress can be retrieved by the job id.
+pub async fn purge_search(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<QueryGid>,
+) -> Result<PackObject<SuccessResponse<JobOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can purge group search".to_string(),
+        ));
+    }
+
+    let gid = *input.gid.to_owned();
+    let job = app.jobs.create("purge_search", gid);
+    ctx.set_kvs(vec![
+        ("action", "purge_group_search".into()),
+        ("gid", gid.to_string().into()),
+        ("job", job.id.to_string().into()),
+    ])
+    .await;
+
+    tokio::spawn(purge_search_job(app.clone(), job.id, gid));
+    Ok(to.with(SuccessResponse::new(JobOutput::from(job, &to))))
+}
+
+pub async fn get_purge_search(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryJobId>,
+) -> Result<PackObject<SuccessResponse<JobOutput>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can purge group search".to_string(),
+        ));
+    }
+
+    let id = *input.id.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "get_purge_group_search".into()),
+        ("job", id.to_string().into()),
+    ])
+    .await;
+
+    match app.jobs.get(id) {
+        Some(job) => Ok(to.with(SuccessResponse::new(JobOutput::from(job, &to)))),
+        None => Err(HTTPError::new(404, format!("Job {} not found", id))),
+    }
+}
+
+async fn purge_search_job(app: Arc<AppState>, id: xid::Id, gid: xid::Id) {
+    let spaces = || {
+        [
+            db::meili::Space::Group(gid),
+            db::meili::Space::Pub(Some(gid)),
+        ]
+    };
+    let mut counts: Vec<usize> = Vec::with_capacity(2);
+    for space in spaces() {
+        counts.push(app.meili.count_by_gid(&space, gid).await.unwrap_or(0));
+    }
+    app.jobs.update(id, |job| job.total = counts.iter().sum());
+
+    for (i, space) in spaces().into_iter().enumerate() {
+        if let Err(err) = app.meili.delete_space(space).await {
+            log::error!(target: "meilisearch",
+                action = "delete_space",
+                gid = gid.to_string(),
+                job = id.to_string();
+                "{}", err.to_string(),
+            );
+            app.jobs.update(id, |job| {
+                job.status = -1;
+                job.error = err.to_string();
+            });
+            return;
+        }
+
+        let done = counts[i];
+        app.jobs.update(id, |job| job.done += done);
+    }
+    app.jobs.update(id, |job| job.status = 1);
+}
+
+// checks the group quota before writing `incoming_bytes` content.
+pub async fn check_quota(
+    app: &AppState,
+    gid: xid::Id,
+    incoming_bytes: usize,
+) -> Result<(), HTTPError> {
+    let doc = db::GroupQuota::load(&app.scylla, gid).await?;
+    doc.check(app.quota.max_bytes, incoming_bytes as i64)
+}
+
+// checks the group quota before replacing the content `content_id` with `incoming_bytes`
+// content. With `snapshot` the edit saves a new content row (creations keep the previous
+// ones in the history) and it is charged in full, otherwise only the growth is charged
+// unless the content is shared and copied on write.
+pub async fn check_quota_update(
+    app: &AppState,
+    gid: xid::Id,
+    content_id: xid::Id,
+    incoming_bytes: usize,
+    snapshot: bool,
+) -> Result<(), HTTPError> {
+    let mut content = db::Content::with_pk(content_id);
+    content
+        .get_one(&app.scylla, vec!["length".to_string()])
+        .await?;
+    // a shared content is copied on write, the copy is a new content row too
+    let refs = db::Content::refs(&app.scylla, content_id).await?;
+    let shared = refs.unwrap_or(0) > 0;
+
+    let doc = db::GroupQuota::load(&app.scylla, gid).await?;
+    doc.check_update(
+        app.quota.max_bytes,
+        content.length as i64,
+        incoming_bytes as i64,
+        snapshot || shared,
+    )
+}
@@ -0,0 +1,122 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::RwLock};
+
+use axum_web::context::unix_ms;
+use axum_web::object::PackObject;
+
+// finished jobs are kept for one day, so that their result can still be retrieved.
+const JOB_RETENTION_MS: i64 = 1000 * 3600 * 24;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Job {
+    pub id: xid::Id,
+    pub kind: String,
+    pub gid: xid::Id,
+    pub status: i8, // 0: running, 1: done, -1: failed
+    pub total: usize,
+    pub done: usize,
+    pub error: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// Jobs keeps the progress of the background jobs in memory,
+// a job is lost if the instance restarts before it finishes.
+#[derive(Debug, Default)]
+pub struct Jobs(RwLock<HashMap<xid::Id, Job>>);
+
+impl Jobs {
+    pub fn create(&self, kind: &str, gid: xid::Id) -> Job {
+        let now = unix_ms() as i64;
+        let job = Job {
+            id: xid::new(),
+            kind: kind.to_string(),
+            gid,
+            created_at: now,
+            updated_at: now,
+            ..Default::default()
+        };
+
+        let mut jobs = self.0.write().unwrap();
+        jobs.retain(|_, v| v.status == 0 || v.updated_at + JOB_RETENTION_MS > now);
+        jobs.insert(job.id, job.clone());
+        job
+    }
+
+    pub fn get(&self, id: xid::Id) -> Option<Job> {
+        self.0.read().unwrap().get(&id).cloned()
+    }
+
+    pub fn update<F: FnOnce(&mut Job)>(&self, id: xid::Id, f: F) {
+        if let Some(job) = self.0.write().unwrap().get_mut(&id) {
+            f(job);
+            job.updated_at = unix_ms() as i64;
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct JobOutput {
+    pub id: PackObject<xid::Id>,
+    pub kind: String,
+    pub gid: PackObject<xid::Id>,
+    pub status: i8,
+    pub total: usize,
+    pub done: usize,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl JobOutput {
+    pub fn from<T>(val: Job, to: &PackObject<T>) -> Self {
+        Self {
+            id: to.with(val.id),
+            kind: val.kind,
+            gid: to.with(val.gid),
+            status: val.status,
+            total: val.total,
+            done: val.done,
+            error: val.error,
+            created_at: val.created_at,
+            updated_at: val.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_works() {
+        let jobs = Jobs::default();
+        let gid = xid::new();
+        let job = jobs.create("purge_search", gid);
+        assert_eq!(job.status, 0);
+        assert_eq!(jobs.get(job.id), Some(job.clone()));
+        assert_eq!(jobs.get(xid::new()), None);
+
+        jobs.update(job.id, |v| {
+            v.total = 10;
+            v.done = 4;
+        });
+        let rt = jobs.get(job.id).unwrap();
+        assert_eq!((rt.total, rt.done, rt.status), (10, 4, 0));
+        assert_eq!(rt.gid, gid);
+
+        jobs.update(job.id, |v| {
+            v.done = v.total;
+            v.status = 1;
+        });
+        assert_eq!(jobs.get(job.id).unwrap().status, 1);
+
+        // expired finished jobs are removed when creating a new one
+        jobs.0.write().unwrap().get_mut(&job.id).unwrap().updated_at -= JOB_RETENTION_MS;
+        let job2 = jobs.create("purge_search", gid);
+        assert_eq!(jobs.get(job.id), None);
+        assert!(jobs.get(job2.id).is_some());
+    }
+}
@@ -0,0 +1,144 @@
+use axum_web::erring::HTTPError;
+
+use crate::conf;
+
+// the SPDX license identifiers accepted for creations and publications, a subset of
+// https://spdx.org/licenses/ that covers the licenses used for writing and documents.
+pub const SPDX_LICENSES: &[&str] = &[
+    "0BSD",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-2.0",
+    "Artistic-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSL-1.0",
+    "CC-BY-1.0",
+    "CC-BY-2.0",
+    "CC-BY-2.5",
+    "CC-BY-3.0",
+    "CC-BY-4.0",
+    "CC-BY-NC-1.0",
+    "CC-BY-NC-2.0",
+    "CC-BY-NC-2.5",
+    "CC-BY-NC-3.0",
+    "CC-BY-NC-4.0",
+    "CC-BY-NC-ND-1.0",
+    "CC-BY-NC-ND-2.0",
+    "CC-BY-NC-ND-2.5",
+    "CC-BY-NC-ND-3.0",
+    "CC-BY-NC-ND-4.0",
+    "CC-BY-NC-SA-1.0",
+    "CC-BY-NC-SA-2.0",
+    "CC-BY-NC-SA-2.5",
+    "CC-BY-NC-SA-3.0",
+    "CC-BY-NC-SA-4.0",
+    "CC-BY-ND-1.0",
+    "CC-BY-ND-2.0",
+    "CC-BY-ND-2.5",
+    "CC-BY-ND-3.0",
+    "CC-BY-ND-4.0",
+    "CC-BY-SA-1.0",
+    "CC-BY-SA-2.0",
+    "CC-BY-SA-2.5",
+    "CC-BY-SA-3.0",
+    "CC-BY-SA-4.0",
+    "CC-PDDC",
+    "CC0-1.0",
+    "EPL-2.0",
+    "EUPL-1.2",
+    "FDL-1.1-only",
+    "FDL-1.1-or-later",
+    "FDL-1.2-only",
+    "FDL-1.2-or-later",
+    "FDL-1.3-only",
+    "FDL-1.3-or-later",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "ISC",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "MIT",
+    "MPL-2.0",
+    "ODbL-1.0",
+    "OFL-1.1",
+    "PDDL-1.0",
+    "Unlicense",
+    "WTFPL",
+    "Zlib",
+];
+
+// returns the canonical SPDX identifier, the matching is case-insensitive.
+pub fn spdx_license(id: &str) -> Option<&'static str> {
+    SPDX_LICENSES
+        .iter()
+        .find(|v| v.eq_ignore_ascii_case(id))
+        .copied()
+}
+
+// normalize_license accepts a URL, or also a known SPDX identifier if enabled by
+// `content.spdx_license`. The SPDX identifier is stored in its canonical form.
+pub fn normalize_license(cfg: &conf::Content, license: String) -> Result<String, HTTPError> {
+    let license = license.trim();
+    if cfg.spdx_license {
+        if let Some(id) = spdx_license(license) {
+            return Ok(id.to_string());
+        }
+    }
+    if validator::validate_url(license) {
+        return Ok(license.to_string());
+    }
+
+    let hint = if cfg.spdx_license {
+        "expected an SPDX license identifier (e.g. CC-BY-4.0, MIT) or a URL"
+    } else {
+        "expected a URL"
+    };
+    Err(HTTPError::new(
+        400,
+        format!("Invalid license {:?}, {}", license, hint),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_license_works() {
+        let cfg = conf::Content {
+            spdx_license: true,
+            ..Default::default()
+        };
+
+        // SPDX id
+        assert_eq!(
+            normalize_license(&cfg, "cc-by-sa-4.0".to_string()).unwrap(),
+            "CC-BY-SA-4.0"
+        );
+        assert_eq!(normalize_license(&cfg, " MIT ".to_string()).unwrap(), "MIT");
+        // URL
+        let url = "https://creativecommons.org/licenses/by/4.0/";
+        assert_eq!(normalize_license(&cfg, url.to_string()).unwrap(), url);
+        // bogus
+        let err = normalize_license(&cfg, "free to use".to_string()).unwrap_err();
+        assert_eq!(err.code, 400);
+        assert!(err.message.contains("SPDX"));
+        assert!(normalize_license(&cfg, "".to_string()).is_err());
+
+        // only URLs when disabled
+        let cfg = conf::Content::default();
+        assert!(!cfg.spdx_license);
+        assert!(normalize_license(&cfg, "MIT".to_string()).is_err());
+        assert_eq!(normalize_license(&cfg, url.to_string()).unwrap(), url);
+
+        // the list is sorted and unique
+        for w in SPDX_LICENSES.windows(2) {
+            assert!(w[0].to_lowercase() < w[1].to_lowercase(), "{:?}", w);
+        }
+    }
+}
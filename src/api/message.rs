@@ -3,6 +3,7 @@ use axum::{
     Extension,
 };
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
@@ -19,7 +20,7 @@ use scylla_orm::ColumnsMap;
 use super::{get_fields, AppState, QueryGidId, QueryId};
 use crate::db;
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct CreateMessageInput {
     pub attach_to: PackObject<xid::Id>,
     pub kind: String,
@@ -40,7 +41,7 @@ pub fn validate_message(data: &PackObject<Vec<u8>>) -> Result<(), ValidationErro
     Ok(())
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct MessageOutput {
     pub id: PackObject<xid::Id>,
     pub i18n_messages: HashMap<String, PackObject<Vec<u8>>>,
@@ -107,6 +108,62 @@ impl MessageOutput {
     }
 }
 
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct MessageHistoryOutput {
+    pub version: i16,
+    pub language: PackObject<Language>,
+    pub updated_at: i64,
+    pub message: PackObject<Vec<u8>>,
+}
+
+impl MessageHistoryOutput {
+    pub fn from<T>(val: db::MessageHistory, to: &PackObject<T>) -> Self {
+        Self {
+            version: val.version,
+            language: to.with(val.language),
+            updated_at: val.updated_at,
+            message: to.with(val.message),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct LanguageStatusOutput {
+    pub language: String, // ISO 639-3
+    pub state: String,    // "missing", "stale" or "current"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i16>, // the primary version it was translated at
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct TranslationStatusOutput {
+    pub id: PackObject<xid::Id>,
+    pub language: PackObject<Language>,
+    pub version: i16,
+    pub total_languages: usize,
+    pub languages: Vec<LanguageStatusOutput>,
+}
+
+impl TranslationStatusOutput {
+    pub fn from<T>(val: db::Message, to: &PackObject<T>) -> Self {
+        Self {
+            id: to.with(val.id),
+            language: to.with(val.language),
+            version: val.version,
+            total_languages: db::LANGUAGES.len(),
+            languages: val
+                .translation_status()
+                .into_iter()
+                .map(|(lang, state, version)| LanguageStatusOutput {
+                    language: lang.to_string(),
+                    state: state.as_str().to_string(),
+                    version,
+                })
+                .collect(),
+        }
+    }
+}
+
 pub async fn create(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
@@ -164,7 +221,7 @@ pub async fn get(
     Ok(to.with(SuccessResponse::new(MessageOutput::from(doc, &to))))
 }
 
-#[derive(Debug, Clone, Deserialize, Validate)]
+#[derive(Debug, Clone, Deserialize, Validate, JsonSchema)]
 pub struct UpdateMessageInput {
     pub id: PackObject<xid::Id>,
     #[validate(range(min = 1, max = 32767))]
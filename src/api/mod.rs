@@ -1,23 +1,54 @@
-use axum::extract::State;
+use axum::{
+    extract::{Query, State},
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use sha3::{Digest, Sha3_256};
+use std::{
+    future::Future,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use validator::Validate;
 
+use axum_web::context::ReqContext;
+use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
 use axum_web::object::{cbor_from_slice, cbor_to_vec, PackObject};
 
+use crate::conf;
 use crate::db;
 
+pub mod blurhash;
 pub mod bookmark;
 pub mod collection;
 pub mod creation;
+pub mod detect;
+pub mod group;
+pub mod job;
+pub mod license;
 pub mod message;
+pub mod model;
+pub mod openapi;
 pub mod publication;
 pub mod search;
+pub mod sitemap;
+pub mod timeout;
+pub mod util;
+pub mod verify;
 
 mod content;
 pub use content::{
-    segment_content, validate_cbor_content, AttrValue, DocumentNode, PartialNode,
+    check_publishable, content_text, extract_media_refs, segment_content, slice_content, to_html,
+    validate_cbor_content, AttrValue, DocumentNode, MediaRef, PartialNode,
     MAX_CREATION_CONTENT_LEN,
 };
 pub use db::{MAX_CONTENT_LEN, MAX_MESSAGE_LEN};
@@ -30,15 +61,25 @@ pub struct AppState {
     pub start_at: u64,
     pub scylla: Arc<db::scylladb::ScyllaDB>,
     pub meili: Arc<db::meili::MeiliSearch>,
+    pub quota: conf::Quota,
+    pub cover: conf::Cover,
+    pub search: conf::Search,
+    pub content: conf::Content,
+    pub metrics: conf::Metrics,
+    pub publish: conf::Publish,
+    pub models: conf::Models,
+    pub site: conf::Site,
+    pub jobs: Arc<job::Jobs>,
+    pub sitemaps: Arc<sitemap::SitemapCache>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct AppVersion {
     pub name: String,
     pub version: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, JsonSchema)]
 pub struct AppInfo {
     pub start_at: u64,
     // https://docs.rs/scylla/latest/scylla/struct.Metrics.html
@@ -50,8 +91,13 @@ pub struct AppInfo {
     pub scylla_errors_iter_num: u64,
     pub scylla_queries_iter_num: u64,
     pub scylla_retries_num: u64,
+    pub scylla_transient_retries_num: u64, // retried by the app on transient errors
+    pub scylla_query_stats: Vec<db::scylladb::QueryStat>,
 }
 
+// the number of query stats included in the healthz payload.
+const HEALTHZ_QUERY_STATS: usize = 10;
+
 pub async fn version(to: PackObject<()>, State(_): State<Arc<AppState>>) -> PackObject<AppVersion> {
     to.with(AppVersion {
         name: APP_NAME.to_string(),
@@ -71,36 +117,140 @@ pub async fn healthz(to: PackObject<()>, State(app): State<Arc<AppState>>) -> Pa
         scylla_errors_iter_num: m.get_errors_iter_num(),
         scylla_queries_iter_num: m.get_queries_iter_num(),
         scylla_retries_num: m.get_retries_num(),
+        scylla_transient_retries_num: app.scylla.retries(),
+        scylla_query_stats: app.scylla.query_stats(HEALTHZ_QUERY_STATS),
     })
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ReadyInfo {
+    pub scylla: bool,
+    pub meili: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+// the max time to wait for a dependency probe.
+const READYZ_TIMEOUT: Duration = Duration::from_secs(3);
+
+// probes the dependencies, returns 503 if any of them is unavailable.
+async fn readiness(
+    timeout: Duration,
+    scylla: impl Future<Output = anyhow::Result<()>>,
+    meili: impl Future<Output = anyhow::Result<()>>,
+) -> (StatusCode, ReadyInfo) {
+    let (scylla, meili) = tokio::join!(
+        tokio::time::timeout(timeout, scylla),
+        tokio::time::timeout(timeout, meili)
+    );
+
+    let mut info = ReadyInfo {
+        scylla: true,
+        meili: true,
+        errors: vec![],
+    };
+    for (name, res, ok) in [
+        ("scylla", scylla, &mut info.scylla),
+        ("meili", meili, &mut info.meili),
+    ] {
+        let err = match res {
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "timeout".to_string(),
+        };
+        *ok = false;
+        info.errors.push(format!("{}: {}", name, err));
+    }
+
+    if info.errors.is_empty() {
+        (StatusCode::OK, info)
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, info)
+    }
+}
+
+// readyz probes ScyllaDB and Meilisearch, while healthz always responds for liveness.
+pub async fn readyz(to: PackObject<()>, State(app): State<Arc<AppState>>) -> Response {
+    let (status, info) = readiness(READYZ_TIMEOUT, app.scylla.ping(), app.meili.ping()).await;
+    if status != StatusCode::OK {
+        log::warn!(target: "readyz",
+            errors = info.errors.join("; ");
+            "not ready",
+        );
+    }
+    (status, to.with(info)).into_response()
+}
+
+// deadline bounds the ScyllaDB queries of a request by the configured request timeout,
+// counted from the start of the request.
+pub async fn deadline<B>(
+    State(app): State<Arc<AppState>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let start = req
+        .extensions()
+        .get::<Arc<ReqContext>>()
+        .map_or_else(Instant::now, |ctx| ctx.start);
+    db::scylladb::with_deadline(start + app.scylla.request_timeout(), next.run(req)).await
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryStatsInput {
+    #[validate(range(min = 1, max = 1000))]
+    pub top: Option<usize>,
+}
+
+pub async fn query_stats(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryStatsInput>,
+) -> Result<PackObject<SuccessResponse<Vec<db::scylladb::QueryStat>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can get query stats".to_string(),
+        ));
+    }
+
+    ctx.set_kvs(vec![("action", "get_query_stats".into())])
+        .await;
+    let res = app
+        .scylla
+        .query_stats(input.top.unwrap_or(db::scylladb::MAX_QUERY_STATS));
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryId {
     pub id: PackObject<xid::Id>,
     pub status: Option<i8>,
     pub fields: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryCid {
     pub cid: PackObject<xid::Id>,
     pub fields: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryIdCid {
     pub id: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryGidIdCid {
     pub gid: PackObject<xid::Id>,
     pub id: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryGidId {
     pub gid: PackObject<xid::Id>,
     pub id: PackObject<xid::Id>,
@@ -108,7 +258,7 @@ pub struct QueryGidId {
     pub fields: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryGidCid {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -116,7 +266,7 @@ pub struct QueryGidCid {
     pub fields: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct Pagination {
     pub page_token: Option<PackObject<Vec<u8>>>,
     #[validate(range(min = 2, max = 1000))]
@@ -124,9 +274,10 @@ pub struct Pagination {
     #[validate(range(min = -1, max = 2))]
     pub status: Option<i8>,
     pub fields: Option<Vec<String>>,
+    pub order: Option<db::Order>, // default desc, see `desc_only`
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct GIDPagination {
     pub gid: PackObject<xid::Id>,
     pub page_token: Option<PackObject<Vec<u8>>>,
@@ -135,9 +286,11 @@ pub struct GIDPagination {
     #[validate(range(min = -1, max = 2))]
     pub status: Option<i8>,
     pub fields: Option<Vec<String>>,
+    pub collapse: Option<bool>, // one row per cid for the publications, default true
+    pub order: Option<db::Order>, // default desc, see `desc_only`
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct IDGIDPagination {
     pub id: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -149,6 +302,56 @@ pub struct IDGIDPagination {
     pub fields: Option<Vec<String>>,
 }
 
+// preloads the hot prepared statements and the Meilisearch index settings,
+// errors are logged and skipped unless `strict` is true.
+pub async fn warm_up(app: &AppState, strict: bool) -> anyhow::Result<()> {
+    let start = Instant::now();
+    for query in db::warm_up_queries() {
+        let t = Instant::now();
+        match app.scylla.prepare(query.as_str()).await {
+            Ok(_) => log::info!(target: "warm_up",
+                query = query,
+                elapsed = t.elapsed().as_millis() as u64;
+                "statement prepared",
+            ),
+            Err(err) => {
+                log::warn!(target: "warm_up",
+                    query = query,
+                    elapsed = t.elapsed().as_millis() as u64;
+                    "failed to prepare statement: {}", err,
+                );
+                if strict {
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    let t = Instant::now();
+    match app.meili.ensure_settings().await {
+        Ok(updated) => log::info!(target: "warm_up",
+            updated = updated,
+            elapsed = t.elapsed().as_millis() as u64;
+            "meilisearch settings ensured",
+        ),
+        Err(err) => {
+            log::warn!(target: "warm_up",
+                elapsed = t.elapsed().as_millis() as u64;
+                "failed to ensure meilisearch settings: {}", err,
+            );
+            if strict {
+                return Err(err);
+            }
+        }
+    }
+
+    log::info!(target: "warm_up",
+        elapsed = start.elapsed().as_millis() as u64;
+        "warm up finished",
+    );
+    Ok(())
+}
+
 pub fn token_to_xid(page_token: &Option<PackObject<Vec<u8>>>) -> Option<xid::Id> {
     match page_token.as_ref().map(|v| v.unwrap_ref()) {
         Some(v) => cbor_from_slice::<PackObject<xid::Id>>(v)
@@ -158,6 +361,17 @@ pub fn token_to_xid(page_token: &Option<PackObject<Vec<u8>>>) -> Option<xid::Id>
     }
 }
 
+// the listers that scan day partitions or secondary tables only list newest first.
+pub fn desc_only(order: Option<db::Order>) -> Result<(), HTTPError> {
+    match order {
+        Some(db::Order::Asc) => Err(HTTPError::new(
+            400,
+            "Order asc is not supported by this listing".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
 pub fn token_from_xid(id: Option<xid::Id>) -> Option<Vec<u8>> {
     match id {
         Some(id) => cbor_to_vec(&PackObject::Cbor(id)).ok(),
@@ -165,7 +379,266 @@ pub fn token_from_xid(id: Option<xid::Id>) -> Option<Vec<u8>> {
     }
 }
 
-#[derive(Debug, Deserialize, Validate)]
+// composite page token of (updated_at, id), for the listings ordered by updated_at.
+pub fn token_to_updated(page_token: &Option<PackObject<Vec<u8>>>) -> Option<(i64, xid::Id)> {
+    match page_token.as_ref().map(|v| v.unwrap_ref()) {
+        Some(v) => cbor_from_slice::<(i64, PackObject<xid::Id>)>(v)
+            .ok()
+            .map(|(updated_at, id)| (updated_at, id.unwrap())),
+        _ => None,
+    }
+}
+
+pub fn token_from_updated(token: Option<(i64, xid::Id)>) -> Option<Vec<u8>> {
+    match token {
+        Some((updated_at, id)) => cbor_to_vec(&(updated_at, PackObject::Cbor(id))).ok(),
+        _ => None,
+    }
+}
+
+// page token of the uncollapsed publication listing, the cursor of (cid, language, version) of
+// the page's last row. The xid tokens are accepted too, they continue after the cid.
+type RowToken = (PackObject<xid::Id>, PackObject<isolang::Language>, i16);
+
+pub fn token_to_row(
+    page_token: &Option<PackObject<Vec<u8>>>,
+) -> (Option<xid::Id>, Option<(isolang::Language, i16)>) {
+    match page_token.as_ref().map(|v| v.unwrap_ref()) {
+        Some(v) => match cbor_from_slice::<RowToken>(v) {
+            Ok((cid, language, version)) => {
+                (Some(cid.unwrap()), Some((language.unwrap(), version)))
+            }
+            Err(_) => (token_to_xid(page_token), None),
+        },
+        _ => (None, None),
+    }
+}
+
+pub fn token_from_row(row: Option<(xid::Id, isolang::Language, i16)>) -> Option<Vec<u8>> {
+    match row {
+        Some((cid, language, version)) => {
+            let token: RowToken = (PackObject::Cbor(cid), PackObject::Cbor(language), version);
+            cbor_to_vec(&token).ok()
+        }
+        _ => None,
+    }
+}
+
+// page token of list_by_gids, the cursor of (day, [(gid, day, cid)]). The xid tokens issued
+// before are accepted too, they resume all the groups from the day before the token.
+type GidsToken = (
+    i32,
+    Vec<(PackObject<xid::Id>, i32, Option<PackObject<xid::Id>>)>,
+);
+
+pub fn token_to_gids(page_token: &Option<PackObject<Vec<u8>>>) -> Option<db::GidsCursor> {
+    match page_token.as_ref().map(|v| v.unwrap_ref()) {
+        Some(v) => match cbor_from_slice::<GidsToken>(v) {
+            Ok((day, positions)) => Some(db::GidsCursor {
+                day,
+                positions: positions
+                    .into_iter()
+                    .map(|(gid, day, cid)| (gid.unwrap(), day, cid.map(|v| v.unwrap())))
+                    .collect(),
+            }),
+            Err(_) => token_to_xid(page_token).map(db::GidsCursor::from_xid),
+        },
+        _ => None,
+    }
+}
+
+pub fn token_from_gids(cursor: Option<db::GidsCursor>) -> Option<Vec<u8>> {
+    match cursor {
+        Some(c) => {
+            let token: GidsToken = (
+                c.day,
+                c.positions
+                    .into_iter()
+                    .map(|(gid, day, cid)| (PackObject::Cbor(gid), day, cid.map(PackObject::Cbor)))
+                    .collect(),
+            );
+            cbor_to_vec(&token).ok()
+        }
+        _ => None,
+    }
+}
+
+// page token of a paginated search, the cursor of (updated_at, ids).
+pub fn token_to_search(
+    page_token: &Option<PackObject<Vec<u8>>>,
+) -> Option<db::meili::SearchCursor> {
+    match page_token.as_ref().map(|v| v.unwrap_ref()) {
+        Some(v) => cbor_from_slice::<(i64, Vec<String>)>(v)
+            .ok()
+            .map(|(updated_at, ids)| db::meili::SearchCursor { updated_at, ids }),
+        _ => None,
+    }
+}
+
+pub fn token_from_search(cursor: Option<db::meili::SearchCursor>) -> Option<Vec<u8>> {
+    match cursor {
+        Some(c) => cbor_to_vec(&(c.updated_at, c.ids)).ok(),
+        _ => None,
+    }
+}
+
+// response header set when a create request is answered from the idempotency record.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "x-idempotent-replayed";
+
+// hashes the action and the request payload, so a reused key with another payload can be detected.
+pub fn idempotency_hash<P: Serialize>(action: &str, payload: &P) -> Result<Vec<u8>, HTTPError> {
+    let mut hasher = Sha3_256::new();
+    hasher.update(action.as_bytes());
+    hasher.update(cbor_to_vec(payload)?);
+    Ok(hasher.finalize().to_vec())
+}
+
+// the idempotency key reserved by a create request. A reservation that is not saved by
+// `idempotency_save` is released when it is dropped, e.g. the create failed.
+pub struct IdempotencyGuard {
+    app: Arc<AppState>,
+    doc: Option<db::Idempotency>,
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        if let Some(doc) = self.doc.take() {
+            let app = self.app.clone();
+            tokio::spawn(async move {
+                if let Err(err) = doc.release(&app.scylla).await {
+                    log::warn!(target: "idempotency",
+                        uid = doc.uid.to_string(),
+                        action = doc.action;
+                        "failed to release idempotency key: {}", err,
+                    );
+                }
+            });
+        }
+    }
+}
+
+// looks up the idempotency key of a create request and reserves it.
+// Returns the stored response if the key was used with the same payload, or the reservation
+// to be saved by `idempotency_save` after the resource is created.
+// A key reused with a different payload is rejected with 422, a key reserved by a create in
+// progress is rejected with 409 so the duplicate is retried later.
+pub async fn idempotency_lookup<P: Serialize>(
+    app: &Arc<AppState>,
+    uid: xid::Id,
+    key: &Option<String>,
+    action: &str,
+    payload: &PackObject<P>,
+) -> Result<(Option<IdempotencyGuard>, Option<Response>), HTTPError> {
+    let key = match key {
+        Some(key) => key.to_owned(),
+        None => return Ok((None, None)),
+    };
+
+    // the payload is hashed with its encoding, so the stored response matches the request's
+    let payload_hash = match payload {
+        PackObject::Json(v) => idempotency_hash(&format!("{}:json", action), v)?,
+        PackObject::Cbor(v) => idempotency_hash(&format!("{}:cbor", action), v)?,
+    };
+    // the key can be released or expire between the reads and the reservation, tries again
+    for _ in 0..3 {
+        let mut doc = db::Idempotency::with_pk(uid, key.clone());
+        match doc.get_one(&app.scylla).await {
+            Ok(_) => {
+                if doc.action != action || doc.payload_hash != payload_hash {
+                    return Err(HTTPError::new(
+                        422,
+                        format!(
+                            "Idempotency key {} was used with a different payload",
+                            doc.idempotency_key
+                        ),
+                    ));
+                }
+                if doc.is_pending() {
+                    return Err(doc.pending_error());
+                }
+                return Ok((None, Some(idempotency_replay(payload, doc.response))));
+            }
+            Err(err) => {
+                let err: HTTPError = err.into();
+                if err.code != 404 {
+                    return Err(err);
+                }
+                doc.action = action.to_string();
+                doc.payload_hash = payload_hash.clone();
+                if doc.reserve(&app.scylla).await? {
+                    let guard = IdempotencyGuard {
+                        app: app.clone(),
+                        doc: Some(doc),
+                    };
+                    return Ok((Some(guard), None));
+                }
+            }
+        }
+    }
+
+    Err(HTTPError::new(
+        409,
+        format!("Idempotency key {} is busy, please try again", key),
+    ))
+}
+
+// saves the encoded response of a successful create to the reserved key, errors are logged
+// and skipped because the resource was created already.
+pub async fn idempotency_save<R: Serialize>(
+    guard: Option<IdempotencyGuard>,
+    resource: String,
+    response: &PackObject<R>,
+) {
+    if let Some(mut guard) = guard {
+        let app = guard.app.clone();
+        let mut doc = match guard.doc.take() {
+            Some(doc) => doc,
+            None => return,
+        };
+        let data = match response {
+            PackObject::Json(v) => serde_json::to_vec(v).map_err(|err| anyhow::anyhow!(err)),
+            PackObject::Cbor(v) => cbor_to_vec(v).map_err(|err| anyhow::anyhow!(err.message)),
+        };
+        let res = match data {
+            Ok(data) => doc.complete(&app.scylla, resource.clone(), data).await,
+            Err(err) => Err(err),
+        };
+        let err = match res {
+            Ok(true) => return,
+            Ok(false) => anyhow::anyhow!("the key is not reserved anymore"),
+            Err(err) => err,
+        };
+        log::warn!(target: "idempotency",
+            uid = doc.uid.to_string(),
+            action = doc.action,
+            resource = resource;
+            "failed to save idempotency key: {}", err,
+        );
+        // the resource is created, the reservation is kept until it expires instead of
+        // released, so a retry is not answered by a duplicate.
+    }
+}
+
+// builds the replayed response from the stored body, in the same encoding as the request.
+pub fn idempotency_replay<P>(to: &PackObject<P>, body: Vec<u8>) -> Response {
+    let content_type = match to {
+        PackObject::Json(_) => mime::APPLICATION_JSON.as_ref(),
+        PackObject::Cbor(_) => "application/cbor",
+    };
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(content_type)),
+            (
+                HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER),
+                HeaderValue::from_static("true"),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdateStatusInput {
     pub id: PackObject<xid::Id>,
     pub gid: Option<PackObject<xid::Id>>,
@@ -174,7 +647,7 @@ pub struct UpdateStatusInput {
     pub updated_at: i64,
 }
 
-#[derive(Debug, Default, Deserialize, Validate)]
+#[derive(Debug, Default, Deserialize, Validate, JsonSchema)]
 pub struct SubscriptionInput {
     pub uid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -183,7 +656,7 @@ pub struct SubscriptionInput {
     pub expire_at: i64,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct SubscriptionOutput {
     pub uid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -193,14 +666,93 @@ pub struct SubscriptionOutput {
     pub expire_at: i64,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+// the max term of a granted subscription, from now.
+pub const MAX_GRANT_DAYS: i64 = 5 * 365;
+// the changelog field of a subscription grant.
+pub const GRANT_CHANGELOG_FIELD: &str = "subscription_grant";
+
+// a subscription granted without a payment, e.g. for a refund apology or a promotion.
+#[derive(Debug, Default, Deserialize, Validate, JsonSchema)]
+pub struct GrantSubscriptionInput {
+    pub gid: Option<PackObject<xid::Id>>, // the owner group, not required for the system user
+    pub uid: PackObject<xid::Id>,
+    pub cid: PackObject<xid::Id>,
+    pub expire_at: i64, // unix time in seconds
+    #[validate(length(min = 1, max = 1024))]
+    pub note: String,
+}
+
+// valid_grant_expire_at checks that the granted expire_at, in seconds, is in the future and
+// within MAX_GRANT_DAYS.
+pub fn valid_grant_expire_at(expire_at: i64, now_ms: i64) -> Result<(), HTTPError> {
+    let now = now_ms / 1000;
+    let max = now + MAX_GRANT_DAYS * 24 * 3600;
+    if expire_at <= now || expire_at > max {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "Invalid expire_at {}, expected in ({}, {}]",
+                expire_at, now, max
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// check_grant_actor passes the system user, and the owner group of the subscribed item.
+pub fn check_grant_actor(
+    user: xid::Id,
+    gid: Option<xid::Id>,
+    owner: xid::Id,
+) -> Result<(), HTTPError> {
+    if user == xid::Id::from_str(db::USER_JARVIS).unwrap() || gid == Some(owner) {
+        return Ok(());
+    }
+    Err(HTTPError::new(
+        403,
+        "Only system user or the owner group can grant subscription".to_string(),
+    ))
+}
+
+// grant_changelog records a grant with its note, the old value is the replaced subscription.
+pub fn grant_changelog(
+    ctx: &ReqContext,
+    owner: xid::Id,
+    target_kind: i8,
+    input: &GrantSubscriptionInput,
+    old: Option<(xid::Id, i64)>,
+) -> db::Changelog {
+    let old_value = match old {
+        Some((txn, expire_at)) => serde_json::json!({
+            "txn": txn.to_string(),
+            "expire_at": expire_at,
+        }),
+        None => serde_json::Value::Null,
+    };
+    db::Changelog::entry(
+        owner,
+        ctx.user,
+        target_kind,
+        *input.cid.to_owned(),
+        GRANT_CHANGELOG_FIELD,
+        old_value,
+        serde_json::json!({
+            "uid": input.uid.to_string(),
+            "txn": db::ZERO_ID.to_string(),
+            "expire_at": input.expire_at,
+            "note": input.note,
+        }),
+    )
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct RFPInfo {
     pub id: PackObject<xid::Id>,
     pub price: i64,
 }
 
 // Request for Payment
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct RFP {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub creation: Option<RFPInfo>,
@@ -208,6 +760,115 @@ pub struct RFP {
     pub collection: Option<RFPInfo>,
 }
 
+// returns the count of a sampled paywall impression, None if it is not sampled.
+// `dice` is a uniform random value in [0, 1).
+pub fn rfp_sample(rate: f64, dice: f64) -> Option<i64> {
+    if rate <= 0.0 || dice >= rate {
+        return None;
+    }
+    Some((1.0 / rate.min(1.0)).round() as i64)
+}
+
+// records a paywall impression in background, it never delays or fails the request.
+pub fn record_rfp_impression(app: &AppState, cid: xid::Id, unix_ms: u64) {
+    let dice = (uuid::Uuid::new_v4().as_u64_pair().0 >> 11) as f64 / (1u64 << 53) as f64;
+    let count = match rfp_sample(app.metrics.rfp_sample_rate, dice) {
+        Some(count) => count,
+        None => return,
+    };
+
+    let scylla = app.scylla.clone();
+    let day = db::unix_ms_day(unix_ms as i64);
+    tokio::spawn(async move {
+        if let Err(err) = db::RfpImpression::incr(&scylla, cid, day, count).await {
+            log::error!(target: "rfp_impression",
+                action = "incr",
+                cid = cid.to_string(),
+                day = day;
+                "{}", err.to_string(),
+            );
+        }
+    });
+}
+
+// MeiliWrite is a write to a MeiliSearch space, see `meili_write`.
+pub enum MeiliWrite {
+    AddOrUpdate(Vec<db::meili::Document>),
+    Upsert(Vec<db::meili::Document>),
+    Delete(Vec<String>),
+}
+
+// meili_write runs the write in a spawned task and waits for it, so that the write is not
+// cancelled halfway when the request times out. It returns the number of documents
+// written, an upsert skips the documents that are unchanged.
+pub async fn meili_write(
+    app: &AppState,
+    space: db::meili::Space,
+    write: MeiliWrite,
+) -> anyhow::Result<usize> {
+    let meili = app.meili.clone();
+    tokio::spawn(async move {
+        match write {
+            MeiliWrite::AddOrUpdate(docs) => {
+                let n = docs.len();
+                meili.add_or_update(space, docs).await.map(|_| n)
+            }
+            MeiliWrite::Upsert(docs) => meili.upsert(space, docs).await,
+            MeiliWrite::Delete(ids) => {
+                let n = ids.len();
+                meili.delete(space, ids).await.map(|_| n)
+            }
+        }
+    })
+    .await?
+}
+
+pub const MAX_KEYWORDS: usize = 5;
+pub const MAX_KEYWORD_LEN: usize = 64;
+pub const MAX_GENRE: usize = 5;
+pub const MAX_GENRE_LEN: usize = 32;
+pub const MAX_LABELS: usize = 5;
+pub const MAX_LABEL_LEN: usize = 32;
+
+// normalize_keywords normalizes the keywords by db::normalize_tags, the same as genre, labels
+// and the collection keywords, then checks the limits.
+pub fn normalize_keywords(keywords: Vec<String>) -> Result<Vec<String>, HTTPError> {
+    normalize_list_input("keywords", keywords, MAX_KEYWORDS, MAX_KEYWORD_LEN)
+}
+
+pub fn normalize_genre(genre: Vec<String>) -> Result<Vec<String>, HTTPError> {
+    normalize_list_input("genre", genre, MAX_GENRE, MAX_GENRE_LEN)
+}
+
+pub fn normalize_labels(labels: Vec<String>) -> Result<Vec<String>, HTTPError> {
+    normalize_list_input("labels", labels, MAX_LABELS, MAX_LABEL_LEN)
+}
+
+fn normalize_list_input(
+    name: &str,
+    list: Vec<String>,
+    max_items: usize,
+    max_len: usize,
+) -> Result<Vec<String>, HTTPError> {
+    let list = db::normalize_tags(list);
+    if list.len() > max_items {
+        return Err(HTTPError::new(
+            400,
+            format!("Too many {}, expected at most {}", name, max_items),
+        ));
+    }
+    if let Some(v) = list.iter().find(|v| v.chars().count() > max_len) {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "Item of {} is too long, expected at most {} characters: {:?}",
+                name, max_len, v
+            ),
+        ));
+    }
+    Ok(list)
+}
+
 pub fn get_fields(fields: Option<String>) -> Vec<String> {
     if fields.is_none() {
         return vec![];
@@ -220,12 +881,179 @@ pub fn get_fields(fields: Option<String>) -> Vec<String> {
     fields.split(',').map(|s| s.trim().to_string()).collect()
 }
 
+pub fn valid_cover_host(allowed_hosts: &[String], cover: &str) -> Result<(), HTTPError> {
+    if allowed_hosts.is_empty() {
+        return Ok(());
+    }
+
+    let host = reqwest::Url::parse(cover)
+        .ok()
+        .and_then(|url| url.host_str().map(|h| h.to_lowercase()))
+        .unwrap_or_default();
+    if allowed_hosts
+        .iter()
+        .any(|h| host == *h || host.ends_with(&format!(".{}", h)))
+    {
+        return Ok(());
+    }
+
+    Err(HTTPError::new(
+        400,
+        format!("Cover host is not allowed: {}", cover),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use faster_hex::hex_string;
 
+    #[test]
+    fn normalize_keywords_works() {
+        assert!(normalize_keywords(vec![]).unwrap().is_empty());
+        assert!(normalize_keywords(vec!["".to_string(), " \t ".to_string()])
+            .unwrap()
+            .is_empty());
+
+        let res = normalize_keywords(vec![
+            " Rust ".to_string(),
+            "rust".to_string(),
+            "RUST".to_string(),
+            "Machine   Learning".to_string(),
+            "machine learning\t".to_string(),
+            "".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(res, vec!["rust", "machine learning"]);
+
+        // unicode
+        let res = normalize_keywords(vec![
+            " 人工智能 ".to_string(),
+            "人工智能".to_string(),
+            "Éclair".to_string(),
+            "ÉCLAIR".to_string(),
+            "éclair".to_string(),
+            "🦀 Crab".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(res, vec!["人工智能", "éclair", "🦀 crab"]);
+
+        // the length is counted in characters
+        assert!(normalize_keywords(vec!["字".repeat(MAX_KEYWORD_LEN)]).is_ok());
+        let err = normalize_keywords(vec!["字".repeat(MAX_KEYWORD_LEN + 1)]).unwrap_err();
+        assert_eq!(err.code, 400);
+        assert!(normalize_keywords(vec!["a".repeat(10 * 1024)]).is_err());
+
+        // the max items are checked after de-duplication
+        let res = normalize_keywords(vec![
+            "a".to_string(),
+            "A".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+            "d".to_string(),
+            "e".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(res.len(), MAX_KEYWORDS);
+        let err =
+            normalize_keywords((0..=MAX_KEYWORDS).map(|i| i.to_string()).collect()).unwrap_err();
+        assert_eq!(err.code, 400);
+
+        assert!(normalize_genre(vec!["x".repeat(MAX_GENRE_LEN + 1)]).is_err());
+        assert_eq!(
+            normalize_labels(vec!["Draft".to_string(), " draft ".to_string()]).unwrap(),
+            vec!["draft"]
+        );
+        assert!(normalize_labels(vec!["x".repeat(MAX_LABEL_LEN + 1)]).is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn readiness_works() {
+        let timeout = Duration::from_millis(50);
+        let (status, info) = readiness(timeout, async { Ok(()) }, async { Ok(()) }).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(info.scylla && info.meili);
+        assert!(info.errors.is_empty());
+
+        let (status, info) = readiness(timeout, async { Ok(()) }, async {
+            Err(anyhow::Error::msg("connection refused"))
+        })
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(info.scylla);
+        assert!(!info.meili);
+        assert_eq!(info.errors, vec!["meili: connection refused".to_string()]);
+
+        let (status, info) = readiness(
+            timeout,
+            async {
+                tokio::time::sleep(timeout * 4).await;
+                Ok(())
+            },
+            async { Err(anyhow::Error::msg("down")) },
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!info.scylla);
+        assert!(!info.meili);
+        assert_eq!(
+            info.errors,
+            vec!["scylla: timeout".to_string(), "meili: down".to_string()]
+        );
+    }
+
+    #[test]
+    fn valid_grant_expire_at_works() {
+        let now_ms: i64 = 1_700_000_000_000;
+        let now = now_ms / 1000;
+        let max = now + MAX_GRANT_DAYS * 24 * 3600;
+
+        assert!(valid_grant_expire_at(now + 1, now_ms).is_ok());
+        assert!(valid_grant_expire_at(max, now_ms).is_ok());
+        assert_eq!(valid_grant_expire_at(now, now_ms).unwrap_err().code, 400);
+        assert_eq!(valid_grant_expire_at(0, now_ms).unwrap_err().code, 400);
+        assert_eq!(
+            valid_grant_expire_at(max + 1, now_ms).unwrap_err().code,
+            400
+        );
+        // milliseconds are far beyond the max term
+        assert!(valid_grant_expire_at(now_ms + 1000, now_ms).is_err());
+    }
+
+    #[test]
+    fn check_grant_actor_works() {
+        let jarvis = xid::Id::from_str(db::USER_JARVIS).unwrap();
+        let owner = xid::new();
+
+        assert!(check_grant_actor(jarvis, None, owner).is_ok());
+        assert!(check_grant_actor(jarvis, Some(xid::new()), owner).is_ok());
+        assert!(check_grant_actor(xid::new(), Some(owner), owner).is_ok());
+        assert_eq!(
+            check_grant_actor(xid::new(), None, owner).unwrap_err().code,
+            403
+        );
+        assert_eq!(
+            check_grant_actor(xid::new(), Some(xid::new()), owner)
+                .unwrap_err()
+                .code,
+            403
+        );
+    }
+
+    #[test]
+    fn rfp_sample_works() {
+        assert_eq!(rfp_sample(0.0, 0.0), None);
+        assert_eq!(rfp_sample(-1.0, 0.0), None);
+        assert_eq!(rfp_sample(1.0, 0.0), Some(1));
+        assert_eq!(rfp_sample(1.0, 0.999), Some(1));
+        assert_eq!(rfp_sample(2.0, 0.5), Some(1));
+        assert_eq!(rfp_sample(0.1, 0.05), Some(10));
+        assert_eq!(rfp_sample(0.1, 0.1), None);
+        assert_eq!(rfp_sample(0.1, 0.5), None);
+        assert_eq!(rfp_sample(0.3, 0.2), Some(3));
+    }
+
     #[test]
     fn get_fields_works() {
         assert_eq!(get_fields(None), Vec::<String>::new());
@@ -242,6 +1070,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn valid_cover_host_works() {
+        assert!(valid_cover_host(&[], "https://example.com/1.png").is_ok());
+
+        let hosts = vec!["yiwen.pub".to_string()];
+        assert!(valid_cover_host(&hosts, "https://yiwen.pub/1.png").is_ok());
+        assert!(valid_cover_host(&hosts, "https://cdn.yiwen.pub/1.png").is_ok());
+        assert!(valid_cover_host(&hosts, "https://CDN.Yiwen.pub/1.png").is_ok());
+        assert!(valid_cover_host(&hosts, "https://example.com/1.png").is_err());
+        assert!(valid_cover_host(&hosts, "https://fakeyiwen.pub/1.png").is_err());
+        assert!(valid_cover_host(&hosts, "not a url").is_err());
+    }
+
+    #[test]
+    fn idempotency_hash_works() {
+        let a = idempotency_hash("create_creation", &vec!["a", "b"]).unwrap();
+        assert_eq!(a.len(), 32);
+        assert_eq!(
+            a,
+            idempotency_hash("create_creation", &vec!["a", "b"]).unwrap()
+        );
+        assert_ne!(
+            a,
+            idempotency_hash("create_creation", &vec!["a", "c"]).unwrap()
+        );
+        assert_ne!(
+            a,
+            idempotency_hash("create_collection", &vec!["a", "b"]).unwrap()
+        );
+
+        let res = idempotency_replay(&PackObject::Cbor(()), vec![0xa0]);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/cbor"
+        );
+        assert_eq!(
+            res.headers().get(IDEMPOTENT_REPLAYED_HEADER).unwrap(),
+            "true"
+        );
+        let res = idempotency_replay(&PackObject::Json(()), b"{}".to_vec());
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+    }
+
     #[test]
     fn token_to_xid_works() {
         let input = xid::new();
@@ -255,6 +1129,80 @@ mod tests {
         assert_eq!(rt, None);
     }
 
+    #[test]
+    fn token_to_gids_works() {
+        let (gid, cid) = (xid::new(), xid::new());
+        let input = db::GidsCursor {
+            day: 19000,
+            positions: vec![(gid, 19002, Some(cid)), (xid::new(), 19001, None)],
+        };
+        let v = token_from_gids(Some(input.clone())).unwrap();
+        let rt = token_to_gids(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, Some(input));
+
+        // the xid tokens resume from the day before
+        let v = token_from_xid(Some(db::day_to_xid(19000))).unwrap();
+        let rt = token_to_gids(&Some(PackObject::Cbor(v))).unwrap();
+        assert_eq!(rt.day, 18999);
+        assert!(rt.positions.is_empty());
+        assert_eq!(rt.position(gid), (18999, None));
+
+        let rt = token_to_gids(&Some(PackObject::Cbor(vec![0x41, 0x02])));
+        assert_eq!(rt, None);
+        assert_eq!(token_to_gids(&None), None);
+        assert_eq!(token_from_gids(None), None);
+    }
+
+    #[test]
+    fn token_to_row_works() {
+        let cid = xid::new();
+        let v = token_from_row(Some((cid, isolang::Language::Jpn, 2))).unwrap();
+        let rt = token_to_row(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, (Some(cid), Some((isolang::Language::Jpn, 2))));
+
+        let v = token_from_xid(Some(cid)).unwrap();
+        let rt = token_to_row(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, (Some(cid), None));
+
+        let rt = token_to_row(&Some(PackObject::Cbor(vec![0x41, 0x02])));
+        assert_eq!(rt, (None, None));
+        assert_eq!(token_to_row(&None), (None, None));
+        assert_eq!(token_from_row(None), None);
+    }
+
+    #[test]
+    fn token_to_updated_works() {
+        let input = (1690000000000i64, xid::new());
+        let v = token_from_updated(Some(input)).unwrap();
+        let rt = token_to_updated(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, Some(input));
+        let v = token_from_xid(Some(input.1)).unwrap();
+        let rt = token_to_updated(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, None);
+        let rt = token_to_updated(&Some(PackObject::Cbor(vec![0x41, 0x02])));
+        assert_eq!(rt, None);
+        let rt = token_to_updated(&None);
+        assert_eq!(rt, None);
+        assert_eq!(token_from_updated(None), None);
+    }
+
+    #[test]
+    fn token_to_search_works() {
+        let input = db::meili::SearchCursor {
+            updated_at: 1690000000000i64,
+            ids: vec!["a".to_string(), "b".to_string()],
+        };
+        let v = token_from_search(Some(input.clone())).unwrap();
+        let rt = token_to_search(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, Some(input));
+        let v = token_from_xid(Some(xid::new())).unwrap();
+        let rt = token_to_search(&Some(PackObject::Cbor(v)));
+        assert_eq!(rt, None);
+        let rt = token_to_search(&None);
+        assert_eq!(rt, None);
+        assert_eq!(token_from_search(None), None);
+    }
+
     // #[test]
     // fn token_to_publication_works() {
     //     let input = (xid::new(), Language::Zho, 9i16);
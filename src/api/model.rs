@@ -0,0 +1,73 @@
+use axum::extract::State;
+use std::sync::Arc;
+
+use axum_web::erring::{HTTPError, SuccessResponse};
+use axum_web::object::PackObject;
+
+use super::AppState;
+use crate::conf;
+
+// validate_model returns the canonical name of the model, the matching is case-insensitive
+// and the known aliases are mapped to their canonical names.
+pub fn validate_model(cfg: &conf::Models, model: &str) -> Result<String, HTTPError> {
+    let name = model.trim().to_lowercase();
+    let name = cfg
+        .aliases
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(&name))
+        .map_or(name.as_str(), |(_, v)| v.as_str());
+
+    match cfg.allowed.iter().find(|v| v.eq_ignore_ascii_case(name)) {
+        Some(v) => Ok(v.to_owned()),
+        None => Err(HTTPError::new(
+            400,
+            format!(
+                "Invalid model {:?}, expected one of: {}",
+                model,
+                cfg.allowed.join(", ")
+            ),
+        )),
+    }
+}
+
+pub async fn list(
+    State(app): State<Arc<AppState>>,
+    to: PackObject<()>,
+) -> Result<PackObject<SuccessResponse<Vec<String>>>, HTTPError> {
+    Ok(to.with(SuccessResponse::new(app.models.allowed.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_model_works() {
+        let cfg = conf::Models::default();
+
+        assert_eq!(validate_model(&cfg, "gpt-3.5").unwrap(), "gpt-3.5");
+        assert_eq!(validate_model(&cfg, "GPT-4").unwrap(), "gpt-4");
+        assert_eq!(validate_model(&cfg, " gpt-4o ").unwrap(), "gpt-4o");
+
+        // aliases
+        assert_eq!(validate_model(&cfg, "gpt-3.5-turbo").unwrap(), "gpt-3.5");
+        assert_eq!(validate_model(&cfg, "GPT4").unwrap(), "gpt-4");
+        assert_eq!(validate_model(&cfg, "gpt4o-mini").unwrap(), "gpt-4o-mini");
+        assert_eq!(validate_model(&cfg, "Gpt4O-Mini").unwrap(), "gpt-4o-mini");
+
+        let err = validate_model(&cfg, "claude").unwrap_err();
+        assert_eq!(err.code, 400);
+        assert!(err.message.contains("gpt-3.5, gpt-4, gpt-4o, gpt-4o-mini"));
+        assert!(validate_model(&cfg, "").is_err());
+
+        // configured aliases and canonical names
+        let cfg = conf::Models {
+            allowed: vec!["Gemini-Pro".to_string()],
+            aliases: [("gemini".to_string(), "gemini-pro".to_string())].into(),
+            default: "Gemini-Pro".to_string(),
+        };
+        assert_eq!(validate_model(&cfg, "GEMINI").unwrap(), "Gemini-Pro");
+        assert_eq!(validate_model(&cfg, "gemini-pro").unwrap(), "Gemini-Pro");
+        assert!(validate_model(&cfg, "gpt-4").is_err());
+    }
+}
@@ -0,0 +1,974 @@
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    visit::Visitor,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::OnceCell;
+
+use axum_web::erring::{ErrorResponse, SuccessResponse};
+use axum_web::object::PackObject;
+
+use super::{
+    blurhash, bookmark, collection, creation, detect, group, message, publication, search, sitemap,
+    verify, AppInfo, AppVersion, GIDPagination, GrantSubscriptionInput, IDGIDPagination,
+    Pagination, QueryCid, QueryGidCid, QueryGidId, QueryGidIdCid, QueryId, QueryStatsInput,
+    ReadyInfo, SubscriptionInput, SubscriptionOutput, UpdateStatusInput, APP_NAME, APP_VERSION,
+};
+use crate::db;
+
+// the media types of the request and response bodies, see axum_web::object::PackObject.
+const MEDIA_TYPES: [&str; 2] = ["application/json", "application/cbor"];
+
+const ERROR_DESCRIPTION: &str =
+    "Error, `error.data.error` is an ErrorCode if the error has a machine readable reason";
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+enum Input {
+    None,
+    Query(SchemaFn), // the fields of the struct are the query parameters
+    Body(SchemaFn),
+}
+
+enum Output {
+    Success(SchemaFn), // the result of SuccessResponse
+    Plain(SchemaFn),
+    Xml,
+    Todo,
+}
+
+struct Operation {
+    method: &'static str,
+    path: String, // OpenAPI path template, "/v1/:id" is "/v1/{id}"
+    input: Input,
+    output: Output,
+}
+
+fn op(method: &'static str, path: &str, input: Input, output: Output) -> Operation {
+    Operation {
+        method,
+        path: path.to_string(),
+        input,
+        output,
+    }
+}
+
+fn subschema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+fn none() -> Input {
+    Input::None
+}
+
+fn query<T: JsonSchema>() -> Input {
+    Input::Query(T::json_schema)
+}
+
+fn body<T: JsonSchema>() -> Input {
+    Input::Body(subschema::<T>)
+}
+
+fn success<T: JsonSchema>() -> Output {
+    Output::Success(subschema::<SuccessResponse<T>>)
+}
+
+fn plain<T: JsonSchema>() -> Output {
+    Output::Plain(subschema::<T>)
+}
+
+// operations lists all the routes registered in router::new, it should be updated with
+// the router, the operations_match_router test fails when they drift.
+fn operations() -> Vec<Operation> {
+    use collection as coll;
+    use publication as publ;
+
+    let mut ops = vec![
+        op("get", "/", none(), plain::<AppVersion>()),
+        op("get", "/healthz", none(), plain::<AppInfo>()),
+        op("get", "/readyz", none(), plain::<ReadyInfo>()),
+        op("get", "/v1/openapi.json", none(), plain::<Value>()),
+        op(
+            "get",
+            "/v1/search",
+            query::<search::SearchInput>(),
+            success::<db::meili::SearchOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/sitemap.xml",
+            query::<sitemap::SitemapInput>(),
+            Output::Xml,
+        ),
+        op(
+            "get",
+            "/v1/sitemap-{n}.xml",
+            query::<sitemap::SitemapInput>(),
+            Output::Xml,
+        ),
+        op(
+            "get",
+            "/v1/search/in_group",
+            query::<search::SearchInput>(),
+            success::<db::meili::SearchOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/search/by_original_url",
+            query::<search::SearchInput>(),
+            success::<db::meili::SearchOutput>(),
+        ),
+        // creation
+        op(
+            "post",
+            "/v1/creation",
+            body::<creation::CreateCreationInput>(),
+            success::<creation::CreationOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/creation",
+            query::<QueryGidId>(),
+            success::<creation::CreationOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/creation",
+            body::<creation::UpdateCreationInput>(),
+            success::<creation::CreationOutput>(),
+        ),
+        op(
+            "delete",
+            "/v1/creation",
+            query::<QueryGidId>(),
+            success::<bool>(),
+        ),
+        op(
+            "post",
+            "/v1/creation/list",
+            body::<creation::ListCreationInput>(),
+            success::<Vec<creation::CreationOutput>>(),
+        ),
+        op(
+            "patch",
+            "/v1/creation/update_status",
+            body::<UpdateStatusInput>(),
+            success::<creation::CreationOutput>(),
+        ),
+        op(
+            "put",
+            "/v1/creation/update_content",
+            body::<creation::UpdateCreationContentInput>(),
+            success::<creation::CreationOutput>(),
+        ),
+        op("patch", "/v1/creation/update_content", none(), Output::Todo),
+        op(
+            "patch",
+            "/v1/creation/update_price",
+            body::<creation::UpdateCreationPriceInput>(),
+            success::<creation::CreationPriceOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/creation/rfp_stats",
+            query::<creation::QueryRfpStats>(),
+            success::<Vec<creation::RfpStatsOutput>>(),
+        ),
+        op(
+            "get",
+            "/v1/creation/media_refs",
+            query::<QueryGidId>(),
+            success::<Vec<super::MediaRef>>(),
+        ),
+        op(
+            "get",
+            "/v1/creation/quota",
+            query::<creation::QueryCreationQuota>(),
+            success::<creation::CreationQuotaOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/creation/batch_check",
+            body::<creation::BatchCheckInput>(),
+            success::<Vec<creation::BatchCheckOutput>>(),
+        ),
+        op(
+            "get",
+            "/v1/creation/content_history",
+            query::<QueryGidId>(),
+            success::<Vec<creation::ContentHistoryOutput>>(),
+        ),
+        op(
+            "patch",
+            "/v1/creation/rollback_content",
+            body::<creation::RollbackCreationContentInput>(),
+            success::<creation::CreationOutput>(),
+        ),
+        op(
+            "put",
+            "/v1/creation/subscription",
+            body::<SubscriptionInput>(),
+            success::<SubscriptionOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/creation/subscription",
+            query::<QueryId>(),
+            success::<SubscriptionOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/creation/grant_subscription",
+            body::<GrantSubscriptionInput>(),
+            success::<SubscriptionOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/creation/collaborator",
+            body::<creation::CollaboratorInput>(),
+            success::<creation::CollaboratorOutput>(),
+        ),
+        op(
+            "delete",
+            "/v1/creation/collaborator",
+            query::<creation::QueryCollaborator>(),
+            success::<bool>(),
+        ),
+        op(
+            "get",
+            "/v1/creation/collaborators",
+            query::<QueryGidId>(),
+            success::<Vec<creation::CollaboratorOutput>>(),
+        ),
+        op(
+            "post",
+            "/v1/creation/list_by_collaborator",
+            body::<Pagination>(),
+            success::<Vec<creation::CreationOutput>>(),
+        ),
+        // publication
+        op(
+            "post",
+            "/v1/publication",
+            body::<publ::CreatePublicationInput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/publication",
+            query::<publ::QueryPublicationInputput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/publication",
+            body::<publ::UpdatePublicationInput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "delete",
+            "/v1/publication",
+            query::<publ::QueryPublicationInputput>(),
+            success::<bool>(),
+        ),
+        op(
+            "post",
+            "/v1/publication/import",
+            body::<publ::ImportPublicationInput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/publication/source",
+            query::<publ::QueryPublicationInputput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/publication/epub",
+            query::<publ::QueryEpubInput>(),
+            success::<publ::EpubManifest>(),
+        ),
+        op(
+            "post",
+            "/v1/publication/list",
+            body::<GIDPagination>(),
+            success::<Vec<publ::PublicationOutput>>(),
+        ),
+        op(
+            "post",
+            "/v1/publication/batch_get",
+            body::<publ::BatchGetPublicationsInput>(),
+            success::<Vec<publ::BatchGetPublicationOutput>>(),
+        ),
+        op(
+            "patch",
+            "/v1/publication/update_status",
+            body::<publ::UpdatePublicationStatusInput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "put",
+            "/v1/publication/update_content",
+            body::<publ::UpdatePublicationContentInput>(),
+            success::<publ::PublicationOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/publication/update_content",
+            none(),
+            Output::Todo,
+        ),
+    ];
+
+    // the public publication APIs are served under both /v1 and /beta
+    for prefix in ["/v1/publication", "/beta/publication"] {
+        let path = |p: &str| format!("{}{}", prefix, p);
+        ops.extend([
+            op(
+                "get",
+                &path("/implicit_get"),
+                query::<publ::ImplicitQueryPublicationInputput>(),
+                success::<publ::PublicationOutput>(),
+            ),
+            op(
+                "get",
+                &path("/publish"),
+                query::<QueryGidCid>(),
+                success::<Vec<publ::PublicationOutput>>(),
+            ),
+            op(
+                "post",
+                &path("/count_publish"),
+                body::<GIDPagination>(),
+                success::<usize>(),
+            ),
+            op(
+                "post",
+                &path("/list_by_gids"),
+                body::<publ::GidsPagination>(),
+                success::<Vec<publ::PublicationOutput>>(),
+            ),
+            op(
+                "post",
+                &path("/list_latest"),
+                body::<Pagination>(),
+                success::<Vec<publ::PublicationOutput>>(),
+            ),
+            op(
+                "get",
+                &path("/genre_facets"),
+                query::<group::QueryGid>(),
+                success::<HashMap<String, usize>>(),
+            ),
+            op(
+                "get",
+                &path("/related"),
+                query::<publ::QueryRelated>(),
+                success::<Vec<db::meili::DocumentOutput>>(),
+            ),
+        ]);
+    }
+
+    ops.extend([
+        // group
+        op(
+            "get",
+            "/v1/group/quota",
+            query::<group::QueryGid>(),
+            success::<group::QuotaOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/group/quota",
+            body::<group::UpdateQuotaInput>(),
+            success::<group::QuotaOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/group/setting",
+            query::<group::QueryGid>(),
+            success::<group::SettingOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/group/setting",
+            body::<group::UpdateSettingInput>(),
+            success::<group::SettingOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/group/changelog",
+            query::<group::QueryChangelog>(),
+            success::<Vec<group::ChangelogOutput>>(),
+        ),
+        op(
+            "get",
+            "/v1/group/purge_search",
+            query::<group::QueryJobId>(),
+            success::<super::job::JobOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/group/purge_search",
+            body::<group::QueryGid>(),
+            success::<super::job::JobOutput>(),
+        ),
+        // content
+        op(
+            "post",
+            "/v1/content/detect_language",
+            body::<detect::DetectLanguageInput>(),
+            success::<detect::DetectLanguageOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/content/verify",
+            body::<verify::VerifyContentInput>(),
+            success::<Vec<verify::VerifyContentOutput>>(),
+        ),
+        // misc
+        op(
+            "post",
+            "/v1/misc/blurhash",
+            body::<blurhash::BlurhashInput>(),
+            success::<blurhash::BlurhashOutput>(),
+        ),
+        op("get", "/v1/misc/models", none(), success::<Vec<String>>()),
+        op(
+            "get",
+            "/v1/debug/query_stats",
+            query::<QueryStatsInput>(),
+            success::<Vec<db::scylladb::QueryStat>>(),
+        ),
+        // message
+        op(
+            "post",
+            "/v1/message",
+            body::<message::CreateMessageInput>(),
+            success::<message::MessageOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/message",
+            query::<QueryId>(),
+            success::<message::MessageOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/message",
+            body::<message::UpdateMessageInput>(),
+            success::<message::MessageOutput>(),
+        ),
+        op(
+            "delete",
+            "/v1/message",
+            query::<QueryGidId>(),
+            success::<bool>(),
+        ),
+        // collection
+        op(
+            "post",
+            "/v1/collection",
+            body::<coll::CreateCollectionInput>(),
+            success::<coll::CollectionOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/collection",
+            query::<QueryGidId>(),
+            success::<coll::CollectionOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/collection",
+            body::<coll::UpdateCollectionInput>(),
+            success::<coll::CollectionOutput>(),
+        ),
+        op(
+            "delete",
+            "/v1/collection",
+            query::<QueryGidId>(),
+            success::<bool>(),
+        ),
+        op(
+            "get",
+            "/v1/collection/info",
+            query::<QueryGidId>(),
+            success::<message::MessageOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/collection/info",
+            body::<message::UpdateMessageInput>(),
+            success::<message::MessageOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/collection/info_history",
+            query::<QueryGidId>(),
+            success::<Vec<message::MessageHistoryOutput>>(),
+        ),
+        op(
+            "patch",
+            "/v1/collection/restore_info",
+            body::<coll::RestoreInfoInput>(),
+            success::<message::MessageOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/collection/translation_status",
+            query::<QueryGidId>(),
+            success::<message::TranslationStatusOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/collection/update_status",
+            body::<UpdateStatusInput>(),
+            success::<coll::CollectionOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/child",
+            body::<coll::AddChildrenInput>(),
+            success::<Vec<coll::AddChildOutput>>(),
+        ),
+        op(
+            "patch",
+            "/v1/collection/child",
+            body::<coll::UpdateChildrenInput>(),
+            success::<bool>(),
+        ),
+        op(
+            "delete",
+            "/v1/collection/child",
+            query::<QueryGidIdCid>(),
+            success::<bool>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/child/move",
+            body::<coll::MoveChildrenInput>(),
+            success::<Vec<coll::MoveChildOutput>>(),
+        ),
+        op(
+            "get",
+            "/v1/collection/child/search",
+            query::<coll::SearchChildrenInput>(),
+            success::<db::meili::SearchOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/clone",
+            body::<coll::CloneCollectionInput>(),
+            success::<coll::CloneCollectionOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/list",
+            body::<GIDPagination>(),
+            success::<Vec<coll::CollectionOutput>>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/list_latest",
+            body::<Pagination>(),
+            success::<Vec<coll::CollectionOutput>>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/list_children",
+            body::<IDGIDPagination>(),
+            success::<Vec<coll::CollectionChildrenOutput>>(),
+        ),
+        op(
+            "get",
+            "/v1/collection/list_by_child",
+            query::<QueryGidCid>(),
+            success::<Vec<coll::CollectionOutput>>(),
+        ),
+        op(
+            "put",
+            "/v1/collection/subscription",
+            body::<SubscriptionInput>(),
+            success::<SubscriptionOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/collection/subscription",
+            query::<QueryId>(),
+            success::<SubscriptionOutput>(),
+        ),
+        op(
+            "post",
+            "/v1/collection/grant_subscription",
+            body::<GrantSubscriptionInput>(),
+            success::<SubscriptionOutput>(),
+        ),
+        // bookmark
+        op(
+            "post",
+            "/v1/bookmark",
+            body::<bookmark::CreateBookmarkInput>(),
+            success::<bookmark::BookmarkOutput>(),
+        ),
+        op(
+            "get",
+            "/v1/bookmark",
+            query::<QueryId>(),
+            success::<bookmark::BookmarkOutput>(),
+        ),
+        op(
+            "patch",
+            "/v1/bookmark",
+            body::<bookmark::UpdateBookmarkInput>(),
+            success::<bookmark::BookmarkOutput>(),
+        ),
+        op(
+            "delete",
+            "/v1/bookmark",
+            query::<QueryId>(),
+            success::<bool>(),
+        ),
+        op(
+            "get",
+            "/v1/bookmark/by_cid",
+            query::<QueryCid>(),
+            success::<Vec<bookmark::BookmarkOutput>>(),
+        ),
+        op(
+            "post",
+            "/v1/bookmark/list",
+            body::<bookmark::ListBookmarkInput>(),
+            success::<Vec<bookmark::BookmarkOutput>>(),
+        ),
+        op(
+            "post",
+            "/v1/bookmark/batch_delete",
+            body::<bookmark::BatchDeleteBookmarkInput>(),
+            success::<Vec<bookmark::BatchDeleteBookmarkOutput>>(),
+        ),
+    ]);
+
+    for path in [
+        "/v1/sys/creation",
+        "/v1/sys/publication",
+        "/v1/sys/publication/comment",
+        "/v1/sys/bookmark",
+    ] {
+        ops.push(op("patch", path, none(), Output::Todo));
+        ops.push(op("delete", path, none(), Output::Todo));
+    }
+
+    ops
+}
+
+// applies the visitors of the OpenAPI settings, such as replacing the bool schemas,
+// they are only applied to the root schema by schemars.
+fn to_value(gen: &mut SchemaGenerator, mut schema: Schema) -> Value {
+    for v in gen.visitors_mut() {
+        v.visit_schema(&mut schema);
+    }
+    serde_json::to_value(schema).unwrap()
+}
+
+fn content(schema: &Value, media_types: &[&str]) -> Value {
+    let mut res = Map::new();
+    for mt in media_types {
+        res.insert(mt.to_string(), json!({ "schema": schema }));
+    }
+    Value::Object(res)
+}
+
+// query_parameters converts the object schema of a query input to the query parameters.
+fn query_parameters(schema: Value) -> Vec<Value> {
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|v| v.iter().filter_map(|s| s.as_str()).collect())
+        .unwrap_or_default();
+    match schema["properties"].as_object() {
+        Some(props) => props
+            .iter()
+            .map(|(name, schema)| {
+                json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&name.as_str()),
+                    "schema": schema,
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// the "{name}" parameters of the path template, a parameter can be a part of a segment.
+fn path_parameters(path: &str) -> Vec<Value> {
+    path.split('{')
+        .skip(1)
+        .filter_map(|s| s.split_once('}').map(|v| v.0))
+        .map(|name| {
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            })
+        })
+        .collect()
+}
+
+// spec generates the OpenAPI document of the API. The paths are in the order of the router,
+// and the schemas are sorted by name, so the document is stable across builds.
+pub fn spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let schema = gen.subschema_for::<ErrorResponse>();
+    let error = content(&to_value(&mut gen, schema), &MEDIA_TYPES);
+
+    let mut paths = Map::new();
+    for o in operations() {
+        let mut operation = Map::new();
+        let mut parameters = path_parameters(&o.path);
+        match o.input {
+            Input::None => {}
+            Input::Query(f) => {
+                let schema = f(&mut gen);
+                parameters.extend(query_parameters(to_value(&mut gen, schema)))
+            }
+            Input::Body(f) => {
+                let schema = f(&mut gen);
+                let schema = to_value(&mut gen, schema);
+                operation.insert(
+                    "requestBody".to_string(),
+                    json!({ "required": true, "content": content(&schema, &MEDIA_TYPES) }),
+                );
+            }
+        }
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_string(), Value::Array(parameters));
+        }
+
+        let responses = match o.output {
+            Output::Success(f) | Output::Plain(f) => {
+                let schema = f(&mut gen);
+                let schema = to_value(&mut gen, schema);
+                json!({
+                    "200": { "description": "OK", "content": content(&schema, &MEDIA_TYPES) },
+                    "default": { "description": ERROR_DESCRIPTION, "content": error },
+                })
+            }
+            Output::Xml => json!({
+                "200": {
+                    "description": "OK",
+                    "content": content(&json!({ "type": "string" }), &["application/xml"]),
+                },
+                "default": { "description": ERROR_DESCRIPTION, "content": error },
+            }),
+            Output::Todo => json!({
+                "501": { "description": "Not Implemented", "content": error },
+            }),
+        };
+        operation.insert("responses".to_string(), responses);
+
+        let item = paths
+            .entry(o.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[o.method] = Value::Object(operation);
+    }
+
+    let mut schemas: BTreeMap<String, Value> = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(k, v)| (k, to_value(&mut gen, v)))
+        .collect();
+    // the machine readable reasons of the errors, see db::DomainError.
+    let codes: Vec<&str> = db::DomainError::ALL.iter().map(|v| v.code()).collect();
+    schemas.insert(
+        "ErrorCode".to_string(),
+        json!({
+            "type": "string",
+            "description": "The reason of an error in `error.data.error`, the other fields of `error.data` are its details.",
+            "enum": codes,
+        }),
+    );
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": APP_NAME,
+            "version": APP_VERSION,
+            "description": "Request and response bodies are JSON or CBOR by the Content-Type and Accept headers, see the descriptions of the string formats for their representations in CBOR.",
+        },
+        "paths": paths,
+        "components": { "schemas": schemas },
+    })
+}
+
+static SPEC: OnceCell<Value> = OnceCell::const_new();
+
+pub async fn openapi(to: PackObject<()>) -> PackObject<&'static Value> {
+    to.with(SPEC.get_or_init(|| async { spec() }).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+    // the end of the call whose "(" is at the start of s.
+    fn call_end(s: &str) -> usize {
+        let mut depth = 0;
+        let mut quoted = false;
+        for (i, c) in s.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                '(' if !quoted => depth += 1,
+                ')' if !quoted => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        panic!("unbalanced call: {}", s)
+    }
+
+    // the first string literal in s.
+    fn literal(s: &str) -> &str {
+        let s = &s[s.find('"').unwrap() + 1..];
+        &s[..s.find('"').unwrap()]
+    }
+
+    // routes parses the (method, OpenAPI path) pairs registered in router::new from its source.
+    fn routes() -> Vec<(String, String)> {
+        let src = include_str!("../router.rs");
+        let src = &src[src.find("let app = Router::new()").unwrap()..];
+        let src = &src[..src.find(".with_state(").unwrap()];
+
+        let mut res = Vec::new();
+        let mut nests: Vec<(&str, usize)> = Vec::new(); // (prefix, end of the nest call)
+        let mut i = 0;
+        while i < src.len() {
+            nests.retain(|n| n.1 > i);
+            let rest = &src[i..];
+            if rest.starts_with(".nest(") {
+                nests.push((literal(rest), i + 5 + call_end(&rest[5..])));
+            } else if rest.starts_with(".route(") {
+                let call = &rest[..6 + call_end(&rest[6..])];
+                let prefix: String = nests.iter().map(|n| n.0).collect();
+                let path = match literal(call) {
+                    "/" if !prefix.is_empty() => prefix,
+                    p => prefix + p,
+                };
+                let path = path
+                    .split('/')
+                    .map(|seg| match seg.strip_prefix(':') {
+                        Some(name) => format!("{{{}}}", name),
+                        None => seg.to_string(),
+                    })
+                    .collect::<Vec<String>>()
+                    .join("/");
+                for m in METHODS {
+                    if call.contains(&format!("::{}(", m)) || call.contains(&format!(".{}(", m)) {
+                        res.push((m.to_string(), path.clone()));
+                    }
+                }
+                i += call.len();
+                continue;
+            } else if rest.starts_with(".merge(api::sitemap::chunk_routes())") {
+                res.push(("get".to_string(), "/v1/sitemap-{n}.xml".to_string()));
+            }
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+        res
+    }
+
+    #[test]
+    fn operations_match_router() {
+        let mut routes = routes();
+        assert!(routes.len() > 100);
+        let mut ops: Vec<(String, String)> = operations()
+            .into_iter()
+            .map(|o| (o.method.to_string(), o.path))
+            .collect();
+        routes.sort();
+        ops.sort();
+        assert_eq!(ops, routes);
+    }
+
+    #[test]
+    fn spec_works() {
+        let doc = spec();
+        assert_eq!(
+            serde_json::to_string(&doc).unwrap(),
+            serde_json::to_string(&spec()).unwrap()
+        );
+
+        let paths = doc["paths"].as_object().unwrap();
+        let ops: usize = paths.values().map(|v| v.as_object().unwrap().len()).sum();
+        assert_eq!(ops, operations().len());
+
+        let op = &doc["paths"]["/v1/creation"]["post"];
+        assert_eq!(
+            op["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CreateCreationInput"
+        );
+        assert_eq!(
+            op["responses"]["200"]["content"]["application/cbor"]["schema"]["$ref"],
+            "#/components/schemas/SuccessResponse_for_CreationOutput"
+        );
+        assert_eq!(
+            op["responses"]["default"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorResponse"
+        );
+        assert_eq!(op["responses"]["default"]["description"], ERROR_DESCRIPTION);
+
+        let op = &doc["paths"]["/v1/creation"]["get"];
+        let params = op["parameters"].as_array().unwrap();
+        let gid = params.iter().find(|p| p["name"] == "gid").unwrap();
+        assert_eq!(gid["in"], "query");
+        assert_eq!(gid["required"], true);
+        assert_eq!(gid["schema"]["format"], "xid");
+
+        let params = doc["paths"]["/v1/sitemap-{n}.xml"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert_eq!(params[0]["name"], "n");
+        assert_eq!(params[0]["in"], "path");
+
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        let names: Vec<&String> = schemas.keys().collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        for name in [
+            "CreationOutput",
+            "PublicationOutput",
+            "CollectionOutput",
+            "Pagination",
+            "GIDPagination",
+            "HTTPError",
+            "Order",
+            "ErrorCode",
+        ] {
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+        let codes = schemas["ErrorCode"]["enum"].as_array().unwrap();
+        assert_eq!(codes.len(), db::DomainError::ALL.len());
+        assert!(codes.contains(&json!("update_conflict")));
+        assert!(codes.contains(&json!("quota_exceeded")));
+        assert_eq!(
+            schemas["CreationOutput"]["properties"]["language"]["format"],
+            "language"
+        );
+        assert_eq!(
+            schemas["PublicationOutput"]["properties"]["cid"]["format"],
+            "xid"
+        );
+    }
+}
@@ -1,29 +1,50 @@
 use axum::{
     extract::{Query, State},
+    response::{IntoResponse, Response},
     Extension,
 };
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 use validator::Validate;
 
-use axum_web::context::ReqContext;
+use axum_web::context::{unix_ms, ReqContext};
 use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
-use axum_web::object::PackObject;
+use axum_web::object::{cbor_from_slice, PackObject};
 use scylla_orm::ColumnsMap;
 
 use crate::api::{
-    get_fields, segment_content, token_from_xid, token_to_xid, validate_cbor_content, AppState,
-    GIDPagination, Pagination, QueryGidCid, RFPInfo, SubscriptionOutput, RFP,
+    blurhash::{set_cover_columns, validate_blurhash},
+    check_publishable, desc_only,
+    detect::detect_und_language,
+    get_fields,
+    group::{check_quota, check_quota_update, QueryGid},
+    idempotency_lookup, idempotency_save,
+    license::normalize_license,
+    meili_write,
+    model::validate_model,
+    normalize_genre, normalize_keywords, record_rfp_impression, segment_content,
+    sitemap::{hreflang, w3c_date},
+    slice_content, to_html, token_from_gids, token_from_row, token_from_xid, token_to_gids,
+    token_to_row, token_to_xid, validate_cbor_content, AppState, DocumentNode, GIDPagination,
+    MeiliWrite, Pagination, QueryGidCid, RFPInfo, SubscriptionOutput, MAX_CREATION_CONTENT_LEN,
+    RFP,
 };
-use crate::{db, db::meili};
+use crate::{conf, db, db::meili};
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
 pub struct PublicationOutput {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
     pub language: PackObject<Language>,
     pub version: i16,
+    pub reading_direction: String, // "ltr" or "rtl", derived from the language
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rating: Option<i8>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -49,6 +70,8 @@ pub struct PublicationOutput {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cover: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_blurhash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub keywords: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub authors: Option<Vec<String>>,
@@ -58,14 +81,24 @@ pub struct PublicationOutput {
     pub content: Option<PackObject<Vec<u8>>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_length: Option<i32>,
+    // the content failed the integrity verification, it may be damaged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_corrupt: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub license: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub subscription: Option<SubscriptionOutput>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rfp: Option<RFP>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub from_gid: Option<PackObject<xid::Id>>,
+    // the number of top-level content nodes, only when the content is sliced by node range.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_nodes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_more: Option<bool>,
 }
 
 impl PublicationOutput {
@@ -75,8 +108,10 @@ impl PublicationOutput {
             cid: to.with(val.cid),
             language: to.with(val.language),
             version: val.version,
+            reading_direction: db::reading_direction(val.language).to_string(),
             rating: val._rating,
             price: val._price,
+            content_corrupt: if val._corrupt { Some(true) } else { None },
             ..Default::default()
         };
 
@@ -94,6 +129,7 @@ impl PublicationOutput {
                 "genre" => rt.genre = Some(val.genre.to_owned()),
                 "title" => rt.title = Some(val.title.to_owned()),
                 "cover" => rt.cover = Some(val.cover.to_owned()),
+                "cover_blurhash" => rt.cover_blurhash = Some(val.cover_blurhash.to_owned()),
                 "keywords" => rt.keywords = Some(val.keywords.to_owned()),
                 "authors" => rt.authors = Some(val.authors.to_owned()),
                 "summary" => rt.summary = Some(val.summary.to_owned()),
@@ -105,6 +141,7 @@ impl PublicationOutput {
                     }
                 }
                 "license" => rt.license = Some(val.license.to_owned()),
+                "publish_at" => rt.publish_at = Some(val.publish_at),
                 _ => {}
             }
         }
@@ -113,7 +150,7 @@ impl PublicationOutput {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct CreatePublicationInput {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -121,21 +158,26 @@ pub struct CreatePublicationInput {
     #[validate(range(min = 1, max = 10000))]
     pub version: i16,
     pub draft: Option<PublicationDraftInput>,
+    #[validate(length(min = 8, max = 128))]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct PublicationDraftInput {
     pub gid: PackObject<xid::Id>,
     pub language: PackObject<isolang::Language>,
     #[validate(length(min = 2, max = 16))]
     pub model: String,
-    #[validate(length(min = 1, max = 256))]
+    // a blank title or summary is inherited from the source publication.
+    #[validate(length(min = 0, max = 256))]
     pub title: String,
     #[validate(url)]
     pub cover: String,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
     #[validate(length(min = 0, max = 5))]
     pub keywords: Vec<String>,
-    #[validate(length(min = 4, max = 2048))]
+    #[validate(length(min = 0, max = 2048))]
     pub summary: String,
     #[validate(custom = "validate_cbor_content")]
     pub content: PackObject<Vec<u8>>,
@@ -145,7 +187,7 @@ pub async fn create(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
     to: PackObject<CreatePublicationInput>,
-) -> Result<PackObject<SuccessResponse<PublicationOutput>>, HTTPError> {
+) -> Result<Response, HTTPError> {
     let (to, input) = to.unpack();
     input.validate()?;
     valid_user(ctx.user)?;
@@ -159,6 +201,19 @@ pub async fn create(
     ])
     .await;
 
+    let (idempotency, replayed) = idempotency_lookup(
+        &app,
+        ctx.user,
+        &input.idempotency_key,
+        "create_publication",
+        &to.with(&input),
+    )
+    .await?;
+    if let Some(res) = replayed {
+        ctx.set("idempotent_replayed", true.into()).await;
+        return Ok(res);
+    }
+
     let gid = input.gid.unwrap();
     let cid = input.cid.unwrap();
     let language = input.language.unwrap();
@@ -172,11 +227,21 @@ pub async fn create(
     }
 
     let mut doc = if input.draft.is_none() {
-        db::Publication::create_from_creation(&app.scylla, gid, cid, ctx.user).await?
+        check_quota(&app, gid, 0).await?;
+        db::Publication::create_from_creation_with(
+            &app.scylla,
+            gid,
+            cid,
+            ctx.user,
+            app.content.dedup,
+            &app.models.default,
+        )
+        .await?
     } else {
         let draft = input.draft.unwrap();
         let content = draft.content.unwrap();
         let user_gid = draft.gid.unwrap();
+        let model = validate_model(&app.models, &draft.model)?;
         if index.rating > ctx.rating && gid != user_gid {
             return Err(HTTPError::new(451, "Can not view publication".to_string()));
         }
@@ -184,10 +249,12 @@ pub async fn create(
         ctx.set_kvs(vec![
             ("draft.gid", user_gid.to_string().into()),
             ("draft.language", draft.language.to_639_3().into()),
-            ("draft.model", draft.model.as_str().into()),
+            ("draft.model", model.as_str().into()),
         ])
         .await;
 
+        check_quota(&app, user_gid, content.len()).await?;
+
         db::Publication::create_from_publication(
             &app.scylla,
             db::Publication::with_pk(gid, cid, language, input.version),
@@ -196,10 +263,11 @@ pub async fn create(
                 cid,
                 language: draft.language.unwrap(),
                 version: input.version,
-                model: draft.model,
+                model,
                 title: draft.title,
                 cover: draft.cover,
-                keywords: draft.keywords,
+                cover_blurhash: draft.cover_blurhash.unwrap_or_default(),
+                keywords: normalize_keywords(draft.keywords)?,
                 summary: draft.summary,
                 ..Default::default()
             },
@@ -209,10 +277,12 @@ pub async fn create(
     };
 
     let meili_start = ctx.start.elapsed().as_millis() as u64;
-    if let Err(err) = app
-        .meili
-        .add_or_update(meili::Space::Group(doc.gid), vec![doc.to_meili()])
-        .await
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+    )
+    .await
     {
         log::error!(target: "meilisearch",
             action = "add_or_update",
@@ -228,11 +298,198 @@ pub async fn create(
 
     doc._rating = Some(index.rating);
     doc._price = Some(index.price);
-    let output = PublicationOutput::from(doc, &to);
-    Ok(to.with(SuccessResponse::new(output)))
+    let resource = format!(
+        "{}:{}:{}:{}",
+        doc.gid,
+        doc.cid,
+        doc.language.to_639_3(),
+        doc.version
+    );
+    let res = to.with(SuccessResponse::new(PublicationOutput::from(doc, &to)));
+    idempotency_save(idempotency, resource, &res).await;
+    Ok(res.into_response())
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
+pub struct ImportPublicationInput {
+    pub gid: PackObject<xid::Id>,
+    pub language: PackObject<Language>,
+    #[validate(range(min = -1, max = 100000))]
+    pub price: Option<i64>,
+    #[validate(url)]
+    pub original_url: String,
+    pub genre: Option<Vec<String>>,
+    #[validate(length(min = 1, max = 256))]
+    pub title: String,
+    #[validate(length(min = 0, max = 2048))]
+    pub summary: Option<String>,
+    #[validate(url)]
+    pub cover: Option<String>,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
+    #[validate(length(min = 0, max = 5))]
+    pub keywords: Option<Vec<String>>,
+    #[validate(length(min = 0, max = 10))]
+    pub authors: Option<Vec<String>>,
+    #[validate(custom = "validate_cbor_content")]
+    pub content: PackObject<Vec<u8>>,
+    #[validate(length(min = 1, max = 256))]
+    pub license: Option<String>,
+    #[validate(range(min = 0, max = 2))]
+    pub status: Option<i8>,
+    // the original creation time in unix ms, the creation id will carry it when backfilling.
+    #[validate(range(min = 946684800000))]
+    pub created_at: Option<i64>,
+}
+
+// imports an externally authored article, the creation is approved inline and
+// the publication is created in the same request.
+pub async fn import(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<ImportPublicationInput>,
+) -> Result<PackObject<SuccessResponse<PublicationOutput>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    if input.content.len() > MAX_CREATION_CONTENT_LEN {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "content length is too long, expected <= {}, got {}",
+                MAX_CREATION_CONTENT_LEN,
+                input.content.len()
+            ),
+        ));
+    }
+
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can import publication".to_string(),
+        ));
+    }
+
+    let gid = *input.gid.to_owned();
+    let mut language = input.language.unwrap();
+    let detected = detect_und_language(&app.content, language, input.content.unwrap_ref());
+    if let Some(lang) = detected {
+        language = lang;
+    }
+    let status = input.status.unwrap_or(0);
+    ctx.set_kvs(vec![
+        ("action", "import_publication".into()),
+        ("gid", gid.to_string().into()),
+        ("language", language.to_639_3().into()),
+        ("language_detected", detected.is_some().into()),
+        ("original_url", input.original_url.as_str().into()),
+        ("status", status.into()),
+        ("created_at", input.created_at.into()),
+    ])
+    .await;
+
+    let exists = db::Creation::list_by_gid_url(
+        &app.scylla,
+        gid,
+        input.original_url.clone(),
+        vec!["status".to_string()],
+    )
+    .await?;
+    if let Some(doc) = exists.first() {
+        return Err(HTTPError::new(
+            409,
+            format!(
+                "Creation {} with the same original_url already exists",
+                doc.id
+            ),
+        ));
+    }
+
+    check_quota(&app, gid, input.content.len()).await?;
+
+    let price = input.price.unwrap_or(0);
+    let content = input.content.unwrap();
+    let length = content.len() as i32;
+    let mut creation = db::Creation {
+        gid,
+        id: input.created_at.map_or_else(xid::new, db::xid_from_unix_ms),
+        language,
+        creator: ctx.user,
+        original_url: input.original_url,
+        genre: normalize_genre(input.genre.unwrap_or_default())?,
+        title: input.title,
+        cover: input.cover.unwrap_or_default(),
+        cover_blurhash: input.cover_blurhash.unwrap_or_default(),
+        keywords: normalize_keywords(input.keywords.unwrap_or_default())?,
+        authors: db::normalize_names(input.authors.unwrap_or_default()),
+        summary: input.summary.unwrap_or_default(),
+        license: input
+            .license
+            .map(|v| normalize_license(&app.content, v))
+            .transpose()?
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut doc = db::Publication::import(
+        &app.scylla,
+        &mut creation,
+        price,
+        content,
+        status,
+        app.content.dedup,
+        input.created_at.is_some(),
+        &app.models.default,
+    )
+    .await?;
+    ctx.set("cid", doc.cid.to_string().into()).await;
+
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(gid),
+        MeiliWrite::AddOrUpdate(vec![creation.to_meili(), doc.to_meili()]),
+    )
+    .await
+    {
+        log::error!(target: "meilisearch",
+            action = "add_or_update",
+            space = "group",
+            rid = ctx.rid,
+            gid = doc.gid.to_string(),
+            cid = doc.cid.to_string(),
+            kind = 1i8,
+            elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+            "{}", err.to_string(),
+        );
+    }
+    if status == 2 {
+        if let Err(err) = meili_write(
+            &app,
+            meili::Space::Pub(None),
+            MeiliWrite::Upsert(vec![doc.to_meili()]),
+        )
+        .await
+        {
+            log::error!(target: "meilisearch",
+                action = "upsert",
+                space = "pub",
+                rid = ctx.rid,
+                gid = doc.gid.to_string(),
+                cid = doc.cid.to_string(),
+                kind = 1i8,
+                elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+                "{}", err.to_string(),
+            );
+        }
+    }
+
+    doc._price = Some(price);
+    doc._length = length;
+    Ok(to.with(SuccessResponse::new(PublicationOutput::from(doc, &to))))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct QueryPublicationInputput {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -242,6 +499,9 @@ pub struct QueryPublicationInputput {
     pub fields: Option<String>,
     pub parent: Option<PackObject<xid::Id>>,
     pub subscription_in: Option<PackObject<xid::Id>>,
+    pub node_offset: Option<usize>,
+    #[validate(range(min = 1, max = 10000))]
+    pub node_limit: Option<usize>,
 }
 
 pub async fn get(
@@ -287,7 +547,11 @@ pub async fn get(
 
     if index.price <= 0 {
         // it is free
-        return Ok(to.with(SuccessResponse::new(output)));
+        return Ok(to.with(SuccessResponse::new(slice_output(
+            output,
+            input.node_offset,
+            input.node_limit,
+        )?)));
     }
 
     // check subscription
@@ -298,7 +562,11 @@ pub async fn get(
             child.get_one(&app.scylla).await?;
             if gid == index.gid {
                 // available subscription in parent collection
-                return Ok(to.with(SuccessResponse::new(output)));
+                return Ok(to.with(SuccessResponse::new(slice_output(
+                    output,
+                    input.node_offset,
+                    input.node_limit,
+                )?)));
             }
         }
 
@@ -330,10 +598,57 @@ pub async fn get(
         ("subscription", output.subscription.is_some().into()),
     ])
     .await;
+    Ok(to.with(SuccessResponse::new(slice_output(
+        output,
+        input.node_offset,
+        input.node_limit,
+    )?)))
+}
+
+pub async fn get_source(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryPublicationInputput>,
+) -> Result<PackObject<SuccessResponse<PublicationOutput>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    let cid = *input.cid.to_owned();
+    let language = *input.language.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "get_publication_source".into()),
+        ("gid", gid.to_string().into()),
+        ("cid", cid.to_string().into()),
+        ("language", language.to_639_3().into()),
+        ("version", input.version.into()),
+    ])
+    .await;
+
+    let mut index = db::CreationIndex::with_pk(cid);
+    if index.get_one(&app.scylla).await.is_err() {
+        return Err(HTTPError::new(404, "Creation not exists".to_string()));
+    }
+    if gid != index.gid && ctx.rating < index.rating {
+        return Err(HTTPError::new(451, "Can not view publication".to_string()));
+    }
+
+    let mut doc = db::Publication::with_pk(gid, cid, language, input.version);
+    let mut src = doc
+        .get_source(&app.scylla, get_fields(input.fields.clone()))
+        .await?;
+    ctx.set("source_language", src.language.to_639_3().into())
+        .await;
+
+    src._rating = Some(index.rating);
+    src._price = Some(index.price);
+    let mut output = PublicationOutput::from(src, &to);
+    output.from_gid = Some(to.with(index.gid));
     Ok(to.with(SuccessResponse::new(output)))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct ImplicitQueryPublicationInputput {
     pub cid: PackObject<xid::Id>,
     pub gid: Option<PackObject<xid::Id>>,
@@ -341,6 +656,9 @@ pub struct ImplicitQueryPublicationInputput {
     pub fields: Option<String>,
     pub parent: Option<PackObject<xid::Id>>,
     pub subscription_in: Option<PackObject<xid::Id>>,
+    pub node_offset: Option<usize>,
+    #[validate(range(min = 1, max = 10000))]
+    pub node_limit: Option<usize>,
 }
 
 pub async fn implicit_get(
@@ -373,7 +691,10 @@ pub async fn implicit_get(
     if index.get_one(&app.scylla).await.is_err() {
         return Err(HTTPError::new(404, "Creation not exists".to_string()));
     }
-    if gid != index.gid && ctx.rating < index.rating {
+    // only the owner group can bypass the rating check,
+    // publications will be resolved from the given gid if provided.
+    let is_owner = gid > db::MIN_ID && gid == index.gid;
+    if !is_owner && ctx.rating < index.rating {
         return Err(HTTPError::new(451, "Can not view publication".to_string()));
     }
 
@@ -390,7 +711,11 @@ pub async fn implicit_get(
 
     if index.price <= 0 {
         // it is free
-        return Ok(to.with(SuccessResponse::new(output)));
+        return Ok(to.with(SuccessResponse::new(slice_output(
+            output,
+            input.node_offset,
+            input.node_limit,
+        )?)));
     }
 
     // check subscription
@@ -401,7 +726,11 @@ pub async fn implicit_get(
             child.get_one(&app.scylla).await?;
             if gid == index.gid {
                 // available subscription in parent collection
-                return Ok(to.with(SuccessResponse::new(output)));
+                return Ok(to.with(SuccessResponse::new(slice_output(
+                    output,
+                    input.node_offset,
+                    input.node_limit,
+                )?)));
             }
         }
 
@@ -417,6 +746,7 @@ pub async fn implicit_get(
         output.rfp = rfp;
         if output.rfp.is_some() {
             output.content = segment_content(output.content, 0.6);
+            record_rfp_impression(&app, cid, ctx.unix_ms);
         }
         output.subscription = subscription.map(|s| SubscriptionOutput {
             uid: to.with(s.uid),
@@ -433,7 +763,34 @@ pub async fn implicit_get(
         ("subscription", output.subscription.is_some().into()),
     ])
     .await;
-    Ok(to.with(SuccessResponse::new(output)))
+    Ok(to.with(SuccessResponse::new(slice_output(
+        output,
+        input.node_offset,
+        input.node_limit,
+    )?)))
+}
+
+// slices the content by the top-level node range if the client asks for it, after the
+// paywall segmenting.
+fn slice_output(
+    mut output: PublicationOutput,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<PublicationOutput, HTTPError> {
+    if offset.is_none() && limit.is_none() {
+        return Ok(output);
+    }
+
+    if let Some(content) = output.content.take() {
+        let to = content.unit();
+        let offset = offset.unwrap_or(0);
+        let limit = limit.unwrap_or(usize::MAX);
+        let (data, total) = slice_content(content.unwrap_ref(), offset, limit)?;
+        output.content = Some(to.with(data));
+        output.total_nodes = Some(total);
+        output.has_more = Some(offset.saturating_add(limit) < total);
+    }
+    Ok(output)
 }
 
 async fn try_get_subscription(
@@ -513,6 +870,151 @@ async fn try_get_subscription(
     (Some(rfp), subscription)
 }
 
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryEpubInput {
+    pub cid: PackObject<xid::Id>,
+    pub gid: Option<PackObject<xid::Id>>,
+    pub language: Option<PackObject<isolang::Language>>,
+    pub parent: Option<PackObject<xid::Id>>,
+}
+
+// EpubManifest is the metadata and the XHTML body for an EPUB packer, the required package
+// metadata are identifier, title, language and modified.
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct EpubManifest {
+    pub identifier: String, // dc:identifier
+    pub title: String,      // dc:title
+    pub language: String,   // dc:language, a BCP 47 language tag
+    pub modified: String,   // dcterms:modified, "CCYY-MM-DDThh:mm:ssZ"
+    pub authors: Vec<String>,
+    pub cover: Option<String>,
+    pub license: Option<String>,
+    pub summary: Option<String>,
+    pub keywords: Vec<String>,
+    pub html: String,
+}
+
+// epub_modified formats the unix time in ms as the dcterms:modified date time in UTC.
+fn epub_modified(unix_ms: i64) -> String {
+    let secs = unix_ms.div_euclid(1000).rem_euclid(24 * 3600);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        w3c_date(unix_ms),
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s.to_string())
+    }
+}
+
+// epub_manifest composes the manifest from the publication loaded with its content.
+pub fn epub_manifest(doc: &db::Publication) -> Result<EpubManifest, HTTPError> {
+    let content = cbor_from_slice::<DocumentNode>(&doc._content).map_err(|err| {
+        HTTPError::new(500, format!("Invalid publication content, error: {}", err))
+    })?;
+
+    Ok(EpubManifest {
+        identifier: format!(
+            "urn:yiwen:{}:{}:{}",
+            doc.cid,
+            doc.language.to_639_3(),
+            doc.version
+        ),
+        title: doc.title.clone(),
+        language: hreflang(doc.language),
+        modified: epub_modified(doc.updated_at),
+        authors: doc.authors.clone(),
+        cover: non_empty(&doc.cover),
+        license: non_empty(&doc.license),
+        summary: non_empty(&doc.summary),
+        keywords: doc.keywords.clone(),
+        html: to_html(&content),
+    })
+}
+
+// epub returns the EPUB manifest of the implicit published publication, the paid one is only
+// available to the subscribers.
+pub async fn epub(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryEpubInput>,
+) -> Result<PackObject<SuccessResponse<EpubManifest>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let cid = *input.cid.to_owned();
+    let gid = *input.gid.to_owned().unwrap_or_default();
+    let mut language = *input.language.to_owned().unwrap_or_default();
+    if language == Language::Und {
+        language = ctx.language.unwrap_or_default()
+    }
+    let parent = *input.parent.to_owned().unwrap_or_default();
+
+    ctx.set_kvs(vec![
+        ("action", "get_publication_epub".into()),
+        ("gid", gid.to_string().into()),
+        ("cid", cid.to_string().into()),
+        ("language", language.to_639_3().into()),
+    ])
+    .await;
+
+    let mut index = db::CreationIndex::with_pk(cid);
+    if index.get_one(&app.scylla).await.is_err() {
+        return Err(HTTPError::new(404, "Creation not exists".to_string()));
+    }
+    let is_owner = gid > db::MIN_ID && gid == index.gid;
+    if !is_owner && ctx.rating < index.rating {
+        return Err(HTTPError::new(451, "Can not view publication".to_string()));
+    }
+
+    if !is_owner && index.price > 0 {
+        let (rfp, _) = try_get_subscription(
+            &app.scylla,
+            &index,
+            ctx.user,
+            parent,
+            ctx.unix_ms as i64,
+            &to,
+        )
+        .await;
+        if rfp.is_some() {
+            return Err(HTTPError::new(
+                402,
+                "Subscription required to download publication".to_string(),
+            ));
+        }
+    }
+
+    let idoc =
+        db::PublicationIndex::get_implicit_published(&app.scylla, cid, gid, language).await?;
+    let mut doc: db::Publication = idoc.into();
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "updated_at".to_string(),
+            "title".to_string(),
+            "cover".to_string(),
+            "keywords".to_string(),
+            "authors".to_string(),
+            "summary".to_string(),
+            "content".to_string(),
+            "license".to_string(),
+        ],
+    )
+    .await?;
+
+    ctx.set("version", doc.version.into()).await;
+    Ok(to.with(SuccessResponse::new(epub_manifest(&doc)?)))
+}
+
 pub async fn list(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
@@ -524,32 +1026,45 @@ pub async fn list(
 
     let gid = input.gid.unwrap();
     let page_size = input.page_size.unwrap_or(10);
+    let order = input.order.unwrap_or_default();
+    let collapse = input.collapse.unwrap_or(true);
     ctx.set_kvs(vec![
         ("action", "list_publication".into()),
         ("gid", gid.to_string().into()),
         ("page_size", page_size.into()),
+        ("collapse", collapse.into()),
+        ("order", serde_json::to_value(order).unwrap_or_default()),
     ])
     .await;
 
     let fields = input.fields.unwrap_or_default();
+    let (page_token, row) = token_to_row(&input.page_token);
     let res = db::Publication::list_by_gid(
         &app.scylla,
         gid,
         fields,
         page_size,
-        token_to_xid(&input.page_token),
+        page_token,
+        if collapse { None } else { row },
         input.status,
         ctx.language,
+        collapse,
+        order,
     )
     .await?;
 
+    let last = if res.len() >= page_size as usize {
+        res.last()
+    } else {
+        None
+    };
     Ok(to.with(SuccessResponse {
         total_size: None,
-        next_page_token: to.with_option(token_from_xid(if res.len() >= page_size as usize {
-            Some(res.last().unwrap().cid)
+        next_page_token: to.with_option(if collapse {
+            token_from_xid(last.map(|v| v.cid))
         } else {
-            None
-        })),
+            token_from_row(last.map(|v| (v.cid, v.language, v.version)))
+        }),
         result: res
             .iter()
             .map(|r| PublicationOutput::from(r.to_owned(), &to))
@@ -557,12 +1072,15 @@ pub async fn list(
     }))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct GidsPagination {
     pub gids: Vec<PackObject<xid::Id>>,
     pub page_token: Option<PackObject<Vec<u8>>>,
     #[validate(range(min = 2, max = 1000))]
     pub page_size: Option<u16>,
+    // the max number of publications from a group in a page.
+    #[validate(range(min = 1, max = 100))]
+    pub per_group: Option<u16>,
     pub fields: Option<Vec<String>>,
 }
 
@@ -575,25 +1093,36 @@ pub async fn list_by_gids(
     input.validate()?;
     valid_user(ctx.user)?;
 
+    let page_size = input.page_size.unwrap_or(10);
+    let per_group = input.per_group.unwrap_or(5);
     ctx.set_kvs(vec![
         ("action", "list_publications_by_gids".into()),
         ("gids", input.gids.len().into()),
+        ("page_size", page_size.into()),
+        ("per_group", per_group.into()),
     ])
     .await;
 
     let fields = input.fields.unwrap_or_default();
-    let (res, next_page_token) = db::PublicationIndex::list_by_gids(
+    let (res, next_page_token, scan) = db::PublicationIndex::list_by_gids(
         &app.scylla,
         input.gids.into_iter().map(|v| v.unwrap()).collect(),
-        token_to_xid(&input.page_token),
+        token_to_gids(&input.page_token),
         ctx.language,
+        per_group as usize,
+        page_size as usize,
     )
     .await?;
+    ctx.set(
+        "scan_stats",
+        serde_json::to_value(&scan).unwrap_or_default(),
+    )
+    .await;
 
     let docs = db::Publication::batch_get(&app.scylla, res, fields).await?;
     Ok(to.with(SuccessResponse {
         total_size: None,
-        next_page_token: to.with_option(token_from_xid(next_page_token)),
+        next_page_token: to.with_option(token_from_gids(next_page_token)),
         result: docs
             .iter()
             .map(|r| PublicationOutput::from(r.to_owned(), &to))
@@ -610,21 +1139,27 @@ pub async fn list_latest(
     input.validate()?;
     valid_user(ctx.user)?;
 
+    desc_only(input.order)?;
     ctx.set_kvs(vec![("action", "list_latest_publications".into())])
         .await;
 
     let fields = input.fields.unwrap_or_default();
-    let (res, next_page_token) = db::PublicationIndex::list_latest(
+    let (res, next_page_token, scan) = db::PublicationIndex::list_latest(
         &app.scylla,
         token_to_xid(&input.page_token),
         ctx.language,
     )
     .await?;
+    ctx.set(
+        "scan_stats",
+        serde_json::to_value(&scan).unwrap_or_default(),
+    )
+    .await;
 
     let docs = db::Publication::batch_get(&app.scylla, res, fields).await?;
     Ok(to.with(SuccessResponse {
         total_size: None,
-        next_page_token: to.with_option(token_from_xid(next_page_token)),
+        next_page_token: to.with_option(token_from_gids(next_page_token)),
         result: docs
             .iter()
             .map(|r| PublicationOutput::from(r.to_owned(), &to))
@@ -632,6 +1167,114 @@ pub async fn list_latest(
     }))
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PublicationKeyInput {
+    pub gid: PackObject<xid::Id>,
+    pub cid: PackObject<xid::Id>,
+    pub language: PackObject<isolang::Language>,
+    pub version: i16,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct BatchGetPublicationsInput {
+    #[validate(length(min = 1, max = 100))]
+    pub items: Vec<PublicationKeyInput>,
+    pub fields: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct BatchGetPublicationOutput {
+    pub gid: PackObject<xid::Id>,
+    pub cid: PackObject<xid::Id>,
+    pub language: PackObject<Language>,
+    pub version: i16,
+    pub found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publication: Option<PublicationOutput>,
+}
+
+pub async fn batch_get(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<BatchGetPublicationsInput>,
+) -> Result<PackObject<SuccessResponse<Vec<BatchGetPublicationOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    ctx.set_kvs(vec![
+        ("action", "batch_get_publications".into()),
+        ("items", input.items.len().into()),
+    ])
+    .await;
+
+    let list: Vec<db::PublicationIndex> = input
+        .items
+        .iter()
+        .map(|v| db::PublicationIndex {
+            gid: *v.gid.to_owned(),
+            cid: *v.cid.to_owned(),
+            language: *v.language.to_owned(),
+            version: v.version,
+            ..Default::default()
+        })
+        .collect();
+
+    // the creation owner can always view, others are restricted by rating.
+    let mut cids: Vec<xid::Id> = list.iter().map(|v| v.cid).collect();
+    cids.sort_by(|a, b| a.0.cmp(&b.0));
+    cids.dedup();
+    let indexes: HashMap<xid::Id, db::CreationIndex> =
+        db::CreationIndex::batch_get(&app.scylla, cids, i8::MAX)
+            .await?
+            .into_iter()
+            .map(|v| (v.id, v))
+            .collect();
+    let allowed: Vec<db::PublicationIndex> = list
+        .iter()
+        .filter(|v| match indexes.get(&v.cid) {
+            Some(index) => v.gid == index.gid || ctx.rating >= index.rating,
+            None => false,
+        })
+        .cloned()
+        .collect();
+
+    let fields = input.fields.unwrap_or_default();
+    let (docs, missing) = db::Publication::batch_get_partial(&app.scylla, allowed, fields).await?;
+    ctx.set_kvs(vec![
+        ("found", docs.len().into()),
+        ("missing", missing.len().into()),
+    ])
+    .await;
+
+    let mut docs: HashMap<(xid::Id, xid::Id, Language, i16), db::Publication> = docs
+        .into_iter()
+        .map(|v| ((v.gid, v.cid, v.language, v.version), v))
+        .collect();
+    let res: Vec<BatchGetPublicationOutput> = list
+        .into_iter()
+        .map(|v| {
+            let doc = docs.remove(&(v.gid, v.cid, v.language, v.version));
+            BatchGetPublicationOutput {
+                gid: to.with(v.gid),
+                cid: to.with(v.cid),
+                language: to.with(v.language),
+                version: v.version,
+                found: doc.is_some(),
+                publication: doc.map(|mut doc| {
+                    if let Some(index) = indexes.get(&doc.cid) {
+                        doc._rating = Some(index.rating);
+                        doc._price = Some(index.price);
+                    }
+                    PublicationOutput::from(doc, &to)
+                }),
+            }
+        })
+        .collect();
+
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
 pub async fn get_publish_list(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
@@ -703,7 +1346,193 @@ pub async fn count_publish(
     Ok(to.with(SuccessResponse::new(res)))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+// counts the group's published documents by genre from meili facets,
+// falls back to a bounded scylla scan if meili is unavailable.
+pub async fn genre_facets(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryGid>,
+) -> Result<PackObject<SuccessResponse<HashMap<String, usize>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let gid = *input.gid.to_owned();
+    ctx.set_kvs(vec![
+        ("action", "genre_facets".into()),
+        ("gid", gid.to_string().into()),
+    ])
+    .await;
+
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    let res = match app.meili.genre_facets(gid).await {
+        Ok(res) => {
+            ctx.set("facets_source", "meili".into()).await;
+            res
+        }
+        Err(err) => {
+            log::warn!(target: "meilisearch",
+                action = "genre_facets",
+                rid = ctx.rid,
+                gid = gid.to_string(),
+                elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+                "{}", err.to_string(),
+            );
+            ctx.set("facets_source", "scylla".into()).await;
+            db::Publication::count_genre_by_gid(&app.scylla, gid, db::GENRE_SCAN_LIMIT).await?
+        }
+    };
+
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct QueryRelated {
+    pub cid: PackObject<xid::Id>,
+    pub language: Option<PackObject<Language>>,
+    #[validate(range(min = 1, max = 20))]
+    pub page_size: Option<u16>,
+}
+
+// the max number of latest publications scanned by the related fallback.
+const RELATED_SCAN_LIMIT: usize = 200;
+
+pub async fn related(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<()>,
+    input: Query<QueryRelated>,
+) -> Result<PackObject<SuccessResponse<Vec<meili::DocumentOutput>>>, HTTPError> {
+    input.validate()?;
+    valid_user(ctx.user)?;
+
+    let cid = *input.cid.to_owned();
+    let mut language = *input.language.to_owned().unwrap_or_default();
+    if language == Language::Und {
+        language = ctx.language.unwrap_or_default()
+    }
+    let page_size = input.page_size.unwrap_or(10) as usize;
+    ctx.set_kvs(vec![
+        ("action", "related_publications".into()),
+        ("cid", cid.to_string().into()),
+        ("language", language.to_639_3().into()),
+    ])
+    .await;
+
+    let idoc = db::PublicationIndex::get_implicit_published(
+        &app.scylla,
+        cid,
+        xid::Id::default(),
+        language,
+    )
+    .await?;
+    let mut doc: db::Publication = idoc.into();
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "updated_at".to_string(),
+            "genre".to_string(),
+            "keywords".to_string(),
+        ],
+    )
+    .await?;
+    let source = doc.to_meili();
+    let lang = if language == Language::Und {
+        None
+    } else {
+        Some(language)
+    };
+
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    // fetches more than a page, the hits above the reader's rating are dropped below.
+    let docs = match app.meili.related(&source, lang, page_size * 2).await {
+        Ok(docs) => {
+            ctx.set("related_source", "meili".into()).await;
+            docs
+        }
+        Err(err) => {
+            log::warn!(target: "meilisearch",
+                action = "related",
+                rid = ctx.rid,
+                cid = cid.to_string(),
+                elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+                "{}", err.to_string(),
+            );
+            ctx.set("related_source", "scylla".into()).await;
+            related_latest(&app.scylla, &source, lang, page_size * 2).await?
+        }
+    };
+
+    let mut cids: Vec<xid::Id> = docs.iter().map(|v| *v.extract_id().0).collect();
+    cids.sort_by(|a, b| a.0.cmp(&b.0));
+    cids.dedup();
+    let allowed: HashSet<xid::Id> = if cids.is_empty() {
+        HashSet::new()
+    } else {
+        db::CreationIndex::batch_get(&app.scylla, cids, ctx.rating)
+            .await?
+            .into_iter()
+            .map(|v| v.id)
+            .collect()
+    };
+
+    let res: Vec<meili::DocumentOutput> = docs
+        .into_iter()
+        .filter(|v| allowed.contains(&*v.extract_id().0))
+        .take(page_size)
+        .map(|v| meili::DocumentOutput::from(v, &to))
+        .collect();
+    ctx.set("result", res.len().into()).await;
+    Ok(to.with(SuccessResponse::new(res)))
+}
+
+// related_latest ranks the latest publications that share a genre with the source, it is
+// used when MeiliSearch is unavailable.
+async fn related_latest(
+    db: &db::scylladb::ScyllaDB,
+    source: &meili::Document,
+    language: Option<Language>,
+    limit: usize,
+) -> anyhow::Result<Vec<meili::Document>> {
+    let genre = source.genre.clone().unwrap_or_default();
+    if genre.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let fields = vec![
+        "updated_at".to_string(),
+        "genre".to_string(),
+        "title".to_string(),
+        "keywords".to_string(),
+        "summary".to_string(),
+    ];
+    let mut docs: Vec<meili::Document> = Vec::new();
+    let mut page_token: Option<xid::Id> = None;
+    let mut scanned = 0usize;
+    while scanned < RELATED_SCAN_LIMIT {
+        let (list, next, scan) =
+            db::PublicationIndex::list_latest(db, page_token, language).await?;
+        if list.is_empty() {
+            break;
+        }
+        scanned += list.len();
+        let (res, _) = db::Publication::batch_get_partial(db, list, fields.clone()).await?;
+        docs.extend(
+            res.iter()
+                .filter(|v| v.genre.iter().any(|g| genre.contains(g)))
+                .map(|v| v.to_meili()),
+        );
+        page_token = next;
+        // stops at the request deadline with what has been scanned.
+        if page_token.is_none() || scan.stopped_early() {
+            break;
+        }
+    }
+
+    Ok(meili::rank_related(source, docs, limit))
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdatePublicationStatusInput {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -713,6 +1542,12 @@ pub struct UpdatePublicationStatusInput {
     pub updated_at: i64,
     #[validate(range(min = -1, max = 2))]
     pub status: i8,
+    // publishes without checking the content, only for the system user.
+    pub force: Option<bool>,
+    // schedules the publishing from status 1 to 2 at the time in ms, a time not in the future
+    // publishes immediately.
+    #[validate(range(min = 0))]
+    pub publish_at: Option<i64>,
 }
 
 pub async fn update_status(
@@ -738,6 +1573,45 @@ pub async fn update_status(
     .await;
 
     let mut doc = db::Publication::with_pk(gid, cid, language, input.version);
+    if input.status == 2 {
+        let force = input.force.unwrap_or_default();
+        if force {
+            if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+                return Err(HTTPError::new(
+                    403,
+                    "Only system user can force publishing".to_string(),
+                ));
+            }
+            ctx.set("force", true.into()).await;
+        }
+        check_publishing(&app, &mut doc, !force).await?;
+
+        if let Some(publish_at) = input.publish_at {
+            if !db::ScheduledPublication::is_due(publish_at, ctx.unix_ms as i64) {
+                if force {
+                    return Err(HTTPError::new(
+                        400,
+                        "Forced publishing can not be scheduled".to_string(),
+                    ));
+                }
+                let ok = doc
+                    .schedule(&app.scylla, publish_at, ctx.user, input.updated_at)
+                    .await?;
+                ctx.set_kvs(vec![
+                    ("scheduled", ok.into()),
+                    ("publish_at", publish_at.into()),
+                ])
+                .await;
+
+                doc._fields = vec![
+                    "updated_at".to_string(),
+                    "status".to_string(),
+                    "publish_at".to_string(),
+                ];
+                return Ok(to.with(SuccessResponse::new(PublicationOutput::from(doc, &to))));
+            }
+        }
+    }
 
     let ok = doc
         .update_status(&app.scylla, input.status, input.updated_at)
@@ -745,27 +1619,11 @@ pub async fn update_status(
     ctx.set("updated", ok.into()).await;
 
     if ok && input.status == 2 {
-        // get full doc for meili
-        doc.get_one(
-            &app.scylla,
-            vec![
-                "updated_at".to_string(),
-                "genre".to_string(),
-                "title".to_string(),
-                "keywords".to_string(),
-                "authors".to_string(),
-                "summary".to_string(),
-            ],
-        )
-        .await?;
         let meili_start = ctx.start.elapsed().as_millis() as u64;
-        if let Err(err) = app
-            .meili
-            .add_or_update(meili::Space::Pub(None), vec![doc.to_meili()])
-            .await
-        {
-            log::error!(target: "meilisearch",
-                action = "add_or_update",
+        match index_published(&app, &mut doc).await {
+            Ok(n) => ctx.set("meili_indexed", n.into()).await,
+            Err(err) => log::error!(target: "meilisearch",
+                action = "upsert",
                 space = "pub",
                 rid = ctx.rid,
                 gid = doc.gid.to_string(),
@@ -773,7 +1631,7 @@ pub async fn update_status(
                 kind = 1i8,
                 elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
                 "{}", err.to_string(),
-            );
+            ),
         }
     }
 
@@ -781,7 +1639,182 @@ pub async fn update_status(
     Ok(to.with(SuccessResponse::new(PublicationOutput::from(doc, &to))))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+// the checks before publishing, the forced publishing skips the content check only.
+async fn check_publishing(
+    app: &AppState,
+    doc: &mut db::Publication,
+    check_content: bool,
+) -> Result<(), HTTPError> {
+    if check_content {
+        doc.get_one(&app.scylla, vec!["content".to_string()])
+            .await?;
+        if doc.status != 2 {
+            check_publishable(&app.publish, &doc._content)?;
+        }
+        doc._content = Vec::new();
+    }
+
+    // the group's rating policy applies to the forced publishing too
+    let mut index = db::CreationIndex::with_pk(doc.cid);
+    index.get_one(&app.scylla).await?;
+    db::GroupSetting::load(&app.scylla, doc.gid)
+        .await?
+        .check_rating(index.rating)?;
+    Ok(())
+}
+
+// indexes the published publication in meili, returns the number of documents indexed.
+async fn index_published(app: &AppState, doc: &mut db::Publication) -> anyhow::Result<usize> {
+    // get full doc for meili, with the word count of the content
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "updated_at".to_string(),
+            "genre".to_string(),
+            "title".to_string(),
+            "keywords".to_string(),
+            "authors".to_string(),
+            "summary".to_string(),
+            "content_length".to_string(),
+        ],
+    )
+    .await?;
+    meili_write(
+        app,
+        meili::Space::Pub(None),
+        MeiliWrite::Upsert(vec![doc.to_meili()]),
+    )
+    .await
+}
+
+// the interval of the scheduled publishing task, it rescans a few past minutes in case the
+// clocks of the instances are skewed. It starts from the oldest pending schedule.
+const SCHEDULE_INTERVAL_SECS: u64 = 30;
+const SCHEDULE_SKEW_MINUTES: i64 = 2;
+
+// run_schedule publishes the scheduled publications when they are due, it runs forever.
+pub async fn run_schedule(app: Arc<AppState>) {
+    let now_minute = db::ScheduledPublication::minute_of(unix_ms() as i64);
+    let mut from = match db::ScheduledPublication::oldest_minute(&app.scylla).await {
+        Ok(oldest) => oldest
+            .unwrap_or(now_minute)
+            .min(now_minute - SCHEDULE_SKEW_MINUTES),
+        Err(err) => {
+            log::error!(target: "scheduler",
+                action = "oldest_scheduled_publication";
+                "{}", err.to_string(),
+            );
+            now_minute - SCHEDULE_SKEW_MINUTES
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULE_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        from = publish_scheduled(&app, from, unix_ms() as i64).await;
+    }
+}
+
+// publishes the due publications scheduled since the minute `from`, returns the minute to
+// scan from next time. A schedule row is deleted after it is published or rejected, the
+// ones failed on transient errors stay and are claimed again after their lease expires.
+async fn publish_scheduled(app: &AppState, from: i64, now_ms: i64) -> i64 {
+    let (items, mut next) =
+        match db::ScheduledPublication::claim_due(&app.scylla, from, now_ms).await {
+            Ok(res) => res,
+            Err(err) => {
+                log::error!(target: "scheduler",
+                    action = "claim_scheduled_publications",
+                    from = from;
+                    "{}", err.to_string(),
+                );
+                return from;
+            }
+        };
+
+    for mut item in items {
+        let res = publish_scheduled_one(app, &item).await;
+        let delay = now_ms - item.publish_at;
+        match res {
+            Ok(published) => log::info!(target: "scheduler",
+                action = "publish_scheduled",
+                uid = item.scheduler.to_string(),
+                gid = item.gid.to_string(),
+                cid = item.cid.to_string(),
+                language = item.language.to_639_3(),
+                version = item.version,
+                published = published,
+                delay = delay;
+                "scheduled publication processed",
+            ),
+            Err(ref err) => log::error!(target: "scheduler",
+                action = "publish_scheduled",
+                uid = item.scheduler.to_string(),
+                gid = item.gid.to_string(),
+                cid = item.cid.to_string(),
+                language = item.language.to_639_3(),
+                version = item.version,
+                delay = delay;
+                "{}", err.to_string(),
+            ),
+        }
+
+        match res {
+            Err(err) if err.code >= 500 => next = next.min(item.minute),
+            _ => {
+                if let Err(err) = item.delete(&app.scylla).await {
+                    log::error!(target: "scheduler",
+                        action = "delete_scheduled_publication",
+                        gid = item.gid.to_string(),
+                        cid = item.cid.to_string(),
+                        language = item.language.to_639_3(),
+                        version = item.version;
+                        "{}", err.to_string(),
+                    );
+                    next = next.min(item.minute);
+                }
+            }
+        }
+    }
+
+    next.min(db::ScheduledPublication::minute_of(now_ms) - SCHEDULE_SKEW_MINUTES)
+}
+
+// the normal publishing of a claimed schedule, returns false if the publication is not
+// scheduled at the time anymore.
+async fn publish_scheduled_one(
+    app: &AppState,
+    item: &db::ScheduledPublication,
+) -> Result<bool, HTTPError> {
+    let mut doc = db::Publication::with_pk(item.gid, item.cid, item.language, item.version);
+    if let Err(err) = doc
+        .get_one(
+            &app.scylla,
+            vec![
+                "status".to_string(),
+                "updated_at".to_string(),
+                "publish_at".to_string(),
+            ],
+        )
+        .await
+    {
+        let err: HTTPError = err.into();
+        // the publication was deleted after it was scheduled
+        return if err.code == 404 { Ok(false) } else { Err(err) };
+    }
+    if doc.status != 1 || doc.publish_at != item.publish_at {
+        return Ok(false);
+    }
+
+    check_publishing(app, &mut doc, true).await?;
+    let updated_at = doc.updated_at;
+    if !doc.update_status(&app.scylla, 2, updated_at).await? {
+        return Ok(false);
+    }
+    index_published(app, &mut doc).await?;
+    Ok(true)
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdatePublicationInput {
     pub cid: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -793,27 +1826,29 @@ pub struct UpdatePublicationInput {
     pub title: Option<String>,
     #[validate(url)]
     pub cover: Option<String>,
+    #[validate(custom = "validate_blurhash")]
+    pub cover_blurhash: Option<String>,
     #[validate(length(min = 0, max = 5))]
     pub keywords: Option<Vec<String>>,
     #[validate(length(min = 4, max = 2048))]
     pub summary: Option<String>,
+    pub genre: Option<Vec<String>>,
+    #[validate(length(min = 2, max = 16))]
+    pub model: Option<String>,
 }
 
 impl UpdatePublicationInput {
-    fn into(self) -> anyhow::Result<ColumnsMap> {
+    fn into(self, models: &conf::Models) -> anyhow::Result<ColumnsMap> {
         let mut cols = ColumnsMap::new();
-        if let Some(title) = self.title {
-            cols.set_as("title", &title);
-        }
-        if let Some(cover) = self.cover {
-            cols.set_as("cover", &cover);
-        }
-        if let Some(keywords) = self.keywords {
-            cols.set_as("keywords", &keywords);
-        }
-        if let Some(summary) = self.summary {
-            cols.set_as("summary", &summary);
-        }
+        cols.set_if_some("title", self.title.as_ref());
+        let model = self.model.map(|v| validate_model(models, &v)).transpose()?;
+        cols.set_if_some("model", model.as_ref());
+        set_cover_columns(&mut cols, self.cover.as_ref(), self.cover_blurhash.as_ref());
+        let keywords = self.keywords.map(normalize_keywords).transpose()?;
+        cols.set_if_some("keywords", keywords.as_ref());
+        cols.set_if_some("summary", self.summary.as_ref());
+        let genre = self.genre.map(normalize_genre).transpose()?;
+        cols.set_if_some("genre", genre.as_ref());
 
         if cols.is_empty() {
             return Err(HTTPError::new(400, "No fields to update".to_string()).into());
@@ -847,18 +1882,42 @@ pub async fn update(
 
     let mut doc = db::Publication::with_pk(gid, cid, language, input.version);
     let updated_at = input.updated_at;
-    let cols = input.into()?;
+    let cols = input.into(&app.models)?;
 
-    let update_meili = cols.has("title") || cols.has("summary") || cols.has("keywords");
-    let ok = doc.update(&app.scylla, cols, updated_at).await?;
+    let update_meili =
+        cols.has("title") || cols.has("summary") || cols.has("keywords") || cols.has("genre");
+    let ok = doc.update(&app.scylla, cols, updated_at, ctx.user).await?;
     ctx.set("updated", ok.into()).await;
 
     if update_meili {
         let meili_start = ctx.start.elapsed().as_millis() as u64;
-        if let Err(err) = app
-            .meili
-            .add_or_update(meili::Space::Group(doc.gid), vec![doc.to_meili()])
+        // a published publication is also indexed in the public space, with the genre facet
+        if doc.status == 2 {
+            if let Err(err) = meili_write(
+                &app,
+                meili::Space::Pub(None),
+                MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+            )
             .await
+            {
+                log::error!(target: "meilisearch",
+                    action = "add_or_update",
+                    space = "pub",
+                    rid = ctx.rid,
+                    gid = doc.gid.to_string(),
+                    cid = doc.cid.to_string(),
+                    kind = 1i8,
+                    elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+                    "{}", err.to_string(),
+                );
+            }
+        }
+        if let Err(err) = meili_write(
+            &app,
+            meili::Space::Group(doc.gid),
+            MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+        )
+        .await
         {
             log::error!(target: "meilisearch",
                 action = "add_or_update",
@@ -877,7 +1936,7 @@ pub async fn update(
     Ok(to.with(SuccessResponse::new(PublicationOutput::from(doc, &to))))
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
 pub struct UpdatePublicationContentInput {
     pub cid: PackObject<xid::Id>,
     pub gid: PackObject<xid::Id>,
@@ -913,12 +1972,45 @@ pub async fn update_content(
     .await;
 
     let mut doc = db::Publication::with_pk(gid, cid, language, input.version);
+    doc.get_one(&app.scylla).await?;
+    check_quota_update(&app, gid, doc.content, content.len(), false).await?;
 
     let ok = doc
         .update_content(&app.scylla, content, input.updated_at)
         .await?;
     ctx.set("updated", ok.into()).await;
 
+    doc.get_one(
+        &app.scylla,
+        vec![
+            "genre".to_string(),
+            "title".to_string(),
+            "keywords".to_string(),
+            "authors".to_string(),
+            "summary".to_string(),
+        ],
+    )
+    .await?;
+    let meili_start = ctx.start.elapsed().as_millis() as u64;
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::AddOrUpdate(vec![doc.to_meili()]),
+    )
+    .await
+    {
+        log::error!(target: "meilisearch",
+            action = "add_or_update",
+            space = "group",
+            rid = ctx.rid,
+            gid = gid.to_string(),
+            cid = cid.to_string(),
+            kind = 1i8,
+            elapsed = ctx.start.elapsed().as_millis() as u64 - meili_start;
+            "{}", err.to_string(),
+        );
+    }
+
     doc._fields = vec!["updated_at".to_string()];
     Ok(to.with(SuccessResponse::new(PublicationOutput::from(doc, &to))))
 }
@@ -946,13 +2038,15 @@ pub async fn delete(
     .await;
 
     let mut doc = db::Publication::with_pk(gid, cid, language, input.version);
-    let res = doc.delete(&app.scylla).await?;
+    let res = doc.delete(&app.scylla, false).await?.would_delete;
 
     let meili_start = ctx.start.elapsed().as_millis() as u64;
-    if let Err(err) = app
-        .meili
-        .delete(meili::Space::Group(doc.gid), vec![doc.to_meili().id])
-        .await
+    if let Err(err) = meili_write(
+        &app,
+        meili::Space::Group(doc.gid),
+        MeiliWrite::Delete(vec![doc.to_meili().id]),
+    )
+    .await
     {
         log::error!(target: "meilisearch",
             action = "delete",
@@ -967,3 +2061,55 @@ pub async fn delete(
     }
     Ok(to.with(SuccessResponse::new(res)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum_web::object::cbor_to_vec;
+
+    #[test]
+    fn epub_manifest_works() {
+        let mut doc = db::Publication::with_pk(
+            db::ZERO_ID,
+            xid::Id::from_str("cirsnps7u2g6qubmm6t0").unwrap(),
+            Language::Jpn,
+            2,
+        );
+        doc.title = "Title".to_string();
+        doc.authors = vec!["Alice".to_string()];
+        doc.license = "CC-BY-4.0".to_string();
+        doc.updated_at = 1_697_846_399_999;
+        doc._content = cbor_to_vec(&serde_json::json!({
+            "type": "doc",
+            "content": [{"type": "paragraph", "content": [{"type": "text", "text": "Hi"}]}],
+        }))
+        .unwrap();
+
+        let manifest = epub_manifest(&doc).unwrap();
+        assert_eq!(manifest.identifier, "urn:yiwen:cirsnps7u2g6qubmm6t0:jpn:2");
+        assert_eq!(manifest.title, "Title");
+        assert_eq!(manifest.language, "ja");
+        assert_eq!(manifest.modified, "2023-10-20T23:59:59Z");
+        assert_eq!(manifest.authors, vec!["Alice".to_string()]);
+        assert_eq!(manifest.license, Some("CC-BY-4.0".to_string()));
+        assert_eq!(manifest.cover, None);
+        assert_eq!(manifest.html, "<p>Hi</p>");
+
+        let value = serde_json::to_value(&manifest).unwrap();
+        for field in [
+            "identifier",
+            "title",
+            "language",
+            "modified",
+            "authors",
+            "cover",
+            "license",
+        ] {
+            assert!(value.get(field).is_some(), "missing {}", field);
+        }
+
+        doc._content = b"not cbor".to_vec();
+        assert_eq!(epub_manifest(&doc).unwrap_err().code, 500);
+    }
+}
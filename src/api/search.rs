@@ -3,8 +3,13 @@ use axum::{
     Extension,
 };
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{convert::From, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::From,
+    sync::Arc,
+};
 use validator::Validate;
 
 use crate::db::{self, meili};
@@ -13,14 +18,47 @@ use axum_web::context::ReqContext;
 use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
 use axum_web::object::PackObject;
 
-use super::AppState;
+use super::{token_from_search, token_to_search, AppState};
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
 pub struct SearchInput {
     pub gid: Option<PackObject<xid::Id>>,
     pub language: Option<PackObject<Language>>,
-    #[validate(length(min = 0, max = 128))]
+    #[validate(length(min = 0, max = 1024))]
     pub q: String,
+    #[validate(range(min = 0))]
+    pub min_words: Option<i32>,
+    #[validate(range(min = 0))]
+    pub max_words: Option<i32>,
+    pub page_token: Option<PackObject<Vec<u8>>>,
+    #[validate(range(min = 2, max = 100))]
+    pub page_size: Option<u16>,
+}
+
+// sanitize_query strips control characters, collapses whitespaces and limits the length
+// of a search query, an empty query is rejected.
+pub fn sanitize_query(q: &str, max_len: usize) -> Result<String, HTTPError> {
+    let q: String = q
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let q = q.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if q.is_empty() {
+        return Err(HTTPError::new(400, "Empty search query".to_string()));
+    }
+
+    let len = q.chars().count();
+    if len > max_len {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "Search query too long, expected <= {}, got {}",
+                max_len, len
+            ),
+        ));
+    }
+
+    Ok(q)
 }
 
 pub async fn search(
@@ -33,20 +71,84 @@ pub async fn search(
 
     let gid = input.gid.to_owned().map(|v| v.unwrap());
     let lang = input.language.to_owned().map(|v| v.unwrap());
-    let q: Vec<&str> = input.q.split_whitespace().collect();
-    let q = q.join(" ");
+    let q = sanitize_query(&input.q, app.search.max_query_len)?;
+    let words = meili::words_filter(input.min_words, input.max_words)?;
 
     ctx.set_kvs(vec![("action", "search".into()), ("q", q.as_str().into())])
         .await;
 
+    // paginated by recency if asked, otherwise ranked by relevance.
+    if input.page_token.is_some() || input.page_size.is_some() {
+        let page_size = input.page_size.unwrap_or(10);
+        let cursor = token_to_search(&input.page_token);
+        let (res, next) = app
+            .meili
+            .search_page(
+                meili::Space::Pub(gid),
+                lang,
+                words,
+                &q,
+                cursor.as_ref(),
+                page_size as usize,
+                &to,
+            )
+            .await?;
+        ctx.set_kvs(vec![
+            ("page_size", page_size.into()),
+            ("result", res.hits.len().into()),
+        ])
+        .await;
+        return Ok(to.with(SuccessResponse {
+            total_size: None,
+            next_page_token: to.with_option(token_from_search(next)),
+            result: res,
+        }));
+    }
+
     let res = app
         .meili
-        .search(meili::Space::Pub(gid), lang, &q, &to)
+        .search(meili::Space::Pub(gid), lang, words, &q, &to)
         .await?;
     ctx.set("result", res.hits.len().into()).await;
     Ok(to.with(SuccessResponse::new(res)))
 }
 
+// verify_hits drops the hits that are missing or deleted (status < 0) in ScyllaDB and
+// returns them as stale, the hits rated above `rating` are dropped too but not stale.
+// collections carry their own rating, the others are rated by their creation index.
+fn verify_hits(
+    hits: Vec<meili::DocumentOutput>,
+    ratings: &HashMap<xid::Id, i8>,
+    creations: &HashMap<xid::Id, i8>,
+    publications: &HashMap<(xid::Id, Language, i16), i8>,
+    collections: &HashMap<xid::Id, (i8, i8)>,
+    rating: i8,
+) -> (Vec<meili::DocumentOutput>, Vec<meili::DocumentOutput>) {
+    let mut res: Vec<meili::DocumentOutput> = Vec::with_capacity(hits.len());
+    let mut stale: Vec<meili::DocumentOutput> = Vec::new();
+    for hit in hits {
+        let cid = *hit.cid.to_owned();
+        let state = match hit.kind {
+            0 => ratings.get(&cid).zip(creations.get(&cid)),
+            2 => collections.get(&cid).map(|(s, r)| (r, s)),
+            _ => ratings.get(&cid).zip(publications.get(&(
+                cid,
+                *hit.language.to_owned(),
+                hit.version,
+            ))),
+        };
+        match state {
+            Some((r, s)) if *s >= 0 => {
+                if *r <= rating {
+                    res.push(hit);
+                }
+            }
+            _ => stale.push(hit),
+        }
+    }
+    (res, stale)
+}
+
 pub async fn group_search(
     State(app): State<Arc<AppState>>,
     Extension(ctx): Extension<Arc<ReqContext>>,
@@ -62,16 +164,120 @@ pub async fn group_search(
         .ok_or_else(|| HTTPError::new(400, "Missing required field `gid`".to_string()))?;
     let gid = *gid.to_owned();
     let lang = input.language.to_owned().map(|v| v.unwrap());
-    let q = input.q.trim();
+    let q = sanitize_query(&input.q, app.search.max_query_len)?;
+    let words = meili::words_filter(input.min_words, input.max_words)?;
 
-    ctx.set_kvs(vec![("action", "group_search".into()), ("q", q.into())])
-        .await;
+    ctx.set_kvs(vec![
+        ("action", "group_search".into()),
+        ("q", q.as_str().into()),
+    ])
+    .await;
 
-    let res = app
+    let mut res = app
         .meili
-        .search(meili::Space::Group(gid), lang, q, &to)
+        .search(meili::Space::Group(gid), lang, words, &q, &to)
         .await?;
-    ctx.set("result", res.hits.len().into()).await;
+
+    // the index may lag behind, verifies the hits with their current state.
+    let mut cids: Vec<xid::Id> = res
+        .hits
+        .iter()
+        .filter(|v| v.kind != 2)
+        .map(|v| *v.cid.to_owned())
+        .collect();
+    cids.sort_by(|a, b| a.0.cmp(&b.0));
+    cids.dedup();
+    let ratings: HashMap<xid::Id, i8> = if cids.is_empty() {
+        HashMap::new()
+    } else {
+        db::CreationIndex::batch_get(&app.scylla, cids, i8::MAX)
+            .await?
+            .into_iter()
+            .map(|v| (v.id, v.rating))
+            .collect()
+    };
+
+    let creation_ids: Vec<xid::Id> = res
+        .hits
+        .iter()
+        .filter(|v| v.kind == 0)
+        .map(|v| *v.cid.to_owned())
+        .collect::<HashSet<xid::Id>>()
+        .into_iter()
+        .collect();
+    let creations = db::Creation::batch_status(&app.scylla, gid, &creation_ids).await?;
+
+    let list: Vec<db::PublicationIndex> = res
+        .hits
+        .iter()
+        .filter(|v| v.kind == 1)
+        .map(|v| db::PublicationIndex {
+            gid: *v.gid.to_owned(),
+            cid: *v.cid.to_owned(),
+            language: *v.language.to_owned(),
+            version: v.version,
+            ..Default::default()
+        })
+        .collect();
+    let (docs, _) =
+        db::Publication::batch_get_partial(&app.scylla, list, vec!["status".to_string()]).await?;
+    let publications: HashMap<(xid::Id, Language, i16), i8> = docs
+        .into_iter()
+        .map(|v| ((v.cid, v.language, v.version), v.status))
+        .collect();
+
+    let collection_ids: Vec<xid::Id> = res
+        .hits
+        .iter()
+        .filter(|v| v.kind == 2)
+        .map(|v| *v.cid.to_owned())
+        .collect::<HashSet<xid::Id>>()
+        .into_iter()
+        .collect();
+    let collections = db::Collection::batch_status(&app.scylla, gid, &collection_ids).await?;
+
+    let total = res.hits.len();
+    let (hits, stale) = verify_hits(
+        res.hits,
+        &ratings,
+        &creations,
+        &publications,
+        &collections,
+        ctx.rating,
+    );
+    res.hits = hits;
+    res.filtered = (total - res.hits.len()) as u32;
+    ctx.set_kvs(vec![
+        ("result", res.hits.len().into()),
+        ("filtered", res.filtered.into()),
+        ("stale", stale.len().into()),
+    ])
+    .await;
+
+    // removes the stale documents so that the index heals itself.
+    if !stale.is_empty() {
+        let ids: Vec<String> = stale
+            .iter()
+            .map(|v| {
+                meili::Document::new(*v.cid.to_owned(), *v.language.to_owned(), *v.gid.to_owned())
+                    .id
+            })
+            .collect();
+        let meili = app.meili.clone();
+        let rid = ctx.rid.clone();
+        tokio::spawn(async move {
+            if let Err(err) = meili.delete(meili::Space::Group(gid), ids).await {
+                log::error!(target: "meilisearch",
+                    action = "delete",
+                    space = "group",
+                    rid = rid,
+                    gid = gid.to_string();
+                    "{}", err.to_string(),
+                );
+            }
+        });
+    }
+
     Ok(to.with(SuccessResponse::new(res)))
 }
 
@@ -123,6 +329,7 @@ pub async fn original_search(
     let mut res = meili::SearchOutput {
         hits: Vec::with_capacity(publications.len() + creations.len()),
         languages: Default::default(),
+        filtered: 0,
     };
     for doc in publications {
         res.hits.push(meili::DocumentOutput {
@@ -134,6 +341,8 @@ pub async fn original_search(
             kind: 1,
             title: doc.title,
             summary: doc.summary,
+            words: None,
+            reading_minutes: None,
         });
     }
     for doc in creations {
@@ -146,20 +355,97 @@ pub async fn original_search(
             kind: 0,
             title: doc.title,
             summary: doc.summary,
+            words: None,
+            reading_minutes: None,
         });
     }
     ctx.set("result", res.hits.len().into()).await;
     Ok(to.with(SuccessResponse::new(res)))
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-
-//     #[test]
-//     fn test_it_q() {
-//         let q: Vec<&str> = "".split_whitespace().collect();
-//         let q = q.join(" ");
-//         println!("q len: {}", q.len())
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_query_works() {
+        assert_eq!(
+            sanitize_query("  hello   world ", 128).unwrap(),
+            "hello world"
+        );
+        assert_eq!(
+            sanitize_query("hello\u{0}\nworld\u{7f}\t!", 128).unwrap(),
+            "hello world !"
+        );
+        assert_eq!(sanitize_query("你好 世界", 5).unwrap(), "你好 世界");
+
+        let err = sanitize_query("", 128).unwrap_err();
+        assert_eq!(err.code, 400);
+        let err = sanitize_query(" \t\r\n\u{1b} ", 128).unwrap_err();
+        assert_eq!(err.code, 400);
+
+        let err = sanitize_query("你好 世界!", 5).unwrap_err();
+        assert_eq!(err.code, 400);
+        assert!(sanitize_query(&"a".repeat(128), 128).is_ok());
+        assert!(sanitize_query(&"a".repeat(129), 128).is_err());
+    }
+
+    #[test]
+    fn verify_hits_works() {
+        let to = PackObject::Json(());
+        let gid = xid::new();
+        let hit = |cid: xid::Id, kind: i8, version: i16| meili::DocumentOutput {
+            gid: to.with(gid),
+            cid: to.with(cid),
+            language: to.with(Language::Eng),
+            version,
+            updated_at: 0,
+            kind,
+            title: "".to_string(),
+            summary: "".to_string(),
+            words: None,
+            reading_minutes: None,
+        };
+
+        let (c1, c2, c3, c4) = (xid::new(), xid::new(), xid::new(), xid::new());
+        let (l1, l2, l3) = (xid::new(), xid::new(), xid::new());
+        let ratings: HashMap<xid::Id, i8> = [(c1, 0), (c2, 0), (c3, 3)].into_iter().collect();
+        let creations: HashMap<xid::Id, i8> = [(c1, 2), (c2, -1), (c3, 1)].into_iter().collect();
+        let publications: HashMap<(xid::Id, Language, i16), i8> =
+            [((c1, Language::Eng, 2), 2), ((c2, Language::Eng, 1), -1)]
+                .into_iter()
+                .collect();
+        let collections: HashMap<xid::Id, (i8, i8)> = [(l1, (2, 0)), (l2, (-1, 0)), (l3, (2, 3))]
+            .into_iter()
+            .collect();
+
+        let hits = vec![
+            hit(c1, 0, 1), // live creation
+            hit(c1, 1, 2), // live publication
+            hit(c1, 1, 1), // missing publication version
+            hit(c2, 0, 1), // deleted creation
+            hit(c2, 1, 1), // deleted publication
+            hit(c3, 0, 1), // rated above
+            hit(c4, 0, 1), // missing creation index
+            hit(l1, 2, 1), // live collection, not in the creation index
+            hit(l2, 2, 1), // archived collection
+            hit(l3, 2, 1), // collection rated above
+            hit(c1, 2, 1), // missing collection
+        ];
+        let (res, stale) = verify_hits(hits, &ratings, &creations, &publications, &collections, 1);
+        assert_eq!(res.len(), 3);
+        assert_eq!((*res[0].cid, res[0].kind), (c1, 0));
+        assert_eq!((*res[1].cid, res[1].kind, res[1].version), (c1, 1, 2));
+        assert_eq!((*res[2].cid, res[2].kind), (l1, 2));
+        assert_eq!(stale.len(), 6);
+        assert_eq!(*stale[3].cid, c4);
+        assert_eq!((*stale[4].cid, stale[4].kind), (l2, 2));
+        assert_eq!((*stale[5].cid, stale[5].kind), (c1, 2));
+
+        // the restricted hits are kept for a higher rating
+        let hits = vec![hit(c3, 0, 1), hit(l3, 2, 1)];
+        let (res, stale) = verify_hits(hits, &ratings, &creations, &publications, &collections, 3);
+        assert_eq!(res.len(), 2);
+        assert!(stale.is_empty());
+    }
+}
@@ -0,0 +1,528 @@
+use axum::{
+    body::StreamBody,
+    extract::{OriginalUri, Query, State},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+    routing, Extension, Router,
+};
+use futures::stream;
+use isolang::Language;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use validator::Validate;
+
+use axum_web::context::{unix_ms, ReqContext};
+use axum_web::erring::{valid_user, HTTPError};
+use axum_web::object::PackObject;
+
+use super::{util::xml_escape, AppState};
+use crate::{conf, db};
+
+// the max URLs of a sitemap file, a larger sitemap is split into chunks with an index file.
+pub const SITEMAP_CHUNK_SIZE: usize = 10_000;
+// the max chunks of a sitemap, "/v1/sitemap-<n>.xml" is routed for each of them.
+pub const MAX_SITEMAP_CHUNKS: usize = 100;
+// how long a built sitemap is served to the index and chunk requests before it is rebuilt.
+const SITEMAP_CACHE_TTL_MS: u64 = 3600 * 1000;
+// the max number of sitemaps cached, the oldest one is evicted when exceeded.
+const SITEMAP_CACHE_SIZE: usize = 100;
+
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+
+#[derive(Debug, Deserialize, Serialize, Validate, JsonSchema)]
+pub struct SitemapInput {
+    pub gid: Option<PackObject<xid::Id>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UrlEntry {
+    pub loc: String,
+    pub lastmod: i64,                      // unix time, ms
+    pub alternates: Vec<(String, String)>, // (hreflang, href) of the translations
+}
+
+// SitemapCache keeps the built sitemap entries by gid (None for the whole site) with their
+// build time, so the chunks of an index are cut from one build instead of rebuilding each.
+#[derive(Debug, Default)]
+pub struct SitemapCache(RwLock<HashMap<Option<xid::Id>, (u64, Arc<Vec<UrlEntry>>)>>);
+
+impl SitemapCache {
+    pub fn get(&self, gid: Option<xid::Id>, now: u64) -> Option<Arc<Vec<UrlEntry>>> {
+        let cache = self.0.read().unwrap();
+        match cache.get(&gid) {
+            Some((built_at, entries)) if built_at + SITEMAP_CACHE_TTL_MS > now => {
+                Some(entries.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn put(
+        &self,
+        gid: Option<xid::Id>,
+        entries: Vec<UrlEntry>,
+        now: u64,
+    ) -> Arc<Vec<UrlEntry>> {
+        let entries = Arc::new(entries);
+        let mut cache = self.0.write().unwrap();
+        cache.retain(|_, v| v.0 + SITEMAP_CACHE_TTL_MS > now);
+        if cache.len() >= SITEMAP_CACHE_SIZE && !cache.contains_key(&gid) {
+            if let Some(oldest) = cache.iter().min_by_key(|(_, v)| v.0).map(|(k, _)| *k) {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(gid, (now, entries.clone()));
+        entries
+    }
+}
+
+fn fill_template(tpl: &str, vars: &[(&str, String)]) -> String {
+    let mut res = tpl.to_string();
+    for (k, v) in vars {
+        res = res.replace(&format!("{{{}}}", k), v);
+    }
+    res
+}
+
+pub fn hreflang(language: Language) -> String {
+    language
+        .to_639_1()
+        .unwrap_or_else(|| language.to_639_3())
+        .to_string()
+}
+
+// publication_entries builds one entry for each cid from the published rows with their
+// updated_at, the rows should be grouped by cid. The entry is the original language, or the
+// first language if the original one is missing, the other languages are its alternates.
+pub fn publication_entries(
+    cfg: &conf::Site,
+    rows: Vec<(db::PublicationIndex, i64)>,
+) -> Vec<UrlEntry> {
+    let url = |doc: &db::PublicationIndex| {
+        fill_template(
+            &cfg.url_template_publication,
+            &[
+                ("gid", doc.gid.to_string()),
+                ("cid", doc.cid.to_string()),
+                ("language", doc.language.to_639_3().to_string()),
+            ],
+        )
+    };
+
+    let mut groups: Vec<Vec<(db::PublicationIndex, i64)>> = Vec::new();
+    for row in rows {
+        match groups.last_mut() {
+            Some(group) if group[0].0.cid == row.0.cid => group.push(row),
+            _ => groups.push(vec![row]),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let i = group.iter().position(|(doc, _)| doc.original).unwrap_or(0);
+            let alternates = if group.len() > 1 {
+                group
+                    .iter()
+                    .map(|(doc, _)| (hreflang(doc.language), url(doc)))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            UrlEntry {
+                loc: url(&group[i].0),
+                lastmod: group[i].1,
+                alternates,
+            }
+        })
+        .collect()
+}
+
+pub fn collection_entries(cfg: &conf::Site, docs: Vec<db::Collection>) -> Vec<UrlEntry> {
+    docs.into_iter()
+        .map(|doc| UrlEntry {
+            loc: fill_template(
+                &cfg.url_template_collection,
+                &[("gid", doc.gid.to_string()), ("id", doc.id.to_string())],
+            ),
+            lastmod: doc.updated_at,
+            alternates: Vec::new(),
+        })
+        .collect()
+}
+
+// w3c_date formats the unix time in ms as the W3C date "YYYY-MM-DD" in UTC.
+pub fn w3c_date(unix_ms: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = unix_ms.div_euclid(1000 * 3600 * 24) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+fn render_url(entry: &UrlEntry) -> String {
+    let mut res = format!("<url><loc>{}</loc>", xml_escape(&entry.loc));
+    if entry.lastmod > 0 {
+        res.push_str(&format!("<lastmod>{}</lastmod>", w3c_date(entry.lastmod)));
+    }
+    for (lang, href) in &entry.alternates {
+        res.push_str(&format!(
+            "<xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}\"/>",
+            xml_escape(lang),
+            xml_escape(href)
+        ));
+    }
+    res.push_str("</url>\n");
+    res
+}
+
+// urlset renders the sitemap of the entries piece by piece.
+pub fn urlset(entries: Vec<UrlEntry>) -> impl Iterator<Item = String> {
+    std::iter::once(format!(
+        "{}<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" xmlns:xhtml=\"http://www.w3.org/1999/xhtml\">\n",
+        XML_HEADER
+    ))
+    .chain(entries.into_iter().map(|entry| render_url(&entry)))
+    .chain(std::iter::once("</urlset>\n".to_string()))
+}
+
+// sitemap_index renders the sitemap index of the chunks, (loc, lastmod) of each chunk.
+pub fn sitemap_index(chunks: Vec<(String, i64)>) -> impl Iterator<Item = String> {
+    std::iter::once(format!(
+        "{}<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        XML_HEADER
+    ))
+    .chain(chunks.into_iter().map(|(loc, lastmod)| {
+        let mut res = format!("<sitemap><loc>{}</loc>", xml_escape(&loc));
+        if lastmod > 0 {
+            res.push_str(&format!("<lastmod>{}</lastmod>", w3c_date(lastmod)));
+        }
+        res.push_str("</sitemap>\n");
+        res
+    }))
+    .chain(std::iter::once("</sitemapindex>\n".to_string()))
+}
+
+// the URL of the nth chunk (1-based), the gid is kept in the query.
+fn chunk_url(cfg: &conf::Site, n: usize, gid: Option<xid::Id>) -> String {
+    let url = fill_template(&cfg.url_template_sitemap, &[("n", n.to_string())]);
+    match gid {
+        Some(gid) if url.contains('?') => format!("{}&gid={}", url, gid),
+        Some(gid) => format!("{}?gid={}", url, gid),
+        None => url,
+    }
+}
+
+// parses the chunk number of the path ".../sitemap-<n>.xml".
+fn chunk_number(path: &str) -> Option<usize> {
+    path.rsplit('/')
+        .next()
+        .and_then(|v| v.strip_prefix("sitemap-"))
+        .and_then(|v| v.strip_suffix(".xml"))
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0 && *n <= MAX_SITEMAP_CHUNKS)
+}
+
+// the routes of "/v1/sitemap-<n>.xml", one for each chunk a sitemap index can list.
+pub fn chunk_routes() -> Router<Arc<AppState>> {
+    (1..=MAX_SITEMAP_CHUNKS).fold(Router::new(), |router, n| {
+        router.route(
+            &format!("/v1/sitemap-{}.xml", n),
+            routing::get(sitemap_chunk),
+        )
+    })
+}
+
+fn xml_response(parts: impl Iterator<Item = String> + Send + 'static) -> Response {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/xml; charset=utf-8"),
+        )],
+        StreamBody::new(stream::iter(parts.map(Ok::<String, Infallible>))),
+    )
+        .into_response()
+}
+
+// the sitemap of the whole site is only for the system user.
+async fn sitemap_gid(ctx: &ReqContext, input: &SitemapInput) -> Result<Option<xid::Id>, HTTPError> {
+    let gid = input.gid.to_owned().map(|v| v.unwrap());
+    if gid.is_none() {
+        valid_user(ctx.user)?;
+        if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+            return Err(HTTPError::new(
+                403,
+                "Only system user can get the sitemap of the site".to_string(),
+            ));
+        }
+    }
+    ctx.set_kvs(vec![
+        ("action", "sitemap".into()),
+        ("gid", gid.map(|v| v.to_string()).unwrap_or_default().into()),
+    ])
+    .await;
+    Ok(gid)
+}
+
+// the entries of the sitemap from the cache, or built if missing or expired.
+async fn cached_entries(
+    app: &AppState,
+    gid: Option<xid::Id>,
+) -> Result<Arc<Vec<UrlEntry>>, HTTPError> {
+    if let Some(entries) = app.sitemaps.get(gid, unix_ms()) {
+        return Ok(entries);
+    }
+    let entries = load_entries(app, gid).await?;
+    Ok(app.sitemaps.put(gid, entries, unix_ms()))
+}
+
+// the publications are streamed from the index, the site-wide one covers the publications
+// imported before the launch day, the collections are scanned day by day.
+async fn load_entries(app: &AppState, gid: Option<xid::Id>) -> Result<Vec<UrlEntry>, HTTPError> {
+    let list = db::PublicationIndex::list_all(&app.scylla, gid).await?;
+    let (docs, _) = db::Publication::batch_get_partial(
+        &app.scylla,
+        list.clone(),
+        vec!["status".to_string(), "updated_at".to_string()],
+    )
+    .await?;
+    // the index may lag behind, only the publications still published are listed.
+    let published: HashMap<(xid::Id, Language), i64> = docs
+        .into_iter()
+        .filter(|v| v.status == 2)
+        .map(|v| ((v.cid, v.language), v.updated_at))
+        .collect();
+    let rows: Vec<(db::PublicationIndex, i64)> = list
+        .into_iter()
+        .filter_map(|v| {
+            published
+                .get(&(v.cid, v.language))
+                .copied()
+                .map(|updated_at| (v, updated_at))
+        })
+        .collect();
+
+    let mut entries = publication_entries(&app.site, rows);
+    let collections = db::Collection::list_all_published(
+        &app.scylla,
+        gid,
+        vec!["gid".to_string(), "updated_at".to_string()],
+    )
+    .await?;
+    entries.extend(collection_entries(&app.site, collections));
+    Ok(entries)
+}
+
+pub async fn sitemap(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    input: Query<SitemapInput>,
+) -> Result<Response, HTTPError> {
+    input.validate()?;
+    let gid = sitemap_gid(&ctx, &input).await?;
+    let entries = cached_entries(&app, gid).await?;
+    ctx.set("urls", entries.len().into()).await;
+
+    if entries.len() <= SITEMAP_CHUNK_SIZE {
+        return Ok(xml_response(urlset(entries.to_vec())));
+    }
+
+    // the URLs beyond the max chunks are left out
+    let chunks: Vec<(String, i64)> = entries
+        .chunks(SITEMAP_CHUNK_SIZE)
+        .take(MAX_SITEMAP_CHUNKS)
+        .enumerate()
+        .map(|(i, chunk)| {
+            (
+                chunk_url(&app.site, i + 1, gid),
+                chunk.iter().map(|v| v.lastmod).max().unwrap_or_default(),
+            )
+        })
+        .collect();
+    Ok(xml_response(sitemap_index(chunks)))
+}
+
+// sitemap_chunk serves "sitemap-<n>.xml", the nth chunk of a sitemap split by the index.
+pub async fn sitemap_chunk(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    OriginalUri(uri): OriginalUri,
+    input: Query<SitemapInput>,
+) -> Result<Response, HTTPError> {
+    let path = uri.path();
+    let n = chunk_number(path).ok_or_else(|| HTTPError::new(404, format!("{} not found", path)))?;
+    input.validate()?;
+    let gid = sitemap_gid(&ctx, &input).await?;
+    let entries = cached_entries(&app, gid).await?;
+    ctx.set_kvs(vec![("urls", entries.len().into()), ("chunk", n.into())])
+        .await;
+
+    let start = (n - 1) * SITEMAP_CHUNK_SIZE;
+    if start >= entries.len() {
+        return Err(HTTPError::new(404, format!("{} not found", path)));
+    }
+    let end = entries.len().min(start + SITEMAP_CHUNK_SIZE);
+    Ok(xml_response(urlset(entries[start..end].to_vec())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GID: &str = "9m4e2mr0ui3e8a215n4g";
+    const CID1: &str = "cirsnps7u2g6qubmm6t0";
+    const CID2: &str = "cirsnps7u2g6qubmm6s0";
+
+    const URLSET_FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:xhtml="http://www.w3.org/1999/xhtml">
+<url><loc>https://www.yiwen.ai/pub/cirsnps7u2g6qubmm6t0?language=jpn</loc><lastmod>2023-10-21</lastmod><xhtml:link rel="alternate" hreflang="en" href="https://www.yiwen.ai/pub/cirsnps7u2g6qubmm6t0?language=eng"/><xhtml:link rel="alternate" hreflang="ja" href="https://www.yiwen.ai/pub/cirsnps7u2g6qubmm6t0?language=jpn"/><xhtml:link rel="alternate" hreflang="zh" href="https://www.yiwen.ai/pub/cirsnps7u2g6qubmm6t0?language=zho"/></url>
+<url><loc>https://www.yiwen.ai/pub/cirsnps7u2g6qubmm6s0?language=eng</loc><lastmod>2024-02-29</lastmod></url>
+<url><loc>https://www.yiwen.ai/collection/cirsnps7u2g6qubmm6t0</loc><lastmod>2023-10-20</lastmod></url>
+</urlset>
+"#;
+
+    const INDEX_FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+<sitemap><loc>https://www.yiwen.ai/sitemap-1.xml?gid=9m4e2mr0ui3e8a215n4g</loc><lastmod>2023-10-20</lastmod></sitemap>
+<sitemap><loc>https://www.yiwen.ai/sitemap-2.xml?gid=9m4e2mr0ui3e8a215n4g</loc></sitemap>
+</sitemapindex>
+"#;
+
+    fn index_row(cid: &str, language: Language, original: bool) -> db::PublicationIndex {
+        db::PublicationIndex {
+            cid: xid::Id::from_str(cid).unwrap(),
+            language,
+            original,
+            version: 1,
+            gid: xid::Id::from_str(GID).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn w3c_date_works() {
+        assert_eq!(w3c_date(0), "1970-01-01");
+        assert_eq!(w3c_date(1_697_760_000_000), "2023-10-20");
+        assert_eq!(w3c_date(1_697_846_399_999), "2023-10-20");
+        assert_eq!(w3c_date(1_709_164_800_000), "2024-02-29");
+        assert_eq!(w3c_date(1_709_251_200_000), "2024-03-01");
+        assert_eq!(w3c_date(951_782_400_000), "2000-02-29");
+    }
+
+    #[test]
+    fn chunk_works() {
+        assert_eq!(chunk_number("/v1/sitemap-1.xml"), Some(1));
+        assert_eq!(chunk_number("/v1/sitemap-12.xml"), Some(12));
+        assert_eq!(chunk_number("/v1/sitemap-100.xml"), Some(100));
+        assert_eq!(chunk_number("/v1/sitemap-101.xml"), None);
+        assert_eq!(chunk_number("/v1/sitemap-0.xml"), None);
+        assert_eq!(chunk_number("/v1/sitemap.xml"), None);
+        assert_eq!(chunk_number("/v1/sitemap-a.xml"), None);
+        assert_eq!(chunk_number("/v1/creation"), None);
+
+        let gid = xid::Id::from_str(GID).unwrap();
+        let mut cfg = conf::Site::default();
+        assert_eq!(
+            chunk_url(&cfg, 2, None),
+            "https://www.yiwen.ai/sitemap-2.xml"
+        );
+        assert_eq!(
+            chunk_url(&cfg, 2, Some(gid)),
+            format!("https://www.yiwen.ai/sitemap-2.xml?gid={}", GID)
+        );
+        cfg.url_template_sitemap = "https://www.yiwen.ai/sitemap.xml?n={n}".to_string();
+        assert_eq!(
+            chunk_url(&cfg, 2, Some(gid)),
+            format!("https://www.yiwen.ai/sitemap.xml?n=2&gid={}", GID)
+        );
+    }
+
+    #[test]
+    fn sitemap_cache_works() {
+        let cache = SitemapCache::default();
+        let gid = Some(xid::new());
+        let entry = UrlEntry {
+            loc: "https://www.yiwen.ai/collection/1".to_string(),
+            ..Default::default()
+        };
+        assert!(cache.get(gid, 1000).is_none());
+        cache.put(gid, vec![entry.clone()], 1000);
+        assert_eq!(cache.get(gid, 1000).unwrap().as_slice(), &[entry.clone()]);
+        assert!(cache.get(None, 1000).is_none());
+        // expired
+        assert!(cache.get(gid, 1000 + SITEMAP_CACHE_TTL_MS).is_none());
+
+        // the oldest one is evicted when full
+        for i in 0..SITEMAP_CACHE_SIZE as u64 {
+            cache.put(Some(xid::new()), Vec::new(), 2000 + i);
+        }
+        assert!(cache.get(gid, 2000).is_none());
+        cache.put(None, vec![entry], 3000);
+        assert!(cache.get(None, 3000).is_some());
+        assert_eq!(cache.0.read().unwrap().len(), SITEMAP_CACHE_SIZE);
+    }
+
+    #[test]
+    fn urlset_works() {
+        let cfg = conf::Site::default();
+        let rows = vec![
+            (index_row(CID1, Language::Eng, false), 1_697_900_000_000),
+            (index_row(CID1, Language::Jpn, true), 1_697_860_000_000),
+            (index_row(CID1, Language::Zho, false), 1_697_950_000_000),
+            (index_row(CID2, Language::Eng, false), 1_709_200_000_000),
+        ];
+        let mut entries = publication_entries(&cfg, rows);
+        assert_eq!(entries.len(), 2);
+        entries.extend(collection_entries(
+            &cfg,
+            vec![db::Collection {
+                id: xid::Id::from_str(CID1).unwrap(),
+                gid: xid::Id::from_str(GID).unwrap(),
+                updated_at: 1_697_760_000_000,
+                ..Default::default()
+            }],
+        ));
+        assert_eq!(urlset(entries).collect::<String>(), URLSET_FIXTURE);
+
+        assert_eq!(
+            urlset(Vec::new()).collect::<String>(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:xhtml="http://www.w3.org/1999/xhtml">
+</urlset>
+"#
+        );
+
+        // escaped
+        let entry = UrlEntry {
+            loc: "https://www.yiwen.ai/pub?cid=1&language=<eng>".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_url(&entry),
+            "<url><loc>https://www.yiwen.ai/pub?cid=1&amp;language=&lt;eng&gt;</loc></url>\n"
+        );
+    }
+
+    #[test]
+    fn sitemap_index_works() {
+        let cfg = conf::Site::default();
+        let gid = Some(xid::Id::from_str(GID).unwrap());
+        let chunks = vec![
+            (chunk_url(&cfg, 1, gid), 1_697_760_000_000),
+            (chunk_url(&cfg, 2, gid), 0),
+        ];
+        assert_eq!(sitemap_index(chunks).collect::<String>(), INDEX_FIXTURE);
+    }
+}
@@ -0,0 +1,214 @@
+use axum::{
+    extract::State,
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum_web::erring::HTTPError;
+
+use crate::{conf, db};
+
+// the default budgets in milliseconds of the route groups.
+const DEFAULT_TIMEOUTS: [(&str, u64); 4] = [
+    ("read", 2000),
+    ("list", 8000),
+    ("write", 8000),
+    ("admin", conf::DEFAULT_REQUEST_TIMEOUT_MS),
+];
+
+// the last path segments of the endpoints that scan many rows.
+const LIST_ROUTES: [&str; 8] = [
+    "publish",
+    "count_publish",
+    "batch_get",
+    "batch_check",
+    "related",
+    "genre_facets",
+    "changelog",
+    "rfp_stats",
+];
+
+// RouteTimeouts holds the request budget of each route group, see `route_group`.
+#[derive(Debug, Clone)]
+pub struct RouteTimeouts {
+    budgets: HashMap<&'static str, Duration>,
+}
+
+impl RouteTimeouts {
+    // the groups missing from `cfg` use the defaults, unknown groups are ignored.
+    pub fn new(cfg: &HashMap<String, u64>) -> Self {
+        let budgets = DEFAULT_TIMEOUTS
+            .iter()
+            .map(|(group, ms)| {
+                let ms = cfg.get(*group).copied().unwrap_or(*ms);
+                (*group, Duration::from_millis(ms))
+            })
+            .collect();
+        Self { budgets }
+    }
+
+    pub fn budget(&self, group: &str) -> Duration {
+        self.budgets
+            .get(group)
+            .or_else(|| self.budgets.get("write"))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+// route_group classifies a request into "admin", "list", "read" or "write".
+pub fn route_group(method: &Method, path: &str) -> &'static str {
+    if path.starts_with("/v1/debug/")
+        || path.starts_with("/v1/sys/")
+        || path.starts_with("/v1/group/purge_search")
+        || path.starts_with("/v1/content/verify")
+        || path.starts_with("/v1/sitemap")
+    {
+        return "admin";
+    }
+
+    let segment = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    if path.starts_with("/v1/search")
+        || segment.starts_with("list")
+        || segment.ends_with("_history")
+        || LIST_ROUTES.contains(&segment)
+    {
+        return "list";
+    }
+
+    if method == Method::GET {
+        "read"
+    } else {
+        "write"
+    }
+}
+
+// middleware responds 503 once a request exceeds the budget of its route group. The handler
+// is dropped at that point, so the writes that must complete run in spawned tasks.
+// The budget also narrows the deadline of the queries, so the day scans can stop before it.
+pub async fn middleware<B>(
+    State(timeouts): State<Arc<RouteTimeouts>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let group = route_group(req.method(), req.uri().path());
+    let budget = timeouts.budget(group);
+    let deadline = Instant::now() + budget;
+    let deadline = db::scylladb::current_deadline().map_or(deadline, |v| v.min(deadline));
+    let fut = db::scylladb::with_deadline(deadline, next.run(req));
+    match tokio::time::timeout(budget, fut).await {
+        Ok(res) => res,
+        Err(_) => timeout_error(group, budget).into_response(),
+    }
+}
+
+fn timeout_error(group: &str, budget: Duration) -> HTTPError {
+    db::DomainError::RequestTimeout.with_data(
+        format!(
+            "Request exceeded the {} timeout of {}ms",
+            group,
+            budget.as_millis()
+        ),
+        serde_json::json!({
+            "group": group,
+            "timeout_ms": budget.as_millis() as u64,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware, routing, Router,
+    };
+    use axum_web::erring::ErrorResponse;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn route_group_works() {
+        for (method, path, group) in [
+            (Method::GET, "/v1/creation", "read"),
+            (Method::POST, "/v1/creation", "write"),
+            (Method::PATCH, "/v1/creation/update_status", "write"),
+            (Method::POST, "/v1/creation/list", "list"),
+            (Method::POST, "/v1/publication/list_latest", "list"),
+            (Method::GET, "/v1/publication/related", "list"),
+            (Method::GET, "/beta/publication/publish", "list"),
+            (Method::GET, "/v1/collection/info_history", "list"),
+            (Method::GET, "/v1/search/in_group", "list"),
+            (Method::GET, "/v1/debug/query_stats", "admin"),
+            (Method::POST, "/v1/group/purge_search", "admin"),
+            (Method::POST, "/v1/content/verify", "admin"),
+            (Method::GET, "/v1/sitemap.xml", "admin"),
+            (Method::GET, "/v1/sitemap-2.xml", "admin"),
+            (Method::GET, "/healthz", "read"),
+        ] {
+            assert_eq!(route_group(&method, path), group, "{} {}", method, path);
+        }
+
+        let timeouts = RouteTimeouts::new(&HashMap::from([
+            ("read".to_string(), 100),
+            ("unknown".to_string(), 1),
+        ]));
+        assert_eq!(timeouts.budget("read"), Duration::from_millis(100));
+        assert_eq!(
+            timeouts.budget("admin"),
+            Duration::from_millis(conf::DEFAULT_REQUEST_TIMEOUT_MS)
+        );
+        assert_eq!(timeouts.budget("unknown"), Duration::from_millis(8000));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn middleware_works() {
+        async fn sleep() -> &'static str {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            "ok"
+        }
+
+        let timeouts = Arc::new(RouteTimeouts::new(&HashMap::from([
+            ("read".to_string(), 20),
+            ("list".to_string(), 1000),
+        ])));
+        let app = Router::new()
+            .route("/v1/creation", routing::get(sleep))
+            .route("/v1/creation/list", routing::post(sleep))
+            .layer(middleware::from_fn_with_state(timeouts, super::middleware));
+
+        // within the list budget
+        let req = Request::post("/v1/creation/list")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // exceeds the read budget
+        let req = Request::get("/v1/creation").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.error.code, 503);
+        let data = body.error.data.as_ref().unwrap();
+        assert_eq!(data["error"], db::DomainError::RequestTimeout.code());
+        assert_eq!(data["group"], "read");
+        assert_eq!(data["timeout_ms"], 20);
+
+        // the error body is CBOR encodable
+        let mut buf: Vec<u8> = Vec::new();
+        ciborium::into_writer(&body, &mut buf).unwrap();
+        let res: ErrorResponse = ciborium::from_reader(&buf[..]).unwrap();
+        assert_eq!(res.error.code, 503);
+        assert_eq!(res.error.message, body.error.message);
+        assert_eq!(res.error.data, body.error.data);
+    }
+}
@@ -0,0 +1,31 @@
+// xml_escape escapes the text for XML content and attribute values, it is shared by the
+// sitemaps and the HTML rendering of contents.
+pub fn xml_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xml_escape_works() {
+        assert_eq!(xml_escape(""), "");
+        assert_eq!(xml_escape("Hello 世界"), "Hello 世界");
+        assert_eq!(
+            xml_escape(r#"<a href="/?a=1&b='2'">"#),
+            "&lt;a href=&quot;/?a=1&amp;b=&apos;2&apos;&quot;&gt;"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use axum::{extract::State, Extension};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc};
+use validator::Validate;
+
+use axum_web::context::ReqContext;
+use axum_web::erring::{valid_user, HTTPError, SuccessResponse};
+use axum_web::object::PackObject;
+
+use super::AppState;
+use crate::db;
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct VerifyContentInput {
+    pub id: Option<PackObject<xid::Id>>,
+    pub cid: Option<PackObject<xid::Id>>,
+    #[validate(range(min = 1, max = 10000))]
+    pub version: Option<i16>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct VerifyContentOutput {
+    pub id: PackObject<xid::Id>,
+    pub ok: bool,
+    pub corrupt: bool,
+    pub status: i8,
+    pub version: i16,
+    pub length: i32, // the stored length
+    pub size: i32,   // the size of the stored bytes
+}
+
+// resolves the contents of the creation version: the creation's content if it is the current
+// version, and the contents of its latest publications in this version.
+async fn contents_of(app: &AppState, cid: xid::Id, version: i16) -> anyhow::Result<Vec<xid::Id>> {
+    let mut index = db::CreationIndex::with_pk(cid);
+    index.get_one(&app.scylla).await?;
+
+    let mut ids: Vec<xid::Id> = Vec::new();
+    let mut creation = db::Creation::with_pk(index.gid, cid);
+    creation
+        .get_one(&app.scylla, vec!["version".to_string()])
+        .await?;
+    if creation.version == version {
+        creation
+            .get_one(&app.scylla, vec!["content".to_string()])
+            .await?;
+        ids.push(creation.content);
+    }
+
+    for idoc in db::PublicationIndex::list_published_by_cid(&app.scylla, cid).await? {
+        if idoc.version != version {
+            continue;
+        }
+        let mut doc = db::Publication::with_pk(idoc.gid, cid, idoc.language, version);
+        // "content_length" loads the content id without the bytes
+        if doc
+            .get_one(&app.scylla, vec!["content_length".to_string()])
+            .await
+            .is_ok()
+            && !ids.contains(&doc.content)
+        {
+            ids.push(doc.content);
+        }
+    }
+
+    Ok(ids)
+}
+
+// verify_content checks the stored content against its hash, by the content id or by the
+// creation id and version.
+pub async fn verify_content(
+    State(app): State<Arc<AppState>>,
+    Extension(ctx): Extension<Arc<ReqContext>>,
+    to: PackObject<VerifyContentInput>,
+) -> Result<PackObject<SuccessResponse<Vec<VerifyContentOutput>>>, HTTPError> {
+    let (to, input) = to.unpack();
+    input.validate()?;
+    valid_user(ctx.user)?;
+    if ctx.user != xid::Id::from_str(db::USER_JARVIS).unwrap() {
+        return Err(HTTPError::new(
+            403,
+            "Only system user can verify content".to_string(),
+        ));
+    }
+
+    let ids = match (input.id, input.cid, input.version) {
+        (Some(id), _, _) => vec![id.unwrap()],
+        (None, Some(cid), Some(version)) => {
+            let cid = cid.unwrap();
+            ctx.set_kvs(vec![
+                ("cid", cid.to_string().into()),
+                ("version", version.into()),
+            ])
+            .await;
+            contents_of(&app, cid, version).await?
+        }
+        _ => {
+            return Err(HTTPError::new(
+                400,
+                "Missing required field `id`, or `cid` and `version`".to_string(),
+            ))
+        }
+    };
+
+    ctx.set_kvs(vec![
+        ("action", "verify_content".into()),
+        (
+            "ids",
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .into(),
+        ),
+    ])
+    .await;
+
+    let mut res: Vec<VerifyContentOutput> = Vec::with_capacity(ids.len());
+    for id in ids {
+        let mut doc = db::Content::with_pk(id);
+        let ok = doc.verify(&app.scylla).await?;
+        if !ok {
+            log::warn!(target: "content",
+                action = "verify_content",
+                rid = ctx.rid,
+                id = id.to_string(),
+                length = doc.length,
+                size = doc.content.len();
+                "content does not match the hash",
+            );
+        }
+        res.push(VerifyContentOutput {
+            id: to.with(id),
+            ok,
+            corrupt: doc.corrupt,
+            status: doc.status,
+            version: doc.version,
+            length: doc.length,
+            size: doc.content.len() as i32,
+        });
+    }
+
+    ctx.set("corrupt", res.iter().filter(|v| !v.ok).count().into())
+        .await;
+    Ok(to.with(SuccessResponse::new(res)))
+}
@@ -1,17 +1,33 @@
 use config::{Config, ConfigError, File, FileFormat};
+use log::LevelFilter;
 use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Log {
     pub level: String,
 }
 
+impl Log {
+    pub fn level_filter(&self) -> Result<LevelFilter, ConfigError> {
+        LevelFilter::from_str(&self.level)
+            .map_err(|_| ConfigError::Message(format!("invalid log level: {:?}", self.level)))
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Server {
     pub port: u16,
     pub cert_file: String,
     pub key_file: String,
     pub graceful_shutdown: usize,
+    #[serde(default)]
+    pub warm_up: bool,
+    #[serde(default)]
+    pub warm_up_strict: bool,
+    // request budgets in milliseconds by route group: read, list, write and admin.
+    #[serde(default)]
+    pub timeouts: HashMap<String, u64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -19,6 +35,34 @@ pub struct ScyllaDB {
     pub nodes: Vec<String>,
     pub username: String,
     pub password: String,
+    #[serde(default = "default_query_timeout_ms")]
+    pub query_timeout_ms: u64,
+    #[serde(default = "default_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+}
+
+impl Default for ScyllaDB {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            username: String::new(),
+            password: String::new(),
+            query_timeout_ms: default_query_timeout_ms(),
+            request_timeout_ms: default_request_timeout_ms(),
+        }
+    }
+}
+
+fn default_query_timeout_ms() -> u64 {
+    3000
+}
+
+// the request budget in milliseconds of the ScyllaDB queries, it is the budget of the admin
+// routes too, the longest of the route budgets, so no route outlives its queries.
+pub const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 15000;
+
+fn default_request_timeout_ms() -> u64 {
+    DEFAULT_REQUEST_TIMEOUT_MS
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -27,6 +71,141 @@ pub struct Meili {
     pub api_key: String,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Quota {
+    pub max_bytes: i64,
+    pub max_creations_per_day: i64,
+    pub trusted_gids: Vec<String>, // groups that bypass max_creations_per_day
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024 * 1024 * 1024,
+            max_creations_per_day: 1000,
+            trusted_gids: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Cover {
+    pub allowed_hosts: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Content {
+    pub dedup: bool,
+    pub detect_language: bool,
+    pub detect_confidence: f64,
+    pub spdx_license: bool,
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self {
+            dedup: false,
+            detect_language: true,
+            detect_confidence: 0.8,
+            spdx_license: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Search {
+    pub max_query_len: usize,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self { max_query_len: 128 }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Metrics {
+    pub rfp_sample_rate: f64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            rfp_sample_rate: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Publish {
+    pub min_chars: usize,
+}
+
+impl Default for Publish {
+    fn default() -> Self {
+        Self { min_chars: 1 }
+    }
+}
+
+// the public URLs of the sitemap, `{gid}`, `{cid}`, `{id}` (the collection id), `{language}`
+// (ISO 639-3) and `{n}` (the 1-based chunk number) are replaced.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Site {
+    pub url_template_publication: String,
+    pub url_template_collection: String,
+    pub url_template_sitemap: String,
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Self {
+            url_template_publication: "https://www.yiwen.ai/pub/{cid}?language={language}"
+                .to_string(),
+            url_template_collection: "https://www.yiwen.ai/collection/{id}".to_string(),
+            url_template_sitemap: "https://www.yiwen.ai/sitemap-{n}.xml".to_string(),
+        }
+    }
+}
+
+// the models accepted as the `model` of publications, the aliases (lowercase) map to
+// the canonical names in `allowed`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Models {
+    pub allowed: Vec<String>,
+    pub aliases: HashMap<String, String>,
+    pub default: String,
+}
+
+impl Default for Models {
+    fn default() -> Self {
+        Self {
+            allowed: vec![
+                "gpt-3.5".to_string(),
+                "gpt-4".to_string(),
+                "gpt-4o".to_string(),
+                "gpt-4o-mini".to_string(),
+            ],
+            aliases: HashMap::from([
+                ("gpt-3.5-turbo".to_string(), "gpt-3.5".to_string()),
+                ("gpt3.5".to_string(), "gpt-3.5".to_string()),
+                ("gpt4".to_string(), "gpt-4".to_string()),
+                ("gpt-4-turbo".to_string(), "gpt-4".to_string()),
+                ("gpt4o".to_string(), "gpt-4o".to_string()),
+                ("gpt4o-mini".to_string(), "gpt-4o-mini".to_string()),
+            ]),
+            default: crate::db::DEFAULT_MODEL.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Conf {
     pub env: String,
@@ -34,13 +213,31 @@ pub struct Conf {
     pub server: Server,
     pub scylla: ScyllaDB,
     pub meili: Meili,
+    #[serde(default)]
+    pub quota: Quota,
+    #[serde(default)]
+    pub cover: Cover,
+    #[serde(default)]
+    pub search: Search,
+    #[serde(default)]
+    pub content: Content,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(default)]
+    pub publish: Publish,
+    #[serde(default)]
+    pub models: Models,
+    #[serde(default)]
+    pub site: Site,
 }
 
 impl Conf {
     pub fn new() -> Result<Self, ConfigError> {
-        let file_name =
-            std::env::var("CONFIG_FILE_PATH").unwrap_or_else(|_| "./config/default.toml".into());
-        Self::from(&file_name)
+        Self::from(&Self::file_name())
+    }
+
+    pub fn file_name() -> String {
+        std::env::var("CONFIG_FILE_PATH").unwrap_or_else(|_| "./config/default.toml".into())
     }
 
     pub fn from(file_name: &str) -> Result<Self, ConfigError> {
@@ -48,3 +245,57 @@ impl Conf {
         builder.build()?.try_deserialize::<Conf>()
     }
 }
+
+// reload re-reads the config file and applies the settings that are safe to change at runtime,
+// only `log.level` for now, the others still need a restart. The current settings are kept
+// if the file is invalid. Returns the new log level.
+pub fn reload(file_name: &str) -> Result<LevelFilter, ConfigError> {
+    let cfg = Conf::from(file_name)?;
+    let level = cfg.log.level_filter()?;
+    // the logger accepts all levels, the records are filtered by the max level, see `main`.
+    log::set_max_level(level);
+    Ok(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_works() {
+        let cfg = std::fs::read_to_string("./config/default.toml").unwrap();
+        assert!(cfg.contains("level = \"info\""));
+        let file_name = std::env::temp_dir().join(format!("writing-{}.toml", xid::new()));
+        let file_name = file_name.to_str().unwrap();
+
+        log::set_max_level(LevelFilter::Info);
+        std::fs::write(
+            file_name,
+            cfg.replace("level = \"info\"", "level = \"debug\""),
+        )
+        .unwrap();
+        assert_eq!(reload(file_name).unwrap(), LevelFilter::Debug);
+        assert_eq!(log::max_level(), LevelFilter::Debug);
+
+        std::fs::write(
+            file_name,
+            cfg.replace("level = \"info\"", "level = \"WARN\""),
+        )
+        .unwrap();
+        assert_eq!(reload(file_name).unwrap(), LevelFilter::Warn);
+        assert_eq!(log::max_level(), LevelFilter::Warn);
+
+        // an invalid level keeps the current one
+        std::fs::write(
+            file_name,
+            cfg.replace("level = \"info\"", "level = \"verbose\""),
+        )
+        .unwrap();
+        assert!(reload(file_name).is_err());
+        assert_eq!(log::max_level(), LevelFilter::Warn);
+
+        let _ = std::fs::remove_file(file_name);
+        assert!(reload(file_name).is_err());
+        assert_eq!(log::max_level(), LevelFilter::Warn);
+    }
+}
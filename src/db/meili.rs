@@ -2,12 +2,19 @@ use base64::{engine::general_purpose, Engine as _};
 use isolang::Language;
 use meilisearch_sdk::{
     client::Client,
+    documents::DocumentDeletionQuery,
     indexes::Index,
     search::{SearchQuery, Selectors},
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    time::Duration,
+};
 
+use axum_web::erring::HTTPError;
 use axum_web::object::{cbor_from_slice, cbor_to_vec, PackObject};
 
 use crate::conf;
@@ -20,6 +27,10 @@ pub struct Document {
     pub kind: i8, // 0: creation, 1: publication, 2: collection
     pub version: i16,
 
+    // the cid in plain text to filter by, the documents indexed before it was added have
+    // it after the next update.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub cid: String,
     #[serde(default)]
     pub updated_at: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -32,8 +43,16 @@ pub struct Document {
     pub authors: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub summary: Option<String>,
+    // the content statistics, left out on the metadata-only updates to keep the indexed ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub words: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_minutes: Option<i16>,
 }
 
+// the reading speed to estimate `reading_minutes`.
+const WORDS_PER_MINUTE: i32 = 250;
+
 type TripleId = (
     PackObject<xid::Id>,
     PackObject<Language>,
@@ -47,12 +66,41 @@ impl Document {
         let id = general_purpose::URL_SAFE_NO_PAD.encode(data);
         Self {
             id,
+            cid: cid.to_string(),
             gid: gid.to_string(),
             language: lang.to_639_3().to_string(),
             ..Default::default()
         }
     }
 
+    // sets the content statistics, a document without words is left without them.
+    pub fn set_words(&mut self, words: i32) {
+        if words > 0 {
+            self.words = Some(words);
+            self.reading_minutes = Some(
+                ((words + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE).min(i16::MAX as i32) as i16,
+            );
+        }
+    }
+
+    // digest of the searchable content, `updated_at` is excluded so that a status flip
+    // without content changes gets the same digest.
+    pub fn digest(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.id.hash(&mut hasher);
+        self.gid.hash(&mut hasher);
+        self.language.hash(&mut hasher);
+        self.kind.hash(&mut hasher);
+        self.version.hash(&mut hasher);
+        self.genre.hash(&mut hasher);
+        self.title.hash(&mut hasher);
+        self.keywords.hash(&mut hasher);
+        self.authors.hash(&mut hasher);
+        self.summary.hash(&mut hasher);
+        self.words.hash(&mut hasher);
+        hasher.finish()
+    }
+
     pub fn extract_id(&self) -> TripleId {
         let data = general_purpose::URL_SAFE_NO_PAD
             .decode(&self.id)
@@ -62,7 +110,7 @@ impl Document {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub struct DocumentOutput {
     pub gid: PackObject<xid::Id>,
     pub cid: PackObject<xid::Id>,
@@ -72,10 +120,14 @@ pub struct DocumentOutput {
     pub kind: i8, // 0: creation, 1: publication
     pub title: String,
     pub summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub words: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_minutes: Option<i16>,
 }
 
 impl DocumentOutput {
-    fn from(val: Document, to: &PackObject<()>) -> Self {
+    pub fn from(val: Document, to: &PackObject<()>) -> Self {
         let (cid, language, gid) = val.extract_id();
         Self {
             gid: to.with(gid.unwrap()),
@@ -86,24 +138,143 @@ impl DocumentOutput {
             kind: val.kind,
             title: val.title.unwrap_or_default(),
             summary: val.summary.unwrap_or_default(),
+            words: val.words,
+            reading_minutes: val.reading_minutes,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub struct SearchOutput {
     pub hits: Vec<DocumentOutput>,
     pub languages: HashMap<String, usize>,
+    #[serde(default)]
+    pub filtered: u32, // stale or restricted hits removed after the search
+}
+
+// rank_related scores the candidates by the genres and keywords they share with the
+// source document, a shared genre weights more than a keyword. The candidates without
+// overlap and the documents of the source creation are dropped, one document is kept
+// for each creation, and ties are broken by recency.
+pub fn rank_related(source: &Document, candidates: Vec<Document>, limit: usize) -> Vec<Document> {
+    let lower = |v: &Option<Vec<String>>| -> HashSet<String> {
+        v.as_ref()
+            .map(|v| v.iter().map(|s| s.to_lowercase()).collect())
+            .unwrap_or_default()
+    };
+    let overlap = |a: &HashSet<String>, b: &Option<Vec<String>>| -> usize {
+        lower(b).intersection(a).count()
+    };
+
+    let cid = *source.extract_id().0;
+    let genre = lower(&source.genre);
+    let keywords = lower(&source.keywords);
+    let mut scored: Vec<(usize, Document)> = candidates
+        .into_iter()
+        .filter(|doc| *doc.extract_id().0 != cid)
+        .map(|doc| {
+            let score = overlap(&genre, &doc.genre) * 2 + overlap(&keywords, &doc.keywords);
+            (score, doc)
+        })
+        .filter(|(score, _)| *score > 0)
+        .collect();
+    scored.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then_with(|| b.1.updated_at.cmp(&a.1.updated_at))
+    });
+
+    let mut seen: HashSet<xid::Id> = HashSet::new();
+    scored
+        .into_iter()
+        .map(|(_, doc)| doc)
+        .filter(|doc| seen.insert(*doc.extract_id().0))
+        .take(limit)
+        .collect()
+}
+
+// words_filter builds the filter expression of the words range, both bounds are inclusive.
+pub fn words_filter(min: Option<i32>, max: Option<i32>) -> Result<Option<String>, HTTPError> {
+    match (min, max) {
+        (Some(min), Some(max)) if min > max => Err(HTTPError::new(
+            400,
+            format!("Invalid words range, min {} > max {}", min, max),
+        )),
+        (Some(min), Some(max)) => Ok(Some(format!("words {} TO {}", min, max))),
+        (Some(min), None) => Ok(Some(format!("words >= {}", min))),
+        (None, Some(max)) => Ok(Some(format!("words <= {}", max))),
+        (None, None) => Ok(None),
+    }
+}
+
+// cids_filter builds the filter expression matching the documents of the cids.
+pub fn cids_filter(cids: &[xid::Id]) -> Option<String> {
+    if cids.is_empty() {
+        return None;
+    }
+    let values: Vec<String> = cids.iter().map(|v| v.to_string()).collect();
+    Some(format!("cid IN [{}]", values.join(",")))
+}
+
+// quotes a value for a filter expression.
+fn filter_value(v: &str) -> String {
+    format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// the order of the paginated search, the document id breaks the ties of updated_at.
+// "sort" should be the first ranking rule so that the hits follow it strictly.
+pub const SEARCH_SORT: [&str; 2] = ["updated_at:desc", "id:asc"];
+
+// SearchCursor is the position of a paginated search, `ids` are the returned documents
+// updated at `updated_at`, they are excluded from the next page.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchCursor {
+    pub updated_at: i64,
+    pub ids: Vec<String>,
+}
+
+impl SearchCursor {
+    // the filter expression matching the documents after the cursor.
+    pub fn filter(&self) -> String {
+        if self.ids.is_empty() {
+            return format!("updated_at < {}", self.updated_at);
+        }
+        let ids: Vec<String> = self.ids.iter().map(|v| filter_value(v)).collect();
+        format!(
+            "updated_at < {} OR (updated_at = {} AND id NOT IN [{}])",
+            self.updated_at,
+            self.updated_at,
+            ids.join(",")
+        )
+    }
+
+    // the cursor after the page, it carries over the ids of the previous cursor when the
+    // page ends at the same updated_at.
+    pub fn next(prev: Option<&Self>, page: &[Document]) -> Option<Self> {
+        let last = page.last()?;
+        let mut ids: Vec<String> = match prev {
+            Some(c) if c.updated_at == last.updated_at => c.ids.clone(),
+            _ => Vec::new(),
+        };
+        ids.extend(
+            page.iter()
+                .filter(|d| d.updated_at == last.updated_at)
+                .map(|d| d.id.clone()),
+        );
+        Some(Self {
+            updated_at: last.updated_at,
+            ids,
+        })
+    }
 }
 
 // Indexe settings
 // {
 //     "rankingRules": [
+//         "sort",
 //         "words",
 //         "typo",
 //         "proximity",
 //         "attribute",
-//         "sort",
 //         "exactness",
 //         "updated_at:desc"
 //     ],
@@ -119,15 +290,28 @@ pub struct SearchOutput {
 //         "kind",
 //         "version",
 //         "updated_at",
+//         "genre",
 //         "title",
-//         "summary"
+//         "keywords",
+//         "summary",
+//         "words",
+//         "reading_minutes"
 //     ],
 //     "sortableAttributes": [
-//         "updated_at"
+//         "id",
+//         "reading_minutes",
+//         "updated_at",
+//         "words"
 //     ],
 //     "filterableAttributes": [
+//         "cid",
+//         "genre",
 //         "gid",
-//         "language"
+//         "id",
+//         "language",
+//         "reading_minutes",
+//         "updated_at",
+//         "words"
 //     ],
 //     "pagination": {
 //         "maxTotalHits": 20
@@ -141,6 +325,11 @@ pub struct MeiliSearch {
     icreation: Index,
     ipublication: Index,
     cli: Client,
+    // the documents put in test, keyed by space and document id.
+    #[cfg(test)]
+    indexed: std::sync::Mutex<HashMap<String, Document>>,
+    #[cfg(test)]
+    put_calls: std::sync::atomic::AtomicUsize,
 }
 
 pub enum Space {
@@ -157,6 +346,8 @@ impl MeiliSearch {
             icreation: client.index("creation"),
             ipublication: client.index("publication"),
             cli: client,
+            indexed: std::sync::Mutex::new(HashMap::new()),
+            put_calls: std::sync::atomic::AtomicUsize::new(0),
         })
     }
 
@@ -172,20 +363,83 @@ impl MeiliSearch {
         })
     }
 
+    // `filter` is an extra filter expression, e.g. the words range or the cids.
     pub async fn search(
         &self,
         space: Space,
         lang: Option<Language>,
+        filter: Option<String>,
         q: &str,
         to: &PackObject<()>,
     ) -> anyhow::Result<SearchOutput> {
+        let limit = if q.is_empty() { 1000 } else { 20 };
+        let (docs, languages) = self.query(space, lang, filter, q, limit, &[]).await?;
+        Ok(SearchOutput {
+            hits: docs
+                .into_iter()
+                .map(|d| DocumentOutput::from(d, to))
+                .collect(),
+            languages,
+            filtered: 0,
+        })
+    }
+
+    // search_page returns a page of the hits ordered by recency, and the cursor of the next
+    // page if the page is full.
+    pub async fn search_page(
+        &self,
+        space: Space,
+        lang: Option<Language>,
+        filter: Option<String>,
+        q: &str,
+        cursor: Option<&SearchCursor>,
+        page_size: usize,
+        to: &PackObject<()>,
+    ) -> anyhow::Result<(SearchOutput, Option<SearchCursor>)> {
+        let filter = match (filter, cursor.map(|c| format!("({})", c.filter()))) {
+            (Some(f), Some(c)) => Some(format!("({}) AND {}", f, c)),
+            (f, c) => f.or(c),
+        };
+        let (docs, languages) = self
+            .query(space, lang, filter, q, page_size, &SEARCH_SORT)
+            .await?;
+        let next = if docs.len() >= page_size {
+            SearchCursor::next(cursor, &docs)
+        } else {
+            None
+        };
+
+        Ok((
+            SearchOutput {
+                hits: docs
+                    .into_iter()
+                    .map(|d| DocumentOutput::from(d, to))
+                    .collect(),
+                languages,
+                filtered: 0,
+            },
+            next,
+        ))
+    }
+
+    async fn query(
+        &self,
+        space: Space,
+        lang: Option<Language>,
+        filter: Option<String>,
+        q: &str,
+        limit: usize,
+        sort: &[&str],
+    ) -> anyhow::Result<(Vec<Document>, HashMap<String, usize>)> {
         let (mut sq, gid) = match space {
             Space::Group(gid) => (SearchQuery::new(&self.icreation), Some(gid)),
             Space::Pub(some_gid) => (SearchQuery::new(&self.ipublication), some_gid),
         };
 
-        let limit = if q.is_empty() { 1000 } else { 20 };
         sq.with_query(q).with_limit(limit);
+        if !sort.is_empty() {
+            sq.with_sort(sort);
+        }
         let mut filters: Vec<&str> = Vec::new();
         let f = if let Some(gid) = gid {
             format!("gid = {}", gid)
@@ -206,50 +460,825 @@ impl MeiliSearch {
             filters.push(&f);
         }
 
+        if let Some(ref f) = filter {
+            filters.push(f);
+        }
+
         if !filters.is_empty() {
             sq.with_array_filter(filters);
         }
 
         let res = sq.with_facets(Selectors::All).execute::<Document>().await?;
-        Ok(SearchOutput {
-            hits: res
-                .hits
-                .into_iter()
-                .map(|d| DocumentOutput::from(d.result, to))
-                .collect(),
-            languages: res
-                .facet_distribution
+        Ok((
+            res.hits.into_iter().map(|d| d.result).collect(),
+            res.facet_distribution
                 .map_or_else(|| None, |mut m| m.remove("language"))
                 .unwrap_or_default(),
-        })
+        ))
     }
 
     #[cfg(test)]
-    pub async fn add_or_update(&self, _space: Space, _docs: Vec<Document>) -> anyhow::Result<()> {
-        Ok(())
+    pub async fn genre_facets(&self, _gid: xid::Id) -> anyhow::Result<HashMap<String, usize>> {
+        Err(anyhow::Error::msg("meilisearch is not available in test"))
+    }
+
+    // counts the group's published documents by genre, a document is counted once per language.
+    #[cfg(not(test))]
+    pub async fn genre_facets(&self, gid: xid::Id) -> anyhow::Result<HashMap<String, usize>> {
+        let filter = format!("gid = {}", gid);
+        let res = SearchQuery::new(&self.ipublication)
+            .with_filter(&filter)
+            .with_facets(Selectors::Some(&["genre"]))
+            .with_limit(0)
+            .execute::<Document>()
+            .await?;
+        Ok(res
+            .facet_distribution
+            .map_or_else(|| None, |mut m| m.remove("genre"))
+            .unwrap_or_default())
+    }
+
+    #[cfg(test)]
+    pub async fn related(
+        &self,
+        _source: &Document,
+        _lang: Option<Language>,
+        _limit: usize,
+    ) -> anyhow::Result<Vec<Document>> {
+        Err(anyhow::Error::msg("meilisearch is not available in test"))
+    }
+
+    // searches the publications sharing genres or keywords with the source document.
+    #[cfg(not(test))]
+    pub async fn related(
+        &self,
+        source: &Document,
+        lang: Option<Language>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Document>> {
+        // the candidates fetched to be ranked
+        const RELATED_CANDIDATES: usize = 100;
+
+        let q = source.keywords.clone().unwrap_or_default().join(" ");
+        let genre = source.genre.clone().unwrap_or_default();
+        if q.is_empty() && genre.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut filters: Vec<String> = Vec::new();
+        if !genre.is_empty() {
+            let values: Vec<String> = genre.iter().map(|v| filter_value(v)).collect();
+            filters.push(format!("genre IN [{}]", values.join(",")));
+        }
+        if let Some(lang) = lang {
+            filters.push(format!("language = {}", lang.to_639_3()));
+        }
+        let filter = filters.join(" AND ");
+
+        let mut sq = SearchQuery::new(&self.ipublication);
+        sq.with_query(&q).with_limit(RELATED_CANDIDATES);
+        if !filter.is_empty() {
+            sq.with_filter(&filter);
+        }
+        let res = sq.execute::<Document>().await?;
+        let docs: Vec<Document> = res.hits.into_iter().map(|d| d.result).collect();
+        Ok(rank_related(source, docs, limit))
     }
 
     #[cfg(test)]
-    pub async fn delete(&self, _space: Space, _ids: Vec<String>) -> anyhow::Result<()> {
+    pub async fn ping(&self) -> anyhow::Result<()> {
         Ok(())
     }
 
+    // checks that the Meilisearch server is available.
     #[cfg(not(test))]
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        let _ = self.cli.health().await?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub async fn ensure_settings(&self) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    // applies the filterable, sortable and displayed attributes and the ranking rules to the
+    // indexes if missing, returns true if any settings was updated.
+    #[cfg(not(test))]
+    pub async fn ensure_settings(&self) -> anyhow::Result<bool> {
+        let filterable = vec![
+            "cid".to_string(),
+            "genre".to_string(),
+            "gid".to_string(),
+            "id".to_string(),
+            "language".to_string(),
+            "reading_minutes".to_string(),
+            "updated_at".to_string(),
+            "words".to_string(),
+        ];
+        let sortable = vec![
+            "id".to_string(),
+            "reading_minutes".to_string(),
+            "updated_at".to_string(),
+            "words".to_string(),
+        ];
+        let mut updated = false;
+        for index in [&self.icreation, &self.ipublication] {
+            // related documents are ranked by their genres and keywords
+            let mut attrs = index.get_displayed_attributes().await?;
+            if !attrs.iter().any(|v| v == "*") {
+                let mut missing = false;
+                for attr in ["genre", "keywords", "words", "reading_minutes"] {
+                    if !attrs.iter().any(|v| v == attr) {
+                        attrs.push(attr.to_string());
+                        missing = true;
+                    }
+                }
+                if missing {
+                    index
+                        .set_displayed_attributes(&attrs)
+                        .await?
+                        .wait_for_completion(&self.cli, None, None)
+                        .await?;
+                    updated = true;
+                }
+            }
+
+            let mut attrs = index.get_filterable_attributes().await?;
+            attrs.sort();
+            if attrs != filterable {
+                index
+                    .set_filterable_attributes(&filterable)
+                    .await?
+                    .wait_for_completion(&self.cli, None, None)
+                    .await?;
+                updated = true;
+            }
+
+            let mut attrs = index.get_sortable_attributes().await?;
+            attrs.sort();
+            if attrs != sortable {
+                index
+                    .set_sortable_attributes(&sortable)
+                    .await?
+                    .wait_for_completion(&self.cli, None, None)
+                    .await?;
+                updated = true;
+            }
+
+            // the paginated search follows the sort strictly, the searches without sort
+            // are ranked by relevance as before.
+            let mut rules = index.get_ranking_rules().await?;
+            if rules.first().map(|v| v.as_str()) != Some("sort") {
+                rules.retain(|v| v != "sort");
+                rules.insert(0, "sort".to_string());
+                index
+                    .set_ranking_rules(&rules)
+                    .await?
+                    .wait_for_completion(&self.cli, None, None)
+                    .await?;
+                updated = true;
+            }
+        }
+
+        Ok(updated)
+    }
+
     pub async fn add_or_update(&self, space: Space, docs: Vec<Document>) -> anyhow::Result<()> {
+        self.put_documents(&space, &docs).await
+    }
+
+    // like add_or_update, but skips the documents whose searchable content is the same as
+    // the one indexed in Meilisearch, returns the number of documents sent to Meilisearch.
+    pub async fn upsert(&self, space: Space, docs: Vec<Document>) -> anyhow::Result<usize> {
+        let indexed = self.get_digests(&space, &docs).await?;
+        let docs: Vec<Document> = docs
+            .into_iter()
+            .filter(|doc| indexed.get(&doc.id) != Some(&doc.digest()))
+            .collect();
+
+        if docs.is_empty() {
+            return Ok(0);
+        }
+
+        let count = docs.len();
+        self.add_or_update(space, docs).await?;
+        Ok(count)
+    }
+
+    pub async fn delete(&self, space: Space, ids: Vec<String>) -> anyhow::Result<()> {
+        self.delete_documents(&space, &ids).await
+    }
+
+    // removes a group's documents from its search space. Space::Pub(None) is rejected,
+    // it would clear the whole public search space.
+    pub async fn delete_space(&self, space: Space) -> anyhow::Result<()> {
+        let gid = match space {
+            Space::Group(gid) | Space::Pub(Some(gid)) => gid,
+            Space::Pub(None) => {
+                return Err(HTTPError::new(
+                    400,
+                    "Can not delete the whole public search space".to_string(),
+                )
+                .into())
+            }
+        };
+
+        self.delete_by_gid(space, gid).await
+    }
+
+    // deletes all documents of the group from the space with a filter-based delete.
+    pub async fn delete_by_gid(&self, space: Space, gid: xid::Id) -> anyhow::Result<()> {
+        self.delete_documents_by_gid(&space, gid).await
+    }
+
+    #[cfg(test)]
+    async fn get_digests(
+        &self,
+        space: &Space,
+        docs: &[Document],
+    ) -> anyhow::Result<HashMap<String, u64>> {
+        let indexed = self.indexed.lock().unwrap();
+        Ok(docs
+            .iter()
+            .filter_map(|doc| {
+                indexed
+                    .get(&digest_key(space, &doc.id))
+                    .map(|v| (doc.id.clone(), v.digest()))
+            })
+            .collect())
+    }
+
+    #[cfg(test)]
+    async fn put_documents(&self, space: &Space, docs: &[Document]) -> anyhow::Result<()> {
+        self.put_calls
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let mut indexed = self.indexed.lock().unwrap();
+        for doc in docs {
+            indexed.insert(digest_key(space, &doc.id), doc.clone());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    async fn delete_documents(&self, space: &Space, ids: &[String]) -> anyhow::Result<()> {
+        let mut indexed = self.indexed.lock().unwrap();
+        for id in ids {
+            indexed.remove(&digest_key(space, id));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub async fn count_by_gid(&self, _space: &Space, _gid: xid::Id) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+
+    #[cfg(test)]
+    async fn delete_documents_by_gid(&self, space: &Space, gid: xid::Id) -> anyhow::Result<()> {
+        let prefix = digest_key(space, "");
+        let gid = gid.to_string();
+        let mut indexed = self.indexed.lock().unwrap();
+        indexed.retain(|key, doc| !key.starts_with(&prefix) || doc.gid != gid);
+        Ok(())
+    }
+
+    // returns the digests of the documents indexed in Meilisearch by id, so that the replicas
+    // share them and a failed indexing task is retried. The fields not displayed are missing
+    // from the indexed documents, their digests do not match and they are always sent.
+    #[cfg(not(test))]
+    async fn get_digests(
+        &self,
+        space: &Space,
+        docs: &[Document],
+    ) -> anyhow::Result<HashMap<String, u64>> {
+        if docs.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let index = match space {
+            Space::Group(_) => &self.icreation,
+            Space::Pub(_) => &self.ipublication,
+        };
+        let ids: Vec<String> = docs.iter().map(|v| filter_value(&v.id)).collect();
+        let filter = format!("id IN [{}]", ids.join(","));
+        let res = SearchQuery::new(index)
+            .with_filter(&filter)
+            .with_limit(docs.len())
+            .execute::<Document>()
+            .await?;
+        Ok(res
+            .hits
+            .into_iter()
+            .map(|v| (v.result.id.clone(), v.result.digest()))
+            .collect())
+    }
+
+    #[cfg(not(test))]
+    async fn put_documents(&self, space: &Space, docs: &[Document]) -> anyhow::Result<()> {
         match space {
-            Space::Group(_) => self.icreation.add_or_update(&docs, Some("id")).await?,
-            Space::Pub(_) => self.ipublication.add_or_update(&docs, Some("id")).await?,
+            Space::Group(_) => self.icreation.add_or_update(docs, Some("id")).await?,
+            Space::Pub(_) => self.ipublication.add_or_update(docs, Some("id")).await?,
         };
 
         Ok(())
     }
 
     #[cfg(not(test))]
-    pub async fn delete(&self, space: Space, ids: Vec<String>) -> anyhow::Result<()> {
+    async fn delete_documents(&self, space: &Space, ids: &[String]) -> anyhow::Result<()> {
         match space {
-            Space::Group(_) => self.icreation.delete_documents(&ids).await?,
-            Space::Pub(_) => self.ipublication.delete_documents(&ids).await?,
+            Space::Group(_) => self.icreation.delete_documents(ids).await?,
+            Space::Pub(_) => self.ipublication.delete_documents(ids).await?,
+        };
+        Ok(())
+    }
+
+    // counts the documents of the group in the space, it is an estimated number.
+    #[cfg(not(test))]
+    pub async fn count_by_gid(&self, space: &Space, gid: xid::Id) -> anyhow::Result<usize> {
+        let index = match space {
+            Space::Group(_) => &self.icreation,
+            Space::Pub(_) => &self.ipublication,
         };
+        let filter = format!("gid = {}", gid);
+        let res = SearchQuery::new(index)
+            .with_filter(&filter)
+            .with_limit(0)
+            .execute::<Document>()
+            .await?;
+        Ok(res.estimated_total_hits.unwrap_or(0))
+    }
+
+    // waits for the deletion task, a large group can have 100k documents.
+    #[cfg(not(test))]
+    async fn delete_documents_by_gid(&self, space: &Space, gid: xid::Id) -> anyhow::Result<()> {
+        let index = match space {
+            Space::Group(_) => &self.icreation,
+            Space::Pub(_) => &self.ipublication,
+        };
+        let filter = format!("gid = {}", gid);
+        let mut query = DocumentDeletionQuery::new(index);
+        query.with_filter(&filter);
+        let task = index
+            .delete_documents_with(&query)
+            .await?
+            .wait_for_completion(
+                &self.cli,
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(600)),
+            )
+            .await?;
+        if task.is_failure() {
+            return Err(anyhow::Error::msg(format!(
+                "delete documents of group {} failed: {:?}",
+                gid, task
+            )));
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+fn digest_key(space: &Space, id: &str) -> String {
+    match space {
+        Space::Group(_) => format!("group:{}", id),
+        Space::Pub(_) => format!("pub:{}", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+
+    #[test]
+    fn rank_related_works() {
+        let gid = xid::new();
+        let doc = |cid: xid::Id, updated_at: i64, genre: &[&str], keywords: &[&str]| {
+            let mut doc = Document::new(cid, Language::Eng, gid);
+            doc.kind = 1;
+            doc.updated_at = updated_at;
+            doc.genre = Some(genre.iter().map(|v| v.to_string()).collect());
+            doc.keywords = Some(keywords.iter().map(|v| v.to_string()).collect());
+            doc
+        };
+
+        let source = doc(xid::new(), 1, &["Fiction"], &["rust", "async"]);
+        let same = doc(*source.extract_id().0, 9, &["Fiction"], &["rust", "async"]);
+        let unrelated = doc(xid::new(), 9, &["Poetry"], &["spring"]);
+        let keyword_only = doc(xid::new(), 8, &["Poetry"], &["Rust"]);
+        let genre_only = doc(xid::new(), 2, &["fiction"], &[]);
+        let both = doc(xid::new(), 1, &["Fiction"], &["async"]);
+        let both_newer = doc(xid::new(), 3, &["Fiction"], &["rust"]);
+        let mut both_zho = both_newer.clone();
+        both_zho.id = Document::new(*both_newer.extract_id().0, Language::Zho, gid).id;
+
+        let candidates = vec![
+            unrelated.clone(),
+            keyword_only.clone(),
+            same,
+            genre_only.clone(),
+            both.clone(),
+            both_newer.clone(),
+            both_zho,
+        ];
+        let ids = |docs: Vec<Document>| docs.into_iter().map(|v| v.id).collect::<Vec<_>>();
+
+        let res = rank_related(&source, candidates.clone(), 10);
+        assert_eq!(
+            ids(res),
+            ids(vec![both_newer.clone(), both, genre_only, keyword_only])
+        );
+        let res = rank_related(&source, candidates, 1);
+        assert_eq!(ids(res), ids(vec![both_newer]));
+
+        let source = doc(xid::new(), 1, &[], &[]);
+        assert!(rank_related(&source, vec![unrelated], 10).is_empty());
+
+        assert_eq!(filter_value("Sci-Fi"), r#""Sci-Fi""#);
+        assert_eq!(filter_value(r#"a"b"#), r#""a\"b""#);
+    }
+
+    #[test]
+    fn words_works() {
+        assert_eq!(words_filter(None, None).unwrap(), None);
+        assert_eq!(
+            words_filter(Some(100), None).unwrap().unwrap(),
+            "words >= 100"
+        );
+        assert_eq!(
+            words_filter(None, Some(100)).unwrap().unwrap(),
+            "words <= 100"
+        );
+        assert_eq!(
+            words_filter(Some(100), Some(100)).unwrap().unwrap(),
+            "words 100 TO 100"
+        );
+        assert_eq!(words_filter(Some(101), Some(100)).unwrap_err().code, 400);
+
+        let mut doc = Document::new(xid::new(), Language::Eng, xid::new());
+        doc.set_words(0);
+        assert_eq!(doc.words, None);
+        assert_eq!(doc.reading_minutes, None);
+        let digest = doc.digest();
+        doc.set_words(1);
+        assert_eq!(doc.words, Some(1));
+        assert_eq!(doc.reading_minutes, Some(1));
+        assert_ne!(doc.digest(), digest);
+        doc.set_words(WORDS_PER_MINUTE * 2 + 1);
+        assert_eq!(doc.reading_minutes, Some(3));
+    }
+
+    #[test]
+    fn cids_filter_works() {
+        assert_eq!(cids_filter(&[]), None);
+        let cids = vec![xid::new(), xid::new()];
+        assert_eq!(
+            cids_filter(&cids).unwrap(),
+            format!("cid IN [{},{}]", cids[0], cids[1])
+        );
+        assert_eq!(
+            Document::new(cids[0], Language::Eng, xid::new()).cid,
+            cids[0].to_string()
+        );
+    }
+
+    #[test]
+    fn search_cursor_works() {
+        let c = SearchCursor {
+            updated_at: 100,
+            ids: vec![],
+        };
+        assert_eq!(c.filter(), "updated_at < 100");
+        let c = SearchCursor {
+            updated_at: 100,
+            ids: vec!["a".to_string(), "b".to_string()],
+        };
+        assert_eq!(
+            c.filter(),
+            r#"updated_at < 100 OR (updated_at = 100 AND id NOT IN ["a","b"])"#
+        );
+        assert_eq!(SearchCursor::next(Some(&c), &[]), None);
+
+        // seeded documents with many ties, paged in the SEARCH_SORT order with the cursor
+        // filter applied in memory.
+        let mut docs: Vec<Document> = (0..23)
+            .map(|i| {
+                let mut doc = Document::new(xid::new(), Language::Eng, xid::new());
+                doc.updated_at = 1000 - (i / 4) as i64;
+                doc
+            })
+            .collect();
+        docs.sort_by(|a, b| {
+            b.updated_at
+                .cmp(&a.updated_at)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+
+        for page_size in [2usize, 3, 4, 5, 7, 23, 30] {
+            let mut cursor: Option<SearchCursor> = None;
+            let mut seen: Vec<String> = Vec::new();
+            let mut pages = 0;
+            loop {
+                let page: Vec<Document> = docs
+                    .iter()
+                    .filter(|d| match cursor {
+                        None => true,
+                        Some(ref c) => {
+                            d.updated_at < c.updated_at
+                                || (d.updated_at == c.updated_at && !c.ids.contains(&d.id))
+                        }
+                    })
+                    .take(page_size)
+                    .cloned()
+                    .collect();
+                pages += 1;
+                seen.extend(page.iter().map(|d| d.id.clone()));
+                if page.len() < page_size {
+                    break;
+                }
+                cursor = SearchCursor::next(cursor.as_ref(), &page);
+                assert!(pages <= docs.len() + 1);
+            }
+
+            // no duplicates nor gaps, in order
+            let expected: Vec<String> = docs.iter().map(|d| d.id.clone()).collect();
+            assert_eq!(seen, expected, "page_size {}", page_size);
+        }
+    }
+
+    // needs a Meilisearch server, the documents are written to the index directly since
+    // `put_documents` is a no-op in test.
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore]
+    async fn search_page_works() {
+        let meili = MeiliSearch::new(conf::Meili {
+            url: "http://127.0.0.1:7700".to_string(),
+            api_key: "".to_string(),
+        })
+        .await
+        .unwrap();
+        meili
+            .ipublication
+            .set_filterable_attributes(["gid", "id", "language", "updated_at"])
+            .await
+            .unwrap()
+            .wait_for_completion(&meili.cli, None, None)
+            .await
+            .unwrap();
+        meili
+            .ipublication
+            .set_sortable_attributes(["id", "updated_at"])
+            .await
+            .unwrap()
+            .wait_for_completion(&meili.cli, None, None)
+            .await
+            .unwrap();
+        let mut rules = meili.ipublication.get_ranking_rules().await.unwrap();
+        rules.retain(|v| v != "sort");
+        rules.insert(0, "sort".to_string());
+        meili
+            .ipublication
+            .set_ranking_rules(&rules)
+            .await
+            .unwrap()
+            .wait_for_completion(&meili.cli, None, None)
+            .await
+            .unwrap();
+
+        let gid = xid::new();
+        let q = format!("page{}", xid::new());
+        let docs: Vec<Document> = (0..11)
+            .map(|i| {
+                let mut doc = Document::new(xid::new(), Language::Eng, gid);
+                doc.kind = 1;
+                doc.version = 1;
+                doc.updated_at = 1000 - (i / 3) as i64;
+                doc.title = Some(format!("{} {}", q, i));
+                doc
+            })
+            .collect();
+        meili
+            .ipublication
+            .add_or_update(&docs, Some("id"))
+            .await
+            .unwrap()
+            .wait_for_completion(&meili.cli, None, None)
+            .await
+            .unwrap();
+
+        let to = PackObject::Json(());
+        let mut cursor: Option<SearchCursor> = None;
+        let mut seen: Vec<xid::Id> = Vec::new();
+        let mut last_updated_at = i64::MAX;
+        loop {
+            let (res, next) = meili
+                .search_page(
+                    Space::Pub(Some(gid)),
+                    None,
+                    None,
+                    &q,
+                    cursor.as_ref(),
+                    4,
+                    &to,
+                )
+                .await
+                .unwrap();
+            for hit in res.hits.iter() {
+                assert!(hit.updated_at <= last_updated_at);
+                last_updated_at = hit.updated_at;
+                seen.push(*hit.cid.to_owned());
+            }
+            if next.is_none() {
+                break;
+            }
+            cursor = next;
+        }
+
+        let mut expected: Vec<xid::Id> = docs.iter().map(|v| *v.extract_id().0).collect();
+        expected.sort();
+        let total = seen.len();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), total, "no duplicates");
+        assert_eq!(seen, expected, "no gaps");
+
+        let ids: Vec<String> = docs.into_iter().map(|v| v.id).collect();
+        let _ = meili.ipublication.delete_documents(&ids).await;
+    }
+
+    // needs a Meilisearch server, the documents are written to the index directly since
+    // `put_documents` is a no-op in test.
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore]
+    async fn search_cids_works() {
+        let meili = MeiliSearch::new(conf::Meili {
+            url: "http://127.0.0.1:7700".to_string(),
+            api_key: "".to_string(),
+        })
+        .await
+        .unwrap();
+        meili
+            .ipublication
+            .set_filterable_attributes(["cid", "genre", "gid", "language"])
+            .await
+            .unwrap()
+            .wait_for_completion(&meili.cli, None, None)
+            .await
+            .unwrap();
+
+        let q = format!("child{}", xid::new());
+        let doc = |cid: xid::Id, title: &str| {
+            let mut doc = Document::new(cid, Language::Eng, xid::new());
+            doc.kind = 1;
+            doc.version = 1;
+            doc.title = Some(title.to_string());
+            doc
+        };
+        let child1 = doc(xid::new(), &format!("{} one", q));
+        let child2 = doc(xid::new(), &format!("{} two", q));
+        let child3 = doc(xid::new(), "Hello World");
+        let other = doc(xid::new(), &format!("{} other", q));
+        let docs = vec![
+            child1.clone(),
+            child2.clone(),
+            child3.clone(),
+            other.clone(),
+        ];
+        meili
+            .ipublication
+            .add_or_update(&docs, Some("id"))
+            .await
+            .unwrap()
+            .wait_for_completion(&meili.cli, None, None)
+            .await
+            .unwrap();
+
+        let to = PackObject::Json(());
+        let cids: Vec<xid::Id> = [&child1, &child2, &child3]
+            .iter()
+            .map(|v| *v.extract_id().0)
+            .collect();
+        let res = meili
+            .search(Space::Pub(None), None, cids_filter(&cids), &q, &to)
+            .await
+            .unwrap();
+        let mut hits: Vec<xid::Id> = res.hits.iter().map(|v| *v.cid.to_owned()).collect();
+        hits.sort();
+        let mut expected = vec![*child1.extract_id().0, *child2.extract_id().0];
+        expected.sort();
+        assert_eq!(hits, expected);
+
+        let ids: Vec<String> = docs.into_iter().map(|v| v.id).collect();
+        let _ = meili.ipublication.delete_documents(&ids).await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn upsert_works() {
+        let meili = MeiliSearch::new(conf::Meili {
+            url: "http://127.0.0.1:7700".to_string(),
+            api_key: "".to_string(),
+        })
+        .await
+        .unwrap();
+
+        let mut doc = Document::new(xid::new(), Language::Eng, xid::new());
+        doc.kind = 1;
+        doc.version = 1;
+        doc.updated_at = 1;
+        doc.title = Some("Hello".to_string());
+
+        assert_eq!(
+            meili
+                .upsert(Space::Pub(None), vec![doc.clone()])
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(meili.put_calls.load(Ordering::SeqCst), 1);
+
+        // a status re-flip only changes updated_at
+        doc.updated_at = 2;
+        assert_eq!(
+            meili
+                .upsert(Space::Pub(None), vec![doc.clone()])
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(meili.put_calls.load(Ordering::SeqCst), 1);
+
+        // the same doc in another space is indexed
+        assert_eq!(
+            meili
+                .upsert(Space::Group(xid::new()), vec![doc.clone()])
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(meili.put_calls.load(Ordering::SeqCst), 2);
+
+        doc.title = Some("Hello World".to_string());
+        assert_eq!(
+            meili
+                .upsert(Space::Pub(None), vec![doc.clone()])
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(meili.put_calls.load(Ordering::SeqCst), 3);
+
+        meili
+            .delete(Space::Pub(None), vec![doc.id.clone()])
+            .await
+            .unwrap();
+        assert_eq!(
+            meili
+                .upsert(Space::Pub(None), vec![doc.clone()])
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(meili.put_calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn delete_space_works() {
+        let meili = MeiliSearch::new(conf::Meili {
+            url: "http://127.0.0.1:7700".to_string(),
+            api_key: "".to_string(),
+        })
+        .await
+        .unwrap();
+
+        let gid = xid::new();
+        let doc1 = Document::new(xid::new(), Language::Eng, gid);
+        let doc2 = Document::new(xid::new(), Language::Eng, xid::new());
+        let docs = vec![doc1.clone(), doc2.clone()];
+        assert_eq!(
+            meili.upsert(Space::Group(gid), docs.clone()).await.unwrap(),
+            2
+        );
+        assert_eq!(
+            meili.upsert(Space::Pub(None), docs.clone()).await.unwrap(),
+            2
+        );
+
+        assert!(meili.delete_space(Space::Pub(None)).await.is_err());
+        meili.delete_space(Space::Group(gid)).await.unwrap();
+        // only the group's documents in the space are removed
+        assert_eq!(
+            meili.upsert(Space::Group(gid), docs.clone()).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            meili.upsert(Space::Pub(None), docs.clone()).await.unwrap(),
+            0
+        );
+
+        meili.delete_space(Space::Pub(Some(gid))).await.unwrap();
+        assert_eq!(meili.upsert(Space::Pub(None), vec![doc2]).await.unwrap(), 0);
+        assert_eq!(meili.upsert(Space::Pub(None), vec![doc1]).await.unwrap(), 1);
+    }
+}
@@ -1,32 +1,187 @@
 mod model_bookmark;
+mod model_changelog;
+mod model_collaborator;
 mod model_collection;
 mod model_content;
 mod model_creation;
+mod model_idempotency;
 mod model_message;
 mod model_publication;
+mod model_quota;
+mod model_rfp;
+mod model_schedule;
+mod model_setting;
 mod model_subscription;
 
-use model_content::Content;
+pub use model_content::Content;
+
+use axum_web::context::unix_ms;
+use axum_web::erring::HTTPError;
+use schemars::JsonSchema;
+use scylla_orm::{ColumnsMap, CqlValue};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::HashSet};
 
 pub mod meili;
 pub mod scylladb;
 
 pub use model_bookmark::Bookmark;
+pub use model_changelog::Changelog;
+pub use model_collaborator::{CreationCollaborator, COLLABORATOR_EDITOR, COLLABORATOR_READER};
 pub use model_collection::{Collection, CollectionChildren, CollectionInfo};
 pub use model_creation::{Creation, CreationIndex};
-pub use model_message::{support_language, Message, MessageTexts, MessageValue};
-pub use model_publication::{Publication, PublicationIndex};
-pub use model_subscription::{CollectionSubscription, CreationSubscription};
+pub use model_idempotency::{Idempotency, IDEMPOTENCY_PENDING_TTL, IDEMPOTENCY_TTL};
+pub use model_message::{
+    reading_direction, resolve_language, support_language, Message, MessageHistory, MessageTexts,
+    MessageValue, LANGUAGES, MAX_MESSAGE_HISTORY,
+};
+pub use model_publication::{GidsCursor, Publication, PublicationIndex, GENRE_SCAN_LIMIT};
+pub use model_quota::{GroupQuota, UserCreationQuota};
+pub use model_rfp::{RfpImpression, MAX_RFP_STATS_DAYS};
+pub use model_schedule::ScheduledPublication;
+pub use model_setting::GroupSetting;
+pub use model_subscription::{
+    CollectionSubscription, CreationSubscription, SubscriptionExpire, SUBSCRIPTION_KIND_COLLECTION,
+    SUBSCRIPTION_KIND_CREATION,
+};
 
 pub static USER_JARVIS: &str = "0000000000000jarvis0"; // system user
 pub static USER_ANON: &str = "000000000000000anon0"; // anonymous user
-pub static DEFAULT_MODEL: &str = "gpt-3.5"; // default model
+pub static DEFAULT_MODEL: &str = "gpt-3.5"; // default model if not configured
 pub static MAX_ID: xid::Id = xid::Id([255; 12]);
 pub static ZERO_ID: xid::Id = xid::Id([0; 12]);
 pub static MIN_ID: xid::Id = xid::Id([0, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255]);
 pub const MAX_CONTENT_LEN: usize = 768 * 1024;
 pub const MAX_MESSAGE_LEN: usize = 100 * 1024;
+pub const MAX_MESSAGE_CONTEXT_LEN: usize = 4096; // in characters
 pub const MAX_COLLECTION_CHILDREN: usize = 10000;
+// the max levels walked up when looking for the ancestors of a collection.
+pub const MAX_COLLECTION_DEPTH: usize = 16;
+// the limits of list and text columns checked by `validate_columns` before writing,
+// as a last defense for the writes that bypass the API validation.
+pub const MAX_LIST_COLUMN_ITEMS: usize = 32;
+pub const MAX_REVIEWERS: usize = 64;
+pub const MAX_LIST_ITEM_BYTES: usize = 256;
+pub const MAX_TEXT_COLUMN_BYTES: usize = 8 * 1024;
+
+// point-read queries on the hot paths, they must be the same as the ones built by `get_one`
+// with default fields.
+pub fn warm_up_queries() -> Vec<String> {
+    vec![
+        format!(
+            "SELECT {} FROM publication WHERE gid=? AND cid=? AND language=? AND version=? LIMIT 1",
+            Publication::fields().join(",")
+        ),
+        format!(
+            "SELECT {} FROM creation WHERE gid=? AND id=? LIMIT 1",
+            Creation::fields().join(",")
+        ),
+        format!(
+            "SELECT {} FROM collection WHERE day=? AND id=? LIMIT 1",
+            Collection::fields().join(",")
+        ),
+        format!(
+            "SELECT {} FROM creation_index WHERE id=? LIMIT 1",
+            CreationIndex::fields().join(",")
+        ),
+        format!(
+            "SELECT {} FROM content WHERE id=? LIMIT 1",
+            Content::fields().join(",")
+        ),
+    ]
+}
+
+// summary of a purge_deleted_before run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PurgeStats {
+    pub scanned: usize,
+    pub purged: usize,
+    pub purged_contents: usize,
+    pub kept_contents: usize, // still referenced by a live creation or publication
+}
+
+// outcome of a delete of a creation or publication, or with dry_run the planned one: the row
+// is moved into the deleted table and its content row is archived.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DeletePlan {
+    pub content: xid::Id, // the content row to archive, unknown if already deleted
+    pub already_deleted: bool,
+    pub would_delete: bool,
+}
+
+// returns the updated_at for an update of a row last updated at `updated_at`. It always
+// advances even if the clock of this node is behind, the `IF updated_at=?` condition of
+// the next update relies on it. The inserts keep `unix_ms` as there is no previous value,
+// and so do the rows that are never updated with `IF updated_at=?`: content (by status or
+// existence), message (by version), group_quota and group_setting (unconditional).
+pub fn next_updated_at(updated_at: i64) -> i64 {
+    next_updated_at_with(unix_ms() as i64, updated_at)
+}
+
+fn next_updated_at_with(now: i64, updated_at: i64) -> i64 {
+    now.max(updated_at + 1)
+}
+
+pub const fn unix_ms_day(unix_ms: i64) -> i32 {
+    (unix_ms / (1000 * 3600 * 24)) as i32
+}
+
+// the day of the launch, 2023-10-20T00:00:00Z. Collections are never stored in an earlier
+// day partition, but imported creations and their publications can be.
+pub const LAUNCH_DAY: i32 = unix_ms_day(1_697_760_000_000);
+// the earliest day that an imported creation id can carry, 2000-01-01T00:00:00Z.
+pub const MIN_IMPORT_DAY: i32 = unix_ms_day(946_684_800_000);
+// the last day that a xid timestamp (seconds in u32) can represent.
+pub const MAX_XID_DAY: i32 = (u32::MAX / (3600 * 24)) as i32;
+
+// the order of a listing by id, newest first by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl Order {
+    // the token to list from without a page token.
+    pub fn first_token(&self) -> xid::Id {
+        match self {
+            Order::Asc => ZERO_ID,
+            Order::Desc => MAX_ID,
+        }
+    }
+
+    // the predicate of the rows after the page token, e.g. "id<?".
+    pub fn after(&self, column: &str) -> String {
+        match self {
+            Order::Asc => format!("{}>?", column),
+            Order::Desc => format!("{}<?", column),
+        }
+    }
+
+    // the ORDER BY clause, the tables are clustered by id desc so it is empty for Desc.
+    pub fn order_by(&self, column: &str) -> String {
+        match self {
+            Order::Asc => format!(" ORDER BY {} ASC", column),
+            Order::Desc => "".to_string(),
+        }
+    }
+
+    pub fn compare(&self, a: &xid::Id, b: &xid::Id) -> Ordering {
+        match self {
+            Order::Asc => a.0.cmp(&b.0),
+            Order::Desc => b.0.cmp(&a.0),
+        }
+    }
+}
+
+// sleeps to keep at most `rate` purges per second, 0 means unlimited.
+pub(crate) async fn purge_throttle(rate: u32) {
+    if rate > 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(1000 / rate as u64)).await;
+    }
+}
 
 pub fn xid_day(xid: xid::Id) -> i32 {
     let raw = xid.as_bytes();
@@ -34,9 +189,712 @@ pub fn xid_day(xid: xid::Id) -> i32 {
     (unix_ts / (3600 * 24)) as i32
 }
 
+// returns the smallest xid of the day, so `xid_day(day_to_xid(day)) == day`.
+// The day is clamped to `0..=MAX_XID_DAY`.
 pub fn day_to_xid(day: i32) -> xid::Id {
-    let unix_ts = (day as u32) * 3600 * 24;
+    let unix_ts = (day.clamp(0, MAX_XID_DAY) as u32) * 3600 * 24;
+    let mut raw = [0_u8; 12];
+    raw[0..=3].copy_from_slice(&unix_ts.to_be_bytes());
+    xid::Id(raw)
+}
+
+// xid_from_unix_ms returns a new xid with the timestamp of `unix_ms`, so that a historical
+// creation is imported into the day partition of its original date.
+pub fn xid_from_unix_ms(unix_ms: i64) -> xid::Id {
+    let unix_ts = (unix_ms / 1000).clamp(0, u32::MAX as i64) as u32;
+    let mut id = xid::new();
+    id.0[0..=3].copy_from_slice(&unix_ts.to_be_bytes());
+    id
+}
+
+// unix_ms_to_xid returns the smallest xid of the first whole second at or after `unix_ms`. A xid
+// only has the time in seconds, so `id >= unix_ms_to_xid(from - 999) && id < unix_ms_to_xid(to)`
+// selects the ids of all the seconds that overlap `[from, to)`, the rows of the first and the
+// last second are then filtered by xid_created_ms.
+pub fn unix_ms_to_xid(unix_ms: i64) -> xid::Id {
+    let unix_ts = (unix_ms.max(0).saturating_add(999) / 1000).min(u32::MAX as i64) as u32;
     let mut raw = [0_u8; 12];
     raw[0..=3].copy_from_slice(&unix_ts.to_be_bytes());
     xid::Id(raw)
 }
+
+// xid_created_ms returns the creation time in ms of the row with `id`: its `created_at` clamped
+// into the second of the id. The id may be taken in the second before `created_at`, and an
+// imported creation has the id of its original time, so the time always agrees with the id order.
+pub fn xid_created_ms(id: xid::Id, created_at: i64) -> i64 {
+    let raw = id.as_bytes();
+    let unix_ms = u32::from_be_bytes([raw[0], raw[1], raw[2], raw[3]]) as i64 * 1000;
+    created_at.clamp(unix_ms, unix_ms + 999)
+}
+
+// normalize_tags trims and collapses whitespace, lowercases, drops empties and
+// de-duplicates free-text tags (genre, keywords, labels), keeping the first occurrence.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    normalize_list(tags, true)
+}
+
+// normalize_names is normalize_tags without lowercasing, for display values like authors.
+// Duplicates are still detected case-insensitively.
+pub fn normalize_names(names: Vec<String>) -> Vec<String> {
+    normalize_list(names, false)
+}
+
+fn normalize_list(list: Vec<String>, lowercase: bool) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::with_capacity(list.len());
+    let mut res: Vec<String> = Vec::with_capacity(list.len());
+    for v in list {
+        let v = v.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if v.is_empty() {
+            continue;
+        }
+        let key = v.to_lowercase();
+        if seen.insert(key.clone()) {
+            res.push(if lowercase { key } else { v });
+        }
+    }
+    res
+}
+
+// update_conflict_error is the 409 of a status update LWT that did not apply, it carries the
+// current status and updated_at from the CAS result row so the client can tell what changed.
+pub fn update_conflict_error(message: String, row: Option<ColumnsMap>) -> HTTPError {
+    let status = row.as_ref().and_then(|r| r.get_as::<i8>("status").ok());
+    let updated_at = row
+        .as_ref()
+        .and_then(|r| r.get_as::<i64>("updated_at").ok());
+    if status.is_none() && updated_at.is_none() {
+        return DomainError::UpdateConflict.with_message(format!("{}, please try again", message));
+    }
+
+    DomainError::UpdateConflict.with_data(
+        format!(
+            "{}, current status {}, updated_at {}",
+            message,
+            status.map_or("unknown".to_string(), |v| v.to_string()),
+            updated_at.map_or("unknown".to_string(), |v| v.to_string()),
+        ),
+        serde_json::json!({
+            "status": status,
+            "updated_at": updated_at,
+        }),
+    )
+}
+
+// DomainError is the machine readable reason of an error. It maps to the HTTP code and is
+// carried as `data.error` in the error payload next to the human message, the other fields
+// of `data` are the details of the error, so clients can branch on it. The codes are
+// snake_case and stable, do not rename them. They are listed as `ErrorCode` in the OpenAPI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainError {
+    UpdateConflict,
+    EmptyContent,
+    RequestTimeout,
+    RatingExceedsGroupPolicy,
+    QuotaExceeded,
+    CreationQuotaExceeded,
+    IdempotencyPending,
+    InvalidPublicationStatus,
+    PublicationStatusTransition,
+    PublicationStatusConflict,
+    PublicationUpdatedAtConflict,
+    PublicationExists,
+    SourcePublicationUnavailable,
+}
+
+impl DomainError {
+    pub const ALL: [DomainError; 13] = [
+        DomainError::UpdateConflict,
+        DomainError::EmptyContent,
+        DomainError::RequestTimeout,
+        DomainError::RatingExceedsGroupPolicy,
+        DomainError::QuotaExceeded,
+        DomainError::CreationQuotaExceeded,
+        DomainError::IdempotencyPending,
+        DomainError::InvalidPublicationStatus,
+        DomainError::PublicationStatusTransition,
+        DomainError::PublicationStatusConflict,
+        DomainError::PublicationUpdatedAtConflict,
+        DomainError::PublicationExists,
+        DomainError::SourcePublicationUnavailable,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            DomainError::UpdateConflict => "update_conflict",
+            DomainError::EmptyContent => "empty_content",
+            DomainError::RequestTimeout => "request_timeout",
+            DomainError::RatingExceedsGroupPolicy => "rating_exceeds_group_policy",
+            DomainError::QuotaExceeded => "quota_exceeded",
+            DomainError::CreationQuotaExceeded => "creation_quota_exceeded",
+            DomainError::IdempotencyPending => "idempotency_pending",
+            DomainError::InvalidPublicationStatus => "invalid_publication_status",
+            DomainError::PublicationStatusTransition => "publication_status_transition",
+            DomainError::PublicationStatusConflict => "publication_status_conflict",
+            DomainError::PublicationUpdatedAtConflict => "publication_updated_at_conflict",
+            DomainError::PublicationExists => "publication_exists",
+            DomainError::SourcePublicationUnavailable => "source_publication_unavailable",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            DomainError::EmptyContent
+            | DomainError::InvalidPublicationStatus
+            | DomainError::PublicationStatusTransition
+            | DomainError::SourcePublicationUnavailable => 400,
+            DomainError::RatingExceedsGroupPolicy | DomainError::QuotaExceeded => 403,
+            DomainError::UpdateConflict
+            | DomainError::IdempotencyPending
+            | DomainError::PublicationStatusConflict
+            | DomainError::PublicationUpdatedAtConflict
+            | DomainError::PublicationExists => 409,
+            DomainError::CreationQuotaExceeded => 429,
+            DomainError::RequestTimeout => 503,
+        }
+    }
+
+    pub fn with_message(self, message: String) -> HTTPError {
+        self.with_data(message, serde_json::json!({}))
+    }
+
+    // with_data builds the error with the details in `data`, an object.
+    pub fn with_data(self, message: String, mut data: serde_json::Value) -> HTTPError {
+        if let serde_json::Value::Object(ref mut v) = data {
+            v.insert("error".to_string(), self.code().into());
+        } else {
+            data = serde_json::json!({ "error": self.code() });
+        }
+        HTTPError {
+            code: self.status(),
+            message,
+            data: Some(data),
+        }
+    }
+}
+
+// StatusMachine is the status transition table of a model kind. The statuses are -1 archived,
+// 0 private, 1 in review and 2 published, a status without any next status is terminal.
+pub struct StatusMachine {
+    pub kind: &'static str,
+    // the allowed next statuses of the statuses -1..=2, in that order.
+    pub transitions: [&'static [i8]; 4],
+    // the error codes of an invalid status and of a disallowed transition, if any.
+    pub invalid: Option<DomainError>,
+    pub transition: Option<DomainError>,
+}
+
+pub const CREATION_STATUS: StatusMachine = StatusMachine {
+    kind: "Creation",
+    transitions: [&[0], &[-1, 0, 1], &[-1, 0, 1, 2], &[-1, 0]],
+    invalid: None,
+    transition: None,
+};
+
+pub const PUBLICATION_STATUS: StatusMachine = StatusMachine {
+    kind: "Publication",
+    transitions: [&[0], &[-1, 0, 1], &[-1, 0, 1, 2], &[]],
+    invalid: Some(DomainError::InvalidPublicationStatus),
+    transition: Some(DomainError::PublicationStatusTransition),
+};
+
+pub const COLLECTION_STATUS: StatusMachine = StatusMachine {
+    kind: "Collection",
+    transitions: [&[0], &[-1, 0, 1, 2], &[-1, 0, 1, 2], &[]],
+    invalid: None,
+    transition: None,
+};
+
+impl StatusMachine {
+    fn next(&self, status: i8) -> Option<&'static [i8]> {
+        if (-1..=2).contains(&status) {
+            Some(self.transitions[(status + 1) as usize])
+        } else {
+            None
+        }
+    }
+
+    fn error(code: Option<DomainError>, message: String) -> HTTPError {
+        match code {
+            Some(code) => code.with_message(message),
+            None => HTTPError::new(400, message),
+        }
+    }
+
+    pub fn validate(&self, current: i8, next: i8) -> Result<(), HTTPError> {
+        let allowed = match (self.next(current), self.next(next)) {
+            (Some(allowed), Some(_)) => allowed,
+            _ => {
+                return Err(Self::error(
+                    self.invalid,
+                    format!("Invalid status, {}", next),
+                ))
+            }
+        };
+
+        if allowed.contains(&next) {
+            return Ok(());
+        }
+        if allowed.is_empty() {
+            return Err(Self::error(
+                self.transition,
+                format!("{} status is {}, can not be updated", self.kind, current),
+            ));
+        }
+
+        // the current status is allowed as a no-op, it is not listed in the hint.
+        let hint: Vec<String> = allowed
+            .iter()
+            .filter(|v| **v != current)
+            .map(|v| v.to_string())
+            .collect();
+        let hint = match hint.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+            _ => hint.join(""),
+        };
+        Err(Self::error(
+            self.transition,
+            format!(
+                "{} status is {}, expected update to {}, got {}",
+                self.kind, current, hint, next
+            ),
+        ))
+    }
+}
+
+// validate_columns rejects the oversized list and text columns with 400.
+pub fn validate_columns(cols: &ColumnsMap) -> anyhow::Result<()> {
+    for (name, val) in cols.iter() {
+        match val {
+            CqlValue::Text(v) if v.len() > MAX_TEXT_COLUMN_BYTES => {
+                return Err(HTTPError::new(
+                    400,
+                    format!(
+                        "Column {} is too long, expected at most {} bytes, got {}",
+                        name,
+                        MAX_TEXT_COLUMN_BYTES,
+                        v.len()
+                    ),
+                )
+                .into());
+            }
+            CqlValue::List(list) => {
+                let max = match name.as_str() {
+                    "keywords" | "genre" | "labels" | "authors" => MAX_LIST_COLUMN_ITEMS,
+                    "reviewers" => MAX_REVIEWERS,
+                    _ => continue,
+                };
+                if list.len() > max {
+                    return Err(HTTPError::new(
+                        400,
+                        format!(
+                            "Column {} has too many items, expected at most {}, got {}",
+                            name,
+                            max,
+                            list.len()
+                        ),
+                    )
+                    .into());
+                }
+                for item in list {
+                    if let CqlValue::Text(v) = item {
+                        if v.len() > MAX_LIST_ITEM_BYTES {
+                            return Err(HTTPError::new(
+                                400,
+                                format!(
+                                    "Column {} has a too long item, expected at most {} bytes, got {}",
+                                    name,
+                                    MAX_LIST_ITEM_BYTES,
+                                    v.len()
+                                ),
+                            )
+                            .into());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_works() {
+        assert_eq!(Order::default(), Order::Desc);
+        let order: Order = serde_json::from_str(r#""asc""#).unwrap();
+        assert_eq!(order, Order::Asc);
+        assert!(serde_json::from_str::<Order>(r#""ASC""#).is_err());
+
+        assert_eq!(Order::Desc.after("id"), "id<?");
+        assert_eq!(Order::Asc.after("cid"), "cid>?");
+        assert_eq!(Order::Desc.order_by("id"), "");
+        assert_eq!(Order::Asc.order_by("id"), " ORDER BY id ASC");
+
+        let a = xid::new();
+        let b = xid::new();
+        assert!(Order::Desc.first_token() > b);
+        assert!(Order::Asc.first_token() < a);
+        let mut ids = vec![a, b];
+        ids.sort_by(|x, y| Order::Desc.compare(x, y));
+        assert_eq!(ids, vec![b, a]);
+        ids.sort_by(|x, y| Order::Asc.compare(x, y));
+        assert_eq!(ids, vec![a, b]);
+    }
+
+    #[test]
+    fn next_updated_at_works() {
+        assert_eq!(next_updated_at_with(1000, 999), 1000);
+        // the clock goes backward or stays
+        assert_eq!(next_updated_at_with(1000, 1000), 1001);
+        assert_eq!(next_updated_at_with(900, 1000), 1001);
+
+        let mut updated_at = unix_ms() as i64 + 60 * 1000;
+        for _ in 0..3 {
+            let next = next_updated_at(updated_at);
+            assert_eq!(next, updated_at + 1);
+            updated_at = next;
+        }
+    }
+
+    #[test]
+    fn normalize_tags_works() {
+        assert!(normalize_tags(vec![]).is_empty());
+        assert!(normalize_tags(vec!["".to_string(), " \t\n ".to_string()]).is_empty());
+
+        let res = normalize_tags(vec![
+            " Rust ".to_string(),
+            "rust".to_string(),
+            "RUST".to_string(),
+            "Machine   Learning".to_string(),
+            "machine learning\t".to_string(),
+            "".to_string(),
+            "AI".to_string(),
+        ]);
+        assert_eq!(res, vec!["rust", "machine learning", "ai"]);
+
+        let res = normalize_names(vec![
+            "  Ada   Lovelace ".to_string(),
+            "ada lovelace".to_string(),
+            "Alan Turing".to_string(),
+            " ".to_string(),
+            "ALAN  TURING".to_string(),
+        ]);
+        assert_eq!(res, vec!["Ada Lovelace", "Alan Turing"]);
+    }
+
+    fn xid_at(unix_ts: u32, tail: u8) -> xid::Id {
+        let mut raw = [tail; 12];
+        raw[0..=3].copy_from_slice(&unix_ts.to_be_bytes());
+        xid::Id(raw)
+    }
+
+    #[test]
+    fn xid_day_works() {
+        assert_eq!(LAUNCH_DAY, 19650);
+        assert_eq!(xid_day(ZERO_ID), 0);
+        assert_eq!(xid_day(MAX_ID), MAX_XID_DAY);
+        assert_eq!(day_to_xid(-1), ZERO_ID);
+        assert_eq!(xid_day(day_to_xid(MAX_XID_DAY + 1)), MAX_XID_DAY);
+
+        for day in (0..=MAX_XID_DAY)
+            .step_by(7)
+            .chain(LAUNCH_DAY - 400..LAUNCH_DAY + 4000)
+        {
+            let start = day_to_xid(day);
+            assert_eq!(xid_day(start), day);
+
+            // 00:00:00 and 23:59:59 of the day
+            let unix_ts = day as u32 * 3600 * 24;
+            let first = xid_at(unix_ts, 0);
+            let last = xid_at(unix_ts + 3600 * 24 - 1, 255);
+            assert_eq!(first, start);
+            assert_eq!(xid_day(first), day);
+            assert_eq!(xid_day(last), day);
+            assert!(start <= xid_at(unix_ts, 1));
+            if day > 0 {
+                assert_eq!(xid_day(xid_at(unix_ts - 1, 255)), day - 1);
+                assert!(day_to_xid(day - 1) < start);
+            }
+        }
+
+        // a xid created now
+        let id = xid::new();
+        let day = xid_day(id);
+        assert!(day > LAUNCH_DAY);
+        assert!(day_to_xid(day) <= id);
+        assert!(day_to_xid(day + 1) > id);
+
+        // a xid of an imported creation
+        assert_eq!(MIN_IMPORT_DAY, 10957);
+        let unix_ms = 1_262_304_000_000 + 3600 * 1000; // 2010-01-01T01:00:00Z
+        let old = xid_from_unix_ms(unix_ms);
+        assert_eq!(
+            old.time(),
+            std::time::UNIX_EPOCH + std::time::Duration::from_millis(unix_ms as u64)
+        );
+        assert_eq!(xid_day(old), unix_ms_day(unix_ms));
+        assert_ne!(old.0[4..], [0u8; 8]);
+        assert_ne!(xid_from_unix_ms(unix_ms), old);
+    }
+
+    #[test]
+    fn unix_ms_to_xid_works() {
+        assert_eq!(unix_ms_to_xid(0), ZERO_ID);
+        assert_eq!(unix_ms_to_xid(-1000), ZERO_ID);
+        assert_eq!(xid_day(unix_ms_to_xid(i64::MAX)), MAX_XID_DAY);
+        assert_eq!(
+            unix_ms_to_xid(LAUNCH_DAY as i64 * 3600 * 24 * 1000),
+            day_to_xid(LAUNCH_DAY)
+        );
+
+        let unix_ts = 1_697_760_000u32;
+        let unix_ms = unix_ts as i64 * 1000;
+        assert_eq!(unix_ms_to_xid(unix_ms), xid_at(unix_ts, 0));
+        assert_eq!(unix_ms_to_xid(unix_ms - 999), xid_at(unix_ts, 0));
+        assert_eq!(unix_ms_to_xid(unix_ms + 1), xid_at(unix_ts + 1, 0));
+        // the ids of the previous second are before it
+        assert!(xid_at(unix_ts - 1, 255) < unix_ms_to_xid(unix_ms));
+        assert!(xid_at(unix_ts, 0) >= unix_ms_to_xid(unix_ms));
+        assert!(xid_at(unix_ts, 255) < unix_ms_to_xid(unix_ms + 1000));
+        // the second of `from` is scanned from its first id
+        assert_eq!(unix_ms_to_xid(unix_ms + 1 - 999), xid_at(unix_ts, 0));
+        assert_eq!(unix_ms_to_xid(unix_ms - 999), xid_at(unix_ts, 0));
+    }
+
+    #[test]
+    fn xid_created_ms_works() {
+        let unix_ts = 1_697_760_000u32;
+        let unix_ms = unix_ts as i64 * 1000;
+        let id = xid_at(unix_ts, 1);
+        assert_eq!(xid_created_ms(id, unix_ms), unix_ms);
+        assert_eq!(xid_created_ms(id, unix_ms + 123), unix_ms + 123);
+        assert_eq!(xid_created_ms(id, unix_ms + 999), unix_ms + 999);
+        // created_at in the next second, or a creation imported with an old id
+        assert_eq!(xid_created_ms(id, unix_ms + 1001), unix_ms + 999);
+        assert_eq!(xid_created_ms(id, unix_ms + 3600 * 1000), unix_ms + 999);
+        assert_eq!(xid_created_ms(id, unix_ms - 1), unix_ms);
+        assert_eq!(xid_created_ms(id, 0), unix_ms);
+    }
+
+    #[test]
+    fn update_conflict_error_works() {
+        let err = update_conflict_error("Creation update_status 2 failed".to_string(), None);
+        assert_eq!(err.code, 409);
+        assert_eq!(err.data.unwrap()["error"], "update_conflict");
+        assert!(err.message.ends_with("please try again"));
+
+        let mut row = ColumnsMap::new();
+        row.set_as("status", &1i8);
+        row.set_as("updated_at", &1700000000000i64);
+        let err = update_conflict_error("Creation update_status 2 failed".to_string(), Some(row));
+        assert_eq!(err.code, 409);
+        assert_eq!(
+            err.message,
+            "Creation update_status 2 failed, current status 1, updated_at 1700000000000"
+        );
+        let data = err.data.unwrap();
+        assert_eq!(data["error"], "update_conflict");
+        assert_eq!(data["status"], 1);
+        assert_eq!(data["updated_at"], 1700000000000i64);
+
+        // only the condition columns may be returned
+        let mut row = ColumnsMap::new();
+        row.set_as("updated_at", &1i64);
+        let err = update_conflict_error("failed".to_string(), Some(row));
+        assert_eq!(err.message, "failed, current status unknown, updated_at 1");
+        assert!(err.data.unwrap()["status"].is_null());
+    }
+
+    #[test]
+    fn domain_error_works() {
+        let err = DomainError::PublicationStatusConflict
+            .with_message("Publication status conflict, expected -1, got 2".to_string());
+        assert_eq!(err.code, 409);
+        assert_eq!(
+            err.message,
+            "Publication status conflict, expected -1, got 2"
+        );
+        assert_eq!(
+            err.data.as_ref().unwrap(),
+            &serde_json::json!({"error": "publication_status_conflict"})
+        );
+        let body = serde_json::to_value(axum_web::erring::ErrorResponse { error: err }).unwrap();
+        assert_eq!(body["error"]["code"], 409);
+        assert_eq!(
+            body["error"]["data"]["error"],
+            "publication_status_conflict"
+        );
+
+        let err =
+            DomainError::InvalidPublicationStatus.with_message("Invalid status, 3".to_string());
+        assert_eq!(err.code, 400);
+        assert_eq!(err.data.unwrap()["error"], "invalid_publication_status");
+
+        // the details are kept next to the code
+        let err = DomainError::QuotaExceeded.with_data(
+            "quota exceeded".to_string(),
+            serde_json::json!({"used_bytes": 100}),
+        );
+        assert_eq!(err.code, 403);
+        assert_eq!(
+            err.data.unwrap(),
+            serde_json::json!({"error": "quota_exceeded", "used_bytes": 100})
+        );
+
+        // the codes are unique snake_case
+        let codes: HashSet<&str> = DomainError::ALL.iter().map(|v| v.code()).collect();
+        assert_eq!(codes.len(), DomainError::ALL.len());
+        for code in codes {
+            assert!(code.chars().all(|c| c.is_ascii_lowercase() || c == '_'));
+        }
+    }
+
+    #[test]
+    fn status_machine_works() {
+        const STATUSES: [i8; 4] = [-1, 0, 1, 2];
+        // allowed[current + 1][next + 1]
+        let tables: [(&StatusMachine, [[bool; 4]; 4]); 3] = [
+            (
+                &CREATION_STATUS,
+                [
+                    [false, true, false, false],
+                    [true, true, true, false],
+                    [true, true, true, true],
+                    [true, true, false, false],
+                ],
+            ),
+            (
+                &PUBLICATION_STATUS,
+                [
+                    [false, true, false, false],
+                    [true, true, true, false],
+                    [true, true, true, true],
+                    [false, false, false, false],
+                ],
+            ),
+            (
+                &COLLECTION_STATUS,
+                [
+                    [false, true, false, false],
+                    [true, true, true, true],
+                    [true, true, true, true],
+                    [false, false, false, false],
+                ],
+            ),
+        ];
+
+        for (machine, allowed) in tables.iter() {
+            for (i, current) in STATUSES.iter().enumerate() {
+                for (j, next) in STATUSES.iter().enumerate() {
+                    let res = machine.validate(*current, *next);
+                    assert_eq!(
+                        res.is_ok(),
+                        allowed[i][j],
+                        "{} {} -> {}",
+                        machine.kind,
+                        current,
+                        next
+                    );
+                    if let Err(err) = res {
+                        assert_eq!(err.code, 400);
+                        assert!(err
+                            .message
+                            .starts_with(&format!("{} status is {}, ", machine.kind, current)));
+                        assert_eq!(
+                            err.data.map(|v| v["error"].clone()),
+                            machine.transition.map(|v| v.code().into())
+                        );
+                    }
+                }
+
+                for next in [-2, 3] {
+                    let err = machine.validate(*current, next).unwrap_err();
+                    assert_eq!(err.code, 400);
+                    assert_eq!(err.message, format!("Invalid status, {}", next));
+                    assert_eq!(
+                        err.data.map(|v| v["error"].clone()),
+                        machine.invalid.map(|v| v.code().into())
+                    );
+                }
+            }
+            assert!(machine.validate(-2, 0).is_err());
+            assert!(machine.validate(3, 0).is_err());
+        }
+
+        let msg = |m: &StatusMachine, current: i8, next: i8| {
+            m.validate(current, next).unwrap_err().message
+        };
+        assert_eq!(
+            msg(&CREATION_STATUS, -1, 1),
+            "Creation status is -1, expected update to 0, got 1"
+        );
+        assert_eq!(
+            msg(&CREATION_STATUS, 0, 2),
+            "Creation status is 0, expected update to -1 or 1, got 2"
+        );
+        assert_eq!(
+            msg(&CREATION_STATUS, 2, 1),
+            "Creation status is 2, expected update to -1 or 0, got 1"
+        );
+        assert_eq!(
+            msg(&COLLECTION_STATUS, -1, 2),
+            "Collection status is -1, expected update to 0, got 2"
+        );
+        assert_eq!(
+            msg(&PUBLICATION_STATUS, 2, 0),
+            "Publication status is 2, can not be updated"
+        );
+    }
+
+    #[test]
+    fn validate_columns_works() {
+        let mut doc = Creation {
+            title: "Hello".to_string(),
+            keywords: vec!["rust".to_string(); MAX_LIST_COLUMN_ITEMS],
+            reviewers: vec![xid::new(); MAX_REVIEWERS],
+            summary: "x".repeat(MAX_TEXT_COLUMN_BYTES),
+            ..Default::default()
+        };
+        doc.validate_columns().unwrap();
+
+        let mut oversized = doc.clone();
+        oversized.keywords = vec!["rust".to_string(); 80000];
+        let err: HTTPError = oversized.validate_columns().unwrap_err().into();
+        assert_eq!(err.code, 400);
+        assert!(err.message.contains("keywords"));
+
+        let mut oversized = doc.clone();
+        oversized.reviewers.push(xid::new());
+        let err: HTTPError = oversized.validate_columns().unwrap_err().into();
+        assert!(err.message.contains("reviewers"));
+
+        let mut oversized = doc.clone();
+        oversized.authors = vec!["a".repeat(MAX_LIST_ITEM_BYTES + 1)];
+        let err: HTTPError = oversized.validate_columns().unwrap_err().into();
+        assert!(err.message.contains("authors"));
+
+        // bytes, not chars
+        doc.summary = "字".repeat(MAX_TEXT_COLUMN_BYTES / 3 + 1);
+        let err: HTTPError = doc.validate_columns().unwrap_err().into();
+        assert!(err.message.contains("summary"));
+
+        let mut cols = ColumnsMap::new();
+        cols.set_as(
+            "genre",
+            &vec!["novel".to_string(); MAX_LIST_COLUMN_ITEMS + 1],
+        );
+        let err: HTTPError = validate_columns(&cols).unwrap_err().into();
+        assert!(err.message.contains("genre"));
+
+        let doc = Collection {
+            cover: "x".repeat(MAX_TEXT_COLUMN_BYTES + 1),
+            ..Default::default()
+        };
+        let err: HTTPError = doc.validate_columns().unwrap_err().into();
+        assert!(err.message.contains("cover"));
+    }
+}
@@ -5,7 +5,12 @@ use axum_web::erring::HTTPError;
 use scylla_orm::{ColumnsMap, CqlValue, ToCqlVal};
 use scylla_orm_macros::CqlOrm;
 
-use crate::db::{scylladb, scylladb::extract_applied, MAX_ID};
+use crate::db::{next_updated_at, scylladb, scylladb::extract_applied, MAX_ID};
+
+// how many bookmarks of a user to load for sorting by `updated_at`.
+const MAX_RECENT_SCAN: i32 = 10000;
+// max bookmarks deleted by one batch_delete call, all in the user's partition.
+pub const MAX_BATCH_DELETE: usize = 100;
 
 #[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
 pub struct Bookmark {
@@ -158,12 +163,12 @@ impl Bookmark {
         let mut set_fields: Vec<String> = Vec::with_capacity(update_fields.len() + 1);
         let mut params: Vec<CqlValue> = Vec::with_capacity(update_fields.len() + 1 + 3);
 
-        let new_updated_at = unix_ms() as i64;
+        let new_updated_at = next_updated_at(updated_at);
         set_fields.push("updated_at=?".to_string());
         params.push(new_updated_at.to_cql());
-        for field in &update_fields {
+        for (field, val) in cols.ordered_pairs() {
             set_fields.push(format!("{}=?", field));
-            params.push(cols.get(field).unwrap().to_owned());
+            params.push(val.to_owned());
         }
 
         let query = format!(
@@ -200,6 +205,69 @@ impl Bookmark {
         Ok(true)
     }
 
+    // batch_delete deletes the bookmarks of the user in one batch, ids not found in the user's
+    // partition are skipped. It returns whether each id was deleted, in the input order.
+    pub async fn batch_delete(
+        db: &scylladb::ScyllaDB,
+        uid: xid::Id,
+        ids: Vec<xid::Id>,
+    ) -> anyhow::Result<Vec<(xid::Id, bool)>> {
+        let mut unique: Vec<xid::Id> = Vec::with_capacity(ids.len());
+        for id in &ids {
+            if !unique.contains(id) {
+                unique.push(*id);
+            }
+        }
+        if unique.is_empty() {
+            return Ok(vec![]);
+        }
+        if unique.len() > MAX_BATCH_DELETE {
+            return Err(HTTPError::new(
+                400,
+                format!(
+                    "Too many bookmarks to delete, expected <= {}, got {}",
+                    MAX_BATCH_DELETE,
+                    unique.len()
+                ),
+            )
+            .into());
+        }
+
+        let fields = vec!["id".to_string()];
+        let query = format!(
+            "SELECT id FROM bookmark WHERE uid=? AND id IN ({})",
+            vec!["?"; unique.len()].join(",")
+        );
+        let mut params: Vec<CqlValue> = Vec::with_capacity(unique.len() + 1);
+        params.push(uid.to_cql());
+        for id in &unique {
+            params.push(id.to_cql());
+        }
+        let rows = db.execute_iter(query, params).await?;
+
+        let mut owned: Vec<xid::Id> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            owned.push(cols.get_as("id")?);
+        }
+
+        if !owned.is_empty() {
+            let query = "DELETE FROM bookmark WHERE uid=? AND id=?";
+            let statements = vec![query; owned.len()];
+            let values: Vec<Vec<CqlValue>> = owned
+                .iter()
+                .map(|id| vec![uid.to_cql(), id.to_cql()])
+                .collect();
+            let _ = db.batch_idempotent(statements, values).await?;
+        }
+
+        Ok(ids
+            .into_iter()
+            .map(|id| (id, owned.contains(&id)))
+            .collect())
+    }
+
     pub async fn list(
         db: &scylladb::ScyllaDB,
         uid: xid::Id,
@@ -215,7 +283,7 @@ impl Bookmark {
         };
 
         let query = format!(
-            "SELECT {} FROM bookmark WHERE uid=? AND id<? LIMIT ? USING TIMEOUT 3s",
+            "SELECT {} FROM bookmark WHERE uid=? AND id<? LIMIT ?",
             fields.clone().join(",")
         );
         let params = (uid.to_cql(), token.to_cql(), page_size as i32);
@@ -234,6 +302,52 @@ impl Bookmark {
         Ok(res)
     }
 
+    // lists bookmarks by `updated_at` desc, a bookmark floats to the top when it is updated,
+    // e.g. the reading progress in payload. The page token is (updated_at, id) of the last one.
+    pub async fn list_recent(
+        db: &scylladb::ScyllaDB,
+        uid: xid::Id,
+        select_fields: Vec<String>,
+        page_size: u16,
+        page_token: Option<(i64, xid::Id)>,
+    ) -> anyhow::Result<Vec<Self>> {
+        let mut fields = Self::select_fields(select_fields, true)?;
+        let field = "updated_at".to_string();
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+
+        let query = format!(
+            "SELECT {} FROM bookmark WHERE uid=? LIMIT ?",
+            fields.clone().join(",")
+        );
+        let params = (uid.to_cql(), MAX_RECENT_SCAN);
+        let rows = db.execute_iter(query, params).await?;
+
+        let mut res: Vec<Self> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut doc = Self::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            doc._fields = fields.clone();
+            if let Some(token) = page_token {
+                if (doc.updated_at, doc.id) >= token {
+                    continue;
+                }
+            }
+            res.push(doc);
+        }
+
+        res.sort_by(|a, b| {
+            (b.updated_at, b.id)
+                .partial_cmp(&(a.updated_at, a.id))
+                .unwrap()
+        });
+        res.truncate(page_size as usize);
+        Ok(res)
+    }
+
     pub async fn list_by_cid(
         db: &scylladb::ScyllaDB,
         uid: xid::Id,
@@ -243,7 +357,7 @@ impl Bookmark {
         let fields = Self::select_fields(select_fields, true)?;
 
         let query = format!(
-            "SELECT {} FROM bookmark WHERE uid=? AND cid=? LIMIT ? USING TIMEOUT 3s",
+            "SELECT {} FROM bookmark WHERE uid=? AND cid=? LIMIT ?",
             fields.clone().join(",")
         );
         let params = (uid.to_cql(), cid.to_cql(), 1000i32);
@@ -330,6 +444,8 @@ mod tests {
     #[ignore]
     async fn test_all() {
         bookmark_model_works().await;
+        list_recent_works().await;
+        batch_delete_works().await;
     }
 
     // #[tokio::test(flavor = "current_thread")]
@@ -417,4 +533,129 @@ mod tests {
             assert!(!res); // already deleted
         }
     }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_recent_works() {
+        let db = get_db().await;
+        let uid = xid::new();
+
+        let mut ids: Vec<xid::Id> = Vec::new();
+        for i in 0..3 {
+            let mut doc = Bookmark::with_pk(uid, xid::new());
+            doc.cid = xid::new();
+            doc.language = Language::Eng;
+            doc.version = 1;
+            doc.title = format!("title {}", i);
+            assert!(doc.save(db).await.unwrap());
+            ids.push(doc.id);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let res = Bookmark::list_recent(db, uid, vec![], 10, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            res.iter().map(|d| d.id).collect::<Vec<xid::Id>>(),
+            vec![ids[2], ids[1], ids[0]]
+        );
+
+        // update the reading progress of the oldest one
+        let mut doc = Bookmark::with_pk(uid, ids[0]);
+        doc.get_one(db, vec![]).await.unwrap();
+        let mut cols = ColumnsMap::new();
+        cols.set_as("payload", &vec![1u8]);
+        assert!(doc.update(db, cols, doc.updated_at).await.unwrap());
+
+        let res = Bookmark::list_recent(db, uid, vec![], 10, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            res.iter().map(|d| d.id).collect::<Vec<xid::Id>>(),
+            vec![ids[0], ids[2], ids[1]]
+        );
+
+        // `created` order is not changed
+        let res = Bookmark::list(db, uid, vec![], 10, None).await.unwrap();
+        assert_eq!(
+            res.iter().map(|d| d.id).collect::<Vec<xid::Id>>(),
+            vec![ids[2], ids[1], ids[0]]
+        );
+
+        // paginate with (updated_at, id) token
+        let res = Bookmark::list_recent(db, uid, vec!["title".to_string()], 2, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].id, ids[0]);
+        let last = res.last().unwrap();
+        let res = Bookmark::list_recent(
+            db,
+            uid,
+            vec!["title".to_string()],
+            2,
+            Some((last.updated_at, last.id)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].id, ids[1]);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn batch_delete_works() {
+        let db = get_db().await;
+        let uid = xid::new();
+        let other = xid::new();
+
+        let mut owned: Vec<xid::Id> = Vec::new();
+        for i in 0..3 {
+            let mut doc = Bookmark::with_pk(uid, xid::new());
+            doc.cid = xid::new();
+            doc.language = Language::Eng;
+            doc.title = format!("title {}", i);
+            assert!(doc.save(db).await.unwrap());
+            owned.push(doc.id);
+        }
+        let mut doc = Bookmark::with_pk(other, xid::new());
+        doc.cid = xid::new();
+        doc.language = Language::Eng;
+        assert!(doc.save(db).await.unwrap());
+        let not_owned = doc.id;
+
+        let missing = xid::new();
+        let res = Bookmark::batch_delete(
+            db,
+            uid,
+            vec![owned[0], not_owned, owned[1], missing, owned[0]],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            res,
+            vec![
+                (owned[0], true),
+                (not_owned, false),
+                (owned[1], true),
+                (missing, false),
+                (owned[0], true),
+            ]
+        );
+
+        // only the owned ones are removed
+        let res = Bookmark::list(db, uid, vec![], 10, None).await.unwrap();
+        assert_eq!(
+            res.iter().map(|d| d.id).collect::<Vec<xid::Id>>(),
+            vec![owned[2]]
+        );
+        let mut doc = Bookmark::with_pk(other, not_owned);
+        doc.get_one(db, vec![]).await.unwrap();
+
+        assert!(Bookmark::batch_delete(db, uid, vec![])
+            .await
+            .unwrap()
+            .is_empty());
+        let res = Bookmark::batch_delete(db, uid, (0..101).map(|_| xid::new()).collect()).await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 400);
+    }
 }
@@ -0,0 +1,232 @@
+use axum_web::context::unix_ms;
+use axum_web::erring::HTTPError;
+use scylla_orm::{ColumnsMap, ToCqlVal};
+use scylla_orm_macros::CqlOrm;
+
+use crate::db::scylladb;
+
+pub const COLLABORATOR_READER: i8 = 0;
+pub const COLLABORATOR_REVIEWER: i8 = 1;
+pub const COLLABORATOR_EDITOR: i8 = 2;
+pub const MAX_CREATION_COLLABORATORS: usize = 10;
+
+// users outside the owner group who participate in a creation, also indexed by uid in
+// `creation_collaborator_index`, see `Creation::list_by_collaborator`.
+#[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
+pub struct CreationCollaborator {
+    pub cid: xid::Id,
+    pub uid: xid::Id,
+    pub role: i8, // 0: reader, 1: reviewer, 2: editor
+    pub added_at: i64,
+
+    pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
+}
+
+impl CreationCollaborator {
+    pub fn with_pk(cid: xid::Id, uid: xid::Id) -> Self {
+        Self {
+            cid,
+            uid,
+            ..Default::default()
+        }
+    }
+
+    pub fn valid_role(role: i8) -> anyhow::Result<()> {
+        match role {
+            COLLABORATOR_READER | COLLABORATOR_REVIEWER | COLLABORATOR_EDITOR => Ok(()),
+            _ => Err(HTTPError::new(400, format!("Invalid collaborator role: {}", role)).into()),
+        }
+    }
+
+    pub async fn get_one(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
+        let fields = Self::fields();
+        self._fields = fields.clone();
+
+        let query = format!(
+            "SELECT {} FROM creation_collaborator WHERE cid=? AND uid=? LIMIT 1",
+            fields.join(",")
+        );
+        let params = (self.cid.to_cql(), self.uid.to_cql());
+        let res = db.execute(query, params).await?.single_row()?;
+
+        let mut cols = ColumnsMap::with_capacity(fields.len());
+        cols.fill(res, &fields)?;
+        self.fill(&cols);
+
+        Ok(())
+    }
+
+    // adds the collaborator or changes the role of an existing one, `gid` is the owner group
+    // of the creation, stored in the index for listing.
+    pub async fn save(&mut self, db: &scylladb::ScyllaDB, gid: xid::Id) -> anyhow::Result<bool> {
+        Self::valid_role(self.role)?;
+
+        let role = self.role;
+        let docs = Self::list_by_cid(db, self.cid).await?;
+        match docs.iter().find(|doc| doc.uid == self.uid) {
+            Some(doc) => {
+                self.added_at = doc.added_at;
+                if doc.role == role {
+                    self._fields = Self::fields();
+                    return Ok(false);
+                }
+            }
+            None => {
+                if docs.len() >= MAX_CREATION_COLLABORATORS {
+                    return Err(HTTPError::new(
+                        400,
+                        format!(
+                            "Creation {} has too many collaborators, expected <= {}",
+                            self.cid, MAX_CREATION_COLLABORATORS
+                        ),
+                    )
+                    .into());
+                }
+                self.added_at = unix_ms() as i64;
+            }
+        }
+
+        self._fields = Self::fields();
+        let query = "INSERT INTO creation_collaborator (cid,uid,role,added_at) VALUES (?,?,?,?)";
+        let params = (self.cid.to_cql(), self.uid.to_cql(), role, self.added_at);
+        let index_query = "INSERT INTO creation_collaborator_index (uid,cid,gid,role,added_at) VALUES (?,?,?,?,?)";
+        let index_params = (
+            self.uid.to_cql(),
+            self.cid.to_cql(),
+            gid.to_cql(),
+            role,
+            self.added_at,
+        );
+
+        let _ = db
+            .batch_idempotent(vec![query, index_query], (params, index_params))
+            .await?;
+        Ok(true)
+    }
+
+    pub async fn delete(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        let res = self.get_one(db).await;
+        if res.is_err() {
+            return Ok(false); // already deleted
+        }
+
+        let query = "DELETE FROM creation_collaborator WHERE cid=? AND uid=?";
+        let params = (self.cid.to_cql(), self.uid.to_cql());
+        let index_query = "DELETE FROM creation_collaborator_index WHERE uid=? AND cid=?";
+        let index_params = (self.uid.to_cql(), self.cid.to_cql());
+
+        let _ = db
+            .batch_idempotent(vec![query, index_query], (params, index_params))
+            .await?;
+        Ok(true)
+    }
+
+    pub async fn list_by_cid(db: &scylladb::ScyllaDB, cid: xid::Id) -> anyhow::Result<Vec<Self>> {
+        let fields = Self::fields();
+        let query = format!(
+            "SELECT {} FROM creation_collaborator WHERE cid=? LIMIT ?",
+            fields.clone().join(",")
+        );
+        // a few more than the max, in case of concurrent adding.
+        let params = (cid.to_cql(), MAX_CREATION_COLLABORATORS as i32 * 2);
+        let rows = db.execute_iter(query, params).await?;
+
+        let mut res: Vec<Self> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut doc = Self::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            doc._fields = fields.clone();
+            res.push(doc);
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conf;
+    use crate::db;
+    use axum_web::erring;
+    use tokio::sync::OnceCell;
+
+    use super::*;
+
+    static DB: OnceCell<db::scylladb::ScyllaDB> = OnceCell::const_new();
+
+    async fn get_db() -> &'static db::scylladb::ScyllaDB {
+        DB.get_or_init(|| async {
+            let cfg = conf::Conf::new().unwrap_or_else(|err| panic!("config error: {}", err));
+            let res = db::scylladb::ScyllaDB::new(cfg.scylla, "writing_test").await;
+            res.unwrap()
+        })
+        .await
+    }
+
+    #[test]
+    fn valid_role_works() {
+        assert!(CreationCollaborator::valid_role(COLLABORATOR_READER).is_ok());
+        assert!(CreationCollaborator::valid_role(COLLABORATOR_REVIEWER).is_ok());
+        assert!(CreationCollaborator::valid_role(COLLABORATOR_EDITOR).is_ok());
+        assert!(CreationCollaborator::valid_role(-1).is_err());
+        assert!(CreationCollaborator::valid_role(3).is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore]
+    async fn test_all() {
+        creation_collaborator_model_works().await;
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn creation_collaborator_model_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let cid = xid::new();
+        let uid = xid::new();
+
+        let mut doc = CreationCollaborator::with_pk(cid, uid);
+        let res = doc.get_one(db).await;
+        assert!(res.is_err());
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 404);
+
+        doc.role = COLLABORATOR_REVIEWER;
+        assert!(doc.save(db, gid).await.unwrap());
+        let added_at = doc.added_at;
+        assert!(added_at > 0);
+        // same role
+        assert!(!doc.save(db, gid).await.unwrap());
+
+        // change role keeps added_at
+        let mut doc = CreationCollaborator::with_pk(cid, uid);
+        doc.role = COLLABORATOR_EDITOR;
+        assert!(doc.save(db, gid).await.unwrap());
+        let mut doc2 = CreationCollaborator::with_pk(cid, uid);
+        doc2.get_one(db).await.unwrap();
+        assert_eq!(doc2.role, COLLABORATOR_EDITOR);
+        assert_eq!(doc2.added_at, added_at);
+
+        // max collaborators
+        for _ in 1..MAX_CREATION_COLLABORATORS {
+            let mut doc = CreationCollaborator::with_pk(cid, xid::new());
+            doc.role = COLLABORATOR_READER;
+            assert!(doc.save(db, gid).await.unwrap());
+        }
+        let mut doc = CreationCollaborator::with_pk(cid, xid::new());
+        let err: erring::HTTPError = doc.save(db, gid).await.unwrap_err().into();
+        assert_eq!(err.code, 400);
+        let docs = CreationCollaborator::list_by_cid(db, cid).await.unwrap();
+        assert_eq!(docs.len(), MAX_CREATION_COLLABORATORS);
+
+        // delete
+        let mut doc = CreationCollaborator::with_pk(cid, uid);
+        assert!(doc.delete(db).await.unwrap());
+        assert!(!doc.delete(db).await.unwrap());
+        let docs = CreationCollaborator::list_by_cid(db, cid).await.unwrap();
+        assert_eq!(docs.len(), MAX_CREATION_COLLABORATORS - 1);
+        assert!(docs.iter().all(|v| v.uid != uid));
+    }
+}
@@ -1,6 +1,11 @@
+use futures::stream::{self, StreamExt, TryStreamExt};
 use isolang::Language;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::convert::TryFrom;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+};
 
 use axum_web::context::unix_ms;
 use axum_web::erring::HTTPError;
@@ -8,10 +13,28 @@ use scylla_orm::{ColumnsMap, CqlValue, FromCqlVal, ToCqlVal};
 use scylla_orm_macros::CqlOrm;
 
 use crate::db::{
-    day_to_xid, meili, scylladb, scylladb::extract_applied, support_language, xid_day, Message,
-    MessageTexts, MessageValue,
+    day_to_xid, meili, next_updated_at, resolve_language, scylladb,
+    scylladb::{extract_applied, extract_applied_with_row, BatchItem, DayScan, EarlyExit},
+    support_language, update_conflict_error, validate_columns, xid_day, Changelog, Creation,
+    CreationIndex, Message, MessageTexts, MessageValue, COLLECTION_STATUS, LAUNCH_DAY,
 };
 
+// how many leading children to look at when deriving the collection cover.
+const COVER_DERIVE_LIMIT: usize = 10;
+// how many children to move in one batch, each one is an insert and a delete.
+const MOVE_CHILDREN_CHUNK: usize = 50;
+// the minimum ord gap between adjacent children, the children are rebalanced below it since
+// the fractional ords around unix ms lose f64 precision after repeated inserts.
+const MIN_CHILDREN_ORD_GAP: f64 = 5e-4;
+// how many day partitions to scan concurrently when listing all published collections.
+const LIST_ALL_CONCURRENCY: usize = 8;
+// how many children partitions to count concurrently in count_children_many.
+const COUNT_CHILDREN_CONCURRENCY: usize = 8;
+// the max number of day buckets list_by_gid scans for a page.
+const LIST_BY_GID_DAYS: u32 = 30;
+// the day window of list_latest, older collections are not listed.
+const LIST_LATEST_DAYS: u32 = 90;
+
 #[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
 pub struct Collection {
     pub day: i32,
@@ -21,12 +44,15 @@ pub struct Collection {
     pub rating: i8,
     pub mid: xid::Id,
     pub cover: String,
+    pub cover_derived: bool,
+    pub cover_blurhash: String,
     pub updated_at: i64,
     pub price: i64,
     pub creation_price: i64,
 
     pub _info: Option<Message>,
-    pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
+    pub _language: Option<Language>, // the language requested for `_info`
+    pub _fields: Vec<String>,        // selected fields，`_` 前缀字段会被 CqlOrm 忽略
 }
 
 #[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
@@ -39,7 +65,7 @@ pub struct CollectionChildren {
     pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, JsonSchema)]
 pub struct CollectionInfo {
     pub title: String,
     pub summary: String,
@@ -196,7 +222,7 @@ impl CollectionChildren {
         let fields = Self::fields();
 
         let query = format!(
-            "SELECT {} FROM collection_children WHERE id=? LIMIT 10000 USING TIMEOUT 3s",
+            "SELECT {} FROM collection_children WHERE id=? LIMIT 10000",
             fields.clone().join(",")
         );
         let params = (id.to_cql(),);
@@ -219,7 +245,7 @@ impl CollectionChildren {
         let fields = Self::fields();
 
         let query = format!(
-            "SELECT {} FROM collection_children WHERE cid=? USING TIMEOUT 3s",
+            "SELECT {} FROM collection_children WHERE cid=?",
             fields.clone().join(",")
         );
         let params = (cid.to_cql(),);
@@ -238,12 +264,192 @@ impl CollectionChildren {
         Ok(res)
     }
 
+    // returns the ancestor collections of the collection, the walk stops after `max_depth`
+    // levels so that a corrupt chain will not run away.
+    pub async fn list_ancestors(
+        db: &scylladb::ScyllaDB,
+        id: xid::Id,
+        max_depth: usize,
+    ) -> anyhow::Result<HashSet<xid::Id>> {
+        let mut res: HashSet<xid::Id> = HashSet::new();
+        let mut level: Vec<xid::Id> = vec![id];
+        for _ in 0..max_depth {
+            let mut next: Vec<xid::Id> = Vec::new();
+            for cid in level {
+                for doc in Self::list_by_child(db, cid).await? {
+                    if doc.id != id && res.insert(doc.id) {
+                        next.push(doc.id);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            level = next;
+        }
+        Ok(res)
+    }
+
+    // copies at most `limit` children (by ord) of collection `from` to collection `to`,
+    // returns the number of children copied.
+    pub async fn copy_children(
+        db: &scylladb::ScyllaDB,
+        from: xid::Id,
+        to: xid::Id,
+        limit: usize,
+    ) -> anyhow::Result<usize> {
+        let mut children = Self::list_children(db, from).await?;
+        children.truncate(limit);
+        if children.is_empty() {
+            return Ok(0);
+        }
+
+        let fields = Self::fields();
+        let query = format!(
+            "INSERT INTO collection_children ({}) VALUES ({})",
+            fields.join(","),
+            vec!["?"; fields.len()].join(",")
+        );
+        let partition = to.to_string();
+        let items: Vec<BatchItem> = children
+            .iter_mut()
+            .map(|v| {
+                v.id = to;
+                let cols = v.to();
+                BatchItem {
+                    statement: query.as_str(),
+                    values: fields
+                        .iter()
+                        .map(|f| cols.get(f).unwrap().to_owned())
+                        .collect(),
+                    partition: partition.clone(),
+                }
+            })
+            .collect();
+
+        let _ = db.batch_guarded(items).await?;
+        Ok(children.len())
+    }
+
+    // moves the children (of one collection) to collection `to`, keeping their kind and
+    // relative order after `after_ord`. Every chunk of children is moved in one batch, returns
+    // whether each child was moved.
+    pub async fn move_children(
+        db: &scylladb::ScyllaDB,
+        children: &[Self],
+        to: xid::Id,
+        after_ord: f64,
+    ) -> anyhow::Result<Vec<(xid::Id, bool)>> {
+        let fields = Self::fields();
+        let insert_query = format!(
+            "INSERT INTO collection_children ({}) VALUES ({})",
+            fields.join(","),
+            vec!["?"; fields.len()].join(",")
+        );
+        let delete_query = "DELETE FROM collection_children WHERE id=? AND cid=?";
+
+        let mut res: Vec<(xid::Id, bool)> = Vec::with_capacity(children.len());
+        for (i, chunk) in children.chunks(MOVE_CHILDREN_CHUNK).enumerate() {
+            let mut statements: Vec<&str> = Vec::with_capacity(chunk.len() * 2);
+            let mut values: Vec<Vec<CqlValue>> = Vec::with_capacity(chunk.len() * 2);
+            for (j, child) in chunk.iter().enumerate() {
+                let doc = Self {
+                    id: to,
+                    cid: child.cid,
+                    kind: child.kind,
+                    ord: after_ord + (i * MOVE_CHILDREN_CHUNK + j + 1) as f64,
+                    ..Default::default()
+                };
+                let cols = doc.to();
+                statements.push(insert_query.as_str());
+                values.push(
+                    fields
+                        .iter()
+                        .map(|f| cols.get(f).unwrap().to_owned())
+                        .collect(),
+                );
+                statements.push(delete_query);
+                values.push(vec![child.id.to_cql(), child.cid.to_cql()]);
+            }
+
+            let moved = match db.batch_idempotent(statements, values).await {
+                Ok(_) => true,
+                Err(err) => {
+                    log::warn!(target: "scylladb",
+                        action = "move_children",
+                        to = to.to_string(),
+                        children = chunk.len();
+                        "{}", err,
+                    );
+                    false
+                }
+            };
+            res.extend(chunk.iter().map(|v| (v.cid, moved)));
+        }
+        Ok(res)
+    }
+
+    // whether any two adjacent children (sorted by ord) are closer than MIN_CHILDREN_ORD_GAP.
+    fn need_rebalance(children: &[Self]) -> bool {
+        children
+            .windows(2)
+            .any(|w| w[1].ord - w[0].ord < MIN_CHILDREN_ORD_GAP)
+    }
+
+    // returns the new ords 1, 2, 3... of the children (sorted by ord) that need to change,
+    // as (index, ord) pairs.
+    fn respace(children: &[Self]) -> Vec<(usize, f64)> {
+        children
+            .iter()
+            .enumerate()
+            .filter_map(|(i, child)| {
+                let ord = (i + 1) as f64;
+                if child.ord != ord {
+                    Some((i, ord))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // re-spaces the ords of the collection's children to evenly distributed integers in their
+    // current order once any two of them are closer than MIN_CHILDREN_ORD_GAP, returns the
+    // number of children updated.
+    pub async fn rebalance(db: &scylladb::ScyllaDB, id: xid::Id) -> anyhow::Result<usize> {
+        let mut children = Self::list_children(db, id).await?;
+        if !Self::need_rebalance(&children) {
+            return Ok(0);
+        }
+
+        let mut updated: usize = 0;
+        for (i, ord) in Self::respace(&children) {
+            // a child removed meanwhile is not recreated
+            if children[i].update_ord(db, ord).await? {
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
     pub async fn count_children(db: &scylladb::ScyllaDB, id: xid::Id) -> anyhow::Result<usize> {
-        let query = "SELECT cid FROM collection_children WHERE id=? USING TIMEOUT 3s";
+        let query = "SELECT cid FROM collection_children WHERE id=?";
         let params = (id.to_cql(),);
         let rows = db.execute_iter(query, params).await?;
         Ok(rows.len())
     }
+
+    // count_children_many counts the children of the collections, in the order of `ids`.
+    // The counts are not read in one snapshot, they may be slightly stale under concurrent edits.
+    pub async fn count_children_many(
+        db: &scylladb::ScyllaDB,
+        ids: &[xid::Id],
+    ) -> anyhow::Result<Vec<usize>> {
+        stream::iter(ids.iter().map(|id| Self::count_children(db, *id)))
+            .buffered(COUNT_CHILDREN_CONCURRENCY)
+            .try_collect()
+            .await
+    }
 }
 
 impl Collection {
@@ -255,6 +461,62 @@ impl Collection {
         }
     }
 
+    // batch_status returns the (status, rating) of the group's collections by id,
+    // the missing ones and the ones of other groups are not in the result.
+    pub async fn batch_status(
+        db: &scylladb::ScyllaDB,
+        gid: xid::Id,
+        ids: &[xid::Id],
+    ) -> anyhow::Result<HashMap<xid::Id, (i8, i8)>> {
+        let mut days: HashMap<i32, Vec<xid::Id>> = HashMap::new();
+        for id in ids {
+            days.entry(xid_day(*id)).or_default().push(*id);
+        }
+
+        let groups: Vec<Vec<(xid::Id, (i8, i8))>> =
+            stream::iter(days.into_iter().map(|(day, ids)| async move {
+                let query = format!(
+                    "SELECT id,gid,status,rating FROM collection WHERE day=? AND id IN ({})",
+                    vec!["?"; ids.len()].join(",")
+                );
+                let mut params: Vec<CqlValue> = Vec::with_capacity(ids.len() + 1);
+                params.push(day.to_cql());
+                for id in &ids {
+                    params.push(id.to_cql());
+                }
+
+                let fields = vec![
+                    "id".to_string(),
+                    "gid".to_string(),
+                    "status".to_string(),
+                    "rating".to_string(),
+                ];
+                let rows = db.execute_iter(query, params).await?;
+                let mut res: Vec<(xid::Id, (i8, i8))> = Vec::with_capacity(rows.len());
+                for row in rows {
+                    let mut cols = ColumnsMap::with_capacity(fields.len());
+                    cols.fill(row, &fields)?;
+                    if cols.get_as::<xid::Id>("gid")? == gid {
+                        res.push((
+                            cols.get_as("id")?,
+                            (cols.get_as("status")?, cols.get_as("rating")?),
+                        ));
+                    }
+                }
+                Ok::<Vec<(xid::Id, (i8, i8))>, anyhow::Error>(res)
+            }))
+            .buffer_unordered(LIST_ALL_CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        Ok(groups.into_iter().flatten().collect())
+    }
+
+    // a collection has only text columns to check, e.g. the cover.
+    pub fn validate_columns(&self) -> anyhow::Result<()> {
+        validate_columns(&self.to())
+    }
+
     pub fn select_fields(select_fields: Vec<String>, with_pk: bool) -> anyhow::Result<Vec<String>> {
         if select_fields.is_empty() {
             return Ok(Self::fields());
@@ -280,6 +542,10 @@ impl Collection {
             }
         }
 
+        let field = "cover_blurhash".to_string();
+        if select_fields.iter().any(|f| f == "cover") && !select_fields.contains(&field) {
+            select_fields.push(field);
+        }
         let field = "gid".to_string();
         if !select_fields.contains(&field) {
             select_fields.push(field);
@@ -316,43 +582,23 @@ impl Collection {
         Ok(select_fields)
     }
 
-    pub fn valid_status(&self, status: i8) -> anyhow::Result<()> {
-        if !(-1..=2).contains(&status) || !(-1..=2).contains(&self.status) {
-            return Err(HTTPError::new(400, format!("Invalid status, {}", status)).into());
-        }
-
-        match self.status {
-            -1 if 0 != status => Err(HTTPError::new(
-                400,
-                format!(
-                    "Collection status is {}, expected update to 0, got {}",
-                    self.status, status
-                ),
-            )
-            .into()),
-            0 if !(-1..=2).contains(&status) => Err(HTTPError::new(
-                400,
-                format!(
-                    "Collection status is {}, expected update to -1, 1 or 2, got {}",
-                    self.status, status
-                ),
-            )
-            .into()),
-            1 if !(-1..=2).contains(&status) => Err(HTTPError::new(
+    // price -1 means not for sale, creation_price should never be greater than price.
+    pub fn valid_price(price: i64, creation_price: i64) -> Result<(), HTTPError> {
+        if creation_price > price {
+            return Err(HTTPError::new(
                 400,
                 format!(
-                    "Collection status is {}, expected update to -1, 0 or 2, got {}",
-                    self.status, status
+                    "Creation price {} cannot be greater than price {}",
+                    creation_price, price
                 ),
-            )
-            .into()),
-            2 => Err(HTTPError::new(
-                400,
-                format!("Collection status is {}, can not be updated", self.status),
-            )
-            .into()),
-            _ => Ok(()),
+            ));
         }
+        Ok(())
+    }
+
+    pub fn valid_status(&self, status: i8) -> anyhow::Result<()> {
+        COLLECTION_STATUS.validate(self.status, status)?;
+        Ok(())
     }
 
     pub fn to_meili(
@@ -375,20 +621,28 @@ impl Collection {
         Ok(doc)
     }
 
-    pub fn to_info(&self, language: Language) -> Option<(Language, CollectionInfo)> {
+    // returns the info in the requested language if translated, otherwise in the primary language.
+    // the bool is true when the info comes from `_i18n_messages`, false from the primary `message`.
+    pub fn to_info(&self, language: Language) -> Option<(Language, CollectionInfo, bool)> {
         if let Some(msg) = &self._info {
-            let (lang, data) = if let Some(data) = msg._i18n_messages.get(language.to_639_3()) {
-                (language, data)
+            let code = resolve_language(language);
+            let (lang, data, is_i18n) = if let Some(data) = msg._i18n_messages.get(code) {
+                (Language::from_639_3(code).unwrap_or(language), data, true)
             } else {
-                (msg.language, &msg.message)
+                (msg.language, &msg.message, false)
             };
             if let Ok(info) = CollectionInfo::from_message(data) {
-                return Some((lang, info));
+                return Some((lang, info, is_i18n));
             }
         }
         None
     }
 
+    // whether the info returned by `to_info` is not in the requested language.
+    pub fn info_is_fallback(language: Language, lang: Language, is_i18n: bool) -> bool {
+        !is_i18n && language != Language::Und && resolve_language(language) != lang.to_639_3()
+    }
+
     pub async fn get_one(
         &mut self,
         db: &scylladb::ScyllaDB,
@@ -418,19 +672,21 @@ impl Collection {
                 "message".to_string(),
             ];
             if let Some(lang) = language {
-                let lang = lang.to_639_3();
+                let lang = resolve_language(lang);
                 if support_language(lang) {
                     msg_fields.push(lang.to_string());
                 }
             }
             msg.get_one(db, msg_fields).await?;
             self._info = Some(msg);
+            self._language = language;
         }
 
         Ok(())
     }
 
     pub async fn save(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        self.validate_columns()?;
         let fields = Self::fields();
         self._fields = fields.clone();
         self.updated_at = unix_ms() as i64;
@@ -509,25 +765,23 @@ impl Collection {
             return Ok(false); // no need to update
         }
 
-        let new_updated_at = unix_ms() as i64;
-        let query =
-            "UPDATE collection SET status=?,updated_at=? WHERE day=? AND id=? IF updated_at=?";
+        let new_updated_at = next_updated_at(updated_at);
+        let query = "UPDATE collection SET status=?,updated_at=? WHERE day=? AND id=? IF status=? AND updated_at=?";
         let params = (
             status,
             new_updated_at,
             self.day,
             self.id.to_cql(),
+            self.status,
             updated_at,
         );
 
         let res = db.execute(query, params).await?;
-        if !extract_applied(res) {
-            return Err(HTTPError::new(
-                409,
-                format!(
-                    "Collection update_status {} failed, please try again",
-                    status
-                ),
+        let (applied, row) = extract_applied_with_row(res);
+        if !applied {
+            return Err(update_conflict_error(
+                format!("Collection update_status {} failed", status),
+                row,
             )
             .into());
         }
@@ -570,20 +824,23 @@ impl Collection {
         gid: xid::Id,
         cols: ColumnsMap,
         updated_at: i64,
+        actor: xid::Id,
     ) -> anyhow::Result<bool> {
-        let valid_fields = ["cover", "price", "creation_price"];
+        let valid_fields = ["cover", "cover_blurhash", "price", "creation_price"];
         let update_fields = cols.keys();
         for field in &update_fields {
             if !valid_fields.contains(&field.as_str()) {
                 return Err(HTTPError::new(400, format!("Invalid field: {}", field)).into());
             }
         }
+        validate_columns(&cols)?;
 
         self.get_one(
             db,
             vec![
                 "gid".to_string(),
                 "updated_at".to_string(),
+                "cover".to_string(),
                 "price".to_string(),
                 "creation_price".to_string(),
             ],
@@ -628,16 +885,26 @@ impl Collection {
             )
             .into());
         }
+        Self::valid_price(
+            cols.get_as("price").unwrap_or(self.price),
+            cols.get_as("creation_price").unwrap_or(self.creation_price),
+        )?;
 
         let mut set_fields: Vec<String> = Vec::with_capacity(update_fields.len() + 1);
         let mut params: Vec<CqlValue> = Vec::with_capacity(update_fields.len() + 1 + 3);
 
-        let new_updated_at = unix_ms() as i64;
+        let new_updated_at = next_updated_at(updated_at);
         set_fields.push("updated_at=?".to_string());
         params.push(new_updated_at.to_cql());
-        for field in &update_fields {
+        for (field, val) in cols.ordered_pairs() {
             set_fields.push(format!("{}=?", field));
-            params.push(cols.get(field).unwrap().to_owned());
+            params.push(val.to_owned());
+        }
+        if cols.has("cover") {
+            // an explicit cover stops derivation, clearing it derives from children again.
+            let cover: String = cols.get_as("cover").unwrap_or_default();
+            set_fields.push("cover_derived=?".to_string());
+            params.push(cover.is_empty().to_cql());
         }
 
         let query = format!(
@@ -657,11 +924,80 @@ impl Collection {
             .into());
         }
 
+        let logs = Changelog::from_update(self.gid, actor, 2, self.id, &self.to(), &cols);
+        let _ = Changelog::record(db, logs).await;
         self.updated_at = new_updated_at;
         self._fields = vec!["updated_at".to_string()];
         Ok(true)
     }
 
+    // refresh_cover derives the cover from the first child creation that has one,
+    // it does nothing when the cover was set explicitly.
+    pub async fn refresh_cover(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        self.get_one(
+            db,
+            vec!["cover".to_string(), "cover_derived".to_string()],
+            None,
+        )
+        .await?;
+        if !self.cover.is_empty() && !self.cover_derived {
+            return Ok(false);
+        }
+
+        let children = CollectionChildren::list_children(db, self.id).await?;
+        let mut cover = String::new();
+        let mut cover_blurhash = String::new();
+        for child in children
+            .iter()
+            .filter(|c| c.kind < 2)
+            .take(COVER_DERIVE_LIMIT)
+        {
+            let mut index = CreationIndex::with_pk(child.cid);
+            if index.get_one(db).await.is_err() {
+                continue;
+            }
+            let mut creation = Creation::with_pk(index.gid, child.cid);
+            if creation
+                .get_one(db, vec!["cover".to_string()])
+                .await
+                .is_err()
+            {
+                continue;
+            }
+            if creation.status >= 0 && !creation.cover.is_empty() {
+                cover = creation.cover;
+                cover_blurhash = creation.cover_blurhash;
+                break;
+            }
+        }
+
+        if cover == self.cover && cover_blurhash == self.cover_blurhash {
+            return Ok(false);
+        }
+
+        let query = "UPDATE collection SET cover=?,cover_blurhash=?,cover_derived=true WHERE day=? AND id=? IF cover=?";
+        let params = (
+            cover.to_cql(),
+            cover_blurhash.to_cql(),
+            self.day,
+            self.id.to_cql(),
+            self.cover.to_cql(),
+        );
+        let res = db.execute(query, params).await?;
+        if !extract_applied(res) {
+            return Err(HTTPError::new(
+                409,
+                "Collection refresh_cover failed, please try again".to_string(),
+            )
+            .into());
+        }
+
+        self.cover = cover;
+        self.cover_blurhash = cover_blurhash;
+        self.cover_derived = true;
+        Ok(true)
+    }
+
     pub async fn delete(&mut self, db: &scylladb::ScyllaDB, gid: xid::Id) -> anyhow::Result<bool> {
         let res = self
             .get_one(
@@ -712,7 +1048,7 @@ impl Collection {
         page_token: Option<xid::Id>,
         status: Option<i8>,
         language: Option<Language>,
-    ) -> anyhow::Result<(Vec<Self>, Option<xid::Id>)> {
+    ) -> anyhow::Result<(Vec<Self>, Option<xid::Id>, DayScan)> {
         let fields = Self::select_fields(select_fields, true)?;
 
         let mut res: Vec<Self> = Vec::new();
@@ -720,13 +1056,13 @@ impl Collection {
         let query = match status {
             v if v == -1 || v == 2 => {
                 format!(
-                    "SELECT {} FROM collection WHERE day=? AND gid=? AND status=? LIMIT 1000 ALLOW FILTERING USING TIMEOUT 3s",
+                    "SELECT {} FROM collection WHERE day=? AND gid=? AND status=? LIMIT 1000 ALLOW FILTERING",
                 fields.clone().join(",")
                 )
             }
             _ => {
                 format!(
-                    "SELECT {} FROM collection WHERE day=? AND gid=? AND status>=? LIMIT 1000 ALLOW FILTERING USING TIMEOUT 3s",
+                    "SELECT {} FROM collection WHERE day=? AND gid=? AND status>=? LIMIT 1000 ALLOW FILTERING",
                     fields.clone().join(",")
                 )
             }
@@ -738,10 +1074,11 @@ impl Collection {
             (unix_ms() / (1000 * 3600 * 24)) as i32
         };
 
-        let mut i = 0i8;
-        while day > 19650 && i < 30 {
+        let mut scan = DayScan::new(LIST_BY_GID_DAYS);
+        while day >= LAUNCH_DAY && scan.next_day() {
             let params = (day, gid.to_cql(), status);
             let rows = db.execute_iter(query.as_str(), params).await?;
+            scan.add_rows(rows.len());
 
             for row in rows {
                 let mut doc = Self::default();
@@ -752,15 +1089,15 @@ impl Collection {
                 res.push(doc);
             }
 
+            day -= 1;
             if res.len() >= page_size as usize {
                 break;
             }
-            i += 1;
-            day -= 1;
         }
 
-        let next = if day > 19650 {
-            Some(day_to_xid(day))
+        // `day` is the next day to list, the next page starts from the day before the token.
+        let next = if day >= LAUNCH_DAY {
+            Some(day_to_xid(day + 1))
         } else {
             None
         };
@@ -773,7 +1110,7 @@ impl Collection {
                 "message".to_string(),
             ];
             if let Some(lang) = language {
-                let lang = lang.to_639_3();
+                let lang = resolve_language(lang);
                 if support_language(lang) {
                     msg_fields.push(lang.to_string());
                 }
@@ -783,23 +1120,77 @@ impl Collection {
                 let mut msg = Message::with_pk(doc.mid);
                 msg.get_one(db, msg_fields.clone()).await?;
                 doc._info = Some(msg);
+                doc._language = language;
             }
         }
 
-        Ok((res, next))
+        Ok((res, next, scan))
+    }
+
+    // lists all the published collections of the group, or of the whole site if gid is None,
+    // by id desc. It scans the day partitions from today back to the launch day.
+    pub async fn list_all_published(
+        db: &scylladb::ScyllaDB,
+        gid: Option<xid::Id>,
+        select_fields: Vec<String>,
+    ) -> anyhow::Result<Vec<Self>> {
+        let fields = Self::select_fields(select_fields, true)?;
+        let query = if gid.is_some() {
+            format!(
+                "SELECT {} FROM collection WHERE day=? AND gid=? AND status=2 ALLOW FILTERING",
+                fields.clone().join(",")
+            )
+        } else {
+            format!(
+                "SELECT {} FROM collection WHERE day=? AND status=2 ALLOW FILTERING",
+                fields.clone().join(",")
+            )
+        };
+
+        let today = (unix_ms() / (1000 * 3600 * 24)) as i32;
+        let days: Vec<Vec<Self>> = stream::iter((LAUNCH_DAY..=today).rev().map(|day| {
+            let query = query.as_str();
+            let fields = &fields;
+            async move {
+                let rows = match gid {
+                    Some(gid) => db.execute_iter(query, (day, gid.to_cql())).await?,
+                    None => db.execute_iter(query, (day,)).await?,
+                };
+                let mut docs: Vec<Self> = Vec::with_capacity(rows.len());
+                for row in rows {
+                    let mut doc = Self::default();
+                    let mut cols = ColumnsMap::with_capacity(fields.len());
+                    cols.fill(row, fields)?;
+                    doc.fill(&cols);
+                    doc._fields = fields.clone();
+                    docs.push(doc);
+                }
+                Ok::<Vec<Self>, anyhow::Error>(docs)
+            }
+        }))
+        .buffered(LIST_ALL_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+        let mut res: Vec<Self> = days.into_iter().flatten().collect();
+        res.sort_by(|a, b| b.id.partial_cmp(&a.id).unwrap());
+        Ok(res)
     }
 
+    // lists the latest published collections, the collections rated above `max_rating`
+    // are filtered out.
     pub async fn list_latest(
         db: &scylladb::ScyllaDB,
         select_fields: Vec<String>,
         page_token: Option<xid::Id>,
         language: Option<Language>,
-    ) -> anyhow::Result<(Vec<Self>, Option<xid::Id>)> {
+        max_rating: i8,
+    ) -> anyhow::Result<(Vec<Self>, Option<xid::Id>, DayScan)> {
         let fields = Self::select_fields(select_fields, true)?;
 
         let mut res: Vec<Self> = Vec::new();
         let query = format!(
-                "SELECT {} FROM collection WHERE day=? AND status=2 LIMIT 100 ALLOW FILTERING USING TIMEOUT 3s",
+                "SELECT {} FROM collection WHERE day=? AND status=2 AND rating<=? LIMIT 100 ALLOW FILTERING",
                 fields.clone().join(",")
             );
 
@@ -809,10 +1200,12 @@ impl Collection {
             (unix_ms() / (1000 * 3600 * 24)) as i32
         };
 
-        let min = (unix_ms() / (1000 * 3600 * 24)) as i32 - 90;
-        while day > min {
-            let params = (day,);
+        let min = (unix_ms() / (1000 * 3600 * 24)) as i32 - LIST_LATEST_DAYS as i32;
+        let mut scan = DayScan::new(LIST_LATEST_DAYS);
+        while day > min && scan.next_day() {
+            let params = (day, max_rating);
             let rows = db.execute_iter(query.as_str(), params).await?;
+            scan.add_rows(rows.len());
             for row in rows {
                 let mut doc = Self::default();
                 let mut cols = ColumnsMap::with_capacity(fields.len());
@@ -830,10 +1223,11 @@ impl Collection {
             day -= 1;
         }
 
-        let next = if res.is_empty() {
-            None
-        } else {
-            Some(res.last().unwrap().id)
+        let next = match res.last() {
+            Some(doc) => Some(doc.id),
+            // resumes from the day that was not scanned
+            None if scan.early_exit == Some(EarlyExit::Deadline) => Some(day_to_xid(day + 1)),
+            None => None,
         };
         res.sort_by(|a, b| b.id.partial_cmp(&a.id).unwrap());
 
@@ -844,7 +1238,7 @@ impl Collection {
                 "message".to_string(),
             ];
             if let Some(lang) = language {
-                let lang = lang.to_639_3();
+                let lang = resolve_language(lang);
                 if support_language(lang) {
                     msg_fields.push(lang.to_string());
                 }
@@ -854,10 +1248,11 @@ impl Collection {
                 let mut msg = Message::with_pk(doc.mid);
                 msg.get_one(db, msg_fields.clone()).await?;
                 doc._info = Some(msg);
+                doc._language = language;
             }
         }
 
-        Ok((res, next))
+        Ok((res, next, scan))
     }
 
     pub async fn list_by_child(
@@ -867,7 +1262,7 @@ impl Collection {
         gid: Option<xid::Id>,
         language: Option<Language>,
     ) -> anyhow::Result<Vec<Self>> {
-        let query = "SELECT id FROM collection_children WHERE cid=? USING TIMEOUT 3s".to_string();
+        let query = "SELECT id FROM collection_children WHERE cid=?".to_string();
 
         let params = (cid.to_cql(),);
         let rows = db.execute_iter(query, params).await?;
@@ -923,6 +1318,105 @@ mod tests {
         .await
     }
 
+    #[test]
+    fn valid_price_works() {
+        assert!(Collection::valid_price(0, 0).is_ok());
+        assert!(Collection::valid_price(100, 10).is_ok());
+        assert!(Collection::valid_price(100, 100).is_ok());
+        assert!(Collection::valid_price(100, -1).is_ok());
+        assert!(Collection::valid_price(-1, -1).is_ok());
+        assert_eq!(Collection::valid_price(10, 100).unwrap_err().code, 400);
+        assert_eq!(Collection::valid_price(-1, 0).unwrap_err().code, 400);
+    }
+
+    #[test]
+    fn rebalance_children_works() {
+        let id = xid::new();
+        let child = |ord: f64| CollectionChildren {
+            id,
+            cid: xid::new(),
+            ord,
+            ..Default::default()
+        };
+
+        // inserts each child right after the first one, as reordering by dragging does
+        let first = 1_700_000_000_000.0;
+        let mut children = vec![child(first), child(first + 1.0)];
+        for _ in 0..60 {
+            let ord = (children[0].ord + children[1].ord) / 2.0;
+            children.insert(1, child(ord));
+        }
+        children.sort_by(|a, b| a.ord.partial_cmp(&b.ord).unwrap());
+        assert!(children.windows(2).any(|w| w[0].ord == w[1].ord));
+        assert!(CollectionChildren::need_rebalance(&children));
+
+        let cids: Vec<xid::Id> = children.iter().map(|v| v.cid).collect();
+        let changes = CollectionChildren::respace(&children);
+        assert_eq!(changes.len(), children.len());
+        for (i, ord) in changes {
+            children[i].ord = ord;
+        }
+        assert_eq!(children.iter().map(|v| v.cid).collect::<Vec<_>>(), cids);
+        assert!(children.windows(2).all(|w| w[1].ord - w[0].ord == 1.0));
+        assert_eq!(children[0].ord, 1.0);
+        assert!(!CollectionChildren::need_rebalance(&children));
+        assert!(CollectionChildren::respace(&children).is_empty());
+
+        // the ords of the most children added at once are still far enough
+        let children: Vec<CollectionChildren> = (0..1000)
+            .map(|i| child(first + i as f64 / 1000.0))
+            .collect();
+        assert!(!CollectionChildren::need_rebalance(&children));
+    }
+
+    #[test]
+    fn to_info_works() {
+        let eng: Vec<u8> = cbor_to_vec(
+            &cbor!([{
+                "id" => "title",
+                "texts" => ["Hello World"],
+            }])
+            .unwrap(),
+        )
+        .unwrap();
+        let zho: Vec<u8> = cbor_to_vec(
+            &cbor!([{
+                "id" => "title",
+                "texts" => ["你好世界"],
+            }])
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut msg = Message::with_pk(xid::new());
+        msg.language = Language::Eng;
+        msg.message = eng;
+        msg._i18n_messages.insert("zho".to_string(), zho);
+        let mut doc = Collection::with_pk(xid::new());
+        doc._info = Some(msg);
+
+        let (lang, info, is_i18n) = doc.to_info(Language::Zho).unwrap();
+        assert_eq!(lang, Language::Zho);
+        assert_eq!(info.title.as_str(), "你好世界");
+        assert!(is_i18n);
+        assert!(!Collection::info_is_fallback(Language::Zho, lang, is_i18n));
+
+        let (lang, info, is_i18n) = doc.to_info(Language::Eng).unwrap();
+        assert_eq!(lang, Language::Eng);
+        assert_eq!(info.title.as_str(), "Hello World");
+        assert!(!is_i18n);
+        assert!(!Collection::info_is_fallback(Language::Eng, lang, is_i18n));
+
+        let (lang, info, is_i18n) = doc.to_info(Language::Jpn).unwrap();
+        assert_eq!(lang, Language::Eng);
+        assert_eq!(info.title.as_str(), "Hello World");
+        assert!(!is_i18n);
+        assert!(Collection::info_is_fallback(Language::Jpn, lang, is_i18n));
+
+        let (lang, _, is_i18n) = doc.to_info(Language::Und).unwrap();
+        assert!(!Collection::info_is_fallback(Language::Und, lang, is_i18n));
+    }
+
     #[test]
     fn collection_info_works() {
         let data: Vec<u8> = cbor_to_vec(
@@ -975,6 +1469,219 @@ mod tests {
     async fn test_all() {
         collection_model_works().await;
         collection_children_model_works().await;
+        list_ancestors_works().await;
+        copy_children_works().await;
+        move_children_works().await;
+        refresh_cover_works().await;
+        list_latest_works().await;
+        count_children_many_works().await;
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn count_children_many_works() {
+        let db = get_db().await;
+        let ids = vec![xid::new(), xid::new(), xid::new()];
+        for (id, n) in ids.iter().zip([2, 0, 1]) {
+            for _ in 0..n {
+                let mut doc = CollectionChildren {
+                    id: *id,
+                    cid: xid::new(),
+                    kind: 0,
+                    ord: unix_ms() as f64,
+                    ..Default::default()
+                };
+                assert!(doc.save(db).await.unwrap());
+            }
+        }
+
+        let res = CollectionChildren::count_children_many(db, &ids)
+            .await
+            .unwrap();
+        assert_eq!(res, vec![2, 0, 1]);
+        assert!(CollectionChildren::count_children_many(db, &[])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_ancestors_works() {
+        let db = get_db().await;
+        let (a, b, c) = (xid::new(), xid::new(), xid::new());
+
+        // A -> B -> C
+        for (id, cid) in [(a, b), (b, c)] {
+            let mut doc = CollectionChildren {
+                id,
+                cid,
+                kind: 2,
+                ord: 1.0,
+                ..Default::default()
+            };
+            assert!(doc.save(db).await.unwrap());
+        }
+
+        let res = CollectionChildren::list_ancestors(db, a, db::MAX_COLLECTION_DEPTH)
+            .await
+            .unwrap();
+        assert!(res.is_empty());
+        let res = CollectionChildren::list_ancestors(db, b, db::MAX_COLLECTION_DEPTH)
+            .await
+            .unwrap();
+        assert_eq!(res, HashSet::from([a]));
+        // adding A to B or C makes a cycle
+        let res = CollectionChildren::list_ancestors(db, c, db::MAX_COLLECTION_DEPTH)
+            .await
+            .unwrap();
+        assert_eq!(res, HashSet::from([a, b]));
+        // bounded by depth
+        let res = CollectionChildren::list_ancestors(db, c, 1).await.unwrap();
+        assert_eq!(res, HashSet::from([b]));
+
+        // a corrupt cycle C -> A does not run away
+        let mut doc = CollectionChildren {
+            id: c,
+            cid: a,
+            kind: 2,
+            ord: 1.0,
+            ..Default::default()
+        };
+        assert!(doc.save(db).await.unwrap());
+        let res = CollectionChildren::list_ancestors(db, c, db::MAX_COLLECTION_DEPTH)
+            .await
+            .unwrap();
+        assert_eq!(res, HashSet::from([a, b]));
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn move_children_works() {
+        let db = get_db().await;
+        let (from, to) = (xid::new(), xid::new());
+
+        let mut existing = CollectionChildren {
+            id: to,
+            cid: xid::new(),
+            kind: 0,
+            ord: 100.0,
+            ..Default::default()
+        };
+        assert!(existing.save(db).await.unwrap());
+        for i in 0..5 {
+            let mut doc = CollectionChildren {
+                id: from,
+                cid: xid::new(),
+                kind: (i % 2) as i8,
+                ord: 10.0 - i as f64,
+                ..Default::default()
+            };
+            assert!(doc.save(db).await.unwrap());
+        }
+
+        // moves the 2nd, 3rd and 5th children by ord
+        let src = CollectionChildren::list_children(db, from).await.unwrap();
+        let moving: Vec<CollectionChildren> = vec![src[1].clone(), src[2].clone(), src[4].clone()];
+        let res = CollectionChildren::move_children(db, &moving, to, existing.ord)
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            moving.iter().map(|v| (v.cid, true)).collect::<Vec<_>>()
+        );
+
+        let rest = CollectionChildren::list_children(db, from).await.unwrap();
+        assert_eq!(
+            rest.iter().map(|v| v.cid).collect::<Vec<_>>(),
+            vec![src[0].cid, src[3].cid]
+        );
+        let dst = CollectionChildren::list_children(db, to).await.unwrap();
+        assert_eq!(dst.len(), 4);
+        assert_eq!(dst[0].cid, existing.cid);
+        for (m, d) in moving.iter().zip(dst[1..].iter()) {
+            assert_eq!(d.id, to);
+            assert_eq!(d.cid, m.cid);
+            assert_eq!(d.kind, m.kind);
+            assert!(d.ord > existing.ord);
+        }
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn copy_children_works() {
+        let db = get_db().await;
+        let (from, to) = (xid::new(), xid::new());
+
+        let res = CollectionChildren::copy_children(db, from, to, 10)
+            .await
+            .unwrap();
+        assert_eq!(res, 0);
+
+        let mut cids: Vec<xid::Id> = Vec::new();
+        for i in 0..5 {
+            let mut doc = CollectionChildren {
+                id: from,
+                cid: xid::new(),
+                kind: (i % 2) as i8,
+                ord: 10.0 - i as f64,
+                ..Default::default()
+            };
+            assert!(doc.save(db).await.unwrap());
+            cids.push(doc.cid);
+        }
+        cids.reverse();
+
+        let res = CollectionChildren::copy_children(db, from, to, 3)
+            .await
+            .unwrap();
+        assert_eq!(res, 3);
+        let src = CollectionChildren::list_children(db, from).await.unwrap();
+        assert_eq!(src.len(), 5);
+        let dst = CollectionChildren::list_children(db, to).await.unwrap();
+        assert_eq!(dst.len(), 3);
+        for (s, d) in src.iter().zip(dst.iter()) {
+            assert_eq!(d.id, to);
+            assert_eq!(d.cid, s.cid);
+            assert_eq!(d.kind, s.kind);
+            assert_eq!(d.ord, s.ord);
+        }
+        assert_eq!(
+            dst.iter().map(|v| v.cid).collect::<Vec<_>>(),
+            cids[0..3].to_vec()
+        );
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_latest_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+
+        let mut ids: Vec<(xid::Id, i8)> = Vec::new();
+        for rating in [0i8, 1, 3, 4] {
+            let mut doc = Collection::with_pk(xid::new());
+            doc.gid = gid;
+            doc.mid = xid::new();
+            doc.status = 2;
+            doc.rating = rating;
+            doc.save(db).await.unwrap();
+            ids.push((doc.id, rating));
+        }
+
+        let fields = vec!["gid".to_string(), "rating".to_string()];
+        let (res, next, scan) = Collection::list_latest(db, fields.clone(), None, None, 1)
+            .await
+            .unwrap();
+        assert!(next.is_some());
+        assert!(scan.days >= 1);
+        assert!(scan.rows >= res.len());
+        assert!(res.iter().all(|doc| doc.rating <= 1));
+        for (id, rating) in &ids {
+            assert_eq!(res.iter().any(|doc| doc.id == *id), *rating <= 1);
+        }
+
+        let (res, _, _) = Collection::list_latest(db, fields, None, None, 4)
+            .await
+            .unwrap();
+        for (id, _) in &ids {
+            assert!(res.iter().any(|doc| doc.id == *id));
+        }
     }
 
     // #[tokio::test(flavor = "current_thread")]
@@ -1059,7 +1766,7 @@ mod tests {
             assert_eq!(doc2.gid, gid);
             assert_eq!(doc2.mid, mid);
             assert!(doc2._info.is_some());
-            let (lang, info) = doc2.to_info(Language::Und).unwrap();
+            let (lang, info, _) = doc2.to_info(Language::Und).unwrap();
             assert_eq!(lang, Language::Eng);
             assert_eq!(info.title.as_str(), "Hello World");
 
@@ -1070,7 +1777,7 @@ mod tests {
             assert_eq!(doc3.gid, gid);
             assert_eq!(doc3.updated_at, 0);
             assert!(doc3._info.is_some());
-            let (lang, info) = doc3.to_info(Language::Eng).unwrap();
+            let (lang, info, _) = doc3.to_info(Language::Eng).unwrap();
             assert_eq!(lang, Language::Eng);
             assert_eq!(info.title.as_str(), "Hello World");
 
@@ -1089,14 +1796,14 @@ mod tests {
             let mut doc = Collection::with_pk(id);
             let mut cols = ColumnsMap::new();
             cols.set_as("version", &2i16);
-            let res = doc.update(db, gid, cols, 0).await;
+            let res = doc.update(db, gid, cols, 0, xid::new()).await;
             assert!(res.is_err());
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 400); // version is not updatable
 
             let mut cols = ColumnsMap::new();
             cols.set_as("price", &50i64);
-            let res = doc.update(db, gid, cols, 1).await;
+            let res = doc.update(db, gid, cols, 1, xid::new()).await;
             assert!(res.is_err());
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 409); // updated_at not match
@@ -1105,7 +1812,10 @@ mod tests {
             cols.set_as("price", &50i64);
             cols.set_as("creation_price", &-1i64);
             doc.get_one(db, vec![], None).await.unwrap();
-            let res = doc.update(db, gid, cols, doc.updated_at).await.unwrap();
+            let res = doc
+                .update(db, gid, cols, doc.updated_at, xid::new())
+                .await
+                .unwrap();
             assert!(res);
             doc.get_one(db, vec![], None).await.unwrap();
             assert_eq!(doc.price, 50);
@@ -1113,12 +1823,56 @@ mod tests {
 
             let mut cols = ColumnsMap::new();
             cols.set_as("creation_price", &10i64);
-            let res = doc.update(db, gid, cols, doc.updated_at).await;
+            let res = doc.update(db, gid, cols, doc.updated_at, xid::new()).await;
             assert!(res.is_err());
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 400); // creation_price is not updatable
         }
 
+        // update price with creation_price <= price invariant
+        {
+            let id = xid::new();
+            let mut doc = Collection::with_pk(id);
+            doc.gid = gid;
+            doc.mid = mid;
+            doc.price = 100;
+            doc.creation_price = 10;
+            assert!(doc.save(db).await.unwrap());
+            doc.get_one(db, vec![], None).await.unwrap();
+
+            let mut cols = ColumnsMap::new();
+            cols.set_as("price", &5i64);
+            let res = doc.update(db, gid, cols, doc.updated_at, xid::new()).await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400); // creation_price 10 > price 5
+
+            let mut cols = ColumnsMap::new();
+            cols.set_as("creation_price", &101i64);
+            let res = doc.update(db, gid, cols, doc.updated_at, xid::new()).await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400); // creation_price 101 > price 100
+
+            let mut cols = ColumnsMap::new();
+            cols.set_as("price", &-1i64);
+            let res = doc.update(db, gid, cols, doc.updated_at, xid::new()).await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400); // creation_price 10 > price -1
+
+            let mut cols = ColumnsMap::new();
+            cols.set_as("price", &-1i64);
+            cols.set_as("creation_price", &-1i64);
+            assert!(doc
+                .update(db, gid, cols, doc.updated_at, xid::new())
+                .await
+                .unwrap());
+            doc.get_one(db, vec![], None).await.unwrap();
+            assert_eq!(doc.price, -1);
+            assert_eq!(doc.creation_price, -1);
+        }
+
         // update_status
         {
             let mut doc = Collection::with_pk(id);
@@ -1317,4 +2071,94 @@ mod tests {
         let err: erring::HTTPError = res.unwrap_err().into();
         assert_eq!(err.code, 404);
     }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn refresh_cover_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut creations: Vec<Creation> = Vec::new();
+        for (cover, cover_blurhash) in [
+            ("", ""),
+            ("https://cdn.yiwen.pub/1.png", "00TI:j"),
+            ("https://cdn.yiwen.pub/2.png", ""),
+        ] {
+            let mut doc = Creation::with_pk(gid, xid::new());
+            doc.language = Language::Eng;
+            doc.title = "Hello World".to_string();
+            doc.cover = cover.to_string();
+            doc.cover_blurhash = cover_blurhash.to_string();
+            doc.save_with(db, 0, content.clone()).await.unwrap();
+            creations.push(doc);
+        }
+
+        // no cover
+        let mut parent = Collection::with_pk(xid::new());
+        parent.gid = gid;
+        parent.cover_derived = true;
+        parent.save(db).await.unwrap();
+        assert!(!parent.refresh_cover(db).await.unwrap());
+        assert_eq!(parent.cover, "");
+
+        // derive from the first child creation that has a cover
+        let ord = unix_ms() as f64;
+        for (i, creation) in creations.iter().enumerate() {
+            let mut child = CollectionChildren {
+                id: parent.id,
+                cid: creation.id,
+                kind: 0,
+                ord: ord + i as f64,
+                ..Default::default()
+            };
+            assert!(child.save(db).await.unwrap());
+        }
+        assert!(parent.refresh_cover(db).await.unwrap());
+        assert_eq!(parent.cover, creations[1].cover);
+        assert_eq!(parent.cover_blurhash, "00TI:j");
+        assert!(!parent.refresh_cover(db).await.unwrap());
+
+        // the first child changes
+        let mut child = CollectionChildren::with_pk(parent.id, creations[1].id);
+        assert!(child.delete(db).await.unwrap());
+        assert!(parent.refresh_cover(db).await.unwrap());
+        assert_eq!(parent.cover, creations[2].cover);
+        assert_eq!(parent.cover_blurhash, "");
+
+        let mut child = CollectionChildren::with_pk(parent.id, creations[1].id);
+        child.kind = 0;
+        child.ord = ord - 1f64;
+        assert!(child.save(db).await.unwrap());
+        assert!(parent.refresh_cover(db).await.unwrap());
+        assert_eq!(parent.cover, creations[1].cover);
+
+        let mut doc = Collection::with_pk(parent.id);
+        doc.get_one(db, vec!["cover".to_string()], None)
+            .await
+            .unwrap();
+        assert_eq!(doc.cover, creations[1].cover);
+        assert_eq!(doc.cover_blurhash, "00TI:j"); // selected along with the cover
+
+        // explicit cover is kept
+        doc.get_one(db, vec!["updated_at".to_string()], None)
+            .await
+            .unwrap();
+        let mut cols = ColumnsMap::new();
+        cols.set_as("cover", &"https://cdn.yiwen.pub/0.png".to_string());
+        doc.update(db, gid, cols, doc.updated_at, xid::new())
+            .await
+            .unwrap();
+        let mut child = CollectionChildren::with_pk(parent.id, creations[1].id);
+        assert!(child.delete(db).await.unwrap());
+        assert!(!parent.refresh_cover(db).await.unwrap());
+        assert_eq!(parent.cover, "https://cdn.yiwen.pub/0.png");
+        assert!(!parent.cover_derived);
+    }
 }
@@ -3,10 +3,12 @@ use sha3::{Digest, Sha3_256};
 
 use axum_web::context::unix_ms;
 use axum_web::erring::HTTPError;
-use scylla_orm::{ColumnsMap, CqlValue, ToCqlVal};
+use scylla_orm::{ColumnsMap, CqlValue, FromCqlVal, ToCqlVal};
 use scylla_orm_macros::CqlOrm;
 
-use crate::db::{scylladb, scylladb::extract_applied};
+use crate::db::{
+    scylladb, scylladb::extract_applied, Creation, CreationIndex, GroupQuota, Publication,
+};
 
 #[derive(Debug, Default, Clone, CqlOrm)]
 pub struct Content {
@@ -18,12 +20,27 @@ pub struct Content {
     pub language: Language,
     pub updated_at: i64,
     pub length: i32,
+    pub words: i32,
     pub hash: Vec<u8>,
     pub content: Vec<u8>,
+    pub corrupt: bool,
 
     pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
 }
 
+// the group quota is updated after the content is written, so a failure is logged instead of
+// failing the write that is already applied.
+async fn add_used(db: &scylladb::ScyllaDB, gid: xid::Id, delta: i64) {
+    if let Err(err) = GroupQuota::add_used(db, gid, delta).await {
+        log::warn!(target: "quota",
+            action = "add_used",
+            gid = gid.to_string(),
+            delta = delta;
+            "{}", err.to_string(),
+        );
+    }
+}
+
 impl Content {
     pub fn with_pk(id: xid::Id) -> Self {
         Self {
@@ -104,6 +121,10 @@ impl Content {
         if self.length == 0 {
             self.length = self.content.len() as i32;
         }
+        // the content saved before counting words
+        if self.words == 0 && !self.content.is_empty() {
+            self.words = count_words(&self.content);
+        }
 
         Ok(())
     }
@@ -113,6 +134,7 @@ impl Content {
         self._fields = fields.clone();
 
         self.length = self.content.len() as i32;
+        self.words = count_words(&self.content);
         let mut hasher = Sha3_256::new();
         hasher.update(&self.content);
         self.hash = hasher.finalize().to_vec();
@@ -141,9 +163,158 @@ impl Content {
             );
         }
 
+        add_used(db, self.gid, self.length as i64).await;
         Ok(true)
     }
 
+    // save_dedup reuses the live content with identical bytes in the same group,
+    // returns true if an existing content is reused, then `self.id` is the shared id.
+    // A reused content gains a reference in content_ref, and does not count against
+    // the group quota again. The references are updated with LWT, so a content being
+    // released by its last owner is never reused.
+    pub async fn save_dedup(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        self.length = self.content.len() as i32;
+        let mut hasher = Sha3_256::new();
+        hasher.update(&self.content);
+        self.hash = hasher.finalize().to_vec();
+
+        let query = "SELECT id FROM content_hash WHERE gid=? AND hash=? LIMIT 1";
+        let params = (self.gid.to_cql(), self.hash.to_cql());
+        if let Ok(row) = db.execute(query, params).await?.single_row() {
+            let id = match row.columns.first() {
+                Some(Some(v)) => xid::Id::from_cql(v)?,
+                _ => xid::Id::default(),
+            };
+
+            let mut prev = Self::with_pk(id);
+            let ok = prev
+                .get_one(
+                    db,
+                    vec![
+                        "status".to_string(),
+                        "length".to_string(),
+                        "hash".to_string(),
+                    ],
+                )
+                .await
+                .is_ok();
+            if ok
+                && prev.status == 0
+                && prev.length == self.length
+                && prev.hash == self.hash
+                && Self::acquire(db, id).await?
+            {
+                self.id = id;
+                self.status = 0;
+                self.words = count_words(&self.content);
+                return Ok(true);
+            }
+        }
+
+        self.save(db).await?;
+        self.save_hash(db).await?;
+        Ok(false)
+    }
+
+    // save_hash makes the content a dedup candidate in its group.
+    pub async fn save_hash(&self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
+        let query = "INSERT INTO content_hash (gid,hash,id) VALUES (?,?,?)";
+        let params = (self.gid.to_cql(), self.hash.to_cql(), self.id.to_cql());
+        let _ = db.execute(query, params).await?;
+        Ok(())
+    }
+
+    // refs returns the shared references of the content besides its first owner,
+    // None if the content was never shared, -1 if it is released by the last owner.
+    pub async fn refs(db: &scylladb::ScyllaDB, id: xid::Id) -> anyhow::Result<Option<i64>> {
+        let query = "SELECT refs FROM content_ref WHERE id=? LIMIT 1";
+        match db.execute(query, (id.to_cql(),)).await?.single_row() {
+            Ok(row) => match row.columns.first() {
+                Some(Some(CqlValue::BigInt(v))) => Ok(Some(*v)),
+                _ => Ok(Some(0)),
+            },
+            Err(_) => Ok(None),
+        }
+    }
+
+    // set_refs changes the references from `prev` (None for no row) to `refs` with LWT,
+    // returns false if they were changed by others.
+    async fn set_refs(
+        db: &scylladb::ScyllaDB,
+        id: xid::Id,
+        prev: Option<i64>,
+        refs: i64,
+    ) -> anyhow::Result<bool> {
+        let res = match prev {
+            Some(prev) => {
+                let query = "UPDATE content_ref SET refs=? WHERE id=? IF refs=?";
+                db.execute(query, (refs, id.to_cql(), prev)).await?
+            }
+            None => {
+                let query = "INSERT INTO content_ref (id,refs) VALUES (?,?) IF NOT EXISTS";
+                db.execute(query, (id.to_cql(), refs)).await?
+            }
+        };
+        Ok(extract_applied(res))
+    }
+
+    // acquire adds a shared reference to the content, returns false if the content is
+    // released, or the references keep changing.
+    async fn acquire(db: &scylladb::ScyllaDB, id: xid::Id) -> anyhow::Result<bool> {
+        for _ in 0..3 {
+            let refs = Self::refs(db, id).await?;
+            if refs.unwrap_or(0) < 0 {
+                return Ok(false);
+            }
+            if Self::set_refs(db, id, refs, refs.unwrap_or(0) + 1).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // release drops a shared reference of the content, returns false if there is none, then
+    // the content is marked as released and the caller (the last owner) should tombstone it.
+    async fn release(db: &scylladb::ScyllaDB, id: xid::Id) -> anyhow::Result<bool> {
+        for _ in 0..3 {
+            let refs = Self::refs(db, id).await?;
+            match refs {
+                Some(n) if n > 0 => {
+                    if Self::set_refs(db, id, refs, n - 1).await? {
+                        return Ok(true);
+                    }
+                }
+                Some(n) if n < 0 => return Ok(false),
+                _ => {
+                    if Self::set_refs(db, id, refs, -1).await? {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+
+        Err(HTTPError::new(409, "Content release failed, please try again".to_string()).into())
+    }
+
+    // check_language returns the language to store, the content should keep the language of
+    // its creation or publication, `Und` on either side is not checked.
+    pub fn check_language(stored: Language, language: Language) -> Result<Language, HTTPError> {
+        if language == Language::Und {
+            return Ok(stored);
+        }
+        if stored != Language::Und && stored != language {
+            return Err(HTTPError::new(
+                400,
+                format!(
+                    "Content language mismatch, expected {}, got {}",
+                    stored.to_639_3(),
+                    language.to_639_3()
+                ),
+            ));
+        }
+        Ok(language)
+    }
+
     pub async fn update_content(
         &mut self,
         db: &scylladb::ScyllaDB,
@@ -153,17 +324,37 @@ impl Content {
     ) -> anyhow::Result<bool> {
         let new_updated_at = unix_ms() as i64;
         let length = content.len() as i32;
+        let words = count_words(&content);
         let mut hasher = Sha3_256::new();
         hasher.update(&content);
         let hash: Vec<u8> = hasher.finalize().to_vec();
 
+        let mut prev = Self::with_pk(self.id);
+        let prev_ok = prev
+            .get_one(
+                db,
+                vec![
+                    "gid".to_string(),
+                    "length".to_string(),
+                    "language".to_string(),
+                ],
+            )
+            .await
+            .is_ok();
+        let language = if prev_ok {
+            Self::check_language(prev.language, language)?
+        } else {
+            language
+        };
+
         let query =
-            "UPDATE content SET updated_at=?,version=?,language=?,length=?,hash=?,content=? WHERE id=? IF EXISTS";
+            "UPDATE content SET updated_at=?,version=?,language=?,length=?,words=?,hash=?,content=? WHERE id=? IF EXISTS";
         let params = (
             new_updated_at,
             version,
             language.to_cql(),
             length,
+            words,
             hash.to_cql(),
             content.to_cql(),
             self.id.to_cql(),
@@ -178,10 +369,15 @@ impl Content {
             .into());
         }
 
+        if prev_ok {
+            add_used(db, prev.gid, length as i64 - prev.length as i64).await;
+        }
+
         self.updated_at = new_updated_at;
         self.version = version;
         self.language = language;
         self.length = length;
+        self.words = words;
         self.hash = hash;
         self.content = content;
         Ok(true)
@@ -192,12 +388,26 @@ impl Content {
         db: &scylladb::ScyllaDB,
         status: i8,
     ) -> anyhow::Result<bool> {
-        self.get_one(db, vec!["status".to_string()]).await?;
+        self.get_one(
+            db,
+            vec![
+                "status".to_string(),
+                "gid".to_string(),
+                "length".to_string(),
+            ],
+        )
+        .await?;
         self.valid_status(status)?;
         if self.status == status {
             return Ok(false); // no need to update
         }
 
+        // a shared content is only released by its owners except the last one
+        if status == -1 && Self::release(db, self.id).await? {
+            self.updated_at = unix_ms() as i64;
+            return Ok(false);
+        }
+
         let new_updated_at = unix_ms() as i64;
         let query = "UPDATE content SET status=?,updated_at=? WHERE id=? IF status=?";
         let params = (status, new_updated_at, self.id.to_cql(), self.status);
@@ -211,10 +421,191 @@ impl Content {
             .into());
         }
 
+        // tombstoned content no longer counts against the group quota
+        let delta = if status == -1 {
+            -(self.length as i64)
+        } else {
+            self.length as i64
+        };
+        add_used(db, self.gid, delta).await;
+        if status == 0 {
+            // the restored content can be shared again
+            let _ = Self::set_refs(db, self.id, Some(-1), 0).await?;
+        }
+
         self.updated_at = new_updated_at;
         self.status = status;
         Ok(true)
     }
+
+    // whether a live creation or publication of `cid` still points at the content.
+    pub async fn is_referenced(
+        db: &scylladb::ScyllaDB,
+        id: xid::Id,
+        cid: xid::Id,
+    ) -> anyhow::Result<bool> {
+        // a deduped content may be referenced by other creations, it is alive until
+        // the last owner tombstones it.
+        if Self::refs(db, id).await?.is_some() {
+            let mut doc = Self::with_pk(id);
+            if doc.get_one(db, vec!["status".to_string()]).await.is_ok() && doc.status == 0 {
+                return Ok(true);
+            }
+        }
+
+        let fields = vec!["content".to_string()];
+        let query = "SELECT content FROM publication WHERE cid=?";
+        let rows = db.execute_iter(query, (cid.to_cql(),)).await?;
+        for row in rows {
+            let mut doc = Publication::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            if doc.content == id {
+                return Ok(true);
+            }
+        }
+
+        let mut index = CreationIndex::with_pk(cid);
+        if index.get_one(db).await.is_err() {
+            return Ok(false);
+        }
+
+        let fields = vec!["content".to_string(), "content_history".to_string()];
+        let query = "SELECT content,content_history FROM creation WHERE gid=? AND id=? LIMIT 1";
+        let rows = db
+            .execute_iter(query, (index.gid.to_cql(), cid.to_cql()))
+            .await?;
+        for row in rows {
+            let mut doc = Creation::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            if doc.content == id || doc.content_history.contains(&id) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // hash_matches recomputes the hash and the length of the loaded content and compares them
+    // with the stored ones, the length of the content saved before it was stored is 0.
+    pub fn hash_matches(&self) -> bool {
+        if self.length != 0 && self.length as usize != self.content.len() {
+            return false;
+        }
+        let mut hasher = Sha3_256::new();
+        hasher.update(&self.content);
+        hasher.finalize().as_slice() == self.hash
+    }
+
+    // verify checks the stored content against its hash, a mismatched content is flagged as
+    // corrupt, and unflagged once it matches again. Returns true if the content matches.
+    pub async fn verify(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        self.get_one(
+            db,
+            vec![
+                "gid".to_string(),
+                "cid".to_string(),
+                "status".to_string(),
+                "version".to_string(),
+                "length".to_string(),
+                "hash".to_string(),
+                "content".to_string(),
+                "corrupt".to_string(),
+            ],
+        )
+        .await?;
+
+        let ok = self.hash_matches();
+        if self.corrupt == ok {
+            self.set_corrupt(db, !ok).await?;
+        }
+        Ok(ok)
+    }
+
+    pub async fn set_corrupt(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        corrupt: bool,
+    ) -> anyhow::Result<()> {
+        let query = "UPDATE content SET corrupt=? WHERE id=? IF EXISTS";
+        let res = db.execute(query, (corrupt, self.id.to_cql())).await?;
+        if !extract_applied(res) {
+            return Err(HTTPError::new(404, format!("Content {} not found", self.id)).into());
+        }
+        self.corrupt = corrupt;
+        Ok(())
+    }
+
+    // purge deletes the content row permanently.
+    pub async fn purge(&self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
+        let query = "DELETE FROM content WHERE id=?";
+        let _ = db.execute(query, (self.id.to_cql(),)).await?;
+        let query = "DELETE FROM content_ref WHERE id=?";
+        let _ = db.execute(query, (self.id.to_cql(),)).await?;
+        Ok(())
+    }
+}
+
+// count_words counts the words in the text nodes of a CBOR document. A CJK character counts
+// as one word, those scripts are written without spaces between words.
+pub fn count_words(content: &[u8]) -> i32 {
+    fn walk(node: &ciborium::Value, count: &mut usize) {
+        if let ciborium::Value::Map(entries) = node {
+            for (key, val) in entries {
+                match (key, val) {
+                    (ciborium::Value::Text(key), ciborium::Value::Text(text)) if key == "text" => {
+                        *count += text_words(text);
+                    }
+                    (ciborium::Value::Text(key), ciborium::Value::Array(nodes))
+                        if key == "content" =>
+                    {
+                        for node in nodes {
+                            walk(node, count);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let mut count = 0usize;
+    if let Ok(doc) = ciborium::from_reader::<ciborium::Value, _>(content) {
+        walk(&doc, &mut count);
+    }
+    count.min(i32::MAX as usize) as i32
+}
+
+fn text_words(text: &str) -> usize {
+    let mut count = 0usize;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            count += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                count += 1;
+                in_word = true;
+            }
+        } else if c != '\'' && c != '-' {
+            in_word = false;
+        }
+    }
+    count
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2fa1f}' // CJK Extension B and beyond
+    )
 }
 
 #[cfg(test)]
@@ -241,10 +632,218 @@ mod tests {
         .await
     }
 
+    #[test]
+    fn count_words_works() {
+        let content = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [{
+                    "type" => "heading",
+                    "attrs" => {"id" => "Y3T1Ik", "level" => 1},
+                    "content" => [{"type" => "text", "text" => "Hello world"}],
+                }, {
+                    "type" => "paragraph",
+                    "content" => [
+                        {"type" => "text", "text" => "It's a well-known fact, "},
+                        {"type" => "text", "text" => "你好世界", "marks" => [{"type" => "bold"}]},
+                        {"type" => "text", "text" => " — 2023"},
+                    ],
+                }, {
+                    "type" => "paragraph",
+                }],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        // hello, world, it's, a, well-known, fact, 你, 好, 世, 界, 2023
+        assert_eq!(count_words(&content), 11);
+
+        assert_eq!(text_words("こんにちは world"), 6);
+        assert_eq!(text_words("  "), 0);
+        assert_eq!(count_words(&[]), 0);
+        assert_eq!(count_words(b"not cbor"), 0);
+        let content = cbor_to_vec(&cbor!({"type" => "doc"}).unwrap()).unwrap();
+        assert_eq!(count_words(&content), 0);
+    }
+
+    #[test]
+    fn hash_matches_works() {
+        let mut doc = Content {
+            content: b"Hello World".to_vec(),
+            ..Default::default()
+        };
+        assert!(!doc.hash_matches());
+
+        let mut hasher = Sha3_256::new();
+        hasher.update(&doc.content);
+        doc.hash = hasher.finalize().to_vec();
+        assert!(doc.hash_matches());
+        doc.length = doc.content.len() as i32 + 1;
+        assert!(!doc.hash_matches());
+        doc.length = doc.content.len() as i32;
+        assert!(doc.hash_matches());
+
+        doc.content[0] = b'h';
+        assert!(!doc.hash_matches());
+        doc.content[0] = b'H';
+        doc.content.push(0);
+        assert!(!doc.hash_matches());
+    }
+
+    #[test]
+    fn check_language_works() {
+        assert_eq!(
+            Content::check_language(Language::Eng, Language::Eng).unwrap(),
+            Language::Eng
+        );
+        assert_eq!(
+            Content::check_language(Language::Eng, Language::Und).unwrap(),
+            Language::Eng
+        );
+        assert_eq!(
+            Content::check_language(Language::Und, Language::Zho).unwrap(),
+            Language::Zho
+        );
+        let err = Content::check_language(Language::Eng, Language::Zho).unwrap_err();
+        assert_eq!(err.code, 400);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     #[ignore]
     async fn test_all() {
         content_model_works().await;
+        content_dedup_works().await;
+        content_verify_works().await;
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn content_verify_works() {
+        let db = get_db().await;
+        let content = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [{
+                    "type" => "text",
+                    "text" => "Hello World",
+                }],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        let mut doc = Content {
+            id: xid::new(),
+            gid: xid::new(),
+            cid: xid::new(),
+            language: Language::Eng,
+            version: 1,
+            content: content.clone(),
+            ..Default::default()
+        };
+        doc.save(db).await.unwrap();
+
+        let mut doc = Content::with_pk(doc.id);
+        assert!(doc.verify(db).await.unwrap());
+        assert!(!doc.corrupt);
+
+        // corrupt the stored bytes
+        let mut corrupted = content.clone();
+        corrupted.truncate(content.len() - 1);
+        let query = "UPDATE content SET content=? WHERE id=?";
+        db.execute(query, (corrupted, doc.id.to_cql()))
+            .await
+            .unwrap();
+        assert!(!doc.verify(db).await.unwrap());
+        assert!(doc.corrupt);
+        let mut doc2 = Content::with_pk(doc.id);
+        doc2.get_one(db, vec!["corrupt".to_string()]).await.unwrap();
+        assert!(doc2.corrupt);
+
+        // repaired
+        db.execute(query, (content, doc.id.to_cql())).await.unwrap();
+        assert!(doc.verify(db).await.unwrap());
+        doc2.get_one(db, vec!["corrupt".to_string()]).await.unwrap();
+        assert!(!doc2.corrupt);
+
+        doc.purge(db).await.unwrap();
+        assert!(Content::with_pk(doc.id).verify(db).await.is_err());
+        let err: erring::HTTPError = Content::with_pk(doc.id)
+            .set_corrupt(db, true)
+            .await
+            .unwrap_err()
+            .into();
+        assert_eq!(err.code, 404);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn content_dedup_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [{
+                    "type" => "text",
+                    "text" => "Hello World",
+                }],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let new_doc = |gid: xid::Id| Content {
+            id: xid::new(),
+            gid,
+            cid: xid::new(),
+            language: Language::Eng,
+            version: 1,
+            content: content.clone(),
+            ..Default::default()
+        };
+
+        let mut doc1 = new_doc(gid);
+        assert!(!doc1.save_dedup(db).await.unwrap());
+        assert_eq!(Content::refs(db, doc1.id).await.unwrap(), None);
+
+        // reuse the identical content in the same group
+        let mut doc2 = new_doc(gid);
+        assert!(doc2.save_dedup(db).await.unwrap());
+        assert_eq!(doc2.id, doc1.id);
+        assert_eq!(Content::refs(db, doc1.id).await.unwrap(), Some(1));
+
+        // not across groups
+        let mut doc3 = new_doc(xid::new());
+        assert!(!doc3.save_dedup(db).await.unwrap());
+        assert_ne!(doc3.id, doc1.id);
+
+        // deleting one owner keeps the shared content
+        let mut doc = Content::with_pk(doc1.id);
+        assert!(!doc.update_status(db, -1).await.unwrap());
+        assert_eq!(Content::refs(db, doc1.id).await.unwrap(), Some(0));
+        let mut doc = Content::with_pk(doc1.id);
+        doc.get_one(db, vec![]).await.unwrap();
+        assert_eq!(doc.status, 0);
+        assert_eq!(doc.content, content);
+        assert!(Content::is_referenced(db, doc1.id, xid::new())
+            .await
+            .unwrap());
+
+        // the last owner tombstones it
+        assert!(doc.update_status(db, -1).await.unwrap());
+        assert_eq!(Content::refs(db, doc1.id).await.unwrap(), Some(-1));
+        assert!(!Content::is_referenced(db, doc1.id, xid::new())
+            .await
+            .unwrap());
+
+        // a tombstoned content is not reused
+        let mut doc4 = new_doc(gid);
+        assert!(!doc4.save_dedup(db).await.unwrap());
+        assert_ne!(doc4.id, doc1.id);
+        let mut doc5 = new_doc(gid);
+        assert!(doc5.save_dedup(db).await.unwrap());
+        assert_eq!(doc5.id, doc4.id);
+
+        Content::with_pk(doc1.id).purge(db).await.unwrap();
+        assert_eq!(Content::refs(db, doc1.id).await.unwrap(), None);
     }
 
     // #[tokio::test(flavor = "current_thread")]
@@ -379,10 +978,33 @@ mod tests {
 
             // update again
             let res = doc
-                .update_content(db, doc.version, doc.language, content)
+                .update_content(db, doc.version, doc.language, content.clone())
                 .await
                 .unwrap();
             assert!(res);
+
+            // the language should match the stored one
+            let res = doc
+                .update_content(db, doc.version, Language::Zho, content.clone())
+                .await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400);
+            let mut doc2 = Content::with_pk(id);
+            doc2.get_one(db, vec![]).await.unwrap();
+            assert_eq!(doc2.language, Language::Eng);
+            assert_eq!(doc2.version, 2);
+
+            // Und keeps the stored language
+            let res = doc
+                .update_content(db, doc.version, Language::Und, content)
+                .await
+                .unwrap();
+            assert!(res);
+            assert_eq!(doc.language, Language::Eng);
+            let mut doc2 = Content::with_pk(id);
+            doc2.get_one(db, vec![]).await.unwrap();
+            assert_eq!(doc2.language, Language::Eng);
         }
 
         // update status
@@ -1,5 +1,9 @@
+use futures::stream::StreamExt;
 use isolang::Language;
-use std::time::{Duration, SystemTime};
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
 
 use axum_web::context::unix_ms;
 use axum_web::erring::HTTPError;
@@ -7,11 +11,25 @@ use scylla_orm::{ColumnsMap, CqlValue, ToCqlVal};
 use scylla_orm_macros::CqlOrm;
 
 use crate::db::{
-    meili,
-    scylladb::{self, extract_applied},
-    Content, MAX_ID,
+    meili, next_updated_at, purge_throttle,
+    scylladb::{self, extract_applied, extract_applied_with_row, BatchItem},
+    unix_ms_day, unix_ms_to_xid, update_conflict_error, validate_columns, xid_created_ms, xid_day,
+    Changelog, Content, DeletePlan, Order, PurgeStats, CREATION_STATUS, MAX_ID, MIN_IMPORT_DAY,
 };
 
+pub const MAX_CONTENT_HISTORY: usize = 10;
+// the clock skew tolerated by valid_new_id.
+const ID_CLOCK_SKEW: Duration = Duration::from_secs(10);
+// max rows scanned by one list_by_gid_label call.
+const MAX_LABEL_SCAN: usize = 1000;
+// how many days to look back when listing creations by updated_at.
+const UPDATED_LIST_DAYS: i32 = 90;
+// the status of the marker entry in creation_by_status, written once the group is backfilled.
+const STATUS_INDEX_READY: i8 = i8::MIN;
+// max creations of a group to backfill into creation_by_status, a larger group is listed
+// by filtering.
+const MAX_STATUS_BACKFILL: usize = 10000;
+
 #[derive(Debug, Default, Clone, CqlOrm)]
 pub struct CreationIndex {
     pub id: xid::Id,
@@ -21,6 +39,22 @@ pub struct CreationIndex {
     pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
 }
 
+// valid_new_id checks the timestamp of an id to be saved at `now`. A new id must be fresh,
+// an imported one can be as old as MIN_IMPORT_DAY. Both must not be in the future, and
+// must not be a day boundary id which is used as page token.
+pub fn valid_new_id(id: xid::Id, allow_old_id: bool, now: SystemTime) -> Result<(), HTTPError> {
+    let at = id.time();
+    let valid = if allow_old_id {
+        xid_day(id) >= MIN_IMPORT_DAY && id.0[4..] != [0u8; 8]
+    } else {
+        at + ID_CLOCK_SKEW >= now
+    };
+    if !valid || at > now + ID_CLOCK_SKEW {
+        return Err(HTTPError::new(400, format!("Invalid id {:?}", id)));
+    }
+    Ok(())
+}
+
 impl CreationIndex {
     pub fn with_pk(id: xid::Id) -> Self {
         Self {
@@ -47,11 +81,18 @@ impl CreationIndex {
     }
 
     pub async fn save(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
-        use std::ops::Sub;
-        let now = SystemTime::now().sub(Duration::from_secs(10));
-        if self.id.time() < now {
-            return Err(HTTPError::new(400, format!("Invalid id {:?}", self.id)).into());
-        }
+        self.save_with_options(db, false).await
+    }
+
+    // save_with_options saves the index, an id older than a few seconds is rejected unless
+    // `allow_old_id` is set, it is set by imports of historical creations only.
+    pub async fn save_with_options(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        allow_old_id: bool,
+    ) -> anyhow::Result<bool> {
+        valid_new_id(self.id, allow_old_id, SystemTime::now())?;
+        validate_columns(&self.to())?;
 
         self._fields = Self::fields();
         let mut cols_name: Vec<&str> = Vec::with_capacity(self._fields.len());
@@ -157,6 +198,7 @@ pub struct Creation {
     pub genre: Vec<String>,
     pub title: String,
     pub cover: String,
+    pub cover_blurhash: String,
     pub keywords: Vec<String>,
     pub labels: Vec<String>,
     pub authors: Vec<String>,
@@ -164,12 +206,16 @@ pub struct Creation {
     pub summary: String,
     pub content: xid::Id,
     pub license: String,
+    pub content_history: Vec<xid::Id>,
 
     pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
     pub _rating: Option<i8>,  // 内容安全分级
     pub _price: Option<i64>,
     pub _length: i32, // 内容字节长度
+    pub _words: i32,  // 内容字数，用于 meilisearch
     pub _content: Vec<u8>,
+    pub _corrupt: bool, // 内容校验失败
+    pub _deleted: bool, // loaded from deleted_creation
 }
 
 impl Creation {
@@ -181,6 +227,11 @@ impl Creation {
         }
     }
 
+    // rejects the oversized list and text columns before writing, see `db::validate_columns`.
+    pub fn validate_columns(&self) -> anyhow::Result<()> {
+        validate_columns(&self.to())
+    }
+
     pub fn select_fields(select_fields: Vec<String>, with_pk: bool) -> anyhow::Result<Vec<String>> {
         if select_fields.is_empty() {
             return Ok(Self::fields());
@@ -194,6 +245,10 @@ impl Creation {
         }
 
         let mut select_fields = select_fields;
+        let field = "cover_blurhash".to_string();
+        if select_fields.iter().any(|f| f == "cover") && !select_fields.contains(&field) {
+            select_fields.push(field);
+        }
         let field = "language".to_string(); // language 字段在 meilisearch 中用作 PK，必须存在
         if !select_fields.contains(&field) {
             select_fields.push(field);
@@ -241,49 +296,13 @@ impl Creation {
         if !self.summary.is_empty() {
             doc.summary = Some(self.summary.clone());
         }
+        doc.set_words(self._words);
         doc
     }
 
     pub fn valid_status(&self, status: i8) -> anyhow::Result<()> {
-        if !(-1..=2).contains(&status) || !(-1..=2).contains(&self.status) {
-            return Err(HTTPError::new(400, format!("Invalid status, {}", status)).into());
-        }
-
-        match self.status {
-            -1 if 0 != status => Err(HTTPError::new(
-                400,
-                format!(
-                    "Creation status is {}, expected update to 0, got {}",
-                    self.status, status
-                ),
-            )
-            .into()),
-            0 if !(-1..=1).contains(&status) => Err(HTTPError::new(
-                400,
-                format!(
-                    "Creation status is {}, expected update to -1 or 1, got {}",
-                    self.status, status
-                ),
-            )
-            .into()),
-            1 if !(-1..=2).contains(&status) => Err(HTTPError::new(
-                400,
-                format!(
-                    "Creation status is {}, expected update to -1, 0 or 2, got {}",
-                    self.status, status
-                ),
-            )
-            .into()),
-            2 if !(-1..=0).contains(&status) => Err(HTTPError::new(
-                400,
-                format!(
-                    "Creation status is {}, expected update to -1, 0, got {}",
-                    self.status, status
-                ),
-            )
-            .into()),
-            _ => Ok(()),
-        }
+        CREATION_STATUS.validate(self.status, status)?;
+        Ok(())
     }
 
     pub async fn get_one(
@@ -307,13 +326,54 @@ impl Creation {
 
         if self._fields.contains(&"content".to_string()) {
             let mut doc = Content::with_pk(self.content);
-            doc.get_one(db, vec!["content".to_string()]).await?;
+            doc.get_one(
+                db,
+                vec![
+                    "content".to_string(),
+                    "words".to_string(),
+                    "corrupt".to_string(),
+                ],
+            )
+            .await?;
+            self._words = doc.words;
+            self._corrupt = doc.corrupt;
             self._content = doc.content;
         }
 
         Ok(())
     }
 
+    // returns the status of the creations in the group, with one query.
+    pub async fn batch_status(
+        db: &scylladb::ScyllaDB,
+        gid: xid::Id,
+        ids: &[xid::Id],
+    ) -> anyhow::Result<HashMap<xid::Id, i8>> {
+        let mut res: HashMap<xid::Id, i8> = HashMap::with_capacity(ids.len());
+        if ids.is_empty() {
+            return Ok(res);
+        }
+
+        let query = format!(
+            "SELECT id,status FROM creation WHERE gid=? AND id IN ({})",
+            vec!["?"; ids.len()].join(",")
+        );
+        let mut params: Vec<CqlValue> = Vec::with_capacity(ids.len() + 1);
+        params.push(gid.to_cql());
+        for id in ids {
+            params.push(id.to_cql());
+        }
+
+        let fields = vec!["id".to_string(), "status".to_string()];
+        let rows = db.execute_iter(query, params).await?;
+        for row in rows {
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            res.insert(cols.get_as("id")?, cols.get_as("status")?);
+        }
+        Ok(res)
+    }
+
     pub async fn get_deleted(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
         let fields = Self::fields();
         self._fields = fields.clone();
@@ -338,10 +398,23 @@ impl Creation {
         price: i64,
         content: Vec<u8>,
     ) -> anyhow::Result<bool> {
+        self.save_with_options(db, price, content, false).await
+    }
+
+    // like save_with, `allow_old_id` accepts the id of a historical creation, see
+    // CreationIndex::save_with_options.
+    pub async fn save_with_options(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        price: i64,
+        content: Vec<u8>,
+        allow_old_id: bool,
+    ) -> anyhow::Result<bool> {
+        self.validate_columns()?;
         let mut index = CreationIndex::with_pk(self.id);
         index.gid = self.gid;
         index.price = price;
-        index.save(db).await?;
+        index.save_with_options(db, allow_old_id).await?;
 
         let now = unix_ms() as i64;
         self.created_at = now;
@@ -360,6 +433,7 @@ impl Creation {
             ..Default::default()
         };
         doc.save(db).await?;
+        self._words = doc.words;
 
         let fields = Self::fields();
         self._fields = fields.clone();
@@ -388,6 +462,7 @@ impl Creation {
             );
         }
 
+        self.index_status(db, None).await?;
         self._content = content;
         Ok(true)
     }
@@ -415,34 +490,49 @@ impl Creation {
         if self.status == status {
             return Ok(false); // no need to update
         }
-        let new_updated_at = unix_ms() as i64;
-        let query =
-            "UPDATE creation SET status=?,updated_at=? WHERE gid=? AND id=? IF updated_at=?";
+        let new_updated_at = next_updated_at(updated_at);
+        // the status condition makes a failed result carry the current status.
+        let query = "UPDATE creation SET status=?,updated_at=? WHERE gid=? AND id=? IF status=? AND updated_at=?";
         let params = (
             status,
             new_updated_at,
             self.gid.to_cql(),
             self.id.to_cql(),
+            self.status,
             updated_at,
         );
 
         let res = db.execute(query, params).await?;
-        if !extract_applied(res) {
-            return Err(HTTPError::new(
-                409,
-                format!("Creation update_status {} failed, please try again", status),
+        let (applied, row) = extract_applied_with_row(res);
+        if !applied {
+            return Err(update_conflict_error(
+                format!("Creation update_status {} failed", status),
+                row,
             )
             .into());
         }
 
+        let old_status = self.status;
         self.updated_at = new_updated_at;
         self.status = status;
+        self.touch_updated(db, updated_at).await;
+        // the status is updated already, so a failure is logged for the stale
+        // creation_by_status entry to be repaired, instead of failing the applied update.
+        if let Err(err) = self.index_status(db, Some(old_status)).await {
+            log::warn!(target: "scylladb",
+                action = "index_status",
+                gid = self.gid.to_string(),
+                id = self.id.to_string(),
+                status = status;
+                "{}", err.to_string(),
+            );
+        }
         Ok(true)
     }
 
     // upgrade when creating publication.
     pub async fn upgrade_version(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
-        let updated_at = unix_ms() as i64;
+        let updated_at = next_updated_at(self.updated_at);
         let query = "UPDATE creation SET updated_at=?,version=? WHERE gid=? AND id=? IF version=?";
         let params = (
             updated_at,
@@ -461,8 +551,10 @@ impl Creation {
             .into());
         }
 
+        let old_updated_at = self.updated_at;
         self.updated_at = updated_at;
         self.version += 1;
+        self.touch_updated(db, old_updated_at).await;
         Ok(())
     }
 
@@ -480,6 +572,7 @@ impl Creation {
                 "version".to_string(),
                 "updated_at".to_string(),
                 "content".to_string(),
+                "content_history".to_string(),
             ],
         )
         .await?;
@@ -506,56 +599,135 @@ impl Creation {
             self.language = language;
         }
 
-        let mut doc = Content::with_pk(self.content);
-        doc.update_content(db, self.version, self.language, content.clone())
+        let mut cols = ColumnsMap::with_capacity(1);
+        cols.set_as("language", &self.language);
+        self.replace_content(db, &cols, content, updated_at, "update_content")
             .await?;
+        Ok(true)
+    }
 
-        let query =
-            "UPDATE creation SET updated_at=?,language=? WHERE gid=? AND id=? IF updated_at=?";
-        let params = (
-            doc.updated_at,
-            self.language.to_cql(),
-            self.gid.to_cql(),
-            self.id.to_cql(),
-            updated_at,
+    // replace_content writes `content` as a new content snapshot, and points the creation at
+    // it with the columns in `cols` under the `updated_at` guard. The snapshot is saved by
+    // Content::save, which counts its words and bytes against the group quota. The previous
+    // content is kept in content_history, the snapshots trimmed off it are released.
+    async fn replace_content(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        cols: &ColumnsMap,
+        content: Vec<u8>,
+        updated_at: i64,
+        action: &str,
+    ) -> anyhow::Result<()> {
+        let mut doc = Content {
+            id: xid::new(),
+            gid: self.gid,
+            cid: self.id,
+            version: self.version,
+            language: self.language,
+            updated_at: next_updated_at(updated_at),
+            content: content.clone(),
+            ..Default::default()
+        };
+        doc.save(db).await?;
+
+        let mut history: Vec<xid::Id> = Vec::with_capacity(self.content_history.len() + 1);
+        history.push(self.content);
+        history.extend(self.content_history.iter().cloned());
+        let trimmed = if history.len() > MAX_CONTENT_HISTORY {
+            history.split_off(MAX_CONTENT_HISTORY)
+        } else {
+            Vec::new()
+        };
+
+        let mut set_fields: Vec<String> = Vec::with_capacity(cols.len() + 3);
+        let mut params: Vec<CqlValue> = Vec::with_capacity(cols.len() + 3 + 3);
+        set_fields.push("updated_at=?".to_string());
+        params.push(doc.updated_at.to_cql());
+        set_fields.push("content=?".to_string());
+        params.push(doc.id.to_cql());
+        set_fields.push("content_history=?".to_string());
+        params.push(history.to_cql());
+        for (field, val) in cols.ordered_pairs() {
+            set_fields.push(format!("{}=?", field));
+            params.push(val.to_owned());
+        }
+
+        let query = format!(
+            "UPDATE creation SET {} WHERE gid=? AND id=? IF updated_at=?",
+            set_fields.join(",")
         );
+        params.push(self.gid.to_cql());
+        params.push(self.id.to_cql());
+        params.push(updated_at.to_cql());
 
         let res = db.execute(query, params).await?;
         if !extract_applied(res) {
+            let _ = doc.update_status(db, -1).await;
             return Err(HTTPError::new(
                 409,
-                "Creation update_content failed, please try again".to_string(),
+                format!("Creation {} failed, please try again", action),
             )
             .into());
         }
 
+        for id in trimmed {
+            let _ = Content::with_pk(id).update_status(db, -1).await;
+        }
+
         self.updated_at = doc.updated_at;
+        self.content = doc.id;
+        self.content_history = history;
         self._content = content;
-        Ok(true)
+        self._words = doc.words;
+        Ok(())
     }
 
-    pub async fn update(
+    pub async fn list_content_history(
         &mut self,
         db: &scylladb::ScyllaDB,
-        cols: ColumnsMap,
-        updated_at: i64,
-    ) -> anyhow::Result<bool> {
-        let valid_fields = [
-            "title", "cover", "keywords", "labels", "authors", "summary", "license",
+    ) -> anyhow::Result<Vec<Content>> {
+        self.get_one(
+            db,
+            vec!["content".to_string(), "content_history".to_string()],
+        )
+        .await?;
+
+        let mut ids: Vec<xid::Id> = Vec::with_capacity(self.content_history.len() + 1);
+        ids.push(self.content);
+        ids.extend(self.content_history.iter().cloned());
+
+        let fields = vec![
+            "status".to_string(),
+            "version".to_string(),
+            "language".to_string(),
+            "updated_at".to_string(),
+            "length".to_string(),
         ];
-        let update_fields = cols.keys();
-        for field in &update_fields {
-            if !valid_fields.contains(&field.as_str()) {
-                return Err(HTTPError::new(400, format!("Invalid field: {}", field)).into());
+        let mut res: Vec<Content> = Vec::with_capacity(ids.len());
+        for id in ids {
+            let mut doc = Content::with_pk(id);
+            if doc.get_one(db, fields.clone()).await.is_ok() {
+                res.push(doc);
             }
         }
 
+        Ok(res)
+    }
+
+    // rollback_content points the creation back to a content snapshot in content_history.
+    pub async fn rollback_content(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        content_id: xid::Id,
+        updated_at: i64,
+    ) -> anyhow::Result<bool> {
         self.get_one(
             db,
             vec![
                 "status".to_string(),
                 "updated_at".to_string(),
-                "version".to_string(), // for meilisearch update
+                "content".to_string(),
+                "content_history".to_string(),
             ],
         )
         .await?;
@@ -569,6 +741,118 @@ impl Creation {
             )
             .into());
         }
+
+        if self.status < 0 || self.status > 1 {
+            return Err(HTTPError::new(
+                409,
+                format!("Creation can not be update, status {}", self.status),
+            )
+            .into());
+        }
+
+        if !self.content_history.contains(&content_id) {
+            return Err(HTTPError::new(
+                404,
+                format!("Content {} not found in content_history", content_id),
+            )
+            .into());
+        }
+
+        let mut doc = Content::with_pk(content_id);
+        doc.get_one(db, vec![]).await?;
+        if doc.status < 0 {
+            return Err(HTTPError::new(410, format!("Content {} was archived", content_id)).into());
+        }
+        self._words = doc.words;
+
+        let mut history: Vec<xid::Id> = Vec::with_capacity(self.content_history.len());
+        history.push(self.content);
+        history.extend(
+            self.content_history
+                .iter()
+                .filter(|id| **id != content_id)
+                .cloned(),
+        );
+
+        let new_updated_at = next_updated_at(updated_at);
+        let query =
+            "UPDATE creation SET updated_at=?,language=?,content=?,content_history=? WHERE gid=? AND id=? IF updated_at=?";
+        let params = (
+            new_updated_at,
+            doc.language.to_cql(),
+            doc.id.to_cql(),
+            history.to_cql(),
+            self.gid.to_cql(),
+            self.id.to_cql(),
+            updated_at,
+        );
+
+        let res = db.execute(query, params).await?;
+        if !extract_applied(res) {
+            return Err(HTTPError::new(
+                409,
+                "Creation rollback_content failed, please try again".to_string(),
+            )
+            .into());
+        }
+
+        self.updated_at = new_updated_at;
+        self.language = doc.language;
+        self.content = doc.id;
+        self.content_history = history;
+        self._content = doc.content;
+        self.touch_updated(db, updated_at).await;
+        Ok(true)
+    }
+
+    // checks the metadata columns to update, returns their names.
+    fn valid_update_columns(cols: &ColumnsMap) -> anyhow::Result<Vec<String>> {
+        let valid_fields = [
+            "title",
+            "cover",
+            "cover_blurhash",
+            "keywords",
+            "labels",
+            "authors",
+            "summary",
+            "license",
+        ];
+        let update_fields = cols.keys();
+        for field in &update_fields {
+            if !valid_fields.contains(&field.as_str()) {
+                return Err(HTTPError::new(400, format!("Invalid field: {}", field)).into());
+            }
+        }
+        validate_columns(cols)?;
+        Ok(update_fields)
+    }
+
+    pub async fn update(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        cols: ColumnsMap,
+        updated_at: i64,
+        actor: xid::Id,
+    ) -> anyhow::Result<bool> {
+        let update_fields = Self::valid_update_columns(&cols)?;
+
+        let mut select_fields = update_fields.clone();
+        select_fields.extend([
+            "status".to_string(),
+            "updated_at".to_string(),
+            "version".to_string(), // for meilisearch update
+        ]);
+        self.get_one(db, select_fields).await?;
+        if self.updated_at != updated_at {
+            return Err(HTTPError::new(
+                409,
+                format!(
+                    "Creation updated_at conflict, expected {}, got {}",
+                    self.updated_at, updated_at
+                ),
+            )
+            .into());
+        }
         if self.status < 0 {
             return Err(HTTPError::new(
                 409,
@@ -580,13 +864,13 @@ impl Creation {
         let mut set_fields: Vec<String> = Vec::with_capacity(update_fields.len() + 1);
         let mut params: Vec<CqlValue> = Vec::with_capacity(update_fields.len() + 1 + 3);
 
-        let new_updated_at = unix_ms() as i64;
+        let new_updated_at = next_updated_at(updated_at);
         set_fields.push("updated_at=?".to_string());
         params.push(new_updated_at.to_cql());
 
-        for field in &update_fields {
+        for (field, val) in cols.ordered_pairs() {
             set_fields.push(format!("{}=?", field));
-            params.push(cols.get(field).unwrap().to_owned());
+            params.push(val.to_owned());
         }
 
         let query = format!(
@@ -606,89 +890,453 @@ impl Creation {
             .into());
         }
 
+        let logs = Changelog::from_update(self.gid, actor, 0, self.id, &self.to(), &cols);
+        let _ = Changelog::record(db, logs).await;
         self.fill(&cols); // fill for meilisearch update
         self.updated_at = new_updated_at;
+        self.touch_updated(db, updated_at).await;
         Ok(true)
     }
 
-    pub async fn delete(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
-        let res = self.get_one(db, Vec::new()).await;
-        if res.is_err() {
-            return Ok(false); // already deleted
+    // update_full updates the metadata in `cols` and the content with one statement under
+    // the `updated_at` guard, so an editor's change is applied entirely or not at all.
+    pub async fn update_full(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        cols: ColumnsMap,
+        content: Vec<u8>,
+        updated_at: i64,
+        actor: xid::Id,
+    ) -> anyhow::Result<bool> {
+        let update_fields = Self::valid_update_columns(&cols)?;
+
+        let mut select_fields = update_fields.clone();
+        select_fields.extend([
+            "status".to_string(),
+            "updated_at".to_string(),
+            "version".to_string(),
+            "language".to_string(),
+            "content".to_string(),
+            "content_history".to_string(),
+        ]);
+        self.get_one(db, select_fields).await?;
+        if self.updated_at != updated_at {
+            return Err(HTTPError::new(
+                409,
+                format!(
+                    "Creation updated_at conflict, expected {}, got {}",
+                    self.updated_at, updated_at
+                ),
+            )
+            .into());
         }
-
-        if self.status != -1 {
+        if self.status < 0 || self.status > 1 {
             return Err(HTTPError::new(
                 409,
-                format!("Creation status conflict, expected -1, got {}", self.status),
+                format!("Creation can not be update, status {}", self.status),
             )
             .into());
         }
 
-        let mut doc = Content::with_pk(self.content);
-        doc.update_status(db, -1).await?;
-        self.updated_at = doc.updated_at;
-
-        let fields = Self::fields();
-        self._fields = fields.iter().map(|f| f.to_string()).collect();
-
-        let mut cols_name: Vec<&str> = Vec::with_capacity(fields.len());
-        let mut vals_name: Vec<&str> = Vec::with_capacity(fields.len());
-        let mut insert_params: Vec<&CqlValue> = Vec::with_capacity(fields.len());
-        let cols = self.to();
+        self.replace_content(db, &cols, content, updated_at, "update")
+            .await?;
 
-        for field in &fields {
-            cols_name.push(field);
-            vals_name.push("?");
-            insert_params.push(cols.get(field).unwrap());
-        }
+        let logs = Changelog::from_update(self.gid, actor, 0, self.id, &self.to(), &cols);
+        let _ = Changelog::record(db, logs).await;
+        self.fill(&cols);
+        Ok(true)
+    }
 
-        let insert_query = format!(
-            "INSERT INTO deleted_creation ({}) VALUES ({})",
-            cols_name.join(","),
-            vals_name.join(","),
+    // moves the creation's entry in creation_by_updated from old_updated_at to updated_at,
+    // the old entry is removed so that an edited creation is listed only once.
+    // An entry left behind is skipped by list_by_gid_updated as its updated_at is outdated.
+    // The update is applied already, so a failure is logged instead of failing it.
+    async fn touch_updated(&self, db: &scylladb::ScyllaDB, old_updated_at: i64) {
+        let delete_query = "DELETE FROM creation_by_updated WHERE gid=? AND updated_day=? AND updated_at=? AND id=?";
+        let delete_params = (
+            self.gid.to_cql(),
+            unix_ms_day(old_updated_at),
+            old_updated_at,
+            self.id.to_cql(),
+        );
+        let insert_query =
+            "INSERT INTO creation_by_updated (gid,updated_day,updated_at,id) VALUES (?,?,?,?)";
+        let insert_params = (
+            self.gid.to_cql(),
+            unix_ms_day(self.updated_at),
+            self.updated_at,
+            self.id.to_cql(),
         );
 
-        let delete_query = "DELETE FROM creation WHERE gid=? AND id=?";
-        let delete_params = (self.gid.to_cql(), self.id.to_cql());
-
-        let _ = db
-            .batch(
-                vec![insert_query.as_str(), delete_query],
-                (insert_params, delete_params),
+        if let Err(err) = db
+            .batch_idempotent(
+                vec![delete_query, insert_query],
+                (delete_params, insert_params),
             )
-            .await?;
-        Ok(true)
+            .await
+        {
+            log::warn!(target: "scylladb",
+                action = "touch_updated",
+                gid = self.gid.to_string(),
+                id = self.id.to_string();
+                "{}", err.to_string(),
+            );
+        }
     }
 
-    pub async fn list_by_gid(
+    // moves the creation's entry in creation_by_status from old_status to the current status.
+    async fn index_status(
+        &self,
+        db: &scylladb::ScyllaDB,
+        old_status: Option<i8>,
+    ) -> anyhow::Result<()> {
+        let insert_query = "INSERT INTO creation_by_status (gid,status,id) VALUES (?,?,?)";
+        let insert_params = (self.gid.to_cql(), self.status, self.id.to_cql());
+        match old_status {
+            Some(old_status) => {
+                let delete_query =
+                    "DELETE FROM creation_by_status WHERE gid=? AND status=? AND id=?";
+                let delete_params = (self.gid.to_cql(), old_status, self.id.to_cql());
+                let _ = db
+                    .batch_idempotent(
+                        vec![delete_query, insert_query],
+                        (delete_params, insert_params),
+                    )
+                    .await?;
+            }
+            None => {
+                let _ = db.execute(insert_query, insert_params).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // backfills creation_by_status with the creations saved before it, and marks the group
+    // as backfilled. Returns false if the group has more than MAX_STATUS_BACKFILL creations.
+    async fn backfill_status(db: &scylladb::ScyllaDB, gid: xid::Id) -> anyhow::Result<bool> {
+        let ready_query = "SELECT id FROM creation_by_status WHERE gid=? AND status=? LIMIT 1";
+        let rows = db
+            .execute_iter(ready_query, (gid.to_cql(), STATUS_INDEX_READY))
+            .await?;
+        if !rows.is_empty() {
+            return Ok(true);
+        }
+
+        let fields = vec!["id".to_string(), "status".to_string()];
+        let query = "SELECT id,status FROM creation WHERE gid=? AND id<? LIMIT ?";
+        let scan_size = 1000i32;
+        let mut token = MAX_ID;
+        let mut entries: Vec<(i8, xid::Id)> = Vec::new();
+        loop {
+            let params = (gid.to_cql(), token.to_cql(), scan_size);
+            let rows = db.execute_iter(query, params).await?;
+            let exhausted = rows.len() < scan_size as usize;
+            for row in rows {
+                let mut cols = ColumnsMap::with_capacity(fields.len());
+                cols.fill(row, &fields)?;
+                token = cols.get_as("id")?;
+                entries.push((cols.get_as("status")?, token));
+            }
+            if entries.len() > MAX_STATUS_BACKFILL {
+                return Ok(false);
+            }
+            if exhausted {
+                break;
+            }
+        }
+
+        entries.sort_by_key(|v| v.0);
+        let query = "INSERT INTO creation_by_status (gid,status,id) VALUES (?,?,?)";
+        let items: Vec<BatchItem> = entries
+            .iter()
+            .map(|(status, id)| BatchItem {
+                statement: query,
+                values: vec![gid.to_cql(), CqlValue::TinyInt(*status), id.to_cql()],
+                partition: format!("{}:{}", gid, status),
+            })
+            .collect();
+        let _ = db.batch_guarded(items).await?;
+        // the marker is written last, a failed backfill is retried by the next listing.
+        let _ = db
+            .execute(query, (gid.to_cql(), STATUS_INDEX_READY, MAX_ID.to_cql()))
+            .await?;
+        Ok(true)
+    }
+
+    // delete moves the row into the deleted table and archives its content and its history.
+    // With dry_run, it only runs the read and validation path and returns the planned actions,
+    // nothing is written.
+    pub async fn delete(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        dry_run: bool,
+    ) -> anyhow::Result<DeletePlan> {
+        let res = self.get_one(db, Vec::new()).await;
+        if res.is_err() {
+            return Ok(DeletePlan {
+                already_deleted: true,
+                ..Default::default()
+            });
+        }
+
+        if self.status != -1 {
+            return Err(HTTPError::new(
+                409,
+                format!("Creation status conflict, expected -1, got {}", self.status),
+            )
+            .into());
+        }
+
+        let plan = DeletePlan {
+            content: self.content,
+            already_deleted: false,
+            would_delete: true,
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+
+        let query = "DELETE FROM creation_by_updated WHERE gid=? AND updated_day=? AND updated_at=? AND id=?";
+        let params = (
+            self.gid.to_cql(),
+            unix_ms_day(self.updated_at),
+            self.updated_at,
+            self.id.to_cql(),
+        );
+        let _ = db.execute(query, params).await;
+        let query = "DELETE FROM creation_by_status WHERE gid=? AND status=? AND id=?";
+        let params = (self.gid.to_cql(), self.status, self.id.to_cql());
+        let _ = db.execute(query, params).await;
+
+        let mut doc = Content::with_pk(self.content);
+        doc.update_status(db, -1).await?;
+        self.updated_at = doc.updated_at;
+        for id in &self.content_history {
+            let _ = Content::with_pk(*id).update_status(db, -1).await;
+        }
+
+        let fields = Self::fields();
+        self._fields = fields.iter().map(|f| f.to_string()).collect();
+
+        let mut cols_name: Vec<&str> = Vec::with_capacity(fields.len());
+        let mut vals_name: Vec<&str> = Vec::with_capacity(fields.len());
+        let mut insert_params: Vec<&CqlValue> = Vec::with_capacity(fields.len());
+        let cols = self.to();
+
+        for field in &fields {
+            cols_name.push(field);
+            vals_name.push("?");
+            insert_params.push(cols.get(field).unwrap());
+        }
+
+        let insert_query = format!(
+            "INSERT INTO deleted_creation ({}) VALUES ({})",
+            cols_name.join(","),
+            vals_name.join(","),
+        );
+
+        let delete_query = "DELETE FROM creation WHERE gid=? AND id=?";
+        let delete_params = (self.gid.to_cql(), self.id.to_cql());
+
+        let _ = db
+            .batch_idempotent(
+                vec![insert_query.as_str(), delete_query],
+                (insert_params, delete_params),
+            )
+            .await?;
+        Ok(plan)
+    }
+
+    // purges the deleted creations that were deleted before `day` (days since unix epoch),
+    // with their contents that are not referenced by any live creation or publication.
+    pub async fn purge_deleted_before(
+        db: &scylladb::ScyllaDB,
+        day: i32,
+        dry_run: bool,
+        rate: u32,
+    ) -> anyhow::Result<PurgeStats> {
+        let fields = vec![
+            "gid".to_string(),
+            "id".to_string(),
+            "updated_at".to_string(),
+            "content".to_string(),
+            "content_history".to_string(),
+        ];
+        let query = format!("SELECT {} FROM deleted_creation", fields.join(","));
+        let mut stream = db.stream(query, ()).await?;
+        let mut stats = PurgeStats::default();
+        while let Some(row) = stream.next().await {
+            let mut doc = Self::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row?, &fields)?;
+            doc.fill(&cols);
+            stats.scanned += 1;
+
+            // a creation can not be deleted before it was created
+            if xid_day(doc.id) >= day || unix_ms_day(doc.updated_at) >= day {
+                continue;
+            }
+
+            let mut contents = vec![doc.content];
+            contents.extend(doc.content_history.iter().cloned());
+            for id in contents {
+                if Content::is_referenced(db, id, doc.id).await? {
+                    stats.kept_contents += 1;
+                    continue;
+                }
+                if !dry_run {
+                    Content::with_pk(id).purge(db).await?;
+                }
+                stats.purged_contents += 1;
+            }
+
+            if !dry_run {
+                let query = "DELETE FROM deleted_creation WHERE gid=? AND id=?";
+                let params = (doc.gid.to_cql(), doc.id.to_cql());
+                let _ = db.execute(query, params).await?;
+            }
+            stats.purged += 1;
+            purge_throttle(rate).await;
+        }
+
+        Ok(stats)
+    }
+
+    // lists the group's creations by id in the `order`, the page token is the id of the last
+    // creation of the previous page.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn list_by_gid(
         db: &scylladb::ScyllaDB,
         gid: xid::Id,
         select_fields: Vec<String>,
         page_size: u16,
         page_token: Option<xid::Id>,
         status: Option<i8>,
+        include_deleted: bool,
+        order: Order,
     ) -> anyhow::Result<Vec<Creation>> {
         let fields = Self::select_fields(select_fields, true)?;
 
         let token = match page_token {
             Some(id) => id,
-            None => MAX_ID,
+            None => order.first_token(),
         };
 
-        let rows = if let Some(status) = status {
-            let query = format!(
-                "SELECT {} FROM creation WHERE gid=? AND status=? AND id<? LIMIT ? USING TIMEOUT 3s",
-                fields.clone().join(","));
-            let params = (gid.to_cql(), status, token.to_cql(), page_size as i32);
-            db.execute_iter(query, params).await?
-        } else {
+        let rows = match (status, order) {
+            (Some(status), Order::Desc) => {
+                let query = format!(
+                    "SELECT {} FROM creation WHERE gid=? AND status=? AND id<? LIMIT ?",
+                    fields.clone().join(",")
+                );
+                let params = (gid.to_cql(), status, token.to_cql(), page_size as i32);
+                db.execute_iter(query, params).await?
+            }
+            (Some(status), Order::Asc) => {
+                // the status index doesn't support ORDER BY, filters by the status range instead.
+                let query = format!(
+                    "SELECT {} FROM creation WHERE gid=? AND id>? AND status>=? AND status<=? ORDER BY id ASC LIMIT ? ALLOW FILTERING",
+                    fields.clone().join(",")
+                );
+                let params = (
+                    gid.to_cql(),
+                    token.to_cql(),
+                    status,
+                    status,
+                    page_size as i32,
+                );
+                db.execute_iter(query, params).await?
+            }
+            (None, _) if Self::backfill_status(db, gid).await? => {
+                Self::list_active_rows(db, gid, &fields, page_size, token, order).await?
+            }
+            (None, _) => {
+                let query = format!(
+                    "SELECT {} FROM creation WHERE gid=? AND {} AND status>=0{} LIMIT ? ALLOW FILTERING",
+                    fields.clone().join(","),
+                    order.after("id"),
+                    order.order_by("id")
+                );
+                let params = (gid.to_cql(), token.to_cql(), page_size as i32);
+                db.execute_iter(query, params).await?
+            }
+        };
+
+        let mut res: Vec<Creation> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut doc = Creation::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            doc._fields = fields.clone();
+            if status.is_none() && doc.status < 0 {
+                continue; // the index entry is outdated
+            }
+            res.push(doc);
+        }
+        res.sort_by(|a, b| order.compare(&a.id, &b.id));
+
+        // deleted creations are archived (status -1) before deletion.
+        if include_deleted && (status.is_none() || status == Some(-1)) {
             let query = format!(
-                "SELECT {} FROM creation WHERE gid=? AND id<? AND status>=0 LIMIT ? ALLOW FILTERING USING TIMEOUT 3s",
-                fields.clone().join(","));
+                "SELECT {} FROM deleted_creation WHERE gid=? AND {}{} LIMIT ?",
+                fields.clone().join(","),
+                order.after("id"),
+                order.order_by("id")
+            );
             let params = (gid.to_cql(), token.to_cql(), page_size as i32);
-            db.execute_iter(query, params).await?
+            let rows = db.execute_iter(query, params).await?;
+            for row in rows {
+                let mut doc = Creation::default();
+                let mut cols = ColumnsMap::with_capacity(fields.len());
+                cols.fill(row, &fields)?;
+                doc.fill(&cols);
+                doc._fields = fields.clone();
+                doc._deleted = true;
+                res.push(doc);
+            }
+
+            res.sort_by(|a, b| order.compare(&a.id, &b.id));
+            res.truncate(page_size as usize);
+        }
+
+        Ok(res)
+    }
+
+    // list_by_gid_created_between lists the creations of the group created in `[from, to)`
+    // (unix ms) by id desc. The time of a xid is in seconds, so it scans the ids of the seconds
+    // that overlap the range and filters the rows by xid_created_ms, see unix_ms_to_xid. A page
+    // may have less than page_size rows while there are more, `page_token` is the last id of
+    // the previous page.
+    pub async fn list_by_gid_created_between(
+        db: &scylladb::ScyllaDB,
+        gid: xid::Id,
+        select_fields: Vec<String>,
+        from: i64,
+        to: i64,
+        page_size: u16,
+        page_token: Option<xid::Id>,
+    ) -> anyhow::Result<(Vec<Creation>, Option<xid::Id>)> {
+        let mut fields = Self::select_fields(select_fields, true)?;
+        let field = "created_at".to_string();
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+        let start = unix_ms_to_xid(from.saturating_sub(999));
+        let end = match page_token {
+            Some(id) if id < unix_ms_to_xid(to) => id,
+            _ => unix_ms_to_xid(to),
         };
+        if start >= end {
+            return Ok((vec![], None));
+        }
+
+        let query = format!(
+            "SELECT {} FROM creation WHERE gid=? AND id>=? AND id<? AND status>=0 LIMIT ? ALLOW FILTERING",
+            fields.clone().join(",")
+        );
+        let params = (gid.to_cql(), start.to_cql(), end.to_cql(), page_size as i32);
+        let rows = db.execute_iter(query, params).await?;
+        let full = rows.len() >= page_size as usize;
 
         let mut res: Vec<Creation> = Vec::with_capacity(rows.len());
         for row in rows {
@@ -700,7 +1348,251 @@ impl Creation {
             res.push(doc);
         }
 
-        Ok(res)
+        let next = if full {
+            res.last().map(|doc| doc.id)
+        } else {
+            None
+        };
+        res.retain(|doc| (from..to).contains(&xid_created_ms(doc.id, doc.created_at)));
+        Ok((res, next))
+    }
+
+    // list_active_rows reads the creations with status >= 0 from creation_by_status, merges
+    // the ids of the statuses and then gets the rows in one query.
+    async fn list_active_rows(
+        db: &scylladb::ScyllaDB,
+        gid: xid::Id,
+        fields: &[String],
+        page_size: u16,
+        token: xid::Id,
+        order: Order,
+    ) -> anyhow::Result<Vec<scylladb::Row>> {
+        let query = format!(
+            "SELECT id FROM creation_by_status WHERE gid=? AND status=? AND {}{} LIMIT ?",
+            order.after("id"),
+            order.order_by("id")
+        );
+        let id_fields = vec!["id".to_string()];
+        let mut ids: Vec<xid::Id> = Vec::with_capacity(page_size as usize * 3);
+        for status in 0..=2i8 {
+            let params = (gid.to_cql(), status, token.to_cql(), page_size as i32);
+            for row in db.execute_iter(query.as_str(), params).await? {
+                let mut cols = ColumnsMap::with_capacity(1);
+                cols.fill(row, &id_fields)?;
+                ids.push(cols.get_as("id")?);
+            }
+        }
+        ids.sort_by(|a, b| order.compare(a, b));
+        ids.dedup();
+        ids.truncate(page_size as usize);
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = format!(
+            "SELECT {} FROM creation WHERE gid=? AND id IN ({})",
+            fields.join(","),
+            vec!["?"; ids.len()].join(",")
+        );
+        let mut params: Vec<CqlValue> = Vec::with_capacity(ids.len() + 1);
+        params.push(gid.to_cql());
+        for id in ids {
+            params.push(id.to_cql());
+        }
+        db.execute_iter(query, params).await
+    }
+
+    // list_by_gid_label lists the creations that have the label, newest first.
+    // It scans at most MAX_LABEL_SCAN rows of the group per call, so the page can be short
+    // while the returned token is still Some, the caller should continue from the token.
+    // The label is matched case-insensitively, labels saved before they were normalized can
+    // be in any case.
+    pub async fn list_by_gid_label(
+        db: &scylladb::ScyllaDB,
+        gid: xid::Id,
+        label: &str,
+        select_fields: Vec<String>,
+        page_size: u16,
+        page_token: Option<xid::Id>,
+        status: Option<i8>,
+    ) -> anyhow::Result<(Vec<Creation>, Option<xid::Id>)> {
+        let mut fields = Self::select_fields(select_fields, true)?;
+        let field = "labels".to_string();
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+
+        let query = if status.is_some() {
+            format!(
+                "SELECT {} FROM creation WHERE gid=? AND status=? AND id<? LIMIT ?",
+                fields.clone().join(","))
+        } else {
+            format!(
+                "SELECT {} FROM creation WHERE gid=? AND id<? AND status>=0 LIMIT ? ALLOW FILTERING",
+                fields.clone().join(","))
+        };
+        let scan_size = 100i32;
+        let label = label.to_lowercase();
+
+        let mut token = match page_token {
+            Some(id) => id,
+            None => MAX_ID,
+        };
+        let mut scanned = 0usize;
+        let mut res: Vec<Creation> = Vec::with_capacity(page_size as usize);
+        while scanned < MAX_LABEL_SCAN {
+            let rows = if let Some(status) = status {
+                let params = (gid.to_cql(), status, token.to_cql(), scan_size);
+                db.execute_iter(query.as_str(), params).await?
+            } else {
+                let params = (gid.to_cql(), token.to_cql(), scan_size);
+                db.execute_iter(query.as_str(), params).await?
+            };
+            let exhausted = rows.len() < scan_size as usize;
+
+            for row in rows {
+                let mut doc = Creation::default();
+                let mut cols = ColumnsMap::with_capacity(fields.len());
+                cols.fill(row, &fields)?;
+                doc.fill(&cols);
+                doc._fields = fields.clone();
+                scanned += 1;
+                token = doc.id;
+                if doc.labels.iter().any(|v| v.to_lowercase() == label) {
+                    res.push(doc);
+                    if res.len() >= page_size as usize {
+                        return Ok((res, Some(token)));
+                    }
+                }
+            }
+
+            if exhausted {
+                return Ok((res, None));
+            }
+        }
+
+        Ok((res, Some(token)))
+    }
+
+    // list_by_collaborator lists the creations that the user collaborates on, the latest
+    // created first. Creations deleted since are skipped, so the page can be short while the
+    // returned token is still Some.
+    pub async fn list_by_collaborator(
+        db: &scylladb::ScyllaDB,
+        uid: xid::Id,
+        select_fields: Vec<String>,
+        page_size: u16,
+        page_token: Option<xid::Id>,
+    ) -> anyhow::Result<(Vec<Creation>, Option<xid::Id>)> {
+        let fields = Self::select_fields(select_fields, true)?;
+        let token = match page_token {
+            Some(id) => id,
+            None => MAX_ID,
+        };
+
+        let entry_fields = vec!["cid".to_string(), "gid".to_string()];
+        let query = "SELECT cid,gid FROM creation_collaborator_index WHERE uid=? AND cid<? LIMIT ?";
+        let params = (uid.to_cql(), token.to_cql(), page_size as i32);
+        let rows = db.execute_iter(query, params).await?;
+        let exhausted = rows.len() < page_size as usize;
+
+        let mut next_token: Option<xid::Id> = None;
+        let mut res: Vec<Creation> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut cols = ColumnsMap::with_capacity(entry_fields.len());
+            cols.fill(row, &entry_fields)?;
+            let cid: xid::Id = cols.get_as("cid")?;
+            let gid: xid::Id = cols.get_as("gid")?;
+            next_token = Some(cid);
+
+            let mut doc = Creation::with_pk(gid, cid);
+            if let Err(err) = doc.get_one(db, fields.clone()).await {
+                let err: HTTPError = err.into();
+                if err.code == 404 {
+                    continue; // deleted
+                }
+                return Err(err.into());
+            }
+            res.push(doc);
+        }
+
+        if exhausted {
+            next_token = None;
+        }
+        Ok((res, next_token))
+    }
+
+    // list_by_gid_updated lists the creations edited in the last UPDATED_LIST_DAYS days,
+    // the most recently edited first. Entries of deleted creations are skipped, so the page
+    // can be short while the returned token is still Some.
+    pub async fn list_by_gid_updated(
+        db: &scylladb::ScyllaDB,
+        gid: xid::Id,
+        select_fields: Vec<String>,
+        page_size: u16,
+        page_token: Option<(i64, xid::Id)>,
+        status: Option<i8>,
+    ) -> anyhow::Result<(Vec<Creation>, Option<(i64, xid::Id)>)> {
+        let mut fields = Self::select_fields(select_fields, true)?;
+        let field = "updated_at".to_string();
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+
+        let entry_fields = vec!["updated_at".to_string(), "id".to_string()];
+        let query = "SELECT updated_at,id FROM creation_by_updated WHERE gid=? AND updated_day=? AND (updated_at,id)<(?,?) LIMIT ?";
+
+        let today = unix_ms_day(unix_ms() as i64);
+        let (mut day, mut token) = match page_token {
+            Some((updated_at, id)) => (unix_ms_day(updated_at), (updated_at, id)),
+            None => (today, (i64::MAX, MAX_ID)),
+        };
+
+        let mut res: Vec<Creation> = Vec::with_capacity(page_size as usize);
+        while day > today - UPDATED_LIST_DAYS {
+            let limit = page_size as i32;
+            let params = (gid.to_cql(), day, token.0, token.1.to_cql(), limit);
+            let rows = db.execute_iter(query, params).await?;
+            let exhausted = rows.len() < limit as usize;
+
+            for row in rows {
+                let mut cols = ColumnsMap::with_capacity(entry_fields.len());
+                cols.fill(row, &entry_fields)?;
+                let updated_at: i64 = cols.get_as("updated_at")?;
+                let id: xid::Id = cols.get_as("id")?;
+                token = (updated_at, id);
+
+                let mut doc = Creation::with_pk(gid, id);
+                if let Err(err) = doc.get_one(db, fields.clone()).await {
+                    let err: HTTPError = err.into();
+                    if err.code == 404 {
+                        continue; // deleted
+                    }
+                    return Err(err.into());
+                }
+                if doc.updated_at != updated_at {
+                    continue; // edited again, the newer entry is listed
+                }
+
+                let listed = match status {
+                    Some(status) => doc.status == status,
+                    None => doc.status >= 0,
+                };
+                if listed {
+                    res.push(doc);
+                    if res.len() >= page_size as usize {
+                        return Ok((res, Some(token)));
+                    }
+                }
+            }
+
+            if exhausted {
+                day -= 1;
+                token = (i64::MAX, MAX_ID);
+            }
+        }
+
+        Ok((res, None))
     }
 
     pub async fn list_by_gid_url(
@@ -716,7 +1608,7 @@ impl Creation {
         }
 
         let query = format!(
-                "SELECT {} FROM creation WHERE gid=? AND original_url=? LIMIT 10 BYPASS CACHE USING TIMEOUT 3s",
+                "SELECT {} FROM creation WHERE gid=? AND original_url=? LIMIT 10 BYPASS CACHE",
                 fields.clone().join(",")
             );
         let params = (gid.to_cql(), url);
@@ -768,6 +1660,122 @@ mod tests {
         creation_index_model_works().await;
         creation_model_works().await;
         creation_find_works().await;
+        content_history_works().await;
+        update_full_works().await;
+        updated_at_advances_works().await;
+        list_by_gid_label_works().await;
+        list_by_gid_updated_works().await;
+        list_by_gid_status_index_works().await;
+        list_by_gid_order_works().await;
+        list_by_collaborator_works().await;
+        purge_deleted_works().await;
+        list_by_gid_created_between_works().await;
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_by_gid_created_between_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        // the items around the day before yesterday
+        let day = unix_ms_day(unix_ms() as i64) - 2;
+        let start = day as i64 * 3600 * 24 * 1000;
+        let end = start + 3600 * 24 * 1000;
+        let mut ids: Vec<xid::Id> = Vec::new();
+        for unix_ms in [start - 1, start, start + 1, start + 1000, end - 1, end] {
+            let mut doc = Creation::with_pk(gid, db::xid_from_unix_ms(unix_ms));
+            doc.language = Language::Eng;
+            doc.title = "Hello World".to_string();
+            assert!(doc
+                .save_with_options(db, 0, content.clone(), true)
+                .await
+                .unwrap());
+            // created_at is the time of the import, sets it to the original time
+            db.execute(
+                "UPDATE creation SET created_at=? WHERE gid=? AND id=?",
+                (unix_ms, gid.to_cql(), doc.id.to_cql()),
+            )
+            .await
+            .unwrap();
+            ids.push(doc.id);
+        }
+
+        let list = |from: i64, to: i64, page_size: u16, page_token: Option<xid::Id>| {
+            Creation::list_by_gid_created_between(db, gid, vec![], from, to, page_size, page_token)
+        };
+
+        let ids_of = |docs: Vec<Creation>| docs.iter().map(|v| v.id).collect::<Vec<xid::Id>>();
+
+        let (res, next) = list(start, end, 10, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[4], ids[3], ids[2], ids[1]]);
+        assert!(next.is_none());
+
+        // the bounds are exact to the ms, also in the second of a bound
+        let (res, _) = list(start + 1, end + 1, 10, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[5], ids[4], ids[3], ids[2]]);
+        let (res, _) = list(start, start + 1, 10, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[1]]);
+        let (res, _) = list(start - 1, start + 1, 10, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[1], ids[0]]);
+        let (res, _) = list(start, end - 1, 10, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[3], ids[2], ids[1]]);
+        let (res, _) = list(end - 1, end, 10, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[4]]);
+        let (res, _) = list(start, start, 10, None).await.unwrap();
+        assert!(res.is_empty());
+
+        // pages
+        let (res, next) = list(start - 1000, end + 1000, 3, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[5], ids[4], ids[3]]);
+        assert_eq!(next, Some(ids[3]));
+        let (res, next) = list(start - 1000, end + 1000, 3, next).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[2], ids[1], ids[0]]);
+        assert_eq!(next, Some(ids[0]));
+        let (res, next) = list(start - 1000, end + 1000, 3, next).await.unwrap();
+        assert!(res.is_empty());
+        assert!(next.is_none());
+
+        // a page is short when rows of a bound second are filtered out
+        let (res, next) = list(start + 1, end, 2, None).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[4], ids[3]]);
+        assert_eq!(next, Some(ids[3]));
+        let (res, next) = list(start + 1, end, 2, next).await.unwrap();
+        assert_eq!(ids_of(res), vec![ids[2]]);
+        assert_eq!(next, Some(ids[1]));
+        let (res, next) = list(start + 1, end, 2, next).await.unwrap();
+        assert!(res.is_empty());
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn valid_new_id_works() {
+        let now = SystemTime::now();
+        let id = xid::new();
+        assert!(valid_new_id(id, false, now).is_ok());
+        assert!(valid_new_id(id, true, now).is_ok());
+
+        let old = db::xid_from_unix_ms(1_262_304_000_000); // 2010-01-01
+        let err = valid_new_id(old, false, now).unwrap_err();
+        assert_eq!(err.code, 400);
+        assert!(valid_new_id(old, true, now).is_ok());
+        // too old
+        let old = db::xid_from_unix_ms(915_148_800_000); // 1999-01-01
+        assert!(valid_new_id(old, true, now).is_err());
+        // a page token
+        let token = db::day_to_xid(xid_day(id));
+        assert!(valid_new_id(token, true, now).is_err());
+        // in the future
+        let future = db::xid_from_unix_ms(unix_ms() as i64 + 3600 * 1000);
+        assert!(valid_new_id(future, false, now).is_err());
+        assert!(valid_new_id(future, true, now).is_err());
     }
 
     // #[tokio::test(flavor = "current_thread")]
@@ -918,21 +1926,24 @@ mod tests {
             let mut doc = Creation::with_pk(gid, cid);
             let mut cols = ColumnsMap::new();
             cols.set_as("status", &2i8);
-            let res = doc.update(db, cols, 0).await;
+            let res = doc.update(db, cols, 0, xid::new()).await;
             assert!(res.is_err());
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 400); // status is not updatable
 
             let mut cols = ColumnsMap::new();
             cols.set_as("title", &"update title 1".to_string());
-            let res = doc.update(db, cols, 1).await;
+            let res = doc.update(db, cols, 1, xid::new()).await;
             assert!(res.is_err());
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 409); // updated_at not match
 
             let mut cols = ColumnsMap::new();
             cols.set_as("title", &"title 1".to_string());
-            let res = doc.update(db, cols, doc.updated_at).await.unwrap();
+            let res = doc
+                .update(db, cols, doc.updated_at, xid::new())
+                .await
+                .unwrap();
             assert!(res);
 
             let mut cols = ColumnsMap::new();
@@ -943,7 +1954,10 @@ mod tests {
             cols.set_as("labels", &vec!["label 1".to_string()]);
             cols.set_as("authors", &vec!["author 1".to_string()]);
             cols.set_as("license", &"license 2".to_string());
-            let res = doc.update(db, cols, doc.updated_at).await.unwrap();
+            let res = doc
+                .update(db, cols, doc.updated_at, xid::new())
+                .await
+                .unwrap();
             assert!(res);
         }
 
@@ -1067,17 +2081,51 @@ mod tests {
             assert_eq!(err.code, 404);
 
             let mut doc = Creation::with_pk(gid, cid);
-            let res = doc.delete(db).await;
+            let res = doc.delete(db, true).await;
             assert!(res.is_err());
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 409);
 
-            doc.update_status(db, -1, doc.updated_at).await.unwrap();
-            let res = doc.delete(db).await.unwrap();
-            assert!(res);
+            let res = doc.delete(db, false).await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 409);
+
+            doc.update_status(db, -1, doc.updated_at).await.unwrap();
+            let res = doc.delete(db, true).await.unwrap();
+            assert_eq!(
+                res,
+                DeletePlan {
+                    content: doc.content,
+                    already_deleted: false,
+                    would_delete: true,
+                }
+            );
+            let res = deleted.get_deleted(db).await;
+            assert!(res.is_err());
+            let mut content = Content::with_pk(doc.content);
+            content
+                .get_one(db, vec!["status".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(content.status, 0); // content not archived
+            let mut doc2 = Creation::with_pk(gid, cid);
+            doc2.get_one(db, vec!["status".to_string()]).await.unwrap();
+            assert_eq!(doc2.status, -1); // row intact
 
-            let res = doc.delete(db).await.unwrap();
-            assert!(!res); // already deleted
+            let res = doc.delete(db, false).await.unwrap();
+            assert_eq!(res.content, doc.content);
+            assert!(res.would_delete);
+            assert!(!res.already_deleted);
+
+            let res = doc.delete(db, false).await.unwrap();
+            assert_eq!(
+                res,
+                DeletePlan {
+                    already_deleted: true,
+                    ..Default::default()
+                }
+            );
 
             deleted.get_deleted(db).await.unwrap();
             deleted.updated_at = 0;
@@ -1112,7 +2160,7 @@ mod tests {
         }
         assert_eq!(docs.len(), 10);
 
-        let latest = Creation::list_by_gid(db, gid, Vec::new(), 1, None, None)
+        let latest = Creation::list_by_gid(db, gid, Vec::new(), 1, None, None, false, Order::Desc)
             .await
             .unwrap();
         assert_eq!(latest.len(), 1);
@@ -1124,20 +2172,47 @@ mod tests {
             .update_status(db, 1, latest.updated_at)
             .await
             .unwrap();
-        let res = Creation::list_by_gid(db, gid, vec!["title".to_string()], 100, None, None)
-            .await
-            .unwrap();
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            100,
+            None,
+            None,
+            false,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
         assert_eq!(res.len(), 10);
 
-        let res = Creation::list_by_gid(db, gid, vec!["title".to_string()], 100, None, Some(1))
-            .await
-            .unwrap();
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            100,
+            None,
+            Some(1),
+            false,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
         assert_eq!(res.len(), 1);
         assert_eq!(res[0].id, docs.last().unwrap().id);
 
-        let res = Creation::list_by_gid(db, gid, vec!["title".to_string()], 5, None, None)
-            .await
-            .unwrap();
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            5,
+            None,
+            None,
+            false,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
         assert_eq!(res.len(), 5);
         assert_eq!(res[4].id, docs[5].id);
 
@@ -1148,6 +2223,8 @@ mod tests {
             5,
             Some(docs[5].id),
             None,
+            false,
+            Order::Desc,
         )
         .await
         .unwrap();
@@ -1161,9 +2238,770 @@ mod tests {
             5,
             Some(docs[5].id),
             Some(1),
+            false,
+            Order::Desc,
         )
         .await
         .unwrap();
         assert_eq!(res.len(), 0);
+
+        // include deleted
+        let mut doc = docs[3].to_owned();
+        doc.update_status(db, -1, doc.updated_at).await.unwrap();
+        assert!(doc.delete(db, false).await.unwrap().would_delete);
+
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            100,
+            None,
+            None,
+            false,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len(), 9);
+        assert!(res.iter().all(|v| v.id != doc.id && !v._deleted));
+
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            100,
+            None,
+            None,
+            true,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len(), 10);
+        let deleted: Vec<&Creation> = res.iter().filter(|v| v._deleted).collect();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].id, doc.id);
+        assert_eq!(deleted[0].title, doc.title);
+        assert_eq!(res[6].id, doc.id); // ordered by id desc
+
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            100,
+            None,
+            Some(1),
+            true,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len(), 1);
+        assert!(!res[0]._deleted);
+
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec!["title".to_string()],
+            5,
+            Some(docs[5].id),
+            None,
+            true,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len(), 5);
+        assert_eq!(res[1].id, doc.id);
+        assert!(res[1]._deleted);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_by_collaborator_works() {
+        let db = get_db().await;
+        let uid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut docs: Vec<Creation> = Vec::new();
+        for i in 0..5 {
+            let gid = xid::new();
+            let mut doc = Creation::with_pk(gid, xid::new());
+            doc.language = Language::Eng;
+            doc.title = format!("Hello World {}", i);
+            doc.save_with(db, 0, content.clone()).await.unwrap();
+
+            let mut collaborator = db::CreationCollaborator::with_pk(doc.id, uid);
+            collaborator.role = (i % 3) as i8;
+            collaborator.save(db, gid).await.unwrap();
+            docs.push(doc)
+        }
+
+        let (res, next) =
+            Creation::list_by_collaborator(db, uid, vec!["title".to_string()], 3, None)
+                .await
+                .unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].id, docs[4].id);
+        assert_eq!(res[0].gid, docs[4].gid);
+        assert_eq!(res[0].title, docs[4].title);
+        assert_eq!(next, Some(docs[2].id));
+
+        // deleted creations are skipped
+        let mut doc = Creation::with_pk(docs[1].gid, docs[1].id);
+        doc.get_one(db, vec![]).await.unwrap();
+        doc.update_status(db, -1, doc.updated_at).await.unwrap();
+        assert!(doc.delete(db, false).await.unwrap().would_delete);
+
+        let (res, next) = Creation::list_by_collaborator(db, uid, vec![], 3, next)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].id, docs[0].id);
+        assert!(next.is_none());
+
+        // removed collaborators
+        let mut collaborator = db::CreationCollaborator::with_pk(docs[4].id, uid);
+        assert!(collaborator.delete(db).await.unwrap());
+        let (res, _) = Creation::list_by_collaborator(db, uid, vec![], 10, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].id, docs[3].id);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_by_gid_label_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut docs: Vec<Creation> = Vec::new();
+        for i in 0..10 {
+            let mut doc = Creation::with_pk(gid, xid::new());
+            doc.language = Language::Eng;
+            doc.title = format!("Hello World {}", i);
+            doc.labels = match i % 3 {
+                0 => vec!["draft-pool".to_string(), "newsletter".to_string()],
+                1 => vec!["Newsletter".to_string()],
+                _ => vec![],
+            };
+            doc.save_with(db, 0, content.clone()).await.unwrap();
+            docs.push(doc)
+        }
+
+        let (res, next) = Creation::list_by_gid_label(
+            db,
+            gid,
+            "draft-pool",
+            vec!["title".to_string()],
+            10,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.len(), 4);
+        assert!(next.is_none());
+        assert_eq!(res[0].id, docs[9].id);
+        assert_eq!(res[3].id, docs[0].id);
+        assert!(res
+            .iter()
+            .all(|v| v.labels.contains(&"newsletter".to_string())));
+
+        let (res, next) = Creation::list_by_gid_label(db, gid, "newsletter", vec![], 3, None, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].id, docs[9].id);
+        assert_eq!(res[2].id, docs[6].id);
+        assert_eq!(next, Some(docs[6].id));
+
+        let (res, next) = Creation::list_by_gid_label(db, gid, "newsletter", vec![], 3, next, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].id, docs[4].id);
+        assert_eq!(res[2].id, docs[1].id);
+
+        let (res, next) = Creation::list_by_gid_label(db, gid, "newsletter", vec![], 3, next, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].id, docs[0].id);
+        assert!(next.is_none());
+
+        let (res, next) =
+            Creation::list_by_gid_label(db, gid, "newsletter", vec![], 10, None, Some(1))
+                .await
+                .unwrap();
+        assert!(res.is_empty());
+        assert!(next.is_none());
+
+        let (res, next) = Creation::list_by_gid_label(db, gid, "nothing", vec![], 10, None, None)
+            .await
+            .unwrap();
+        assert!(res.is_empty());
+        assert!(next.is_none());
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_by_gid_status_index_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut docs: Vec<Creation> = Vec::new();
+        for i in 0..6 {
+            let mut doc = Creation::with_pk(gid, xid::new());
+            doc.language = Language::Eng;
+            doc.title = format!("Hello World {}", i);
+            doc.save_with(db, 0, content.clone()).await.unwrap();
+            docs.push(doc)
+        }
+        for (i, statuses) in [(1, vec![1]), (2, vec![1, 2]), (3, vec![-1])] {
+            for status in statuses {
+                let updated_at = docs[i].updated_at;
+                assert!(docs[i].update_status(db, status, updated_at).await.unwrap());
+            }
+        }
+
+        let filtering = move || async move {
+            let query =
+                "SELECT id FROM creation WHERE gid=? AND id<? AND status>=0 LIMIT ? ALLOW FILTERING";
+            let params = (gid.to_cql(), MAX_ID.to_cql(), 100i32);
+            let fields = vec!["id".to_string()];
+            let mut ids: Vec<xid::Id> = Vec::new();
+            for row in db.execute_iter(query, params).await.unwrap() {
+                let mut cols = ColumnsMap::with_capacity(1);
+                cols.fill(row, &fields).unwrap();
+                ids.push(cols.get_as("id").unwrap());
+            }
+            ids
+        };
+        let list = move |page_size: u16, token: Option<xid::Id>| async move {
+            Creation::list_by_gid(db, gid, vec![], page_size, token, None, false, Order::Desc)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|v| v.id)
+                .collect::<Vec<xid::Id>>()
+        };
+
+        let expected = filtering().await;
+        assert_eq!(expected.len(), 5);
+        assert!(!expected.contains(&docs[3].id));
+        assert_eq!(list(100, None).await, expected);
+
+        // paging
+        let mut ids: Vec<xid::Id> = Vec::new();
+        let mut token: Option<xid::Id> = None;
+        loop {
+            let page = list(2, token).await;
+            if page.is_empty() {
+                break;
+            }
+            token = page.last().cloned();
+            ids.extend(page);
+        }
+        assert_eq!(ids, expected);
+
+        // restored
+        let updated_at = docs[3].updated_at;
+        assert!(docs[3].update_status(db, 0, updated_at).await.unwrap());
+        let expected = filtering().await;
+        assert_eq!(expected.len(), 6);
+        assert_eq!(list(100, None).await, expected);
+
+        // a group saved before the index is backfilled on listing
+        for status in [STATUS_INDEX_READY, -1, 0, 1, 2] {
+            let query = "DELETE FROM creation_by_status WHERE gid=? AND status=?";
+            db.execute(query, (gid.to_cql(), status)).await.unwrap();
+        }
+        assert_eq!(list(100, None).await, expected);
+        let query = "SELECT id FROM creation_by_status WHERE gid=? AND status=? LIMIT 1";
+        let rows = db
+            .execute_iter(query, (gid.to_cql(), STATUS_INDEX_READY))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+    }
+
+    // lists all the pages of 2 creations in the order.
+    async fn list_all_pages(
+        db: &db::scylladb::ScyllaDB,
+        gid: xid::Id,
+        status: Option<i8>,
+        include_deleted: bool,
+        order: Order,
+    ) -> Vec<xid::Id> {
+        let mut ids: Vec<xid::Id> = Vec::new();
+        let mut token: Option<xid::Id> = None;
+        loop {
+            let page =
+                Creation::list_by_gid(db, gid, vec![], 2, token, status, include_deleted, order)
+                    .await
+                    .unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 2);
+            token = page.last().map(|v| v.id);
+            ids.extend(page.into_iter().map(|v| v.id));
+        }
+        ids
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_by_gid_order_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut docs: Vec<Creation> = Vec::new();
+        for i in 0..7 {
+            let mut doc = Creation::with_pk(gid, xid::new());
+            doc.language = Language::Eng;
+            doc.title = format!("Hello World {}", i);
+            doc.save_with(db, 0, content.clone()).await.unwrap();
+            docs.push(doc)
+        }
+        for (i, status) in [(1, 1), (2, 1), (5, 1), (4, -1)] {
+            let updated_at = docs[i].updated_at;
+            assert!(docs[i].update_status(db, status, updated_at).await.unwrap());
+        }
+        let mut deleted = docs[4].to_owned();
+        assert!(deleted.delete(db, false).await.unwrap().would_delete);
+
+        let ids = |idx: &[usize]| idx.iter().map(|i| docs[*i].id).collect::<Vec<xid::Id>>();
+        for (status, include_deleted, expected) in [
+            (None, false, ids(&[0, 1, 2, 3, 5, 6])),
+            (None, true, ids(&[0, 1, 2, 3, 4, 5, 6])),
+            (Some(1), false, ids(&[1, 2, 5])),
+        ] {
+            let asc = list_all_pages(db, gid, status, include_deleted, Order::Asc).await;
+            assert_eq!(asc, expected);
+            let mut desc = list_all_pages(db, gid, status, include_deleted, Order::Desc).await;
+            desc.reverse();
+            assert_eq!(desc, expected);
+        }
+
+        // the page token continues in the order
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec![],
+            2,
+            Some(docs[2].id),
+            None,
+            false,
+            Order::Asc,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            res.iter().map(|v| v.id).collect::<Vec<xid::Id>>(),
+            ids(&[3, 5])
+        );
+        let res = Creation::list_by_gid(
+            db,
+            gid,
+            vec![],
+            2,
+            Some(docs[2].id),
+            None,
+            false,
+            Order::Desc,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            res.iter().map(|v| v.id).collect::<Vec<xid::Id>>(),
+            ids(&[1, 0])
+        );
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn list_by_gid_updated_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut docs: Vec<Creation> = Vec::new();
+        for i in 0..5 {
+            let mut doc = Creation::with_pk(gid, xid::new());
+            doc.language = Language::Eng;
+            doc.title = format!("Hello World {}", i);
+            doc.save_with(db, 0, content.clone()).await.unwrap();
+            docs.push(doc)
+        }
+
+        // nothing was edited yet
+        let (res, next) = Creation::list_by_gid_updated(db, gid, vec![], 10, None, None)
+            .await
+            .unwrap();
+        assert!(res.is_empty());
+        assert!(next.is_none());
+
+        for doc in docs.iter_mut() {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            let mut cols = ColumnsMap::new();
+            cols.set_as("title", &format!("{} edited", doc.title));
+            let updated_at = doc.updated_at;
+            assert!(doc.update(db, cols, updated_at, xid::new()).await.unwrap());
+        }
+
+        // edited again, it is listed once at the top
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        let mut cols = ColumnsMap::new();
+        cols.set_as("summary", &"Edited again".to_string());
+        let updated_at = docs[1].updated_at;
+        assert!(docs[1]
+            .update(db, cols, updated_at, xid::new())
+            .await
+            .unwrap());
+
+        let (res, next) = Creation::list_by_gid_updated(db, gid, vec![], 3, None, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].id, docs[1].id);
+        assert_eq!(res[0].updated_at, docs[1].updated_at);
+        assert_eq!(res[1].id, docs[4].id);
+        assert_eq!(res[2].id, docs[3].id);
+        assert_eq!(next, Some((docs[3].updated_at, docs[3].id)));
+
+        let (res, next) = Creation::list_by_gid_updated(db, gid, vec![], 3, next, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].id, docs[2].id);
+        assert_eq!(res[1].id, docs[0].id);
+        assert!(next.is_none());
+
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        let updated_at = docs[4].updated_at;
+        assert!(docs[4].update_status(db, -1, updated_at).await.unwrap());
+
+        let (res, _) = Creation::list_by_gid_updated(db, gid, vec![], 10, None, None)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 4);
+        assert_eq!(res[0].id, docs[1].id);
+        assert!(res.iter().all(|v| v.id != docs[4].id));
+
+        let (res, next) = Creation::list_by_gid_updated(db, gid, vec![], 10, None, Some(-1))
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].id, docs[4].id);
+        assert_eq!(res[0].status, -1);
+        assert!(next.is_none());
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn content_history_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let text_content = |text: &str| -> Vec<u8> {
+            cbor_to_vec(
+                &cbor!({
+                    "type" => "doc",
+                    "content" => [{
+                        "type" => "text",
+                        "text" => text,
+                    }],
+                })
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut doc = Creation::with_pk(gid, xid::new());
+        doc.language = Language::Eng;
+        doc.title = "Hello World".to_string();
+        doc.save_with(db, 0, text_content("v0")).await.unwrap();
+        let first = doc.content;
+
+        let mut ids: Vec<xid::Id> = vec![first];
+        for i in 1..=MAX_CONTENT_HISTORY + 2 {
+            doc.update_content(
+                db,
+                Language::Und,
+                text_content(&format!("v{}", i)),
+                doc.updated_at,
+            )
+            .await
+            .unwrap();
+            assert_ne!(doc.content, ids[ids.len() - 1]);
+            ids.push(doc.content);
+        }
+        assert_eq!(doc.content_history.len(), MAX_CONTENT_HISTORY);
+
+        let mut doc2 = Creation::with_pk(gid, doc.id);
+        let res = doc2.list_content_history(db).await.unwrap();
+        assert_eq!(res.len(), MAX_CONTENT_HISTORY + 1);
+        assert_eq!(res[0].id, doc.content);
+        assert_eq!(res[1].id, ids[ids.len() - 2]);
+        assert!(res.iter().all(|v| v.status == 0 && v.length > 0));
+
+        // trimmed snapshots are archived
+        let mut content = Content::with_pk(first);
+        content
+            .get_one(db, vec!["status".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(content.status, -1);
+
+        // rollback
+        let target = ids[ids.len() - 3];
+        let res = doc.rollback_content(db, target, doc.updated_at - 1).await;
+        assert!(res.is_err());
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 409); // updated_at not match
+
+        let res = doc.rollback_content(db, first, doc.updated_at).await;
+        assert!(res.is_err());
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 404); // trimmed
+
+        let current = doc.content;
+        assert!(doc
+            .rollback_content(db, target, doc.updated_at)
+            .await
+            .unwrap());
+        assert_eq!(doc.content, target);
+        assert_eq!(doc.content_history[0], current);
+        assert!(!doc.content_history.contains(&target));
+        assert_eq!(&doc._content, &text_content(&format!("v{}", ids.len() - 3)));
+
+        let mut doc2 = Creation::with_pk(gid, doc.id);
+        doc2.get_one(db, vec![]).await.unwrap();
+        assert_eq!(doc2.content, target);
+        assert_eq!(&doc2._content, &doc._content);
+        assert_eq!(doc2.content_history, doc.content_history);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn update_full_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let actor = xid::new();
+        let text_content = |text: &str| -> Vec<u8> {
+            cbor_to_vec(
+                &cbor!({
+                    "type" => "doc",
+                    "content" => [{
+                        "type" => "text",
+                        "text" => text,
+                    }],
+                })
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut doc = Creation::with_pk(gid, xid::new());
+        doc.language = Language::Eng;
+        doc.title = "Hello World".to_string();
+        doc.save_with(db, 0, text_content("v0")).await.unwrap();
+        let first = doc.content;
+        let updated_at = doc.updated_at;
+
+        // invalid field
+        let mut cols = ColumnsMap::new();
+        cols.set_as("status", &2i8);
+        let res = doc
+            .update_full(db, cols, text_content("v1"), updated_at, actor)
+            .await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 400);
+
+        // stale updated_at rejects both changes
+        let mut cols = ColumnsMap::new();
+        cols.set_as("title", &"Hello Stale".to_string());
+        let res = doc
+            .update_full(db, cols, text_content("stale"), updated_at - 1, actor)
+            .await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 409);
+
+        let mut doc2 = Creation::with_pk(gid, doc.id);
+        doc2.get_one(db, vec![]).await.unwrap();
+        assert_eq!(doc2.title, "Hello World");
+        assert_eq!(doc2.content, first);
+        assert_eq!(doc2.updated_at, updated_at);
+        assert_eq!(&doc2._content, &text_content("v0"));
+
+        // both changes are applied with one updated_at bump
+        let mut cols = ColumnsMap::new();
+        cols.set_as("title", &"Hello Rust".to_string());
+        cols.set_as("summary", &"A new summary".to_string());
+        assert!(doc
+            .update_full(db, cols, text_content("v1"), updated_at, actor)
+            .await
+            .unwrap());
+        assert!(doc.updated_at > updated_at);
+        assert_ne!(doc.content, first);
+        assert_eq!(doc._words, 1);
+
+        // the snapshot in content_history still counts, the rejected one is released
+        let quota = db::GroupQuota::load(db, gid).await.unwrap();
+        assert_eq!(
+            quota.used_bytes,
+            (text_content("v0").len() + text_content("v1").len()) as i64
+        );
+
+        let mut doc2 = Creation::with_pk(gid, doc.id);
+        doc2.get_one(db, vec![]).await.unwrap();
+        assert_eq!(doc2.title, "Hello Rust");
+        assert_eq!(doc2.summary, "A new summary");
+        assert_eq!(doc2.updated_at, doc.updated_at);
+        assert_eq!(doc2.content, doc.content);
+        assert_eq!(doc2.content_history, vec![first]);
+        assert_eq!(&doc2._content, &text_content("v1"));
+
+        // the previous updated_at is stale now
+        let mut cols = ColumnsMap::new();
+        cols.set_as("title", &"Hello Again".to_string());
+        let res = doc
+            .update_full(db, cols, text_content("v2"), updated_at, actor)
+            .await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 409);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn updated_at_advances_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let actor = xid::new();
+
+        let mut doc = Creation::with_pk(gid, xid::new());
+        doc.language = Language::Eng;
+        doc.title = "Hello World".to_string();
+        let content: Vec<u8> = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        doc.save_with(db, 0, content).await.unwrap();
+
+        // the row was updated by a node with a clock one minute ahead
+        let ahead = doc.updated_at + 60 * 1000;
+        let query = "UPDATE creation SET updated_at=? WHERE gid=? AND id=?";
+        db.execute(query, (ahead, gid.to_cql(), doc.id.to_cql()))
+            .await
+            .unwrap();
+
+        let mut cols = ColumnsMap::new();
+        cols.set_as("title", &"Hello Rust".to_string());
+        assert!(doc.update(db, cols, ahead, actor).await.unwrap());
+        assert_eq!(doc.updated_at, ahead + 1);
+
+        assert!(doc.update_status(db, 1, ahead + 1).await.unwrap());
+        assert_eq!(doc.updated_at, ahead + 2);
+
+        doc.upgrade_version(db).await.unwrap();
+        assert_eq!(doc.updated_at, ahead + 3);
+
+        let mut doc2 = Creation::with_pk(gid, doc.id);
+        doc2.get_one(db, vec![]).await.unwrap();
+        assert_eq!(doc2.updated_at, ahead + 3);
+        assert_eq!(doc2.title, "Hello Rust");
+        assert_eq!(doc2.status, 1);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn purge_deleted_works() {
+        let db = get_db().await;
+        let gid = xid::new();
+        let content = cbor_to_vec(
+            &cbor!({
+                "type" => "doc",
+                "content" => [{
+                    "type" => "text",
+                    "text" => "Hello World",
+                }],
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let mut doc = Creation::with_pk(gid, xid::new());
+        doc.language = Language::Eng;
+        doc.title = "Hello World".to_string();
+        doc.save_with(db, 0, content).await.unwrap();
+        doc.update_status(db, -1, doc.updated_at).await.unwrap();
+        assert!(doc.delete(db, false).await.unwrap().would_delete);
+        assert!(!Content::is_referenced(db, doc.content, doc.id)
+            .await
+            .unwrap());
+
+        let today = unix_ms_day(unix_ms() as i64);
+        let stats = Creation::purge_deleted_before(db, today - 1, false, 0)
+            .await
+            .unwrap();
+        assert!(stats.scanned >= 1);
+        let mut deleted = Creation::with_pk(gid, doc.id);
+        deleted.get_deleted(db).await.unwrap(); // not old enough
+
+        let stats = Creation::purge_deleted_before(db, today + 1, true, 0)
+            .await
+            .unwrap();
+        assert!(stats.purged >= 1);
+        assert!(stats.purged_contents >= 1);
+        deleted.get_deleted(db).await.unwrap(); // dry run
+        Content::with_pk(doc.content)
+            .get_one(db, vec![])
+            .await
+            .unwrap();
+
+        Creation::purge_deleted_before(db, today + 1, false, 0)
+            .await
+            .unwrap();
+        let res = deleted.get_deleted(db).await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 404);
+        let res = Content::with_pk(doc.content).get_one(db, vec![]).await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 404);
     }
 }
@@ -0,0 +1,234 @@
+use axum_web::context::unix_ms;
+use axum_web::erring::HTTPError;
+use scylla_orm::{ColumnsMap, CqlValue, ToCqlVal};
+use scylla_orm_macros::CqlOrm;
+
+use crate::db::{scylladb, scylladb::extract_applied, DomainError};
+
+// how long an idempotency key is remembered, in seconds.
+pub const IDEMPOTENCY_TTL: i32 = 3600 * 24;
+// how long a key is reserved for a create in progress, in seconds. It outlives the request
+// deadline, so a crashed request doesn't block the key for long.
+pub const IDEMPOTENCY_PENDING_TTL: i32 = 60;
+
+const IDEMPOTENCY_RETRY_AFTER_MS: i64 = 1000;
+
+#[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
+pub struct Idempotency {
+    pub uid: xid::Id,
+    pub idempotency_key: String,
+    pub action: String,
+    pub payload_hash: Vec<u8>,
+    pub resource: String,  // primary key of the created resource
+    pub response: Vec<u8>, // CBOR encoded response
+    pub created_at: i64,
+
+    pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
+}
+
+impl Idempotency {
+    pub fn with_pk(uid: xid::Id, idempotency_key: String) -> Self {
+        Self {
+            uid,
+            idempotency_key,
+            ..Default::default()
+        }
+    }
+
+    pub async fn get_one(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
+        let fields = Self::fields();
+        self._fields = fields.clone();
+
+        let query = format!(
+            "SELECT {} FROM idempotency WHERE uid=? AND idempotency_key=? LIMIT 1",
+            fields.join(",")
+        );
+        let params = (self.uid.to_cql(), self.idempotency_key.to_cql());
+        let res = db.execute(query, params).await?.single_row()?;
+
+        let mut cols = ColumnsMap::with_capacity(fields.len());
+        cols.fill(res, &fields)?;
+        self.fill(&cols);
+
+        Ok(())
+    }
+
+    // a key without resource is reserved by a create in progress.
+    pub fn is_pending(&self) -> bool {
+        self.resource.is_empty()
+    }
+
+    pub fn pending_error(&self) -> HTTPError {
+        DomainError::IdempotencyPending.with_data(
+            format!(
+                "Idempotency key {} is used by a request in progress, please retry later",
+                self.idempotency_key
+            ),
+            serde_json::json!({
+                "retry_after_ms": IDEMPOTENCY_RETRY_AFTER_MS,
+            }),
+        )
+    }
+
+    // reserves the key before the create, the first writer wins, returns false if the key
+    // is reserved or saved already.
+    pub async fn reserve(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        self.created_at = unix_ms() as i64;
+        self.resource = String::new();
+        self.response = Vec::new();
+
+        let fields = Self::fields();
+        self._fields = fields.clone();
+
+        let mut cols_name: Vec<&str> = Vec::with_capacity(fields.len());
+        let mut vals_name: Vec<&str> = Vec::with_capacity(fields.len());
+        let mut params: Vec<&CqlValue> = Vec::with_capacity(fields.len());
+        let cols = self.to();
+
+        for field in &fields {
+            cols_name.push(field);
+            vals_name.push("?");
+            params.push(cols.get(field).unwrap());
+        }
+
+        let query = format!(
+            "INSERT INTO idempotency ({}) VALUES ({}) IF NOT EXISTS USING TTL {}",
+            cols_name.join(","),
+            vals_name.join(","),
+            IDEMPOTENCY_PENDING_TTL
+        );
+
+        let res = db.execute(query, params).await?;
+        Ok(extract_applied(res))
+    }
+
+    // saves the created resource and its response to the reserved key, and keeps the key
+    // for IDEMPOTENCY_TTL. Returns false if the key is not reserved by this request anymore.
+    pub async fn complete(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        resource: String,
+        response: Vec<u8>,
+    ) -> anyhow::Result<bool> {
+        if resource.is_empty() {
+            return Err(HTTPError::new(400, "Idempotency resource is empty".to_string()).into());
+        }
+
+        // all the columns are written again, the row outlives the TTL of the reservation.
+        let query = format!(
+            "UPDATE idempotency USING TTL {} SET action=?,payload_hash=?,resource=?,response=?,created_at=? WHERE uid=? AND idempotency_key=? IF resource=?",
+            IDEMPOTENCY_TTL
+        );
+        let params = (
+            self.action.to_cql(),
+            self.payload_hash.to_cql(),
+            resource.to_cql(),
+            response.to_cql(),
+            self.created_at,
+            self.uid.to_cql(),
+            self.idempotency_key.to_cql(),
+            "",
+        );
+
+        let res = db.execute(query, params).await?;
+        if !extract_applied(res) {
+            return Ok(false);
+        }
+        self.resource = resource;
+        self.response = response;
+        Ok(true)
+    }
+
+    // releases the reserved key of a failed create, so the request can be retried at once.
+    pub async fn release(&self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        let query = "DELETE FROM idempotency WHERE uid=? AND idempotency_key=? IF resource=?";
+        let params = (self.uid.to_cql(), self.idempotency_key.to_cql(), "");
+        let res = db.execute(query, params).await?;
+        Ok(extract_applied(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::OnceCell;
+
+    use crate::conf;
+    use crate::db;
+    use axum_web::erring;
+
+    use super::*;
+
+    static DB: OnceCell<db::scylladb::ScyllaDB> = OnceCell::const_new();
+
+    async fn get_db() -> &'static db::scylladb::ScyllaDB {
+        DB.get_or_init(|| async {
+            let cfg = conf::Conf::new().unwrap_or_else(|err| panic!("config error: {}", err));
+            let res = db::scylladb::ScyllaDB::new(cfg.scylla, "writing_test").await;
+            res.unwrap()
+        })
+        .await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore]
+    async fn test_all() {
+        idempotency_model_works().await;
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn idempotency_model_works() {
+        let db = get_db().await;
+        let uid = xid::new();
+        let key = xid::new().to_string();
+
+        let mut doc = Idempotency::with_pk(uid, key.clone());
+        let res = doc.get_one(db).await;
+        assert!(res.is_err());
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 404);
+
+        // two concurrent creates with the same key, only one reserves it
+        doc.action = "create_creation".to_string();
+        doc.payload_hash = vec![1, 2, 3];
+        let mut doc2 = doc.clone();
+        let (a, b) = tokio::join!(doc.reserve(db), doc2.reserve(db));
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert!(a ^ b);
+        let (mut doc, mut other) = if a { (doc, doc2) } else { (doc2, doc) };
+
+        // the other one sees the reservation in progress
+        other.get_one(db).await.unwrap();
+        assert!(other.is_pending());
+        let err = other.pending_error();
+        assert_eq!(err.code, 409);
+        assert_eq!(
+            err.data.unwrap()["error"],
+            DomainError::IdempotencyPending.code()
+        );
+
+        // a failed create releases the key, it can be reserved again
+        assert!(doc.release(db).await.unwrap());
+        assert!(other.get_one(db).await.is_err());
+        assert!(doc.reserve(db).await.unwrap());
+
+        let resource = xid::new().to_string();
+        assert!(doc
+            .complete(db, resource.clone(), vec![0xa0])
+            .await
+            .unwrap());
+        assert!(!doc.is_pending());
+        assert!(!other
+            .complete(db, xid::new().to_string(), vec![0xa1])
+            .await
+            .unwrap());
+        // a saved key is not released
+        assert!(!other.release(db).await.unwrap());
+
+        let mut doc3 = Idempotency::with_pk(uid, key);
+        doc3.get_one(db).await.unwrap();
+        assert!(!doc3.is_pending());
+        assert_eq!(doc3.resource, resource);
+        assert_eq!(doc3.response, vec![0xa0]);
+        assert_eq!(doc3.payload_hash, vec![1, 2, 3]);
+    }
+}
@@ -13,7 +13,9 @@ use axum_web::{
 use scylla_orm::{ColumnsMap, CqlValue, ToCqlVal};
 use scylla_orm_macros::CqlOrm;
 
-use crate::db::{scylladb, scylladb::extract_applied, xid_day};
+use crate::db::{
+    scylladb, scylladb::extract_applied, xid_day, MAX_MESSAGE_CONTEXT_LEN, MAX_MESSAGE_LEN,
+};
 
 pub static LANGUAGES: [&str; 158] = [
     "abk", "aar", "afr", "aka", "sqi", "amh", "ara", "arg", "hye", "asm", "ava", "aze", "bam",
@@ -31,10 +33,91 @@ pub static LANGUAGES: [&str; 158] = [
     "yor", "zul",
 ];
 
+// the message versions kept in message_history.
+pub const MAX_MESSAGE_HISTORY: i16 = 20;
+
 pub fn support_language(lang: &str) -> bool {
     LANGUAGES.contains(&lang)
 }
 
+// resolve_language returns the ISO 639-3 code of the stored i18n column for the language.
+// Individual languages are resolved to their macrolanguage if only the macrolanguage is stored,
+// e.g. `cmn` and `yue` are resolved to `zho`, `nob` is resolved to `nor`.
+pub fn resolve_language(lang: Language) -> &'static str {
+    let code = lang.to_639_3();
+    if support_language(code) {
+        return code;
+    }
+
+    match code {
+        "cmn" | "yue" | "wuu" | "hak" | "nan" | "gan" | "hsn" | "cdo" | "cjy" | "cpx" | "czh"
+        | "czo" | "mnp" | "lzh" => "zho",
+        "nob" => "nor",
+        "arb" | "arz" | "apc" | "ary" | "acm" | "ajp" | "aeb" => "ara",
+        "zsm" => "msa",
+        "pes" | "prs" => "fas",
+        "ekk" => "est",
+        "lvs" => "lav",
+        "khk" => "mon",
+        "uzn" | "uzs" => "uzb",
+        "swh" | "swc" => "swa",
+        "ydd" | "yih" => "yid",
+        "plt" => "mlg",
+        "quz" | "quy" => "que",
+        "azj" | "azb" => "aze",
+        "kmr" | "ckb" | "sdh" => "kur",
+        "gaz" => "orm",
+        "pbu" | "pst" => "pus",
+        "als" | "aln" => "sqi",
+        _ => code,
+    }
+}
+
+// the ISO 639-3 codes of the languages written right-to-left, checked before and after
+// resolve_language, e.g. `ckb` (Sorani) is RTL while `kur` is not.
+const RTL_LANGUAGES: [&str; 12] = [
+    "ara", "ckb", "div", "fas", "heb", "pus", "sdh", "snd", "syr", "uig", "urd", "yid",
+];
+
+// reading_direction returns "rtl" for the languages written right-to-left, otherwise "ltr".
+pub fn reading_direction(lang: Language) -> &'static str {
+    if RTL_LANGUAGES.contains(&lang.to_639_3()) || RTL_LANGUAGES.contains(&resolve_language(lang)) {
+        "rtl"
+    } else {
+        "ltr"
+    }
+}
+
+fn validate_context(context: &str) -> anyhow::Result<()> {
+    let len = context.chars().count();
+    if len > MAX_MESSAGE_CONTEXT_LEN {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "Message context is too long, expected <= {}, got {}",
+                MAX_MESSAGE_CONTEXT_LEN, len
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn validate_message_len(message: &[u8]) -> anyhow::Result<()> {
+    if message.len() > MAX_MESSAGE_LEN {
+        return Err(HTTPError::new(
+            400,
+            format!(
+                "Message is too long, expected <= {} bytes, got {}",
+                MAX_MESSAGE_LEN,
+                message.len()
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
 #[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
 pub struct Message {
     pub day: i32,
@@ -47,12 +130,42 @@ pub struct Message {
     pub language: Language,
     pub languages: HashSet<Language>,
     pub version: i16,
+    pub i18n_versions: HashMap<String, i16>,
     pub message: Vec<u8>,
 
     pub _i18n_messages: HashMap<String, Vec<u8>>,
     pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationState {
+    Missing,
+    Stale,
+    Current,
+}
+
+impl TranslationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranslationState::Missing => "missing",
+            TranslationState::Stale => "stale",
+            TranslationState::Current => "current",
+        }
+    }
+}
+
+// MessageHistory keeps a message value before it is overwritten by `update_message`.
+#[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
+pub struct MessageHistory {
+    pub mid: xid::Id,
+    pub version: i16,
+    pub language: Language,
+    pub updated_at: i64,
+    pub message: Vec<u8>,
+
+    pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MessageTexts {
     pub id: String, // node id in the document
@@ -133,6 +246,10 @@ impl Message {
             if !select_fields.contains(&field) {
                 select_fields.push(field);
             }
+            let field = "i18n_versions".to_string();
+            if !select_fields.contains(&field) {
+                select_fields.push(field);
+            }
             for l in LANGUAGES {
                 select_fields.push(l.to_string());
             }
@@ -181,19 +298,66 @@ impl Message {
         }
     }
 
+    // translation_status returns the state of every supported language against the primary
+    // version, with the version the language was translated at. The translations written before
+    // `i18n_versions` was tracked have no version, they are current only for the first version.
+    pub fn translation_status(&self) -> Vec<(&'static str, TranslationState, Option<i16>)> {
+        let primary = resolve_language(self.language);
+        let translated: HashSet<&str> = self
+            .languages
+            .iter()
+            .map(|l| resolve_language(*l))
+            .collect();
+
+        LANGUAGES
+            .iter()
+            .map(|lang| {
+                if *lang == primary {
+                    return (*lang, TranslationState::Current, Some(self.version));
+                }
+                if !translated.contains(lang) {
+                    return (*lang, TranslationState::Missing, None);
+                }
+                let version = self.i18n_versions.get(*lang).copied();
+                let state = if version.unwrap_or(1) >= self.version {
+                    TranslationState::Current
+                } else {
+                    TranslationState::Stale
+                };
+                (*lang, state, version)
+            })
+            .collect()
+    }
+
     pub fn to_language_message(&self) -> Vec<(Language, &[u8])> {
         let mut res: Vec<(Language, &[u8])> = Vec::with_capacity(self.languages.len() + 1);
         if !self.message.is_empty() {
             res.push((self.language, self.message.as_slice()));
         }
         for lang in &self.languages {
-            if let Some(v) = self._i18n_messages.get(lang.to_639_3()) {
+            if let Some(v) = self._i18n_messages.get(resolve_language(*lang)) {
                 res.push((lang.clone(), v.as_slice()));
             }
         }
         res
     }
 
+    // all_languages returns the primary language with the translated languages and the loaded
+    // i18n messages, one per resolved language, sorted by the resolved code.
+    pub fn all_languages(&self) -> Vec<Language> {
+        let mut res: BTreeMap<&str, Language> = BTreeMap::new();
+        res.insert(resolve_language(self.language), self.language);
+        for lang in &self.languages {
+            res.entry(resolve_language(*lang)).or_insert(*lang);
+        }
+        for code in self._i18n_messages.keys() {
+            if let Some(lang) = Language::from_639_3(code) {
+                res.entry(resolve_language(lang)).or_insert(lang);
+            }
+        }
+        res.into_values().collect()
+    }
+
     pub async fn get_one(
         &mut self,
         db: &scylladb::ScyllaDB,
@@ -247,7 +411,14 @@ impl Message {
         Ok(res)
     }
 
+    // validate checks the limits of the context and the message, for all message kinds.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        validate_context(&self.context)?;
+        validate_message_len(&self.message)
+    }
+
     pub async fn save(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<bool> {
+        self.validate()?;
         let fields = Self::fields();
         self._fields = fields.clone();
         self.updated_at = unix_ms() as i64;
@@ -295,6 +466,9 @@ impl Message {
                 return Err(HTTPError::new(400, format!("Invalid field: {}", field)).into());
             }
         }
+        if cols.has("context") {
+            validate_context(&cols.get_as::<String>("context")?)?;
+        }
 
         self.get_one(db, vec!["version".to_string()]).await?;
         if self.version != version {
@@ -314,9 +488,9 @@ impl Message {
         let new_updated_at = unix_ms() as i64;
         set_fields.push("updated_at=?".to_string());
         params.push(new_updated_at.to_cql());
-        for field in &update_fields {
+        for (field, val) in cols.ordered_pairs() {
             set_fields.push(format!("{}=?", field));
-            params.push(cols.get(field).unwrap().to_owned());
+            params.push(val.to_owned());
         }
 
         let query = format!(
@@ -346,13 +520,22 @@ impl Message {
         message: &Vec<u8>,
         version: i16,
     ) -> anyhow::Result<bool> {
-        let lang = lang.to_639_3().to_string();
-        if !LANGUAGES.contains(&lang.as_str()) {
+        let lang = resolve_language(lang).to_string();
+        if !support_language(&lang) {
             return Err(HTTPError::new(400, format!("Invalid language: {}", lang)).into());
         }
-
-        self.get_one(db, vec!["version".to_string(), "language".to_string()])
-            .await?;
+        validate_message_len(message)?;
+
+        self.get_one(
+            db,
+            vec![
+                "version".to_string(),
+                "language".to_string(),
+                "message".to_string(),
+                lang.clone(),
+            ],
+        )
+        .await?;
         if self.version != version {
             return Err(HTTPError::new(
                 409,
@@ -364,8 +547,32 @@ impl Message {
             .into());
         }
 
+        // keep the value to be overwritten
+        let is_original = lang == resolve_language(self.language);
+        let prev = if is_original {
+            std::mem::take(&mut self.message)
+        } else {
+            self._i18n_messages.remove(&lang).unwrap_or_default()
+        };
+        self._i18n_messages.clear();
+        if !prev.is_empty() {
+            let mut history = MessageHistory {
+                mid: self.id,
+                version,
+                language: if is_original {
+                    self.language
+                } else {
+                    Language::from_639_3(&lang).unwrap_or_default()
+                },
+                updated_at: self.updated_at,
+                message: prev,
+                ..Default::default()
+            };
+            history.save(db).await?;
+        }
+
         let new_updated_at = unix_ms() as i64;
-        let res = if lang == self.language.to_639_3() {
+        let res = if is_original {
             if version == 32767 {
                 return Err(HTTPError::new(
                     400,
@@ -388,13 +595,16 @@ impl Message {
             self._fields = vec!["updated_at".to_string(), "version".to_string()];
             db.execute(query, params).await?
         } else {
+            // records the primary version the translation was made at.
             let query = format!(
-                "UPDATE message SET updated_at=?,languages=languages+{{?}},{}=? WHERE day=? AND id=? IF version=?",
+                "UPDATE message SET updated_at=?,languages=languages+{{?}},i18n_versions[?]=?,{}=? WHERE day=? AND id=? IF version=?",
                 lang
             );
             let params = (
                 new_updated_at,
                 lang.to_cql(),
+                lang.to_cql(),
+                version,
                 message.to_cql(),
                 self.day,
                 self.id.to_cql(),
@@ -413,6 +623,87 @@ impl Message {
         Ok(true)
     }
 
+    pub async fn delete_language(
+        &mut self,
+        db: &scylladb::ScyllaDB,
+        lang: Language,
+        version: i16,
+    ) -> anyhow::Result<bool> {
+        let lang = resolve_language(lang).to_string();
+        if !support_language(&lang) {
+            return Err(HTTPError::new(400, format!("Invalid language: {}", lang)).into());
+        }
+
+        self.get_one(
+            db,
+            vec![
+                "version".to_string(),
+                "language".to_string(),
+                "languages".to_string(),
+            ],
+        )
+        .await?;
+        if self.version != version {
+            return Err(HTTPError::new(
+                409,
+                format!(
+                    "Message version conflict, expected {}, got {}",
+                    self.version, version
+                ),
+            )
+            .into());
+        }
+
+        if lang == resolve_language(self.language) {
+            return Err(HTTPError::new(
+                400,
+                format!(
+                    "Can not delete the primary language {}, delete the message instead",
+                    lang
+                ),
+            )
+            .into());
+        }
+
+        if !self
+            .languages
+            .iter()
+            .any(|l| resolve_language(*l) == lang.as_str())
+        {
+            return Ok(false); // no need to delete
+        }
+
+        let new_updated_at = unix_ms() as i64;
+        let query = format!(
+            "UPDATE message SET updated_at=?,languages=languages-{{?}},i18n_versions=i18n_versions-{{?}},{}=null WHERE day=? AND id=? IF version=?",
+            lang
+        );
+        let params = (
+            new_updated_at,
+            lang.to_cql(),
+            lang.to_cql(),
+            self.day,
+            self.id.to_cql(),
+            version,
+        );
+        let res = db.execute(query, params).await?;
+        if !extract_applied(res) {
+            return Err(HTTPError::new(
+                409,
+                "Message delete_language failed, please try again".to_string(),
+            )
+            .into());
+        }
+
+        self.languages
+            .retain(|l| resolve_language(*l) != lang.as_str());
+        self._i18n_messages.remove(&lang);
+        self.i18n_versions.remove(&lang);
+        self.updated_at = new_updated_at;
+        self._fields = vec!["updated_at".to_string()];
+        Ok(true)
+    }
+
     pub async fn delete(
         &mut self,
         db: &scylladb::ScyllaDB,
@@ -434,6 +725,89 @@ impl Message {
     }
 }
 
+impl MessageHistory {
+    pub fn with_pk(mid: xid::Id, version: i16, language: Language) -> Self {
+        Self {
+            mid,
+            version,
+            language,
+            ..Default::default()
+        }
+    }
+
+    pub async fn get_one(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
+        let fields = Self::fields();
+        self._fields = fields.clone();
+
+        let query = format!(
+            "SELECT {} FROM message_history WHERE mid=? AND version=? AND language=? LIMIT 1",
+            fields.join(",")
+        );
+        let params = (self.mid.to_cql(), self.version, self.language.to_cql());
+        let res = db.execute(query, params).await?.single_row()?;
+
+        let mut cols = ColumnsMap::with_capacity(fields.len());
+        cols.fill(res, &fields)?;
+        self.fill(&cols);
+
+        Ok(())
+    }
+
+    // saves the value and deletes the versions older than the last MAX_MESSAGE_HISTORY ones.
+    pub async fn save(&mut self, db: &scylladb::ScyllaDB) -> anyhow::Result<()> {
+        let fields = Self::fields();
+        self._fields = fields.clone();
+
+        let mut cols_name: Vec<&str> = Vec::with_capacity(fields.len());
+        let mut vals_name: Vec<&str> = Vec::with_capacity(fields.len());
+        let mut params: Vec<&CqlValue> = Vec::with_capacity(fields.len());
+        let cols = self.to();
+
+        for field in &fields {
+            cols_name.push(field);
+            vals_name.push("?");
+            params.push(cols.get(field).unwrap());
+        }
+
+        let query = format!(
+            "INSERT INTO message_history ({}) VALUES ({})",
+            cols_name.join(","),
+            vals_name.join(",")
+        );
+        let _ = db.execute(query, params).await?;
+
+        if self.version > MAX_MESSAGE_HISTORY {
+            let query = "DELETE FROM message_history WHERE mid=? AND version<=?";
+            let params = (self.mid.to_cql(), self.version - MAX_MESSAGE_HISTORY);
+            let _ = db.execute(query, params).await?;
+        }
+
+        Ok(())
+    }
+
+    // lists the kept values of the message, ordered by version desc.
+    pub async fn list(db: &scylladb::ScyllaDB, mid: xid::Id) -> anyhow::Result<Vec<Self>> {
+        let fields = Self::fields();
+        let query = format!(
+            "SELECT {} FROM message_history WHERE mid=? LIMIT 1000",
+            fields.join(",")
+        );
+        let rows = db.execute_iter(query, (mid.to_cql(),)).await?;
+
+        let mut res: Vec<Self> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut doc = Self::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            doc._fields = fields.clone();
+            res.push(doc);
+        }
+
+        Ok(res)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ciborium::cbor;
@@ -456,6 +830,27 @@ mod tests {
         .await
     }
 
+    #[test]
+    fn message_validate_works() {
+        let mut doc = Message {
+            context: "字".repeat(MAX_MESSAGE_CONTEXT_LEN),
+            message: vec![0; MAX_MESSAGE_LEN],
+            ..Default::default()
+        };
+        doc.validate().unwrap();
+
+        doc.context.push('a');
+        let err: erring::HTTPError = doc.validate().unwrap_err().into();
+        assert_eq!(err.code, 400);
+        assert!(err.message.contains("context"));
+
+        doc.context.pop();
+        doc.message.push(0);
+        let err: erring::HTTPError = doc.validate().unwrap_err().into();
+        assert_eq!(err.code, 400);
+        assert!(err.message.contains("Message is too long"));
+    }
+
     #[test]
     fn message_value_works() {
         let data: Vec<u8> = cbor_to_vec(
@@ -509,10 +904,99 @@ mod tests {
         assert!(MessageValue::try_from(Vec::new()).is_err());
     }
 
+    #[test]
+    fn resolve_language_works() {
+        assert_eq!(resolve_language(Language::Zho), "zho");
+        assert_eq!(resolve_language(Language::Cmn), "zho");
+        assert_eq!(resolve_language(Language::Yue), "zho");
+        assert_eq!(resolve_language(Language::Nor), "nor");
+        assert_eq!(resolve_language(Language::Nob), "nor");
+        assert_eq!(resolve_language(Language::Nno), "nno");
+        assert_eq!(resolve_language(Language::Arb), "ara");
+        assert_eq!(resolve_language(Language::Pes), "fas");
+        assert_eq!(resolve_language(Language::Eng), "eng");
+        assert_eq!(resolve_language(Language::Und), "und");
+        assert!(!support_language(resolve_language(Language::Und)));
+    }
+
+    #[test]
+    fn reading_direction_works() {
+        for lang in [
+            Language::Ara,
+            Language::Arb,
+            Language::Heb,
+            Language::Fas,
+            Language::Pes,
+            Language::Urd,
+            Language::Ckb,
+        ] {
+            assert_eq!(reading_direction(lang), "rtl", "{:?}", lang);
+        }
+        for lang in [
+            Language::Eng,
+            Language::Zho,
+            Language::Jpn,
+            Language::Kmr,
+            Language::Tur,
+            Language::Und,
+        ] {
+            assert_eq!(reading_direction(lang), "ltr", "{:?}", lang);
+        }
+    }
+
     #[tokio::test(flavor = "current_thread")]
     #[ignore]
     async fn test_all() {
         message_model_works().await;
+        message_history_works().await;
+        translation_status_works().await;
+    }
+
+    #[test]
+    fn translation_state_works() {
+        let mut doc = Message {
+            language: Language::Cmn,
+            languages: HashSet::from([Language::Eng, Language::Fra, Language::Jpn]),
+            version: 3,
+            i18n_versions: HashMap::from([("eng".to_string(), 3), ("fra".to_string(), 2)]),
+            ..Default::default()
+        };
+
+        let status: HashMap<&str, (TranslationState, Option<i16>)> = doc
+            .translation_status()
+            .into_iter()
+            .map(|(lang, state, version)| (lang, (state, version)))
+            .collect();
+        assert_eq!(status.len(), LANGUAGES.len());
+        assert_eq!(status["zho"], (TranslationState::Current, Some(3)));
+        assert_eq!(status["eng"], (TranslationState::Current, Some(3)));
+        assert_eq!(status["fra"], (TranslationState::Stale, Some(2)));
+        assert_eq!(status["deu"], (TranslationState::Missing, None));
+        // translated before the versions were tracked
+        assert_eq!(status["jpn"], (TranslationState::Stale, None));
+
+        doc.version = 1;
+        let status = doc.translation_status();
+        let jpn = status.iter().find(|v| v.0 == "jpn").unwrap();
+        assert_eq!((jpn.1, jpn.2), (TranslationState::Current, None));
+        assert_eq!(TranslationState::Stale.as_str(), "stale");
+    }
+
+    #[test]
+    fn all_languages_works() {
+        let mut doc = Message {
+            language: Language::Cmn,
+            languages: HashSet::from([Language::Eng, Language::Zho]),
+            ..Default::default()
+        };
+        assert_eq!(doc.all_languages(), vec![Language::Eng, Language::Cmn]);
+
+        doc._i18n_messages.insert("fra".to_string(), vec![0x80]);
+        doc._i18n_messages.insert("eng".to_string(), vec![0x80]);
+        assert_eq!(
+            doc.all_languages(),
+            vec![Language::Eng, Language::Fra, Language::Cmn]
+        );
     }
 
     // #[tokio::test(flavor = "current_thread")]
@@ -586,6 +1070,18 @@ mod tests {
             let err: erring::HTTPError = res.unwrap_err().into();
             assert_eq!(err.code, 409); // version not match
 
+            let mut cols = ColumnsMap::new();
+            cols.set_as("context", &"a".repeat(MAX_MESSAGE_CONTEXT_LEN + 1));
+            let res = doc.update(db, cols, 1).await;
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400); // context is too long
+
+            let res = doc
+                .update_message(db, Language::Eng, &vec![0x80; MAX_MESSAGE_LEN + 1], 1)
+                .await;
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400); // message is too long
+
             let mut cols = ColumnsMap::new();
             cols.set_as("context", &"context 1".to_string());
             let res = doc.update(db, cols, 1).await.unwrap();
@@ -642,6 +1138,52 @@ mod tests {
             assert!(res.is_err());
         }
 
+        // message_history
+        {
+            let res = MessageHistory::list(db, id).await.unwrap();
+            assert_eq!(res.len(), 1);
+            assert_eq!(res[0].version, 1);
+            assert_eq!(res[0].language, Language::Eng);
+            assert_eq!(res[0].message, message);
+        }
+
+        // delete_language
+        {
+            let mut doc = Message::with_pk(id);
+            doc.update_message(db, Language::Fra, &message, 2)
+                .await
+                .unwrap();
+            doc.update_message(db, Language::Jpn, &message, 2)
+                .await
+                .unwrap();
+
+            let res = doc.delete_language(db, Language::Fra, 1).await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 409); // version not match
+
+            let res = doc.delete_language(db, Language::Eng, 2).await;
+            assert!(res.is_err());
+            let err: erring::HTTPError = res.unwrap_err().into();
+            assert_eq!(err.code, 400); // primary language
+
+            assert!(doc.delete_language(db, Language::Fra, 2).await.unwrap());
+            assert!(!doc.delete_language(db, Language::Fra, 2).await.unwrap());
+
+            let mut doc2 = Message::with_pk(id);
+            doc2.get_one(db, vec!["i18n".to_string()]).await.unwrap();
+            assert_eq!(doc2.version, 2);
+            assert_eq!(doc2.languages.len(), 2);
+            assert!(doc2.languages.contains(&Language::Zho));
+            assert!(doc2.languages.contains(&Language::Jpn));
+            assert!(!doc2.languages.contains(&Language::Fra));
+            assert_eq!(doc2.message, message);
+            assert_eq!(doc2._i18n_messages.len(), 2);
+            assert_eq!(doc2._i18n_messages.get("zho").unwrap(), &message);
+            assert_eq!(doc2._i18n_messages.get("jpn").unwrap(), &message);
+            assert!(!doc2._i18n_messages.contains_key("fra"));
+        }
+
         // delete
         {
             let mut doc = Message::with_pk(id);
@@ -652,4 +1194,130 @@ mod tests {
             assert!(!res); // already deleted
         }
     }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn message_history_works() {
+        let db = get_db().await;
+        let id = xid::new();
+        let text = |i: i16| {
+            cbor_to_vec(
+                &cbor!([{
+                    "id" => "title",
+                    "texts" => [format!("Title {}", i)],
+                }])
+                .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let mut doc = Message::with_pk(id);
+        doc.attach_to = xid::new();
+        doc.kind = "collection".to_string();
+        doc.language = Language::Eng;
+        doc.message = text(1);
+        doc.save(db).await.unwrap();
+
+        for v in 1..=(MAX_MESSAGE_HISTORY + 2) {
+            let mut doc = Message::with_pk(id);
+            doc.update_message(db, Language::Eng, &text(v + 1), v)
+                .await
+                .unwrap();
+            assert_eq!(doc.version, v + 1);
+        }
+        let version = MAX_MESSAGE_HISTORY + 3;
+
+        // a translation is kept with the version it belongs to
+        let mut doc = Message::with_pk(id);
+        doc.update_message(db, Language::Zho, &text(100), version)
+            .await
+            .unwrap();
+        doc.update_message(db, Language::Zho, &text(101), version)
+            .await
+            .unwrap();
+
+        let res = MessageHistory::list(db, id).await.unwrap();
+        assert_eq!(res.len(), MAX_MESSAGE_HISTORY as usize);
+        assert_eq!(res[0].version, version);
+        assert_eq!(res[0].language, Language::Zho);
+        assert_eq!(res[0].message, text(100));
+        assert_eq!(res[1].version, version - 1);
+        assert_eq!(res[1].language, Language::Eng);
+        assert_eq!(res[1].message, text(version - 1));
+        let last = res.last().unwrap();
+        assert_eq!(last.version, version - MAX_MESSAGE_HISTORY + 1);
+
+        let mut history = MessageHistory::with_pk(id, 1, Language::Eng);
+        let res = history.get_one(db).await;
+        assert!(res.is_err()); // deleted
+
+        let mut history = MessageHistory::with_pk(id, 5, Language::Eng);
+        history.get_one(db).await.unwrap();
+        assert_eq!(history.message, text(5));
+        assert!(history.updated_at > 0);
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn translation_status_works() {
+        let db = get_db().await;
+        let id = xid::new();
+        let message: Vec<u8> = cbor_to_vec(
+            &cbor!([{
+                "id" => "title",
+                "texts" => ["Hello World"],
+            }])
+            .unwrap(),
+        )
+        .unwrap();
+        let state = |doc: &Message, lang: &str| -> (TranslationState, Option<i16>) {
+            let status = doc.translation_status();
+            let v = status.iter().find(|v| v.0 == lang).unwrap();
+            (v.1, v.2)
+        };
+
+        let mut doc = Message::with_pk(id);
+        doc.attach_to = xid::new();
+        doc.kind = "collection.info".to_string();
+        doc.language = Language::Eng;
+        doc.message = message.clone();
+        doc.save(db).await.unwrap();
+
+        doc.update_message(db, Language::Zho, &message, 1)
+            .await
+            .unwrap();
+        doc.update_message(db, Language::Fra, &message, 1)
+            .await
+            .unwrap();
+
+        let mut doc = Message::with_pk(id);
+        doc.get_one(db, vec!["i18n".to_string()]).await.unwrap();
+        assert_eq!(
+            doc.i18n_versions,
+            HashMap::from([("zho".to_string(), 1), ("fra".to_string(), 1)])
+        );
+        assert_eq!(state(&doc, "zho"), (TranslationState::Current, Some(1)));
+
+        // the primary message is updated after the translations
+        doc.update_message(db, Language::Eng, &message, 1)
+            .await
+            .unwrap();
+        assert_eq!(doc.version, 2);
+        // zho is translated again, fra is not
+        doc.update_message(db, Language::Zho, &message, 2)
+            .await
+            .unwrap();
+
+        let mut doc = Message::with_pk(id);
+        doc.get_one(db, vec!["i18n".to_string()]).await.unwrap();
+        assert_eq!(state(&doc, "eng"), (TranslationState::Current, Some(2)));
+        assert_eq!(state(&doc, "zho"), (TranslationState::Current, Some(2)));
+        assert_eq!(state(&doc, "fra"), (TranslationState::Stale, Some(1)));
+        assert_eq!(state(&doc, "jpn"), (TranslationState::Missing, None));
+
+        // a deleted translation is missing
+        assert!(doc.delete_language(db, Language::Fra, 2).await.unwrap());
+        let mut doc = Message::with_pk(id);
+        doc.get_one(db, vec!["i18n".to_string()]).await.unwrap();
+        assert!(!doc.i18n_versions.contains_key("fra"));
+        assert_eq!(state(&doc, "fra"), (TranslationState::Missing, None));
+    }
 }
@@ -0,0 +1,111 @@
+use scylla_orm::{CqlValue, ToCqlVal};
+
+use crate::db::scylladb;
+
+// the max days of RFP impressions that can be listed.
+pub const MAX_RFP_STATS_DAYS: i32 = 90;
+
+// daily paywall (RFP) impressions of a creation or collection.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RfpImpression {
+    pub day: i32,
+    pub impressions: i64,
+}
+
+impl RfpImpression {
+    pub async fn incr(
+        db: &scylladb::ScyllaDB,
+        cid: xid::Id,
+        day: i32,
+        count: i64,
+    ) -> anyhow::Result<()> {
+        let query = "UPDATE rfp_impression SET impressions=impressions+? WHERE cid=? AND day=?";
+        let _ = db.execute(query, (count, cid.to_cql(), day)).await?;
+        Ok(())
+    }
+
+    // lists the impressions from `from_day` (inclusive), ordered by day desc,
+    // the days without impression are absent.
+    pub async fn list(
+        db: &scylladb::ScyllaDB,
+        cid: xid::Id,
+        from_day: i32,
+    ) -> anyhow::Result<Vec<Self>> {
+        let query = "SELECT day,impressions FROM rfp_impression WHERE cid=? AND day>=? LIMIT ?";
+        let params = (cid.to_cql(), from_day, MAX_RFP_STATS_DAYS + 1);
+        let rows = db.execute_iter(query, params).await?;
+
+        let mut res: Vec<Self> = Vec::with_capacity(rows.len());
+        for row in rows {
+            match (row.columns.get(0), row.columns.get(1)) {
+                (Some(Some(CqlValue::Int(day))), Some(Some(CqlValue::Counter(v)))) => {
+                    res.push(Self {
+                        day: *day,
+                        impressions: v.0,
+                    });
+                }
+                _ => continue,
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::OnceCell;
+
+    use crate::conf;
+    use crate::db;
+
+    use super::*;
+
+    static DB: OnceCell<db::scylladb::ScyllaDB> = OnceCell::const_new();
+
+    async fn get_db() -> &'static db::scylladb::ScyllaDB {
+        DB.get_or_init(|| async {
+            let cfg = conf::Conf::new().unwrap_or_else(|err| panic!("config error: {}", err));
+            let res = db::scylladb::ScyllaDB::new(cfg.scylla, "writing_test").await;
+            res.unwrap()
+        })
+        .await
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    #[ignore]
+    async fn test_all() {
+        rfp_impression_model_works().await;
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn rfp_impression_model_works() {
+        let db = get_db().await;
+        let cid = xid::new();
+        let day = db::xid_day(cid);
+
+        assert!(RfpImpression::list(db, cid, day - 30)
+            .await
+            .unwrap()
+            .is_empty());
+
+        RfpImpression::incr(db, cid, day, 1).await.unwrap();
+        RfpImpression::incr(db, cid, day, 10).await.unwrap();
+        RfpImpression::incr(db, cid, day - 2, 1).await.unwrap();
+        RfpImpression::incr(db, cid, day - 31, 1).await.unwrap();
+
+        let res = RfpImpression::list(db, cid, day - 30).await.unwrap();
+        assert_eq!(
+            res,
+            vec![
+                RfpImpression {
+                    day,
+                    impressions: 11
+                },
+                RfpImpression {
+                    day: day - 2,
+                    impressions: 1
+                },
+            ]
+        );
+    }
+}
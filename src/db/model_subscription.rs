@@ -3,7 +3,7 @@ use axum_web::erring::HTTPError;
 use scylla_orm::{ColumnsMap, CqlValue, ToCqlVal};
 use scylla_orm_macros::CqlOrm;
 
-use crate::db::{scylladb, scylladb::extract_applied, MAX_ID};
+use crate::db::{next_updated_at, scylladb, scylladb::extract_applied, MAX_ID};
 
 #[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
 pub struct CollectionSubscription {
@@ -27,6 +27,111 @@ pub struct CreationSubscription {
     pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
 }
 
+// subscriptions indexed by expire day, for renewal reminders.
+#[derive(Debug, Default, Clone, CqlOrm, PartialEq)]
+pub struct SubscriptionExpire {
+    pub day: i32,
+    pub expire_at: i64,
+    pub kind: i8, // 0: creation, 2: collection
+    pub uid: xid::Id,
+    pub cid: xid::Id,
+
+    pub _fields: Vec<String>, // selected fields，`_` 前缀字段会被 CqlOrm 忽略
+}
+
+pub const SUBSCRIPTION_KIND_CREATION: i8 = 0;
+pub const SUBSCRIPTION_KIND_COLLECTION: i8 = 2;
+const MAX_EXPIRE_WINDOW_DAYS: i32 = 31;
+
+impl SubscriptionExpire {
+    // expire_at is unix time in seconds.
+    pub fn day_of(expire_at: i64) -> i32 {
+        (expire_at / (3600 * 24)) as i32
+    }
+
+    // moves the index row from prev_expire_at to expire_at.
+    pub async fn sync(
+        db: &scylladb::ScyllaDB,
+        kind: i8,
+        uid: xid::Id,
+        cid: xid::Id,
+        prev_expire_at: i64,
+        expire_at: i64,
+    ) -> anyhow::Result<()> {
+        if prev_expire_at == expire_at {
+            return Ok(());
+        }
+
+        if prev_expire_at > 0 {
+            let query = "DELETE FROM subscription_expire WHERE day=? AND expire_at=? AND kind=? AND uid=? AND cid=?";
+            let params = (
+                Self::day_of(prev_expire_at),
+                prev_expire_at,
+                kind,
+                uid.to_cql(),
+                cid.to_cql(),
+            );
+            let _ = db.execute(query, params).await?;
+        }
+
+        if expire_at > 0 {
+            let query =
+                "INSERT INTO subscription_expire (day,expire_at,kind,uid,cid) VALUES (?,?,?,?,?)";
+            let params = (
+                Self::day_of(expire_at),
+                expire_at,
+                kind,
+                uid.to_cql(),
+                cid.to_cql(),
+            );
+            let _ = db.execute(query, params).await?;
+        }
+
+        Ok(())
+    }
+
+    // lists subscriptions that expire in [from, to), in seconds, ordered by expire_at.
+    pub async fn list_expiring_between(
+        db: &scylladb::ScyllaDB,
+        from: i64,
+        to: i64,
+    ) -> anyhow::Result<Vec<Self>> {
+        let (from_day, to_day) = (Self::day_of(from), Self::day_of(to));
+        if from >= to || to_day - from_day > MAX_EXPIRE_WINDOW_DAYS {
+            return Err(HTTPError::new(
+                400,
+                format!(
+                    "Invalid expire window [{}, {}), should be within {} days",
+                    from, to, MAX_EXPIRE_WINDOW_DAYS
+                ),
+            )
+            .into());
+        }
+
+        let fields = Self::fields();
+        let query = format!(
+            "SELECT {} FROM subscription_expire WHERE day=? AND expire_at>=? AND expire_at<?",
+            fields.clone().join(",")
+        );
+
+        let mut res: Vec<Self> = Vec::new();
+        for day in from_day..=to_day {
+            let params = (day, from, to);
+            let rows = db.execute_iter(query.as_str(), params).await?;
+            for row in rows {
+                let mut doc = Self::default();
+                let mut cols = ColumnsMap::with_capacity(fields.len());
+                cols.fill(row, &fields)?;
+                doc.fill(&cols);
+                doc._fields = fields.clone();
+                res.push(doc);
+            }
+        }
+
+        Ok(res)
+    }
+}
+
 impl CollectionSubscription {
     pub fn with_pk(uid: xid::Id, cid: xid::Id) -> Self {
         Self {
@@ -118,6 +223,15 @@ impl CollectionSubscription {
             .into());
         }
 
+        let _ = SubscriptionExpire::sync(
+            db,
+            SUBSCRIPTION_KIND_COLLECTION,
+            self.uid,
+            self.cid,
+            0,
+            self.expire_at,
+        )
+        .await;
         Ok(true)
     }
 
@@ -128,7 +242,10 @@ impl CollectionSubscription {
         expire_at: i64,
         updated_at: i64,
     ) -> anyhow::Result<bool> {
-        let new_updated_at = unix_ms() as i64;
+        let mut prev = Self::with_pk(self.uid, self.cid);
+        let _ = prev.get_one(db, vec!["expire_at".to_string()]).await;
+
+        let new_updated_at = next_updated_at(updated_at);
         let query = "UPDATE collection_subscription SET txn=?,expire_at=?,updated_at=? WHERE uid=? AND cid=? IF updated_at=?";
         let params = (
             txn.to_cql(),
@@ -148,6 +265,16 @@ impl CollectionSubscription {
             .into());
         }
 
+        let _ = SubscriptionExpire::sync(
+            db,
+            SUBSCRIPTION_KIND_COLLECTION,
+            self.uid,
+            self.cid,
+            prev.expire_at,
+            expire_at,
+        )
+        .await;
+
         self.txn = txn;
         self.expire_at = expire_at;
         self.updated_at = new_updated_at;
@@ -169,7 +296,7 @@ impl CollectionSubscription {
         };
 
         let query = format!(
-            "SELECT {} FROM collection_subscription WHERE uid=? AND cid<? LIMIT ? USING TIMEOUT 3s",
+            "SELECT {} FROM collection_subscription WHERE uid=? AND cid<? LIMIT ?",
             fields.clone().join(",")
         );
         let params = (uid.to_cql(), token.to_cql(), page_size as i32);
@@ -187,6 +314,42 @@ impl CollectionSubscription {
 
         Ok(res)
     }
+
+    // gets the user's subscriptions of the given cids in one query.
+    pub async fn batch_get(
+        db: &scylladb::ScyllaDB,
+        uid: xid::Id,
+        cids: &[xid::Id],
+    ) -> anyhow::Result<Vec<Self>> {
+        if cids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let fields = Self::fields();
+        let query = format!(
+            "SELECT {} FROM collection_subscription WHERE uid=? AND cid IN ({})",
+            fields.clone().join(","),
+            vec!["?"; cids.len()].join(",")
+        );
+        let mut params: Vec<CqlValue> = Vec::with_capacity(cids.len() + 1);
+        params.push(uid.to_cql());
+        for cid in cids {
+            params.push(cid.to_cql());
+        }
+        let rows = db.execute_iter(query, params).await?;
+
+        let mut res: Vec<Self> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut doc = Self::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            doc._fields = fields.clone();
+            res.push(doc);
+        }
+
+        Ok(res)
+    }
 }
 
 impl CreationSubscription {
@@ -280,6 +443,15 @@ impl CreationSubscription {
             .into());
         }
 
+        let _ = SubscriptionExpire::sync(
+            db,
+            SUBSCRIPTION_KIND_CREATION,
+            self.uid,
+            self.cid,
+            0,
+            self.expire_at,
+        )
+        .await;
         Ok(true)
     }
 
@@ -290,7 +462,10 @@ impl CreationSubscription {
         expire_at: i64,
         updated_at: i64,
     ) -> anyhow::Result<bool> {
-        let new_updated_at = unix_ms() as i64;
+        let mut prev = Self::with_pk(self.uid, self.cid);
+        let _ = prev.get_one(db, vec!["expire_at".to_string()]).await;
+
+        let new_updated_at = next_updated_at(updated_at);
         let query = "UPDATE creation_subscription SET txn=?,expire_at=?,updated_at=? WHERE uid=? AND cid=? IF updated_at=?";
         let params = (
             txn.to_cql(),
@@ -310,6 +485,16 @@ impl CreationSubscription {
             .into());
         }
 
+        let _ = SubscriptionExpire::sync(
+            db,
+            SUBSCRIPTION_KIND_CREATION,
+            self.uid,
+            self.cid,
+            prev.expire_at,
+            expire_at,
+        )
+        .await;
+
         self.txn = txn;
         self.expire_at = expire_at;
         self.updated_at = new_updated_at;
@@ -331,7 +516,7 @@ impl CreationSubscription {
         };
 
         let query = format!(
-            "SELECT {} FROM creation_subscription WHERE uid=? AND cid<? LIMIT ? USING TIMEOUT 3s",
+            "SELECT {} FROM creation_subscription WHERE uid=? AND cid<? LIMIT ?",
             fields.clone().join(",")
         );
         let params = (uid.to_cql(), token.to_cql(), page_size as i32);
@@ -349,6 +534,42 @@ impl CreationSubscription {
 
         Ok(res)
     }
+
+    // gets the user's subscriptions of the given cids in one query.
+    pub async fn batch_get(
+        db: &scylladb::ScyllaDB,
+        uid: xid::Id,
+        cids: &[xid::Id],
+    ) -> anyhow::Result<Vec<Self>> {
+        if cids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let fields = Self::fields();
+        let query = format!(
+            "SELECT {} FROM creation_subscription WHERE uid=? AND cid IN ({})",
+            fields.clone().join(","),
+            vec!["?"; cids.len()].join(",")
+        );
+        let mut params: Vec<CqlValue> = Vec::with_capacity(cids.len() + 1);
+        params.push(uid.to_cql());
+        for cid in cids {
+            params.push(cid.to_cql());
+        }
+        let rows = db.execute_iter(query, params).await?;
+
+        let mut res: Vec<Self> = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut doc = Self::default();
+            let mut cols = ColumnsMap::with_capacity(fields.len());
+            cols.fill(row, &fields)?;
+            doc.fill(&cols);
+            doc._fields = fields.clone();
+            res.push(doc);
+        }
+
+        Ok(res)
+    }
 }
 
 #[cfg(test)]
@@ -376,6 +597,8 @@ mod tests {
     async fn test_all() {
         collection_subscription_model_works().await;
         creation_subscription_model_works().await;
+        subscription_expire_works().await;
+        batch_get_works().await;
     }
 
     // #[tokio::test(flavor = "current_thread")]
@@ -497,4 +720,116 @@ mod tests {
             assert_eq!(doc.txn, txn);
         }
     }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn subscription_expire_works() {
+        let db = get_db().await;
+        let uid = xid::new();
+        let base = unix_ms() as i64 / 1000 + 3600 * 24 * 7;
+
+        let res = SubscriptionExpire::list_expiring_between(db, base, base).await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 400);
+        let res = SubscriptionExpire::list_expiring_between(db, base, base + 3600 * 24 * 40).await;
+        let err: erring::HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 400);
+
+        let offsets = [-3600i64, 0, 3600 * 12, 3600 * 24 * 2, 3600 * 24 * 3];
+        let mut cids: Vec<xid::Id> = Vec::new();
+        for (i, offset) in offsets.iter().enumerate() {
+            let cid = xid::new();
+            if i % 2 == 0 {
+                let mut doc = CreationSubscription::with_pk(uid, cid);
+                doc.txn = xid::new();
+                doc.expire_at = base + offset;
+                assert!(doc.save(db).await.unwrap());
+            } else {
+                let mut doc = CollectionSubscription::with_pk(uid, cid);
+                doc.txn = xid::new();
+                doc.expire_at = base + offset;
+                assert!(doc.save(db).await.unwrap());
+            }
+            cids.push(cid);
+        }
+
+        let list = |from: i64, to: i64| async move {
+            let res = SubscriptionExpire::list_expiring_between(db, from, to)
+                .await
+                .unwrap();
+            res.into_iter()
+                .filter(|v| v.uid == uid)
+                .map(|v| v.cid)
+                .collect::<Vec<xid::Id>>()
+        };
+
+        // [base, base + 3 days) spans 4 day buckets
+        assert_eq!(
+            list(base, base + 3600 * 24 * 3).await,
+            vec![cids[1], cids[2], cids[3]]
+        );
+        assert_eq!(list(base - 3600, base).await, vec![cids[0]]);
+
+        // extending a subscription moves it out of the window
+        let mut doc = CreationSubscription::with_pk(uid, cids[2]);
+        doc.get_one(db, vec![]).await.unwrap();
+        assert_eq!(doc.expire_at, base + 3600 * 12);
+        doc.update(db, xid::new(), base + 3600 * 24 * 10, doc.updated_at)
+            .await
+            .unwrap();
+        assert_eq!(
+            list(base, base + 3600 * 24 * 3).await,
+            vec![cids[1], cids[3]]
+        );
+        assert_eq!(
+            list(base + 3600 * 24 * 10, base + 3600 * 24 * 11).await,
+            vec![cids[2]]
+        );
+
+        let res = SubscriptionExpire::list_expiring_between(db, base, base + 3600 * 24 * 3)
+            .await
+            .unwrap();
+        let res: Vec<SubscriptionExpire> = res.into_iter().filter(|v| v.uid == uid).collect();
+        assert_eq!(res[0].kind, SUBSCRIPTION_KIND_COLLECTION);
+        assert_eq!(res[0].expire_at, base);
+        assert_eq!(res[0].day, SubscriptionExpire::day_of(base));
+    }
+
+    // #[tokio::test(flavor = "current_thread")]
+    async fn batch_get_works() {
+        let db = get_db().await;
+        let uid = xid::new();
+        let cids: Vec<xid::Id> = (0..3).map(|_| xid::new()).collect();
+
+        let res = CreationSubscription::batch_get(db, uid, &[]).await.unwrap();
+        assert!(res.is_empty());
+
+        for cid in &cids[0..2] {
+            let mut doc = CreationSubscription::with_pk(uid, *cid);
+            doc.txn = xid::new();
+            doc.expire_at = unix_ms() as i64 / 1000 + 3600;
+            assert!(doc.save(db).await.unwrap());
+        }
+        let mut doc = CollectionSubscription::with_pk(uid, cids[2]);
+        doc.txn = xid::new();
+        doc.expire_at = unix_ms() as i64 / 1000 + 3600;
+        assert!(doc.save(db).await.unwrap());
+
+        let res = CreationSubscription::batch_get(db, uid, &cids)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(res.iter().all(|v| cids[0..2].contains(&v.cid)));
+        assert!(res.iter().all(|v| v.uid == uid && v.expire_at > 0));
+
+        let res = CollectionSubscription::batch_get(db, uid, &cids)
+            .await
+            .unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].cid, cids[2]);
+
+        let res = CollectionSubscription::batch_get(db, xid::new(), &cids)
+            .await
+            .unwrap();
+        assert!(res.is_empty());
+    }
 }
@@ -1,11 +1,32 @@
-use futures::{stream::StreamExt, Stream};
+use futures::{
+    stream::{BoxStream, StreamExt},
+    Future, Stream,
+};
+use schemars::JsonSchema;
 use scylla::{
-    frame::value::{BatchValues, ValueList},
+    frame::{
+        response::result::CqlValue,
+        value::{BatchValues, ValueList},
+    },
     statement::{Consistency, SerialConsistency},
-    transport::{iterator::RowIterator, query_result::QueryResult, Compression, ExecutionProfile},
+    transport::{
+        errors::{DbError, QueryError},
+        iterator::RowIterator,
+        query_result::QueryResult,
+        Compression, ExecutionProfile,
+    },
     CachingSession, Metrics, Session, SessionBuilder,
 };
-use std::{sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 pub use scylla::{
     batch::Batch,
@@ -14,10 +35,250 @@ pub use scylla::{
     Bytes,
 };
 
+use axum_web::erring::HTTPError;
+use scylla_orm::{ColumnsMap, CqlModel};
+
 use crate::conf;
 
+// limits of one sub-batch in `batch_guarded`, well below Scylla's
+// batch_size_fail_threshold_in_kb (1024 KB by default).
+pub const MAX_BATCH_STATEMENTS: usize = 100;
+pub const MAX_BATCH_BYTES: usize = 64 * 1024;
+// a batch spans more partitions than this is an anti-pattern.
+pub const MAX_BATCH_PARTITIONS: usize = 10;
+
+// CqlTable is a CqlOrm model with the table it is stored in, see `ScyllaDB::stream_model`.
+pub trait CqlTable: CqlModel + Default {
+    const TABLE: &'static str;
+}
+
+// BatchItem is one statement of a guarded batch, `partition` identifies the partition
+// it writes to (e.g. the partition key values joined as a string).
+#[derive(Debug, Clone)]
+pub struct BatchItem<'a> {
+    pub statement: &'a str,
+    pub values: Vec<CqlValue>,
+    pub partition: String,
+}
+
+// the max number of distinct query strings tracked by QueryStats, new queries are not
+// tracked when exceeded.
+pub const MAX_QUERY_STATS: usize = 1000;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct QueryStat {
+    pub query: String,
+    pub count: u64,
+    pub rows_max: u64,
+    pub rows_mean: u64,
+}
+
+// QueryStats records the execution count and the returned rows of every query string,
+// it helps to find the queries that scan too many rows, such as ALLOW FILTERING queries.
+#[derive(Debug, Default)]
+pub struct QueryStats(Mutex<HashMap<String, (u64, u64, u64)>>); // (count, rows_total, rows_max)
+
+impl QueryStats {
+    pub fn record(&self, query: &str, rows: usize) {
+        let rows = rows as u64;
+        let mut stats = self.0.lock().unwrap();
+        match stats.get_mut(query) {
+            Some(v) => {
+                v.0 += 1;
+                v.1 += rows;
+                v.2 = v.2.max(rows);
+            }
+            None if stats.len() < MAX_QUERY_STATS => {
+                stats.insert(query.to_string(), (1, rows, rows));
+            }
+            None => {}
+        }
+    }
+
+    // returns the top `n` queries ordered by the total returned rows.
+    pub fn top(&self, n: usize) -> Vec<QueryStat> {
+        let stats = self.0.lock().unwrap();
+        let mut res: Vec<(u64, QueryStat)> = stats
+            .iter()
+            .map(|(k, v)| {
+                (
+                    v.1,
+                    QueryStat {
+                        query: k.clone(),
+                        count: v.0,
+                        rows_max: v.2,
+                        rows_mean: v.1 / v.0,
+                    },
+                )
+            })
+            .collect();
+        res.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.count.cmp(&a.1.count)));
+        res.truncate(n);
+        res.into_iter().map(|v| v.1).collect()
+    }
+}
+
+tokio::task_local! {
+    // the deadline of the current request, see `with_deadline`.
+    static DEADLINE: Instant;
+}
+
+// runs the future with a deadline, the queries executed by it share the remaining time
+// and fail fast with 504 once the deadline is exceeded.
+// Tasks spawned by the future are not bounded by it.
+pub async fn with_deadline<F: Future>(deadline: Instant, fut: F) -> F::Output {
+    DEADLINE.scope(deadline, fut).await
+}
+
+// returns the deadline of the current request, if any.
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.try_with(|v| *v).ok()
+}
+
+// the time left for the response when a day scan stops at the deadline.
+const SCAN_DEADLINE_RESERVE: Duration = Duration::from_millis(300);
+
+// why a day scan stopped before the end of its range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EarlyExit {
+    Budget,
+    Deadline,
+}
+
+// DayScan bounds a scan over the day partitions by a budget of days and by the deadline of
+// the current request, no day is started when the deadline is near so the scanned rows can
+// still be returned with a page token. It is logged as `scan_stats`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DayScan {
+    pub days: u32,
+    pub rows: usize,
+    pub early_exit: Option<EarlyExit>, // None: not stopped by the scan
+    #[serde(skip)]
+    max_days: u32,
+}
+
+impl DayScan {
+    pub fn new(max_days: u32) -> Self {
+        Self {
+            max_days,
+            ..Default::default()
+        }
+    }
+
+    // returns true if one more day can be scanned, and counts it.
+    pub fn next_day(&mut self) -> bool {
+        self.next_day_at(current_deadline(), Instant::now())
+    }
+
+    fn next_day_at(&mut self, deadline: Option<Instant>, now: Instant) -> bool {
+        if self.days >= self.max_days {
+            self.early_exit = Some(EarlyExit::Budget);
+            return false;
+        }
+        if let Some(deadline) = deadline {
+            if now + SCAN_DEADLINE_RESERVE >= deadline {
+                self.early_exit = Some(EarlyExit::Deadline);
+                return false;
+            }
+        }
+        self.days += 1;
+        true
+    }
+
+    pub fn add_rows(&mut self, rows: usize) {
+        self.rows += rows;
+    }
+
+    pub fn stopped_early(&self) -> bool {
+        self.early_exit.is_some()
+    }
+}
+
+// returns the timeout of the next query, bounded by the deadline of the current request.
+pub fn query_timeout(
+    timeout: Duration,
+    deadline: Option<Instant>,
+    now: Instant,
+) -> Option<Duration> {
+    match deadline {
+        None => Some(timeout),
+        Some(deadline) if deadline > now => Some(timeout.min(deadline - now)),
+        Some(_) => None,
+    }
+}
+
+fn deadline_exceeded() -> anyhow::Error {
+    HTTPError::new(504, "Query deadline exceeded".to_string()).into()
+}
+
+fn map_query_error(err: QueryError) -> anyhow::Error {
+    match err {
+        QueryError::RequestTimeout(_) => deadline_exceeded(),
+        err => err.into(),
+    }
+}
+
+// the max retries of an idempotent statement on transient errors.
+pub const MAX_RETRIES: u32 = 2;
+// the base backoff before a retry, doubled on every retry and jittered.
+const RETRY_BACKOFF_MS: u64 = 50;
+
+// marks the query as idempotent so that it is retried on transient errors,
+// plain SELECTs are always treated as idempotent.
+pub fn idempotent(query: impl Into<Query>) -> Query {
+    let mut query: Query = query.into();
+    query.set_is_idempotent(true);
+    query
+}
+
+// LWT statements are never retried, their outcome is unknown after a timeout.
+pub fn is_lwt(statement: &str) -> bool {
+    statement.to_uppercase().contains(" IF ")
+}
+
+pub fn is_idempotent(statement: &str, marked: bool) -> bool {
+    if is_lwt(statement) {
+        return false;
+    }
+    marked
+        || statement
+            .trim_start()
+            .get(..7)
+            .map_or(false, |v| v.eq_ignore_ascii_case("SELECT "))
+}
+
+// timeouts, overloaded and unavailable errors may succeed on retry.
+pub fn is_transient(err: &QueryError) -> bool {
+    matches!(
+        err,
+        QueryError::RequestTimeout(_)
+            | QueryError::TimeoutError
+            | QueryError::DbError(
+                DbError::Overloaded
+                    | DbError::Unavailable { .. }
+                    | DbError::ReadTimeout { .. }
+                    | DbError::WriteTimeout { .. },
+                _,
+            )
+    )
+}
+
+// exponential backoff with jitter, in `[base, 2 * base)` of the retry.
+pub fn retry_backoff(retry: u32) -> Duration {
+    let base = RETRY_BACKOFF_MS << retry.saturating_sub(1).min(10);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.subsec_nanos() as u64);
+    Duration::from_millis(base + nanos % base)
+}
+
 pub struct ScyllaDB {
     session: CachingSession,
+    stats: QueryStats,
+    query_timeout: Duration,
+    request_timeout: Duration,
+    retries: AtomicU64,
 }
 
 impl ScyllaDB {
@@ -45,20 +306,104 @@ impl ScyllaDB {
 
         Ok(Self {
             session: CachingSession::from(session, 100000),
+            stats: QueryStats::default(),
+            query_timeout: Duration::from_millis(cfg.query_timeout_ms),
+            request_timeout: Duration::from_millis(cfg.request_timeout_ms),
+            retries: AtomicU64::new(0),
         })
     }
 
+    // the number of retries on transient errors by this layer, besides the driver's.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    // waits before the next retry if the error is transient and the statement can be retried.
+    async fn should_retry(
+        &self,
+        statement: &str,
+        idempotent: bool,
+        retry: u32,
+        err: &QueryError,
+    ) -> bool {
+        if !idempotent || retry >= MAX_RETRIES || !is_transient(err) {
+            return false;
+        }
+
+        let retry = retry + 1;
+        self.retries.fetch_add(1, Ordering::Relaxed);
+        log::warn!(target: "scylladb",
+            action = "retry",
+            retry = retry,
+            statement = statement;
+            "{}", err.to_string(),
+        );
+        tokio::time::sleep(retry_backoff(retry)).await;
+        true
+    }
+
+    // the total time budget of the queries of one request.
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    fn timeout(&self) -> anyhow::Result<Duration> {
+        query_timeout(self.query_timeout, current_deadline(), Instant::now())
+            .ok_or_else(deadline_exceeded)
+    }
+
     pub fn metrics(&self) -> Arc<Metrics> {
         self.session.get_session().get_metrics()
     }
 
+    pub fn query_stats(&self, n: usize) -> Vec<QueryStat> {
+        self.stats.top(n)
+    }
+
+    // checks that the cluster answers a cheap query.
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        let _ = self.execute("SELECT now() FROM system.local", ()).await?;
+        Ok(())
+    }
+
+    // prepares the query and puts it into the statement cache, so the first `execute` of it
+    // will not pay for the preparation round trip.
+    pub async fn prepare(&self, query: impl Into<Query>) -> anyhow::Result<()> {
+        let query: Query = query.into();
+        let _ = self.session.add_prepared_statement(&query).await?;
+        Ok(())
+    }
+
     pub async fn execute(
         &self,
         query: impl Into<Query>,
         params: impl ValueList,
     ) -> anyhow::Result<QueryResult> {
-        let res = self.session.execute(query, params).await?;
-        Ok(res)
+        let mut query: Query = query.into();
+        let values = params.serialized()?.into_owned();
+        let idempotent = is_idempotent(&query.contents, query.get_is_idempotent());
+        let mut retry = 0;
+        loop {
+            query.set_request_timeout(Some(self.timeout()?));
+            match self.session.execute(query.clone(), &values).await {
+                Ok(res) => {
+                    self.stats.record(
+                        &query.contents,
+                        res.rows.as_ref().map_or(0, |rows| rows.len()),
+                    );
+                    return Ok(res);
+                }
+                Err(err) => {
+                    if !self
+                        .should_retry(&query.contents, idempotent, retry, &err)
+                        .await
+                    {
+                        return Err(map_query_error(err));
+                    }
+                    retry += 1;
+                }
+            }
+        }
     }
 
     pub async fn execute_iter(
@@ -66,14 +411,43 @@ impl ScyllaDB {
         query: impl Into<Query>,
         params: impl ValueList,
     ) -> anyhow::Result<Vec<Row>> {
-        let mut rows_stream = self.session.execute_iter(query, params).await?;
+        let mut query: Query = query.into();
+        let values = params.serialized()?.into_owned();
+        let idempotent = is_idempotent(&query.contents, query.get_is_idempotent());
+        let mut retry = 0;
+        loop {
+            let timeout = self.timeout()?;
+            query.set_request_timeout(Some(timeout));
 
-        let (capacity, _) = rows_stream.size_hint();
-        let mut rows: Vec<Row> = Vec::with_capacity(capacity);
-        while let Some(next_row) = rows_stream.next().await {
-            rows.push(next_row?);
+            // all pages of the query share the timeout.
+            let res = tokio::time::timeout(timeout, async {
+                let mut rows_stream = self.session.execute_iter(query.clone(), &values).await?;
+                let (capacity, _) = rows_stream.size_hint();
+                let mut rows: Vec<Row> = Vec::with_capacity(capacity);
+                while let Some(next_row) = rows_stream.next().await {
+                    rows.push(next_row?);
+                }
+                Ok::<Vec<Row>, QueryError>(rows)
+            })
+            .await
+            .unwrap_or_else(|_| Err(QueryError::RequestTimeout("query timeout".to_string())));
+
+            match res {
+                Ok(rows) => {
+                    self.stats.record(&query.contents, rows.len());
+                    return Ok(rows);
+                }
+                Err(err) => {
+                    if !self
+                        .should_retry(&query.contents, idempotent, retry, &err)
+                        .await
+                    {
+                        return Err(map_query_error(err));
+                    }
+                    retry += 1;
+                }
+            }
         }
-        Ok(rows)
     }
 
     pub async fn stream(
@@ -85,20 +459,154 @@ impl ScyllaDB {
         Ok(stream)
     }
 
+    // stream_model streams the rows of the model's table as typed models with all fields,
+    // `extra_where` is appended to the SELECT, e.g. "WHERE gid=?" with the params.
+    pub async fn stream_model<T: CqlTable + Send + 'static>(
+        &self,
+        extra_where: &str,
+        params: impl ValueList,
+    ) -> anyhow::Result<BoxStream<'static, anyhow::Result<T>>> {
+        let fields = T::fields();
+        let query = format!(
+            "SELECT {} FROM {} {}",
+            fields.join(","),
+            T::TABLE,
+            extra_where
+        );
+        let stream = self.stream(query.trim_end().to_string(), params).await?;
+        Ok(stream
+            .map(move |row| {
+                let mut cols = ColumnsMap::with_capacity(fields.len());
+                cols.fill(row?, &fields)?;
+                let mut doc = T::default();
+                doc.fill(&cols);
+                Ok(doc)
+            })
+            .boxed())
+    }
+
     // https://opensource.docs.scylladb.com/master/cql/dml.html#batch-statement
     // BATCH operations are only isolated within a single partition.
     // BATCH with conditions cannot span multiple tables
     pub async fn batch(
         &self,
         statements: Vec<&str>,
-        values: impl BatchValues,
+        values: impl BatchValues + Clone,
+    ) -> anyhow::Result<QueryResult> {
+        self.batch_with(statements, values, false).await
+    }
+
+    // batch_idempotent is batch that is retried on transient errors, the statements
+    // should not be LWT or counter updates.
+    pub async fn batch_idempotent(
+        &self,
+        statements: Vec<&str>,
+        values: impl BatchValues + Clone,
+    ) -> anyhow::Result<QueryResult> {
+        self.batch_with(statements, values, true).await
+    }
+
+    async fn batch_with(
+        &self,
+        statements: Vec<&str>,
+        values: impl BatchValues + Clone,
+        idempotent: bool,
     ) -> anyhow::Result<QueryResult> {
+        let idempotent = idempotent && statements.iter().all(|v| !is_lwt(v));
+        let contents = statements.join("; ");
         let mut batch: Batch = Default::default();
         for statement in statements {
             batch.append_statement(statement);
         }
-        let res = self.session.batch(&batch, values).await?;
-        Ok(res)
+        batch.set_is_idempotent(idempotent);
+
+        let mut retry = 0;
+        loop {
+            match self.session.batch(&batch, values.clone()).await {
+                Ok(res) => return Ok(res),
+                Err(err) => {
+                    if !self.should_retry(&contents, idempotent, retry, &err).await {
+                        return Err(err.into());
+                    }
+                    retry += 1;
+                }
+            }
+        }
+    }
+
+    // batch_guarded splits the items into sub-batches bounded by MAX_BATCH_STATEMENTS
+    // and MAX_BATCH_BYTES, and executes them in order. Sub-batches are not atomic with
+    // each other, so it should not be used for conditional batches.
+    // It returns the number of sub-batches executed.
+    pub async fn batch_guarded(&self, items: Vec<BatchItem<'_>>) -> anyhow::Result<usize> {
+        let ranges = split_batch(&items, MAX_BATCH_STATEMENTS, MAX_BATCH_BYTES);
+        for range in &ranges {
+            let group = &items[range.clone()];
+            let partitions = count_partitions(group);
+            if partitions > MAX_BATCH_PARTITIONS {
+                log::warn!(target: "scylladb",
+                    action = "batch",
+                    statements = group.len(),
+                    partitions = partitions;
+                    "batch spans too many partitions",
+                );
+            }
+
+            let statements: Vec<&str> = group.iter().map(|v| v.statement).collect();
+            let values: Vec<Vec<CqlValue>> = group.iter().map(|v| v.values.clone()).collect();
+            let _ = self.batch_idempotent(statements, values).await?;
+        }
+        Ok(ranges.len())
+    }
+}
+
+// split_batch returns the ranges of items for each sub-batch, every range holds at most
+// `max_statements` items and at most `max_bytes` of statements and values, unless a single
+// item exceeds it.
+pub fn split_batch(
+    items: &[BatchItem<'_>],
+    max_statements: usize,
+    max_bytes: usize,
+) -> Vec<Range<usize>> {
+    let mut res: Vec<Range<usize>> = Vec::new();
+    let mut start = 0usize;
+    let mut bytes = 0usize;
+    for (i, item) in items.iter().enumerate() {
+        let size = item.statement.len() + item.values.iter().map(cql_value_size).sum::<usize>();
+        if i > start && (i - start >= max_statements || bytes + size > max_bytes) {
+            res.push(start..i);
+            start = i;
+            bytes = 0;
+        }
+        bytes += size;
+    }
+    if start < items.len() {
+        res.push(start..items.len());
+    }
+    res
+}
+
+pub fn count_partitions(items: &[BatchItem<'_>]) -> usize {
+    items
+        .iter()
+        .map(|v| v.partition.as_str())
+        .collect::<HashSet<&str>>()
+        .len()
+}
+
+// estimated serialized size of the value.
+fn cql_value_size(val: &CqlValue) -> usize {
+    match val {
+        CqlValue::Ascii(v) | CqlValue::Text(v) => v.len(),
+        CqlValue::Blob(v) => v.len(),
+        CqlValue::List(v) | CqlValue::Set(v) => v.iter().map(cql_value_size).sum::<usize>() + 4,
+        CqlValue::Map(v) => {
+            v.iter()
+                .map(|(k, v)| cql_value_size(k) + cql_value_size(v))
+                .sum::<usize>()
+                + 4
+        }
+        _ => 8,
     }
 }
 
@@ -110,6 +618,30 @@ pub fn extract_applied(res: QueryResult) -> bool {
     res == Some(true)
 }
 
+// extract_applied_with_row is extract_applied that also returns the other columns of the
+// result row by name. When the LWT did not apply, they are the current values of the row.
+pub fn extract_applied_with_row(res: QueryResult) -> (bool, Option<ColumnsMap>) {
+    let names: Vec<String> = res.col_specs.iter().map(|c| c.name.clone()).collect();
+    let row = match res.rows.and_then(|rows| rows.into_iter().next()) {
+        Some(row) if !row.columns.is_empty() && row.columns.len() == names.len() => row,
+        _ => return (false, None),
+    };
+
+    let applied = row.columns[0].as_ref().and_then(|v| v.as_boolean()) == Some(true);
+    if row.columns.len() == 1 {
+        return (applied, None);
+    }
+
+    let mut cols = ColumnsMap::with_capacity(names.len() - 1);
+    let row = Row {
+        columns: row.columns.into_iter().skip(1).collect(),
+    };
+    match cols.fill(row, &names[1..].to_vec()) {
+        Ok(_) => (applied, Some(cols)),
+        Err(_) => (applied, None),
+    }
+}
+
 pub async fn exec_cqls(db: &ScyllaDB, cqls: &str) -> anyhow::Result<()> {
     let lines = cqls.lines();
     let mut cql = String::new();
@@ -162,6 +694,255 @@ mod tests {
         .await
     }
 
+    #[test]
+    fn extract_applied_with_row_works() {
+        use scylla::frame::response::result::{ColumnSpec, TableSpec};
+
+        let spec = |name: &str, typ: ColumnType| ColumnSpec {
+            table_spec: TableSpec {
+                ks_name: "writing_test".to_string(),
+                table_name: "creation".to_string(),
+            },
+            name: name.to_string(),
+            typ,
+        };
+        let result = |columns: Vec<Option<CqlValue>>| QueryResult {
+            rows: Some(vec![Row { columns }]),
+            col_specs: vec![
+                spec("[applied]", ColumnType::Boolean),
+                spec("status", ColumnType::TinyInt),
+                spec("updated_at", ColumnType::BigInt),
+            ],
+            ..Default::default()
+        };
+
+        // a failed CAS returns the current values
+        let (applied, row) = extract_applied_with_row(result(vec![
+            Some(CqlValue::Boolean(false)),
+            Some(CqlValue::TinyInt(2)),
+            Some(CqlValue::BigInt(1700000000000)),
+        ]));
+        assert!(!applied);
+        let row = row.unwrap();
+        assert_eq!(row.len(), 2);
+        assert_eq!(row.get_as::<i8>("status").unwrap(), 2);
+        assert_eq!(row.get_as::<i64>("updated_at").unwrap(), 1700000000000);
+        assert!(!row.has("[applied]"));
+
+        // null values are absent
+        let (applied, row) =
+            extract_applied_with_row(result(vec![Some(CqlValue::Boolean(true)), None, None]));
+        assert!(applied);
+        assert!(row.unwrap().is_empty());
+
+        // only the [applied] column
+        let res = QueryResult {
+            rows: Some(vec![Row {
+                columns: vec![Some(CqlValue::Boolean(true))],
+            }]),
+            col_specs: vec![spec("[applied]", ColumnType::Boolean)],
+            ..Default::default()
+        };
+        assert_eq!(extract_applied_with_row(res), (true, None));
+
+        // no rows
+        assert_eq!(
+            extract_applied_with_row(QueryResult::default()),
+            (false, None)
+        );
+    }
+
+    #[test]
+    fn split_batch_works() {
+        let item = |i: usize, text: &str| BatchItem {
+            statement: "INSERT INTO changelog (gid,day,id,field) VALUES (?,?,?,?)",
+            values: vec![
+                CqlValue::Blob(vec![0; 12]),
+                CqlValue::Int(1),
+                CqlValue::Blob(vec![0; 12]),
+                CqlValue::Text(text.to_string()),
+            ],
+            partition: format!("p{}", i % 20),
+        };
+
+        assert!(split_batch(&[], MAX_BATCH_STATEMENTS, MAX_BATCH_BYTES).is_empty());
+
+        // the existing insert + delete pair is not split
+        let items = vec![item(0, "a"), item(0, "b")];
+        let res = split_batch(&items, MAX_BATCH_STATEMENTS, MAX_BATCH_BYTES);
+        assert_eq!(res, vec![0..2]);
+        assert_eq!(count_partitions(&items), 1);
+
+        // bounded by statements
+        let items: Vec<BatchItem> = (0..1050).map(|i| item(i, "hello")).collect();
+        let res = split_batch(&items, MAX_BATCH_STATEMENTS, MAX_BATCH_BYTES);
+        assert_eq!(res.len(), 11);
+        assert!(res.iter().all(|r| r.len() <= MAX_BATCH_STATEMENTS));
+        assert_eq!(res.last().unwrap().to_owned(), 1000..1050);
+        assert_eq!(res.iter().map(|r| r.len()).sum::<usize>(), items.len());
+        assert_eq!(count_partitions(&items[res[0].clone()]), 20);
+
+        // bounded by bytes
+        let text = "x".repeat(10 * 1024);
+        let items: Vec<BatchItem> = (0..30).map(|i| item(i, &text)).collect();
+        let res = split_batch(&items, MAX_BATCH_STATEMENTS, MAX_BATCH_BYTES);
+        assert_eq!(res.len(), 5);
+        for r in &res {
+            assert!(r.len() <= 6);
+            let bytes: usize = items[r.clone()]
+                .iter()
+                .map(|v| v.statement.len() + v.values.iter().map(cql_value_size).sum::<usize>())
+                .sum();
+            assert!(bytes <= MAX_BATCH_BYTES);
+        }
+        // ranges are contiguous and in order
+        for w in res.windows(2) {
+            assert_eq!(w[0].end, w[1].start);
+        }
+
+        // a single oversized item is still sent alone
+        let text = "x".repeat(MAX_BATCH_BYTES * 2);
+        let items = vec![item(0, "a"), item(0, &text), item(0, "b")];
+        let res = split_batch(&items, MAX_BATCH_STATEMENTS, MAX_BATCH_BYTES);
+        assert_eq!(res, vec![0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn query_timeout_works() {
+        let timeout = Duration::from_secs(3);
+        let now = Instant::now();
+        assert_eq!(query_timeout(timeout, None, now), Some(timeout));
+        assert_eq!(
+            query_timeout(timeout, Some(now + Duration::from_secs(10)), now),
+            Some(timeout)
+        );
+        assert_eq!(
+            query_timeout(timeout, Some(now + Duration::from_millis(100)), now),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(query_timeout(timeout, Some(now), now), None);
+        assert_eq!(
+            query_timeout(timeout, Some(now), now + Duration::from_millis(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn day_scan_works() {
+        let now = Instant::now();
+        let mut scan = DayScan::new(2);
+        assert_eq!(
+            serde_json::to_value(&scan).unwrap(),
+            serde_json::json!({"days": 0, "rows": 0, "early_exit": null})
+        );
+        assert!(scan.next_day_at(None, now));
+        scan.add_rows(10);
+        assert!(scan.next_day_at(Some(now + Duration::from_secs(10)), now));
+        scan.add_rows(5);
+        assert!(!scan.stopped_early());
+        assert!(!scan.next_day_at(None, now));
+        assert_eq!(scan.days, 2);
+        assert_eq!(scan.rows, 15);
+        assert_eq!(scan.early_exit, Some(EarlyExit::Budget));
+        assert!(scan.stopped_early());
+
+        let mut scan = DayScan::new(30);
+        assert!(scan.next_day_at(Some(now + Duration::from_secs(1)), now));
+        assert!(!scan.next_day_at(Some(now + Duration::from_millis(200)), now));
+        assert_eq!(scan.days, 1);
+        assert_eq!(scan.early_exit, Some(EarlyExit::Deadline));
+        assert_eq!(
+            serde_json::to_value(&scan).unwrap(),
+            serde_json::json!({"days": 1, "rows": 0, "early_exit": "deadline"})
+        );
+
+        // no deadline without the scope
+        assert_eq!(current_deadline(), None);
+    }
+
+    #[test]
+    fn retry_works() {
+        assert!(is_idempotent("SELECT id FROM creation WHERE gid=?", false));
+        assert!(is_idempotent("  select id FROM creation", false));
+        assert!(!is_idempotent("SELECTED", false));
+        assert!(!is_idempotent("DELETE FROM creation WHERE gid=?", false));
+        assert!(is_idempotent("DELETE FROM creation WHERE gid=?", true));
+        // LWT is never retried
+        assert!(!is_idempotent(
+            "INSERT INTO creation (gid,id) VALUES (?,?) IF NOT EXISTS",
+            true
+        ));
+        assert!(!is_idempotent(
+            "UPDATE creation SET status=? WHERE gid=? AND id=? if updated_at=?",
+            true
+        ));
+        assert!(idempotent("DELETE FROM creation WHERE gid=?").get_is_idempotent());
+
+        assert!(is_transient(&QueryError::TimeoutError));
+        assert!(is_transient(&QueryError::RequestTimeout("".to_string())));
+        assert!(is_transient(&QueryError::DbError(
+            DbError::Overloaded,
+            "".to_string()
+        )));
+        assert!(!is_transient(&QueryError::DbError(
+            DbError::Invalid,
+            "".to_string()
+        )));
+
+        for retry in 1..=MAX_RETRIES {
+            let base = Duration::from_millis(RETRY_BACKOFF_MS << (retry - 1));
+            let v = retry_backoff(retry);
+            assert!(v >= base && v < base * 2, "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn query_stats_works() {
+        let stats = QueryStats::default();
+        assert!(stats.top(10).is_empty());
+
+        stats.record("SELECT a", 1);
+        stats.record("SELECT a", 3);
+        stats.record("SELECT b", 10);
+        stats.record("SELECT c", 0);
+        stats.record("SELECT c", 0);
+
+        let res = stats.top(10);
+        assert_eq!(res.len(), 3);
+        assert_eq!(
+            res[0],
+            QueryStat {
+                query: "SELECT b".to_string(),
+                count: 1,
+                rows_max: 10,
+                rows_mean: 10,
+            }
+        );
+        assert_eq!(
+            res[1],
+            QueryStat {
+                query: "SELECT a".to_string(),
+                count: 2,
+                rows_max: 3,
+                rows_mean: 2,
+            }
+        );
+        assert_eq!(res[2].query, "SELECT c");
+        assert_eq!(stats.top(1).len(), 1);
+
+        // new queries are not tracked when full
+        let stats = QueryStats::default();
+        for i in 0..MAX_QUERY_STATS {
+            stats.record(&format!("SELECT {}", i), 1);
+        }
+        stats.record("SELECT x", 100);
+        stats.record("SELECT 0", 100);
+        let res = stats.top(MAX_QUERY_STATS + 1);
+        assert_eq!(res.len(), MAX_QUERY_STATS);
+        assert_eq!(res[0].query, "SELECT 0");
+        assert!(res.iter().all(|v| v.query != "SELECT x"));
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn exec_cqls_works() {
         let db = get_db().await;
@@ -172,4 +953,28 @@ mod tests {
         let schema = std::include_str!("../../cql/schema_table.cql");
         exec_cqls(db, schema).await.unwrap();
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn with_deadline_works() {
+        let db = get_db().await;
+        let query = "SELECT now() FROM system.local";
+        db.execute(query, ()).await.unwrap();
+
+        // the queries are aborted as a whole once the tiny deadline is exceeded
+        let start = Instant::now();
+        let res = with_deadline(start + Duration::from_millis(20), async {
+            for _ in 0..10000 {
+                db.execute(query, ()).await?;
+                db.execute_iter(query, ()).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        })
+        .await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let err: HTTPError = res.unwrap_err().into();
+        assert_eq!(err.code, 504);
+
+        // no deadline out of the scope
+        db.execute(query, ()).await.unwrap();
+    }
 }
@@ -1,2 +1,4 @@
+pub mod api;
 pub mod conf;
 pub mod db;
+pub mod router;
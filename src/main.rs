@@ -11,14 +11,26 @@ mod router;
 async fn main() -> anyhow::Result<()> {
     let cfg = conf::Conf::new().unwrap_or_else(|err| panic!("config error: {}", err));
 
-    Builder::with_level(cfg.log.level.as_str())
+    let level = cfg
+        .log
+        .level_filter()
+        .unwrap_or_else(|err| panic!("config error: {}", err));
+    // the logger accepts all levels and the max level filters the records, so that the level
+    // can be changed by SIGHUP without rebuilding the logger.
+    Builder::with_level("trace")
         .with_target_writer("*", new_writer(io::stdout()))
         .init();
+    log::set_max_level(level);
+    #[cfg(unix)]
+    tokio::spawn(reload_signal());
 
     log::debug!("{:?}", cfg);
     let server_cfg = cfg.server.clone();
     let server_env = cfg.env.clone();
     let (app_state, app) = router::new(cfg).await?;
+    if server_cfg.warm_up {
+        api::warm_up(&app_state, server_cfg.warm_up_strict).await?;
+    }
 
     let addr = SocketAddr::from(([0, 0, 0, 0], server_cfg.port));
     log::info!(
@@ -36,6 +48,25 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// reload_signal reloads the config on SIGHUP, see `conf::reload`.
+#[cfg(unix)]
+async fn reload_signal() {
+    let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            log::error!("failed to install SIGHUP handler: {}", err);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        match conf::reload(&conf::Conf::file_name()) {
+            Ok(level) => log::warn!("config reloaded, log level: {}", level),
+            Err(err) => log::error!("config reload failed: {}", err),
+        }
+    }
+}
+
 async fn shutdown_signal(_app: Arc<api::AppState>, _wait_secs: usize) {
     let ctrl_c = async {
         signal::ctrl_c()
@@ -31,22 +31,57 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
 
     let scylla = db::scylladb::ScyllaDB::new(cfg.scylla, keyspace).await?;
     let meili = db::meili::MeiliSearch::new(cfg.meili).await?;
+    let quota = cfg.quota;
+    let cover = cfg.cover;
+    let search = cfg.search;
+    let content = cfg.content;
+    let metrics = cfg.metrics;
+    let publish = cfg.publish;
+    let site = cfg.site;
+    let mut models = cfg.models;
+    // the configured default should be a canonical model too
+    models.default = api::model::validate_model(&models, &models.default)?;
 
     let app_state = Arc::new(api::AppState {
         start_at: context::unix_ms(),
         scylla: Arc::new(scylla),
         meili: Arc::new(meili),
+        quota,
+        cover,
+        search,
+        content,
+        metrics,
+        publish,
+        models,
+        site,
+        jobs: Arc::new(api::job::Jobs::default()),
+        sitemaps: Arc::new(api::sitemap::SitemapCache::default()),
     });
 
+    // publishes the scheduled publications when they are due
+    tokio::spawn(api::publication::run_schedule(app_state.clone()));
+
     let mds = ServiceBuilder::new()
         .layer(CatchPanicLayer::new())
         .layer(middleware::from_fn(context::middleware))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            api::deadline,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(api::timeout::RouteTimeouts::new(&cfg.server.timeouts)),
+            api::timeout::middleware,
+        ))
         .layer(CompressionLayer::new().compress_when(SizeAbove::new(encoding::MIN_ENCODING_SIZE)));
 
     let app = Router::new()
         .route("/", routing::get(api::version))
         .route("/healthz", routing::get(api::healthz))
+        .route("/readyz", routing::get(api::readyz))
         .route("/v1/search", routing::get(api::search::search))
+        .route("/v1/openapi.json", routing::get(api::openapi::openapi))
+        .route("/v1/sitemap.xml", routing::get(api::sitemap::sitemap))
+        .merge(api::sitemap::chunk_routes())
         .route(
             "/v1/search/in_group",
             routing::get(api::search::group_search),
@@ -74,10 +109,40 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                     "/update_content",
                     routing::put(api::creation::update_content).patch(todo),
                 )
+                .route("/update_price", routing::patch(api::creation::update_price))
+                .route("/rfp_stats", routing::get(api::creation::rfp_stats))
+                .route("/quota", routing::get(api::creation::get_quota))
+                .route("/media_refs", routing::get(api::creation::media_refs))
+                .route("/batch_check", routing::post(api::creation::batch_check))
+                .route(
+                    "/content_history",
+                    routing::get(api::creation::list_content_history),
+                )
+                .route(
+                    "/rollback_content",
+                    routing::patch(api::creation::rollback_content),
+                )
                 .route(
                     "/subscription",
                     routing::put(api::creation::update_subscription)
                         .get(api::creation::get_subscription),
+                )
+                .route(
+                    "/grant_subscription",
+                    routing::post(api::creation::grant_subscription),
+                )
+                .route(
+                    "/collaborator",
+                    routing::post(api::creation::add_collaborator)
+                        .delete(api::creation::remove_collaborator),
+                )
+                .route(
+                    "/collaborators",
+                    routing::get(api::creation::list_collaborators),
+                )
+                .route(
+                    "/list_by_collaborator",
+                    routing::post(api::creation::list_by_collaborator),
                 ),
         )
         .nest(
@@ -94,17 +159,26 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                     "/implicit_get",
                     routing::get(api::publication::implicit_get),
                 )
+                .route("/import", routing::post(api::publication::import))
+                .route("/source", routing::get(api::publication::get_source))
+                .route("/epub", routing::get(api::publication::epub))
                 .route("/publish", routing::get(api::publication::get_publish_list))
                 .route(
                     "/count_publish",
                     routing::post(api::publication::count_publish),
                 )
                 .route("/list", routing::post(api::publication::list))
+                .route("/batch_get", routing::post(api::publication::batch_get))
                 .route(
                     "/list_by_gids",
                     routing::post(api::publication::list_by_gids),
                 )
                 .route("/list_latest", routing::post(api::publication::list_latest))
+                .route(
+                    "/genre_facets",
+                    routing::get(api::publication::genre_facets),
+                )
+                .route("/related", routing::get(api::publication::related))
                 .route(
                     "/update_status",
                     routing::patch(api::publication::update_status),
@@ -130,7 +204,48 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                     "/list_by_gids",
                     routing::post(api::publication::list_by_gids),
                 )
-                .route("/list_latest", routing::post(api::publication::list_latest)),
+                .route("/list_latest", routing::post(api::publication::list_latest))
+                .route(
+                    "/genre_facets",
+                    routing::get(api::publication::genre_facets),
+                )
+                .route("/related", routing::get(api::publication::related)),
+        )
+        .nest(
+            "/v1/group",
+            Router::new()
+                .route(
+                    "/quota",
+                    routing::get(api::group::get_quota).patch(api::group::update_quota),
+                )
+                .route(
+                    "/setting",
+                    routing::get(api::group::get_setting).patch(api::group::update_setting),
+                )
+                .route("/changelog", routing::get(api::group::list_changelog))
+                .route(
+                    "/purge_search",
+                    routing::get(api::group::get_purge_search).post(api::group::purge_search),
+                ),
+        )
+        .nest(
+            "/v1/content",
+            Router::new()
+                .route(
+                    "/detect_language",
+                    routing::post(api::detect::detect_language),
+                )
+                .route("/verify", routing::post(api::verify::verify_content)),
+        )
+        .nest(
+            "/v1/misc",
+            Router::new()
+                .route("/blurhash", routing::post(api::blurhash::create))
+                .route("/models", routing::get(api::model::list)),
+        )
+        .nest(
+            "/v1/debug",
+            Router::new().route("/query_stats", routing::get(api::query_stats)),
         )
         .nest(
             "/v1/message",
@@ -156,6 +271,18 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                     "/info",
                     routing::get(api::collection::get_info).patch(api::collection::update_info),
                 )
+                .route(
+                    "/info_history",
+                    routing::get(api::collection::list_info_history),
+                )
+                .route(
+                    "/restore_info",
+                    routing::patch(api::collection::restore_info),
+                )
+                .route(
+                    "/translation_status",
+                    routing::get(api::collection::translation_status),
+                )
                 .route(
                     "/update_status",
                     routing::patch(api::collection::update_status),
@@ -166,6 +293,12 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                         .patch(api::collection::update_child)
                         .delete(api::collection::remove_child),
                 )
+                .route("/child/move", routing::post(api::collection::move_children))
+                .route(
+                    "/child/search",
+                    routing::get(api::collection::search_children),
+                )
+                .route("/clone", routing::post(api::collection::clone))
                 .route("/list", routing::post(api::collection::list))
                 .route("/list_latest", routing::post(api::collection::list_latest))
                 .route(
@@ -180,6 +313,10 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                     "/subscription",
                     routing::put(api::collection::update_subscription)
                         .get(api::collection::get_subscription),
+                )
+                .route(
+                    "/grant_subscription",
+                    routing::post(api::collection::grant_subscription),
                 ),
         )
         .nest(
@@ -193,7 +330,8 @@ pub async fn new(cfg: conf::Conf) -> anyhow::Result<(Arc<api::AppState>, Router)
                         .delete(api::bookmark::delete),
                 )
                 .route("/by_cid", routing::get(api::bookmark::get_by_cid))
-                .route("/list", routing::post(api::bookmark::list)),
+                .route("/list", routing::post(api::bookmark::list))
+                .route("/batch_delete", routing::post(api::bookmark::batch_delete)),
         )
         .nest(
             "/v1/sys",
@@ -0,0 +1,390 @@
+#![cfg(feature = "integration")]
+//! API integration tests, run with `make test-integration` or:
+//!
+//! ```sh
+//! SCYLLA_NODES=127.0.0.1:9042 MEILI_URL=http://127.0.0.1:7700 cargo test --features integration --test api
+//! ```
+//!
+//! The `writing_test` keyspace is created from `cql/schema_keyspace_test.cql` and `cql/schema_table.cql`.
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use ciborium::cbor;
+use meilisearch_sdk::client::Client;
+use serde::de::DeserializeOwned;
+use std::{str::FromStr, time::Duration};
+use tokio::{sync::OnceCell, time};
+use tower::ServiceExt;
+
+use axum_web::erring::SuccessResponse;
+use writing::{api, conf, db, router};
+
+static APP: OnceCell<Router> = OnceCell::const_new();
+
+async fn get_app() -> &'static Router {
+    APP.get_or_init(|| async {
+        let mut cfg = conf::Conf::new().unwrap_or_else(|err| panic!("config error: {}", err));
+        cfg.env = "test".to_string();
+        if let Ok(nodes) = std::env::var("SCYLLA_NODES") {
+            cfg.scylla.nodes = nodes.split(',').map(|s| s.to_string()).collect();
+        }
+        if let Ok(url) = std::env::var("MEILI_URL") {
+            cfg.meili.url = url;
+        }
+        if let Ok(api_key) = std::env::var("MEILI_API_KEY") {
+            cfg.meili.api_key = api_key;
+        }
+
+        let sess = db::scylladb::ScyllaDB::new(cfg.scylla.clone(), "")
+            .await
+            .unwrap();
+        let schema = std::include_str!("../cql/schema_keyspace_test.cql");
+        db::scylladb::exec_cqls(&sess, schema).await.unwrap();
+        let schema = std::include_str!("../cql/schema_table.cql");
+        db::scylladb::exec_cqls(&sess, schema).await.unwrap();
+
+        let cli = Client::new(cfg.meili.url.clone(), Some(cfg.meili.api_key.clone()));
+        for index in ["creation", "publication"] {
+            cli.index(index)
+                .set_filterable_attributes(["gid", "language"])
+                .await
+                .unwrap()
+                .wait_for_completion(&cli, None, None)
+                .await
+                .unwrap();
+        }
+
+        let (_, app) = router::new(cfg).await.unwrap();
+        app
+    })
+    .await
+}
+
+fn gid() -> xid::Id {
+    xid::Id::from_str(db::USER_JARVIS).unwrap()
+}
+
+fn xid_bytes(id: xid::Id) -> ciborium::Value {
+    ciborium::Value::Bytes(id.as_bytes().to_vec())
+}
+
+fn encode_cbor(val: &ciborium::Value) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    ciborium::into_writer(val, &mut buf).unwrap();
+    buf
+}
+
+async fn call(
+    method: Method,
+    uri: &str,
+    body: Option<ciborium::Value>,
+) -> (StatusCode, hyper::body::Bytes) {
+    let app = get_app().await;
+    let req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::ACCEPT, "application/cbor")
+        .header(header::CONTENT_TYPE, "application/cbor")
+        .header("x-auth-user", db::USER_JARVIS)
+        .body(match body {
+            Some(val) => Body::from(encode_cbor(&val)),
+            None => Body::empty(),
+        })
+        .unwrap();
+
+    let res = app.clone().oneshot(req).await.unwrap();
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    (status, body)
+}
+
+async fn call_ok<T: DeserializeOwned>(
+    method: Method,
+    uri: &str,
+    body: Option<ciborium::Value>,
+) -> T {
+    let (status, body) = call(method, uri, body).await;
+    if status != StatusCode::OK {
+        panic!("{} response: {:?}", status, String::from_utf8_lossy(&body));
+    }
+    let res: SuccessResponse<T> = ciborium::from_reader(&body[..]).unwrap();
+    res.result
+}
+
+fn sample_content(text: &str) -> Vec<u8> {
+    encode_cbor(
+        &cbor!({
+            "type" => "doc",
+            "content" => [{
+                "type" => "heading",
+                "attrs" => {
+                    "id" => "Y3T1Ik",
+                    "level" => 1u8,
+                },
+                "content" => [{
+                    "type" => "text",
+                    "text" => text,
+                }],
+            }],
+        })
+        .unwrap(),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn api_works() {
+    healthz_works().await;
+    publish_and_search_works().await;
+    error_paths_works().await;
+    collection_children_works().await;
+}
+
+async fn healthz_works() {
+    let (status, _) = call(Method::GET, "/healthz", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn create_creation(title: &str) -> api::creation::CreationOutput {
+    call_ok(
+        Method::POST,
+        "/v1/creation",
+        Some(
+            cbor!({
+                "gid" => xid_bytes(gid()),
+                "language" => "eng",
+                "title" => title,
+                "content" => ciborium::Value::Bytes(sample_content(title)),
+            })
+            .unwrap(),
+        ),
+    )
+    .await
+}
+
+async fn publish_and_search_works() {
+    let keyword = format!("integration{}", xid::new());
+    let creation = create_creation(&format!("Hello {}", keyword)).await;
+    let cid = creation.id.unwrap();
+    let mut updated_at = creation.updated_at.unwrap();
+
+    // creation: draft -> review -> approved
+    for status in [1i8, 2i8] {
+        let res: api::creation::CreationOutput = call_ok(
+            Method::PATCH,
+            "/v1/creation/update_status",
+            Some(
+                cbor!({
+                    "gid" => xid_bytes(gid()),
+                    "id" => xid_bytes(cid),
+                    "status" => status,
+                    "updated_at" => updated_at,
+                })
+                .unwrap(),
+            ),
+        )
+        .await;
+        assert_eq!(res.status, Some(status));
+        updated_at = res.updated_at.unwrap();
+    }
+
+    // publication: review -> approved -> published
+    let publication: api::publication::PublicationOutput = call_ok(
+        Method::POST,
+        "/v1/publication",
+        Some(
+            cbor!({
+                "gid" => xid_bytes(gid()),
+                "cid" => xid_bytes(cid),
+                "language" => "eng",
+                "version" => 1,
+            })
+            .unwrap(),
+        ),
+    )
+    .await;
+    assert_eq!(publication.cid.unwrap(), cid);
+    let mut updated_at = publication.updated_at.unwrap();
+    for status in [1i8, 2i8] {
+        let res: api::publication::PublicationOutput = call_ok(
+            Method::PATCH,
+            "/v1/publication/update_status",
+            Some(
+                cbor!({
+                    "gid" => xid_bytes(gid()),
+                    "cid" => xid_bytes(cid),
+                    "language" => "eng",
+                    "version" => 1,
+                    "status" => status,
+                    "updated_at" => updated_at,
+                })
+                .unwrap(),
+            ),
+        )
+        .await;
+        assert_eq!(res.status, Some(status));
+        updated_at = res.updated_at.unwrap();
+    }
+
+    let res: api::publication::PublicationOutput = call_ok(
+        Method::GET,
+        &format!("/v1/publication/implicit_get?cid={}&fields=title", cid),
+        None,
+    )
+    .await;
+    assert_eq!(res.gid.unwrap(), gid());
+    assert_eq!(res.title.unwrap(), format!("Hello {}", keyword));
+
+    // Meilisearch indexes documents asynchronously
+    let mut found = false;
+    for _ in 0..20 {
+        let res: db::meili::SearchOutput =
+            call_ok(Method::GET, &format!("/v1/search?q={}", keyword), None).await;
+        if res.hits.iter().any(|hit| *hit.cid == cid) {
+            found = true;
+            break;
+        }
+        time::sleep(Duration::from_millis(500)).await;
+    }
+    assert!(found, "publication {} not found in search", cid);
+}
+
+async fn error_paths_works() {
+    // 404: creation not exists
+    let (status, _) = call(
+        Method::GET,
+        &format!("/v1/creation?gid={}&id={}", gid(), xid::new()),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // 404: publish a creation that not exists
+    let (status, _) = call(
+        Method::POST,
+        "/v1/publication",
+        Some(
+            cbor!({
+                "gid" => xid_bytes(gid()),
+                "cid" => xid_bytes(xid::new()),
+                "language" => "eng",
+                "version" => 1,
+            })
+            .unwrap(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let creation = create_creation("Hello conflict").await;
+    let cid = creation.id.unwrap();
+    let updated_at = creation.updated_at.unwrap();
+
+    // 409: updated_at conflict
+    let (status, _) = call(
+        Method::PATCH,
+        "/v1/creation/update_status",
+        Some(
+            cbor!({
+                "gid" => xid_bytes(gid()),
+                "id" => xid_bytes(cid),
+                "status" => 1,
+                "updated_at" => updated_at - 1,
+            })
+            .unwrap(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // 409: delete a creation that is not archived
+    let (status, _) = call(
+        Method::DELETE,
+        &format!("/v1/creation?gid={}&id={}", gid(), cid),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // 400: publish a creation that is not approved
+    let (status, _) = call(
+        Method::POST,
+        "/v1/publication",
+        Some(
+            cbor!({
+                "gid" => xid_bytes(gid()),
+                "cid" => xid_bytes(cid),
+                "language" => "eng",
+                "version" => 1,
+            })
+            .unwrap(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+async fn collection_children_works() {
+    let collection: api::collection::CollectionOutput = call_ok(
+        Method::POST,
+        "/v1/collection",
+        Some(
+            cbor!({
+                "gid" => xid_bytes(gid()),
+                "language" => "eng",
+                "context" => "",
+                "info" => {
+                    "title" => "Hello collection",
+                },
+            })
+            .unwrap(),
+        ),
+    )
+    .await;
+    let id = collection.id.unwrap();
+    let cid = create_creation("Hello child").await.id.unwrap();
+
+    let add_children = |cids: Vec<xid::Id>, kind: i8| {
+        cbor!({
+            "id" => xid_bytes(id),
+            "gid" => xid_bytes(gid()),
+            "cids" => cids.into_iter().map(xid_bytes).collect::<Vec<_>>(),
+            "kind" => kind,
+        })
+        .unwrap()
+    };
+
+    let res: Vec<api::collection::AddChildOutput> = call_ok(
+        Method::POST,
+        "/v1/collection/child",
+        Some(add_children(vec![cid, cid], 0)),
+    )
+    .await;
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[0].cid.unwrap(), cid);
+    assert!(res[0].added);
+    assert_eq!(
+        res[0].reason.as_deref(),
+        Some(api::collection::ADD_CHILD_OK)
+    );
+    assert!(!res[1].added);
+    assert_eq!(
+        res[1].reason.as_deref(),
+        Some(api::collection::ADD_CHILD_DUPLICATE)
+    );
+
+    let res: Vec<api::collection::AddChildOutput> = call_ok(
+        Method::POST,
+        "/v1/collection/child",
+        Some(add_children(vec![id], 2)),
+    )
+    .await;
+    assert_eq!(res.len(), 1);
+    assert!(!res[0].added);
+    assert_eq!(
+        res[0].reason.as_deref(),
+        Some(api::collection::ADD_CHILD_SELF_REFERENCE)
+    );
+}